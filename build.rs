@@ -10,6 +10,11 @@ fn get_dtrace_libpath() -> PathBuf {
 }
 
 fn main() {
+    // Stub builds neither need nor link libdtrace, see src/stub.rs
+    if env::var_os("CARGO_FEATURE_STUB").is_some() {
+        return;
+    }
+
     // Tell cargo to invalidate the built crate whenever the wrapper changes
     println!("cargo:rerun-if-changed=wrapper.h");
 
@@ -43,6 +48,12 @@ fn main() {
         .allowlist_var(".*(dt_.*|(?i)dtrace).*")
         .allowlist_type(".*(dt_.*|(?i)dtrace).*")
         .allowlist_function(".*(dt_.*|(?i)dtrace).*")
+        // libdtrace error numbers, members of an anonymous enum generated as constants
+        .allowlist_var("EDT_.*")
+        .constified_enum("EDT_.*")
+        // CTF type introspection, used to describe the types of symbols and probe arguments
+        .allowlist_var("CTF_(K|INT)_.*")
+        .allowlist_function("ctf_(type|member|array|enum)_.*")
         // Include paths for dtrace
         .clang_arg("-Itarget\\dtrace\\lib\\libctf\\common")
         .clang_arg("-Itarget\\dtrace\\lib\\libdtrace\\common")
@@ -1,7 +1,7 @@
 use libdtrace_rs::*;
 
 fn main() {
-    let handle = wrapper::dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap();
+    let mut handle = wrapper::dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap();
     handle.dtrace_setopt("bufsize", "4m").unwrap();
     handle.dtrace_setopt("aggsize", "4m").unwrap();
     handle
@@ -10,7 +10,7 @@ fn main() {
     let prog = handle
         .dtrace_program_strcompile(
             "syscall:::entry { @num[execname] = count(); }",
-            dtrace_probespec::DTRACE_PROBESPEC_NAME,
+            types::ProbeSpec::Name,
             DTRACE_C_ZDEFS,
             None,
         )
@@ -27,7 +27,7 @@ pub unsafe extern "C" fn custom_callback(
 }
 
 fn main() {
-    let handle = wrapper::dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap();
+    let mut handle = wrapper::dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap();
     handle.dtrace_setopt("bufsize", "4m").unwrap();
     handle
         .dtrace_register_handler(crate::types::dtrace_handler::Buffered(Some(callbacks::buffered)), None)
@@ -35,7 +35,7 @@ fn main() {
     let prog = handle
         .dtrace_program_strcompile(
             "BEGIN {trace(\"Hello World\");}",
-            dtrace_probespec::DTRACE_PROBESPEC_NAME,
+            types::ProbeSpec::Name,
             DTRACE_C_ZDEFS,
             None,
         )
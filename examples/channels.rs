@@ -1,6 +1,7 @@
 use libdtrace_rs::*;
 use std::sync::mpsc;
 use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
 use std::thread;
 static PROGRAM: &str = r#"
     syscall:::entry
@@ -10,33 +11,29 @@ static PROGRAM: &str = r#"
     }
 "#;
 
-pub unsafe extern "C" fn buffered(
-    bufdata: *const crate::dtrace_bufdata_t,
-    arg: *mut ::core::ffi::c_void,
-) -> ::core::ffi::c_int {
-    let tx = & *(arg as *mut ::std::sync::mpsc::Sender<::std::string::String>);
-    let msg = ::core::ffi::CStr::from_ptr((*bufdata).dtbda_buffered)
+fn buffered(bufdata: &dtrace_bufdata_t, tx: &Sender<String>) -> ::core::ffi::c_int {
+    let msg = unsafe { ::core::ffi::CStr::from_ptr(bufdata.dtbda_buffered) }
         .to_str()
         .expect("Failed to convert buffer to string");
     tx.send(msg.to_string()).unwrap();
 
-    crate::DTRACE_HANDLE_OK as ::core::ffi::c_int
+    DTRACE_HANDLE_OK as ::core::ffi::c_int
 }
 
 fn main() {
     let (tx, rx): (Sender<String>, Receiver<String>) = mpsc::channel();
 
     thread::spawn(move || {
-        let handle = wrapper::dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap();
+        let mut handle = wrapper::dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap();
         handle.dtrace_setopt("bufsize", "4m").unwrap();
         handle.dtrace_setopt("aggsize", "4m").unwrap();
         handle
-            .dtrace_register_handler(crate::types::dtrace_handler::Buffered(Some(buffered)), Some(&tx as *const _ as *mut _))
+            .register_handler_with(handler::Handler::Buffered(buffered), Arc::new(tx))
             .unwrap();
         let prog = handle
             .dtrace_program_strcompile(
                 PROGRAM,
-                dtrace_probespec::DTRACE_PROBESPEC_NAME,
+                types::ProbeSpec::Name,
                 DTRACE_C_ZDEFS,
                 None,
             )
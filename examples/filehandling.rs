@@ -1,7 +1,7 @@
 use libdtrace_rs::*;
 
 fn main() {
-    let handle = wrapper::dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap();
+    let mut handle = wrapper::dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap();
     handle.dtrace_setopt("bufsize", "4m").unwrap();
     handle.dtrace_setopt("aggsize", "4m").unwrap();
     handle
@@ -18,11 +18,11 @@ fn main() {
     handle.dtrace_program_exec(prog, None).unwrap();
     handle.dtrace_go().unwrap();
 
-    let output = utils::File::new("output.txt", "w").unwrap();
+    let mut output = std::fs::File::create("output.txt").unwrap();
     match handle.dtrace_status().unwrap() {
         types::dtrace_status::Ok => {
             handle
-                .dtrace_consume(Some(&output), Some(callbacks::chew), Some(callbacks::chew_rec), None)
+                .dtrace_consume(Some(&mut output), Some(callbacks::chew), Some(callbacks::chew_rec), None)
                 .unwrap();
         }
         _ => {}
@@ -18,7 +18,7 @@ syscall::NtCreateUserProcess:entry
 "#;
 
 fn main() -> Result<(), utils::Error> {
-    let handle = wrapper::dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0)?;
+    let mut handle = wrapper::dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0)?;
     handle.dtrace_setopt("bufsize", "4m")?;
     handle.dtrace_setopt("aggsize", "4m")?;
     handle.dtrace_setopt("sympath", "C:/symbols")?;
@@ -28,7 +28,7 @@ fn main() -> Result<(), utils::Error> {
     )?;
     let prog = handle.dtrace_program_strcompile(
         PROGRAM,
-        dtrace_probespec::DTRACE_PROBESPEC_NAME,
+        types::ProbeSpec::Name,
         DTRACE_C_ZDEFS,
         None,
     )?;
@@ -26,7 +26,7 @@ pub unsafe extern "C" fn custom_callback(
 }
 
 fn main() {
-    let handle = wrapper::dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap();
+    let mut handle = wrapper::dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap();
     handle.dtrace_setopt("bufsize", "4m").unwrap();
     handle
         .dtrace_register_handler(crate::types::dtrace_handler::Buffered(Some(callbacks::buffered)), None)
@@ -34,7 +34,7 @@ fn main() {
     let prog = handle
         .dtrace_program_strcompile(
             "syscall",
-            dtrace_probespec::DTRACE_PROBESPEC_PROVIDER,
+            types::ProbeSpec::Provider,
             DTRACE_C_ZDEFS,
             None,
         )
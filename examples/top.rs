@@ -0,0 +1,20 @@
+use libdtrace_rs::*;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut handle = wrapper::dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0)?;
+    handle.dtrace_setopt("bufsize", "4m")?;
+    handle.dtrace_setopt("aggsize", "4m")?;
+    let prog = handle.dtrace_program_strcompile(
+        "syscall:::entry { @calls[execname] = count(); @funcs[probefunc] = count(); }",
+        types::ProbeSpec::Name,
+        DTRACE_C_ZDEFS,
+        None,
+    )?;
+    handle.dtrace_program_exec(prog, None)?;
+    handle.dtrace_go()?;
+
+    tui::run(&mut handle)?;
+
+    handle.dtrace_stop()?;
+    Ok(())
+}
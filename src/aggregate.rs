@@ -0,0 +1,1144 @@
+use ::core::ffi::{c_int, c_void, CStr};
+use crate::probe::RecordDescription;
+use crate::utils::Error;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// Number of buckets in a `quantize()` aggregation
+pub const QUANTIZE_NBUCKETS: usize = 127;
+/// Index of the bucket holding the value zero in a `quantize()` aggregation
+pub const QUANTIZE_ZEROBUCKET: usize = 63;
+
+/// A single component of an aggregation key.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum KeyItem {
+    /// Integer key, such as `pid` or `cpu`
+    Int(i64),
+    /// String key, such as `execname` or `probefunc`
+    Str(String),
+    /// Any other key (e.g. a stack), kept as raw bytes
+    Bytes(Vec<u8>),
+}
+
+impl KeyItem {
+    /// Decodes a key record of an aggregation entry.
+    ///
+    /// # Safety
+    ///
+    /// `data` must point to the start of the aggregation data described by `rec`.
+    pub(crate) unsafe fn decode(rec: &RecordDescription, data: *const u8) -> Self {
+        Self::from_bytes(std::slice::from_raw_parts(data.add(rec.offset as usize), rec.size as usize))
+    }
+
+    /// Decodes the bytes of a record: records of 1, 2, 4 or 8 bytes are integers, larger ones strings, or bytes when
+    /// they do not hold text.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
+        match *bytes {
+            [byte] => KeyItem::Int(byte as i8 as i64),
+            [_, _] => KeyItem::Int(i16::from_ne_bytes(bytes.try_into().unwrap()) as i64),
+            [_, _, _, _] => KeyItem::Int(i32::from_ne_bytes(bytes.try_into().unwrap()) as i64),
+            [_, _, _, _, _, _, _, _] => KeyItem::Int(i64::from_ne_bytes(bytes.try_into().unwrap())),
+            _ => {
+                let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+                match std::str::from_utf8(&bytes[..len]) {
+                    Ok(s) if len > 0 && s.chars().all(|c| !c.is_control()) => {
+                        KeyItem::Str(s.to_string())
+                    }
+                    _ => KeyItem::Bytes(bytes.to_vec()),
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for KeyItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            KeyItem::Int(value) => write!(f, "{}", value),
+            KeyItem::Str(value) => write!(f, "{}", value),
+            KeyItem::Bytes(bytes) => {
+                for byte in bytes {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The (possibly empty) tuple of keys an aggregation entry is indexed by.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AggKey(pub Vec<KeyItem>);
+
+impl std::fmt::Display for AggKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (i, item) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", item)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&str> for AggKey {
+    /// Creates the key of an aggregation indexed by a single string, e.g. `@bytes["recv"]`.
+    fn from(key: &str) -> Self {
+        AggKey(vec![KeyItem::Str(key.to_string())])
+    }
+}
+
+impl From<i64> for AggKey {
+    /// Creates the key of an aggregation indexed by a single integer, e.g. `@bytes[pid]`.
+    fn from(key: i64) -> Self {
+        AggKey(vec![KeyItem::Int(key)])
+    }
+}
+
+/// The value of an aggregation entry, by aggregating function.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AggValue {
+    /// `count()`
+    Count(u64),
+    /// `sum()`
+    Sum(i64),
+    /// `min()`
+    Min(i64),
+    /// `max()`
+    Max(i64),
+    /// `avg()`
+    Avg(f64),
+    /// `stddev()`
+    Stddev(f64),
+    /// `quantize()`
+    Quantize(Histogram),
+    /// `lquantize()`
+    LQuantize(Histogram),
+    /// `llquantize()`
+    LLQuantize(Histogram),
+    /// Any other aggregating action, with its data as 64-bit words
+    Other { action: u16, data: Vec<i64> },
+}
+
+impl AggValue {
+    /// Decodes the data of the aggregating action `action`.
+    pub fn decode(action: u16, data: &[i64]) -> Self {
+        let first = data.first().copied().unwrap_or(0);
+        match action as u32 {
+            crate::DTRACEAGG_COUNT => AggValue::Count(first as u64),
+            crate::DTRACEAGG_SUM => AggValue::Sum(first),
+            crate::DTRACEAGG_MIN => AggValue::Min(first),
+            crate::DTRACEAGG_MAX => AggValue::Max(first),
+            crate::DTRACEAGG_AVG => AggValue::Avg(match data {
+                [count, total, ..] if *count != 0 => *total as f64 / *count as f64,
+                _ => 0.0,
+            }),
+            crate::DTRACEAGG_STDDEV => AggValue::Stddev(match data {
+                [count, total, sq_lo, sq_hi, ..] if *count != 0 => {
+                    let count = *count as f64;
+                    let mean = *total as f64 / count;
+                    let squares = (*sq_hi as u64 as f64) * 2f64.powi(64) + *sq_lo as u64 as f64;
+                    (squares / count - mean * mean).max(0.0).sqrt()
+                }
+                _ => 0.0,
+            }),
+            crate::DTRACEAGG_QUANTIZE => AggValue::Quantize(Histogram::quantize(data)),
+            crate::DTRACEAGG_LQUANTIZE if !data.is_empty() => {
+                AggValue::LQuantize(Histogram::lquantize(first as u64, &data[1..]))
+            }
+            crate::DTRACEAGG_LLQUANTIZE if !data.is_empty() => {
+                AggValue::LLQuantize(Histogram::llquantize(first as u64, &data[1..]))
+            }
+            _ => AggValue::Other {
+                action,
+                data: data.to_vec(),
+            },
+        }
+    }
+
+    /// Returns the histogram of a quantizing aggregation.
+    pub fn histogram(&self) -> Option<&Histogram> {
+        match self {
+            AggValue::Quantize(hist) | AggValue::LQuantize(hist) | AggValue::LLQuantize(hist) => Some(hist),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as an integer, as dtrace(1) prints it: averages and standard deviations are truncated and
+    /// histograms count their values.
+    pub fn as_i64(&self) -> i64 {
+        match self {
+            AggValue::Count(count) => *count as i64,
+            AggValue::Sum(value) | AggValue::Min(value) | AggValue::Max(value) => *value,
+            AggValue::Avg(value) | AggValue::Stddev(value) => *value as i64,
+            AggValue::Quantize(hist) | AggValue::LQuantize(hist) | AggValue::LLQuantize(hist) => hist.total(),
+            AggValue::Other { data, .. } => data.first().copied().unwrap_or(0),
+        }
+    }
+
+    /// Divides the value by `normal`, like D's `normalize()` does before printing. Histograms have each bucket
+    /// count divided; values of other aggregating actions are returned unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `normal` - The divisor, e.g. the number of seconds or CPUs the values were collected over. `0` and `1`
+    ///   leave the value unchanged.
+    pub fn normalize(&self, normal: u64) -> AggValue {
+        if normal <= 1 {
+            return self.clone();
+        }
+        let divide = |value: i64| (value as i128 / normal as i128) as i64;
+        match self {
+            AggValue::Count(count) => AggValue::Count(count / normal),
+            AggValue::Sum(value) => AggValue::Sum(divide(*value)),
+            AggValue::Min(value) => AggValue::Min(divide(*value)),
+            AggValue::Max(value) => AggValue::Max(divide(*value)),
+            AggValue::Avg(value) => AggValue::Avg(value / normal as f64),
+            AggValue::Stddev(value) => AggValue::Stddev(value / normal as f64),
+            AggValue::Quantize(hist) => AggValue::Quantize(hist.normalize(normal)),
+            AggValue::LQuantize(hist) => AggValue::LQuantize(hist.normalize(normal)),
+            AggValue::LLQuantize(hist) => AggValue::LLQuantize(hist.normalize(normal)),
+            AggValue::Other { .. } => self.clone(),
+        }
+    }
+
+    /// Returns the change of the value since `previous`, the value of the same entry in an earlier snapshot.
+    /// Counts, sums, histogram buckets and the words of other aggregating actions are subtracted; minimums,
+    /// maximums, averages and standard deviations cannot be, and are returned unchanged, as is a value of another
+    /// aggregating action than `previous`.
+    pub fn delta(&self, previous: &AggValue) -> AggValue {
+        match (self, previous) {
+            (AggValue::Count(count), AggValue::Count(prev)) => AggValue::Count(count.saturating_sub(*prev)),
+            (AggValue::Sum(value), AggValue::Sum(prev)) => AggValue::Sum(value.wrapping_sub(*prev)),
+            (AggValue::Quantize(hist), AggValue::Quantize(prev)) => AggValue::Quantize(hist.delta(prev)),
+            (AggValue::LQuantize(hist), AggValue::LQuantize(prev)) => AggValue::LQuantize(hist.delta(prev)),
+            (AggValue::LLQuantize(hist), AggValue::LLQuantize(prev)) => AggValue::LLQuantize(hist.delta(prev)),
+            (AggValue::Other { action, data }, AggValue::Other { action: prev_action, data: prev })
+                if action == prev_action =>
+            {
+                AggValue::Other {
+                    action: *action,
+                    data: data
+                        .iter()
+                        .enumerate()
+                        .map(|(i, word)| word.wrapping_sub(prev.get(i).copied().unwrap_or(0)))
+                        .collect(),
+                }
+            }
+            _ => self.clone(),
+        }
+    }
+}
+
+/// Description of an aggregation, shared by all of its entries, see `dtrace_hdl::lookup_aggregation`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AggregationDescription {
+    /// Aggregation ID
+    pub id: crate::dtrace_aggid_t,
+    /// Aggregation variable ID
+    pub varid: crate::dtrace_aggvarid_t,
+    /// Enabled probe ID of the clause aggregating
+    pub epid: crate::dtrace_epid_t,
+    /// Name of the aggregation variable, without the leading `@`
+    pub name: String,
+    /// Layout of the keys of an entry
+    pub keys: Vec<RecordDescription>,
+    /// Layout of the value of an entry, recorded by the aggregating action
+    pub value: RecordDescription,
+}
+
+impl AggregationDescription {
+    /// Builds an aggregation description from its libdtrace description.
+    ///
+    /// # Safety
+    ///
+    /// `desc` must be a valid pointer owned by a DTrace instance.
+    pub unsafe fn from_raw(desc: *const crate::dtrace_aggdesc_t) -> Self {
+        let desc = &*desc;
+        // The records are allocated past the end of the description.
+        let recs = std::slice::from_raw_parts(desc.dtagd_rec.as_ptr(), desc.dtagd_nrecs as usize);
+        let name = if desc.dtagd_name.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(desc.dtagd_name).to_string_lossy().into_owned()
+        };
+
+        // The first record holds the aggregation ID and the last one the aggregating action;
+        // everything in between is a key.
+        let (action, keys) = recs.split_last().expect("Aggregation without records");
+        Self {
+            id: desc.dtagd_id,
+            varid: desc.dtagd_varid,
+            epid: desc.dtagd_epid,
+            name,
+            keys: keys.iter().skip(1).map(RecordDescription::from).collect(),
+            value: RecordDescription::from(action),
+        }
+    }
+}
+
+/// Aggregation ID to description cache kept by each `dtrace_hdl`, so entries are decoded without re-reading the
+/// description of their aggregation.
+#[derive(Default)]
+pub(crate) struct AggregationCache {
+    descriptions: Mutex<HashMap<crate::dtrace_aggid_t, Arc<AggregationDescription>>>,
+}
+
+impl AggregationCache {
+    /// Returns the cached description of `desc`, caching it when it is first seen.
+    ///
+    /// # Safety
+    ///
+    /// `desc` must be a valid pointer owned by a DTrace instance.
+    pub(crate) unsafe fn get(&self, desc: *const crate::dtrace_aggdesc_t) -> Arc<AggregationDescription> {
+        self.descriptions
+            .lock()
+            .unwrap()
+            .entry((*desc).dtagd_id)
+            .or_insert_with(|| Arc::new(AggregationDescription::from_raw(desc)))
+            .clone()
+    }
+
+    /// Looks up `aggid` in the cache, falling back to the descriptions known to `handle`.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be a valid DTrace handle.
+    pub(crate) unsafe fn lookup(
+        &self,
+        handle: *mut crate::dtrace_hdl_t,
+        aggid: crate::dtrace_aggid_t,
+    ) -> Option<Arc<AggregationDescription>> {
+        if let Some(desc) = self.descriptions.lock().unwrap().get(&aggid) {
+            return Some(desc.clone());
+        }
+
+        let hdl = &*handle;
+        if aggid as usize >= hdl.dt_maxagg || hdl.dt_aggdesc.is_null() {
+            return None;
+        }
+        let desc = *hdl.dt_aggdesc.add(aggid as usize);
+        (!desc.is_null()).then(|| self.get(desc))
+    }
+}
+
+/// A decoded aggregation entry.
+#[derive(Debug, Clone)]
+pub struct AggregateEntry {
+    /// Name of the aggregation variable, without the leading `@`
+    pub name: String,
+    /// Aggregation variable ID
+    pub varid: crate::dtrace_aggvarid_t,
+    /// Keys of the entry
+    pub key: AggKey,
+    /// Value of the entry, as recorded
+    pub value: AggValue,
+    /// Divisor the value is printed with, set by D's `normalize()` or [`AggregateSnapshot::normalize`], `1` if the
+    /// aggregation is not normalized
+    pub normal: u64,
+}
+
+impl AggregateEntry {
+    /// Decodes an aggregation entry.
+    ///
+    /// # Safety
+    ///
+    /// `aggdata` must be a valid pointer handed out by libdtrace during an aggregation walk.
+    pub unsafe fn from_raw(aggdata: *const crate::dtrace_aggdata_t) -> Self {
+        Self::decode(&AggregationDescription::from_raw((*aggdata).dtada_desc), aggdata)
+    }
+
+    /// Decodes an aggregation entry with the description of its aggregation, e.g. one returned by
+    /// `dtrace_hdl::lookup_aggregation`.
+    ///
+    /// # Safety
+    ///
+    /// `aggdata` must be a valid pointer handed out by libdtrace during an aggregation walk, with `desc` describing
+    /// its aggregation.
+    pub unsafe fn decode(desc: &AggregationDescription, aggdata: *const crate::dtrace_aggdata_t) -> Self {
+        let aggdata = &*aggdata;
+        let data = aggdata.dtada_data as *const u8;
+        let key = AggKey(desc.keys.iter().map(|rec| KeyItem::decode(rec, data)).collect());
+
+        let addr = data.add(desc.value.offset as usize) as *const i64;
+        let words = desc.value.size as usize / std::mem::size_of::<i64>();
+        let data: Vec<i64> = (0..words).map(|i| addr.add(i).read_unaligned()).collect();
+        let value = AggValue::decode(desc.value.action, &data);
+
+        Self {
+            name: desc.name.clone(),
+            varid: desc.varid,
+            key,
+            value,
+            normal: aggdata.dtada_normal.max(1),
+        }
+    }
+
+    /// Returns the value divided by the normalization of the entry, see [`AggValue::normalize`].
+    pub fn normalized(&self) -> AggValue {
+        self.value.normalize(self.normal)
+    }
+}
+
+/// The decoded entries of all aggregations at one point in time, see `dtrace_hdl::snapshot_aggregations`.
+#[derive(Debug, Clone, Default)]
+pub struct AggregateSnapshot {
+    /// The entries, in the order they were walked in
+    pub entries: Vec<AggregateEntry>,
+}
+
+impl From<Vec<AggregateEntry>> for AggregateSnapshot {
+    fn from(entries: Vec<AggregateEntry>) -> Self {
+        Self { entries }
+    }
+}
+
+impl AggregateSnapshot {
+    /// Returns the value of the entry of the aggregation `name` (without the leading `@`) indexed by `key`.
+    pub fn get(&self, name: &str, key: &AggKey) -> Option<&AggValue> {
+        self.entries
+            .iter()
+            .find(|entry| entry.name == name && entry.key == *key)
+            .map(|entry| &entry.value)
+    }
+
+    /// Normalizes the aggregation `name` (without the leading `@`), like D's `normalize(@name, normal)`: its entries
+    /// report their values divided by `normal` through [`AggregateEntry::normalized`].
+    ///
+    /// ```no_run
+    /// # fn snapshot() -> libdtrace_rs::aggregate::AggregateSnapshot { unimplemented!() }
+    /// // @syscalls was collected over 10 seconds, report it per second.
+    /// let mut snapshot = snapshot();
+    /// snapshot.normalize("syscalls", 10);
+    /// for entry in &snapshot.entries {
+    ///     println!("{} {}", entry.key, entry.normalized().as_i64());
+    /// }
+    /// ```
+    pub fn normalize(&mut self, name: &str, normal: u64) {
+        for entry in self.entries.iter_mut().filter(|entry| entry.name == name) {
+            entry.normal = normal.max(1);
+        }
+    }
+
+    /// Removes the normalization of the aggregation `name` (without the leading `@`), like D's `denormalize(@name)`.
+    pub fn denormalize(&mut self, name: &str) {
+        self.normalize(name, 1);
+    }
+
+    /// Returns the changes since `previous`, an earlier snapshot of the same aggregations, so aggregations that keep
+    /// accumulating can be reported per interval without clearing them.
+    ///
+    /// Each entry holds the change of its value since the entry with the same name and key in `previous`, see
+    /// [`AggValue::delta`]. Entries new since `previous` are kept as they are, and entries that disappeared are left
+    /// out.
+    ///
+    /// ```no_run
+    /// # fn snapshot() -> libdtrace_rs::aggregate::AggregateSnapshot { unimplemented!() }
+    /// let mut previous = snapshot();
+    /// loop {
+    ///     std::thread::sleep(std::time::Duration::from_secs(1));
+    ///     let current = snapshot();
+    ///     for entry in &current.delta(&previous).entries {
+    ///         println!("{} {}/s", entry.key, entry.value.as_i64());
+    ///     }
+    ///     previous = current;
+    /// }
+    /// ```
+    pub fn delta(&self, previous: &AggregateSnapshot) -> AggregateSnapshot {
+        let previous: HashMap<(&str, &AggKey), &AggValue> = previous
+            .entries
+            .iter()
+            .map(|entry| ((entry.name.as_str(), &entry.key), &entry.value))
+            .collect();
+        let entries = self
+            .entries
+            .iter()
+            .map(|entry| match previous.get(&(entry.name.as_str(), &entry.key)) {
+                Some(prev) => AggregateEntry {
+                    value: entry.value.delta(prev),
+                    ..entry.clone()
+                },
+                None => entry.clone(),
+            })
+            .collect();
+        AggregateSnapshot { entries }
+    }
+
+    /// Converts the snapshot to a map from aggregation name, without the leading `@`, to the values of the
+    /// aggregation by key.
+    ///
+    /// ```no_run
+    /// # fn snapshot() -> libdtrace_rs::aggregate::AggregateSnapshot { unimplemented!() }
+    /// use libdtrace_rs::aggregate::AggKey;
+    ///
+    /// let aggregations = snapshot().into_map();
+    /// let recv = &aggregations["bytes"][&AggKey::from("recv")];
+    /// ```
+    pub fn into_map(self) -> HashMap<String, BTreeMap<AggKey, AggValue>> {
+        let mut map: HashMap<String, BTreeMap<AggKey, AggValue>> = HashMap::new();
+        for entry in self.entries {
+            map.entry(entry.name).or_default().insert(entry.key, entry.value);
+        }
+        map
+    }
+}
+
+/// A callback receiving the entries of an aggregation, see [`AggregationRouter`].
+type AggregationCallback = Box<dyn FnMut(&AggregateEntry) + Send>;
+
+/// Routes the entries of each aggregation to the callback registered for it, so programs with many aggregations are
+/// handled without matching on their names.
+///
+/// As a [`RecordVisitor`](crate::visit::RecordVisitor), the router receives the entries of
+/// `dtrace_hdl::visit_aggregates` or of a session, see `TraceSession::with_visitor`.
+///
+/// ```no_run
+/// use libdtrace_rs::aggregate::AggregationRouter;
+/// use libdtrace_rs::types::dtrace_aggwalk_order;
+///
+/// # let mut handle: libdtrace_rs::wrapper::dtrace_hdl = unimplemented!();
+/// let mut router = AggregationRouter::new()
+///     .on_aggregation("@latency", |entry| println!("latency {} {:?}", entry.key, entry.value))
+///     .on_aggregation("@bytes", |entry| println!("bytes {} {}", entry.key, entry.value.as_i64()));
+/// handle.visit_aggregates(dtrace_aggwalk_order::Sorted, &mut router).unwrap();
+/// ```
+#[derive(Default)]
+pub struct AggregationRouter {
+    /// Callbacks by aggregation name, without the leading `@`
+    routes: HashMap<String, AggregationCallback>,
+    /// Callback for the aggregations without one of their own
+    fallback: Option<AggregationCallback>,
+}
+
+impl AggregationRouter {
+    /// Creates a router without callbacks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls `callback` with every entry of the aggregation `name`, with or without the leading `@`, replacing the
+    /// callback registered for it before.
+    pub fn on_aggregation(mut self, name: &str, callback: impl FnMut(&AggregateEntry) + Send + 'static) -> Self {
+        let name = name.strip_prefix('@').unwrap_or(name);
+        self.routes.insert(name.to_string(), Box::new(callback));
+        self
+    }
+
+    /// Calls `callback` with the entries of the aggregations without a callback of their own, which are ignored
+    /// otherwise.
+    pub fn on_other(mut self, callback: impl FnMut(&AggregateEntry) + Send + 'static) -> Self {
+        self.fallback = Some(Box::new(callback));
+        self
+    }
+
+    /// Returns the names of the aggregations with a callback, without the leading `@`.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.routes.keys().map(String::as_str)
+    }
+
+    /// Hands `entry` to the callback of its aggregation.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if a callback received the entry, including the fallback callback.
+    pub fn dispatch(&mut self, entry: &AggregateEntry) -> bool {
+        match self.routes.get_mut(&entry.name).or(self.fallback.as_mut()) {
+            Some(callback) => {
+                callback(entry);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl crate::visit::RecordVisitor for AggregationRouter {
+    fn visit_aggregate(&mut self, entry: &AggregateEntry) {
+        self.dispatch(entry);
+    }
+}
+
+/// Entries collected by an aggregation walk with `collect`.
+pub(crate) struct Collector<'a> {
+    pub(crate) entries: Vec<AggregateEntry>,
+    pub(crate) perf: &'a crate::perf::PerfCounters,
+    pub(crate) descriptions: &'a AggregationCache,
+}
+
+/// Aggregation walker that appends every entry to the [`Collector`] passed as `arg`.
+pub(crate) unsafe extern "C" fn collect(
+    aggdata: *const crate::dtrace_aggdata_t,
+    arg: *mut c_void,
+) -> c_int {
+    let collector = &mut *(arg as *mut Collector);
+    let entry = collector
+        .perf
+        .time(crate::perf::Section::Decoding, || {
+            AggregateEntry::decode(&collector.descriptions.get((*aggdata).dtada_desc), aggdata)
+        });
+    collector.entries.push(entry);
+
+    crate::DTRACE_AGGWALK_NEXT as c_int
+}
+
+/// An aggregation entry as libdtrace holds it, before decoding, see `dtrace_hdl::aggregate_walk_raw`.
+///
+/// The data is laid out as described by [`RawAggregate::description`]: the aggregation ID, then the keys, then the
+/// value recorded by the aggregating action, each at the offset of its record.
+#[derive(Debug, Clone, Copy)]
+pub struct RawAggregate<'a> {
+    description: &'a AggregationDescription,
+    data: &'a [u8],
+    normal: u64,
+}
+
+impl<'a> RawAggregate<'a> {
+    /// Views `data` as an entry of the aggregation described by `description`, e.g. to decode data sent by another
+    /// process with [`RawAggregate::decode`].
+    pub fn new(description: &'a AggregationDescription, data: &'a [u8], normal: u64) -> Self {
+        Self {
+            description,
+            data,
+            normal: normal.max(1),
+        }
+    }
+
+    /// Returns the description of the aggregation of the entry.
+    pub fn description(&self) -> &'a AggregationDescription {
+        self.description
+    }
+
+    /// Returns all the data of the entry.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the bytes of `rec`, empty if the data is too short to hold them.
+    pub fn record(&self, rec: &RecordDescription) -> &'a [u8] {
+        let start = rec.offset as usize;
+        self.data.get(start..start + rec.size as usize).unwrap_or_default()
+    }
+
+    /// Returns the keys of the entry, with the description of each.
+    pub fn keys(&self) -> impl Iterator<Item = (&'a RecordDescription, &'a [u8])> + 'a {
+        let entry = *self;
+        self.description.keys.iter().map(move |rec| (rec, entry.record(rec)))
+    }
+
+    /// Returns the value of the entry, as recorded by the aggregating action.
+    pub fn value(&self) -> &'a [u8] {
+        self.record(&self.description.value)
+    }
+
+    /// Returns the divisor the entry is printed with, `1` if the aggregation is not normalized.
+    pub fn normal(&self) -> u64 {
+        self.normal
+    }
+
+    /// Decodes the entry, like the entries of `dtrace_hdl::aggregate_entries`.
+    pub fn decode(&self) -> AggregateEntry {
+        let key = AggKey(
+            self.description
+                .keys
+                .iter()
+                .map(|rec| match self.record(rec).len() == rec.size as usize {
+                    // SAFETY: the record lies within the data.
+                    true => unsafe { KeyItem::decode(rec, self.data.as_ptr()) },
+                    false => KeyItem::Bytes(Vec::new()),
+                })
+                .collect(),
+        );
+        let data: Vec<i64> = self
+            .value()
+            .chunks_exact(std::mem::size_of::<i64>())
+            .map(|word| i64::from_ne_bytes(word.try_into().unwrap()))
+            .collect();
+        AggregateEntry {
+            name: self.description.name.clone(),
+            varid: self.description.varid,
+            key,
+            value: AggValue::decode(self.description.value.action, &data),
+            normal: self.normal,
+        }
+    }
+}
+
+/// State of an aggregation walk with `walk_raw`.
+pub(crate) struct RawWalk<'a> {
+    pub(crate) descriptions: &'a AggregationCache,
+    pub(crate) callback: &'a mut dyn FnMut(&RawAggregate),
+}
+
+/// Aggregation walker handing every entry, undecoded, to the callback of the [`RawWalk`] passed as `arg`.
+pub(crate) unsafe extern "C" fn walk_raw(aggdata: *const crate::dtrace_aggdata_t, arg: *mut c_void) -> c_int {
+    let walk = &mut *(arg as *mut RawWalk);
+    let aggdata = &*aggdata;
+    let description = walk.descriptions.get(aggdata.dtada_desc);
+    let data = match aggdata.dtada_data.is_null() {
+        true => &[][..],
+        false => std::slice::from_raw_parts(aggdata.dtada_data as *const u8, aggdata.dtada_size),
+    };
+    (walk.callback)(&RawAggregate::new(&description, data, aggdata.dtada_normal));
+
+    crate::DTRACE_AGGWALK_NEXT as c_int
+}
+
+/// Part of an aggregation entry formatted by a [`PrintaChunk`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChunkKind {
+    /// A key of the entry
+    Key,
+    /// The value of one of the aggregations printed
+    Value,
+    /// Text of the `printa()` format between conversions
+    Format,
+    /// Output that is not part of an entry, e.g. the blank line before an aggregation
+    Other,
+}
+
+/// A piece of `printa()`-formatted output, paired with the entry it formats, see
+/// `dtrace_hdl::aggregate_print_chunks`.
+#[derive(Debug, Clone)]
+pub struct PrintaChunk {
+    /// The formatted text
+    pub text: String,
+    /// What the text formats
+    pub kind: ChunkKind,
+    /// `true` for the last chunk of an entry
+    pub last: bool,
+    /// The entry formatted, shared by its chunks, `None` for [`ChunkKind::Other`]
+    pub entry: Option<Arc<AggregateEntry>>,
+}
+
+impl PrintaChunk {
+    /// Returns the kind of the chunk described by the `dtbda_flags` of a buffered record.
+    pub fn kind_of(flags: u32) -> ChunkKind {
+        if flags & crate::DTRACE_BUFDATA_AGGKEY != 0 {
+            ChunkKind::Key
+        } else if flags & crate::DTRACE_BUFDATA_AGGVAL != 0 {
+            ChunkKind::Value
+        } else if flags & crate::DTRACE_BUFDATA_AGGFORMAT != 0 {
+            ChunkKind::Format
+        } else {
+            ChunkKind::Other
+        }
+    }
+}
+
+/// State of a buffered handler installed with `printa_chunk`.
+pub(crate) struct PrintaContext<'a> {
+    pub(crate) descriptions: &'a AggregationCache,
+    pub(crate) callback: &'a mut dyn FnMut(&PrintaChunk),
+    /// The entry of the previous chunk, with the libdtrace data it was decoded from
+    pub(crate) entry: Option<(*const crate::dtrace_aggdata_t, Arc<AggregateEntry>)>,
+}
+
+/// Buffered handler handing each chunk of formatted aggregation output to the callback of the [`PrintaContext`]
+/// passed as `arg`, decoding the entry of consecutive chunks once.
+pub(crate) unsafe extern "C" fn printa_chunk(bufdata: *const crate::dtrace_bufdata_t, arg: *mut c_void) -> c_int {
+    let ctx = &mut *(arg as *mut PrintaContext);
+    let bufdata = &*bufdata;
+    let aggdata = bufdata.dtbda_aggdata;
+    let entry = match ctx.entry.as_ref() {
+        _ if aggdata.is_null() => None,
+        Some((previous, entry)) if *previous == aggdata => Some(entry.clone()),
+        _ => {
+            let entry = Arc::new(AggregateEntry::decode(&ctx.descriptions.get((*aggdata).dtada_desc), aggdata));
+            ctx.entry = Some((aggdata, entry.clone()));
+            Some(entry)
+        }
+    };
+    let chunk = PrintaChunk {
+        text: CStr::from_ptr(bufdata.dtbda_buffered).to_string_lossy().into_owned(),
+        kind: match entry {
+            Some(_) => PrintaChunk::kind_of(bufdata.dtbda_flags),
+            None => ChunkKind::Other,
+        },
+        last: bufdata.dtbda_flags & crate::DTRACE_BUFDATA_AGGLAST != 0,
+        entry,
+    };
+    (ctx.callback)(&chunk);
+
+    crate::DTRACE_HANDLE_OK as c_int
+}
+
+/// Normalization applied by an aggregation walk with `normalize`.
+pub(crate) struct Normalization<'a> {
+    pub(crate) name: &'a str,
+    /// The divisor, `None` to denormalize
+    pub(crate) normal: Option<u64>,
+}
+
+/// Aggregation walker that normalizes or denormalizes the entries of the aggregation named by the [`Normalization`]
+/// passed as `arg`.
+pub(crate) unsafe extern "C" fn normalize(
+    aggdata: *const crate::dtrace_aggdata_t,
+    arg: *mut c_void,
+) -> c_int {
+    let normalization = &*(arg as *const Normalization);
+    let desc = &*(*aggdata).dtada_desc;
+    if desc.dtagd_name.is_null() || CStr::from_ptr(desc.dtagd_name).to_bytes() != normalization.name.as_bytes() {
+        return crate::DTRACE_AGGWALK_NEXT as c_int;
+    }
+
+    match normalization.normal {
+        Some(normal) => {
+            // libdtrace reads the divisor back from the entry when the walker asks for normalization.
+            (*(aggdata as *mut crate::dtrace_aggdata_t)).dtada_normal = normal.max(1);
+            crate::DTRACE_AGGWALK_NORMALIZE as c_int
+        }
+        None => crate::DTRACE_AGGWALK_DENORMALIZE as c_int,
+    }
+}
+
+/// Bucketization used by a [`Histogram`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistogramKind {
+    /// Power-of-two buckets, as produced by `quantize()`
+    Quantize,
+    /// Linear buckets, as produced by `lquantize(value, base, limit, step)`
+    LQuantize { base: i32, step: u16, levels: u16 },
+    /// Log-linear buckets, as produced by `llquantize(value, factor, low, high, nsteps)`
+    LLQuantize {
+        factor: u16,
+        low: u16,
+        high: u16,
+        nsteps: u16,
+    },
+}
+
+/// A single histogram bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bucket {
+    /// Lower bound of the bucket
+    pub value: i64,
+    /// Number of values that fell into the bucket
+    pub count: i64,
+}
+
+/// A decoded `quantize()`, `lquantize()` or `llquantize()` aggregation value.
+///
+/// For linear and log-linear histograms the first bucket counts values below its `value`
+/// and the last bucket counts values greater than or equal to its `value`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    pub kind: HistogramKind,
+    pub buckets: Vec<Bucket>,
+}
+
+impl Histogram {
+    /// Returns the histogram of the value of an aggregation entry, see [`AggValue::histogram`].
+    ///
+    /// # Returns
+    ///
+    /// Returns [`None`] if the value was not produced by one of the quantizing actions.
+    pub fn from_value(value: &AggValue) -> Option<Self> {
+        value.histogram().cloned()
+    }
+
+    /// Builds a power-of-two histogram from `quantize()` bucket counts.
+    pub fn quantize(counts: &[i64]) -> Self {
+        let buckets = counts
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| Bucket {
+                value: quantize_bucket_value(i),
+                count,
+            })
+            .collect();
+
+        Self {
+            kind: HistogramKind::Quantize,
+            buckets,
+        }
+    }
+
+    /// Builds a linear histogram from the encoded `lquantize()` argument and bucket counts.
+    pub fn lquantize(arg: u64, counts: &[i64]) -> Self {
+        let base = arg as u32 as i32;
+        let levels = (arg >> 32) as u16;
+        let step = (arg >> 48) as u16;
+
+        let mut values = vec![base as i64];
+        values.extend((0..=levels as i64).map(|level| base as i64 + level * step as i64));
+
+        Self {
+            kind: HistogramKind::LQuantize { base, step, levels },
+            buckets: zip_buckets(&values, counts),
+        }
+    }
+
+    /// Builds a log-linear histogram from the encoded `llquantize()` argument and bucket counts.
+    pub fn llquantize(arg: u64, counts: &[i64]) -> Self {
+        let nsteps = arg as u16;
+        let high = (arg >> 16) as u16;
+        let low = (arg >> 32) as u16;
+        let factor = (arg >> 48) as u16;
+
+        let mut value = (factor as i64).saturating_pow(low as u32);
+        let mut values = vec![value];
+        let mut next = value.saturating_mul(factor as i64);
+        let mut step = if next > nsteps as i64 { next / nsteps as i64 } else { 1 };
+        let mut order = low;
+        while order <= high && values.len() <= counts.len() {
+            values.push(value);
+            value += step;
+            if value >= next {
+                value = next;
+                next = next.saturating_mul(factor as i64);
+                step = if next > nsteps as i64 { next / nsteps as i64 } else { 1 };
+                order += 1;
+            }
+        }
+        values.push(value);
+
+        Self {
+            kind: HistogramKind::LLQuantize {
+                factor,
+                low,
+                high,
+                nsteps,
+            },
+            buckets: zip_buckets(&values, counts),
+        }
+    }
+
+    /// Returns the histogram with each bucket count divided by `normal`, see [`AggValue::normalize`].
+    pub fn normalize(&self, normal: u64) -> Self {
+        let normal = normal.max(1) as i128;
+        Self {
+            kind: self.kind,
+            buckets: self
+                .buckets
+                .iter()
+                .map(|bucket| Bucket {
+                    value: bucket.value,
+                    count: (bucket.count as i128 / normal) as i64,
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the histogram with the count of each bucket reduced by the count of the matching bucket of `previous`,
+    /// see [`AggValue::delta`].
+    ///
+    /// Buckets are matched one by one when the histograms are compatible (see [`Histogram::is_compatible`]), and by
+    /// value otherwise. The underflow bucket of linear and log-linear histograms has the value of the first bucket, so
+    /// buckets of equal value are matched in order.
+    pub fn delta(&self, previous: &Histogram) -> Self {
+        if let Ok(delta) = self.combine(previous, |count, previous| count - previous) {
+            return delta;
+        }
+        let mut counts: HashMap<(i64, usize), i64> = HashMap::new();
+        for (key, bucket) in occurrences(&previous.buckets).zip(&previous.buckets) {
+            counts.insert(key, bucket.count);
+        }
+        Self {
+            kind: self.kind,
+            buckets: occurrences(&self.buckets)
+                .zip(&self.buckets)
+                .map(|(key, bucket)| Bucket {
+                    value: bucket.value,
+                    count: bucket.count - counts.get(&key).copied().unwrap_or(0),
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the sum of all bucket counts.
+    pub fn total(&self) -> i64 {
+        self.buckets.iter().map(|bucket| bucket.count).sum()
+    }
+
+    /// Returns the approximate value below which `percentile` percent of the values fall, e.g. `99.0` for the p99.
+    ///
+    /// The values of a bucket are taken as spread evenly up to the `value` of the next bucket, and the values of the
+    /// last bucket, and of the first bucket of linear and log-linear histograms, as equal to its `value`.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`None`] if the histogram counts no values.
+    pub fn percentile(&self, percentile: f64) -> Option<f64> {
+        let total = self.total();
+        if total <= 0 {
+            return None;
+        }
+        let rank = percentile.clamp(0.0, 100.0) / 100.0 * total as f64;
+        let mut below = 0.0;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            if bucket.count <= 0 {
+                continue;
+            }
+            let count = bucket.count as f64;
+            if below + count >= rank {
+                let upper = match (i, self.kind) {
+                    (0, HistogramKind::LQuantize { .. } | HistogramKind::LLQuantize { .. }) => bucket.value,
+                    _ => self.buckets.get(i + 1).map_or(bucket.value, |next| next.value),
+                };
+                let fraction = (rank - below) / count;
+                return Some(bucket.value as f64 + fraction * (upper - bucket.value) as f64);
+            }
+            below += count;
+        }
+        self.buckets.iter().rev().find(|bucket| bucket.count > 0).map(|bucket| bucket.value as f64)
+    }
+
+    /// Returns the number of values in the buckets starting at or above `threshold`. When `threshold` falls within a
+    /// bucket, the values of that bucket are not counted, so pick thresholds on bucket bounds for exact counts.
+    pub fn count_above(&self, threshold: i64) -> i64 {
+        self.buckets
+            .iter()
+            .filter(|bucket| bucket.value >= threshold)
+            .map(|bucket| bucket.count)
+            .sum()
+    }
+
+    /// Evaluates `slo` on the values of the histogram.
+    pub fn evaluate(&self, slo: &Slo) -> SloReport {
+        let observed = self.percentile(slo.percentile);
+        SloReport {
+            slo: *slo,
+            observed,
+            total: self.total(),
+            above: self.count_above(slo.target),
+            passed: observed.is_none_or(|observed| observed <= slo.target as f64),
+        }
+    }
+
+    /// Returns `true` if `other` has the same bucketization, so their buckets can be combined one by one.
+    pub fn is_compatible(&self, other: &Histogram) -> bool {
+        self.kind == other.kind
+            && self.buckets.len() == other.buckets.len()
+            && self.buckets.iter().zip(&other.buckets).all(|(a, b)| a.value == b.value)
+    }
+
+    /// Returns the histogram counting the values of both histograms, e.g. to merge the histograms of several hosts.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `other` has another bucketization, see [`Histogram::is_compatible`].
+    pub fn merge(&self, other: &Histogram) -> Result<Self, Error> {
+        self.combine(other, i64::saturating_add)
+    }
+
+    /// Returns the histogram counting the values of this histogram that are not in `other`, e.g. the values of an
+    /// interval given the histograms at its start and end. Unlike [`Histogram::delta`], the buckets must match.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `other` has another bucketization, see [`Histogram::is_compatible`].
+    pub fn subtract(&self, other: &Histogram) -> Result<Self, Error> {
+        self.combine(other, i64::saturating_sub)
+    }
+
+    /// Returns the histogram with each bucket count multiplied by `factor` and rounded, e.g. `1.0 / seconds` to get
+    /// counts per second.
+    pub fn scale(&self, factor: f64) -> Self {
+        Self {
+            kind: self.kind,
+            buckets: self
+                .buckets
+                .iter()
+                .map(|bucket| Bucket {
+                    value: bucket.value,
+                    count: (bucket.count as f64 * factor).round() as i64,
+                })
+                .collect(),
+        }
+    }
+
+    /// Combines the counts of the buckets of both histograms with `op`.
+    fn combine(&self, other: &Histogram, op: fn(i64, i64) -> i64) -> Result<Self, Error> {
+        if !self.is_compatible(other) {
+            return Err(Error::new(
+                -1,
+                format!("The histograms have different buckets: {:?} and {:?}", self.kind, other.kind),
+            ));
+        }
+        Ok(Self {
+            kind: self.kind,
+            buckets: self
+                .buckets
+                .iter()
+                .zip(&other.buckets)
+                .map(|(a, b)| Bucket {
+                    value: a.value,
+                    count: op(a.count, b.count),
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Keys the buckets of a histogram by their value and the number of buckets of the same value before them.
+fn occurrences(buckets: &[Bucket]) -> impl Iterator<Item = (i64, usize)> + '_ {
+    let mut seen: HashMap<i64, usize> = HashMap::new();
+    buckets.iter().map(move |bucket| {
+        let occurrence = seen.entry(bucket.value).or_default();
+        *occurrence += 1;
+        (bucket.value, *occurrence - 1)
+    })
+}
+
+/// An objective on a distribution of values, e.g. latencies: `percentile` percent of the values are at most `target`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Slo {
+    /// Percentage of the values held to the target, e.g. `99.0`
+    pub percentile: f64,
+    /// Highest value allowed at the percentile, in the unit of the histogram
+    pub target: i64,
+}
+
+impl Slo {
+    /// Creates an objective of `percentile` percent of the values at most `target`.
+    pub fn new(percentile: f64, target: i64) -> Self {
+        Self { percentile, target }
+    }
+}
+
+/// The outcome of evaluating an [`Slo`] on a histogram, see [`Histogram::evaluate`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SloReport {
+    /// The objective evaluated
+    pub slo: Slo,
+    /// Approximate value at the percentile of the objective, [`None`] if the histogram counts no values
+    pub observed: Option<f64>,
+    /// Number of values in the histogram
+    pub total: i64,
+    /// Number of values in the buckets at or above the target, see [`Histogram::count_above`]
+    pub above: i64,
+    /// Whether the objective is met; it is when there are no values
+    pub passed: bool,
+}
+
+impl std::fmt::Display for SloReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = if self.passed { "pass" } else { "FAIL" };
+        match self.observed {
+            Some(observed) => write!(
+                f,
+                "{}: p{} = {:.0} (target {}), {} of {} at or above target",
+                status, self.slo.percentile, observed, self.slo.target, self.above, self.total
+            ),
+            None => write!(f, "{}: p{} has no values (target {})", status, self.slo.percentile, self.slo.target),
+        }
+    }
+}
+
+/// Lower bound of the `quantize()` bucket at `index`.
+fn quantize_bucket_value(index: usize) -> i64 {
+    match index.cmp(&QUANTIZE_ZEROBUCKET) {
+        std::cmp::Ordering::Less => -(1i64 << (QUANTIZE_ZEROBUCKET - 1 - index)),
+        std::cmp::Ordering::Equal => 0,
+        std::cmp::Ordering::Greater => 1i64 << (index - QUANTIZE_ZEROBUCKET - 1),
+    }
+}
+
+fn zip_buckets(values: &[i64], counts: &[i64]) -> Vec<Bucket> {
+    values
+        .iter()
+        .zip(counts)
+        .map(|(&value, &count)| Bucket { value, count })
+        .collect()
+}
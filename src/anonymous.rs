@@ -0,0 +1,175 @@
+//! Anonymous tracing, which enables programs while the system boots, like `dtrace -A` and `dtrace -a`.
+//!
+//! Programs are compiled as usual, converted to DOF and installed in the configuration of the DTrace driver with
+//! [`AnonymousConfig::install`]. After a reboot, the driver enables them before any consumer exists, and
+//! [`claim`] takes over the resulting anonymous state to consume the data recorded since boot.
+//!
+//! ```no_run
+//! use libdtrace_rs::anonymous::{self, AnonymousConfig};
+//! use libdtrace_rs::compile::CompileOptions;
+//! use libdtrace_rs::wrapper::dtrace_hdl;
+//!
+//! // Before rebooting.
+//! let handle = dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap();
+//! let prog = handle
+//!     .compile("syscall::NtCreateFile:entry { @[execname] = count(); }", &CompileOptions::new())
+//!     .unwrap();
+//! let mut config = AnonymousConfig::new();
+//! config.add_program(&handle, prog).unwrap();
+//! config.add_options(&handle).unwrap();
+//! config.install().unwrap();
+//!
+//! // After rebooting.
+//! let mut handle = dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap();
+//! anonymous::claim(&mut handle).unwrap();
+//! handle.aggregate_snapshot(libdtrace_rs::types::dtrace_aggwalk_order::None).unwrap();
+//! AnonymousConfig::remove().unwrap();
+//! ```
+
+use crate::utils::Error;
+use crate::wrapper::dtrace_hdl;
+use std::fmt::Write as _;
+use std::process::Command;
+
+/// Registry key holding the configuration of the DTrace driver
+pub const REGISTRY_KEY: &str = r"HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Services\dtrace\Parameters";
+
+/// Returns the name of the registry value holding the DOF at `index`, numbered like the `dof-data-N` properties
+/// `dtrace -A` writes.
+pub fn value_name(index: usize) -> String {
+    format!("dof-data-{}", index)
+}
+
+/// The DOF of the programs and options to enable at boot.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnonymousConfig {
+    /// The DOF of each program, and of the options if added, in the order they are enabled in
+    pub dofs: Vec<Vec<u8>>,
+}
+
+impl AnonymousConfig {
+    /// Creates a configuration enabling nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a compiled program to enable at boot.
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - The handle the program was compiled with.
+    /// * `program` - The compiled program.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the DOF of the program could not be created.
+    pub fn add_program(&mut self, handle: &dtrace_hdl, program: &mut crate::dtrace_prog) -> Result<(), Error> {
+        self.dofs.push(handle.dtrace_dof_create(program, 0)?);
+        Ok(())
+    }
+
+    /// Adds the options set on `handle`, e.g. buffer sizes, to apply to the programs enabled at boot.
+    ///
+    /// Like `dtrace -A`, the options should be added after all programs, once every option is set.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the DOF of the options could not be created.
+    pub fn add_options(&mut self, handle: &dtrace_hdl) -> Result<(), Error> {
+        self.dofs.push(handle.dtrace_getopt_dof()?);
+        Ok(())
+    }
+
+    /// Formats the configuration as a `.reg` file, to inspect it or import it with `reg import` on another system.
+    pub fn to_reg(&self) -> String {
+        let mut reg = format!("Windows Registry Editor Version 5.00\r\n\r\n[{}]\r\n", REGISTRY_KEY);
+        for (index, dof) in self.dofs.iter().enumerate() {
+            let _ = write!(reg, "\"{}\"=hex:", value_name(index));
+            let bytes: Vec<String> = dof.iter().map(|byte| format!("{:02x}", byte)).collect();
+            // regedit wraps long binary values over continuation lines ending with a backslash.
+            for (i, line) in bytes.chunks(25).enumerate() {
+                if i > 0 {
+                    reg.push_str(",\\\r\n  ");
+                }
+                reg.push_str(&line.join(","));
+            }
+            reg.push_str("\r\n");
+        }
+        reg
+    }
+
+    /// Installs the configuration in the registry, replacing any previous one, so the programs are enabled at the
+    /// next boot. Requires administrator privileges.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the configuration could not be written.
+    pub fn install(&self) -> Result<(), Error> {
+        Self::remove()?;
+        let path = std::env::temp_dir().join(format!("libdtrace-anonymous-{}.reg", std::process::id()));
+        std::fs::write(&path, self.to_reg())?;
+        let result = reg(&["import", &path.to_string_lossy()]);
+        let _ = std::fs::remove_file(&path);
+        if result? {
+            Ok(())
+        } else {
+            Err(Error::new(-1, "reg import failed").with_context("installing anonymous tracing"))
+        }
+    }
+
+    /// Removes the installed configuration from the registry, so nothing is enabled at the next boot. Requires
+    /// administrator privileges.
+    ///
+    /// Every DOF value of the key is removed, including values left out of sequence by other tools, while the other
+    /// values of the key are kept.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if `reg.exe` could not be run or a value could not be removed.
+    pub fn remove() -> Result<(), Error> {
+        let output = Command::new("reg").args(["query", REGISTRY_KEY]).output()?;
+        // Without the key, there is nothing to remove.
+        if !output.status.success() {
+            return Ok(());
+        }
+        for name in dof_values(&String::from_utf8_lossy(&output.stdout)) {
+            if !reg(&["delete", REGISTRY_KEY, "/v", &name, "/f"])? {
+                return Err(Error::new(-1, format!("reg delete {} failed", name))
+                    .with_context("removing anonymous tracing"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the names of the DOF values listed by `reg query` of [`REGISTRY_KEY`], see `value_name`.
+pub(crate) fn dof_values(query: &str) -> Vec<String> {
+    query
+        .lines()
+        .filter_map(|line| line.trim_start().split_once("    REG_"))
+        .map(|(name, _)| name.trim_end())
+        .filter(|name| name.strip_prefix("dof-data-").is_some_and(|index| index.parse::<usize>().is_ok()))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Runs `reg.exe` quietly, returning whether it succeeded.
+fn reg(args: &[&str]) -> Result<bool, Error> {
+    let output = Command::new("reg").args(args).output()?;
+    Ok(output.status.success())
+}
+
+/// Claims the anonymous state enabled at boot and starts consuming it, like `dtrace -a`.
+///
+/// The handle then consumes the data recorded since boot like any other handle, e.g. with `dtrace_work` or
+/// `aggregate_snapshot`. The anonymous state is released when the handle is closed.
+///
+/// # Returns
+///
+/// Returns an error if there is no anonymous state or it could not be claimed.
+pub fn claim(handle: &mut dtrace_hdl) -> Result<(), Error> {
+    handle.dtrace_setopt("grabanon", "")?;
+    handle
+        .dtrace_go()
+        .map_err(|error| error.with_context("claiming anonymous state"))
+}
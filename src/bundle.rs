@@ -0,0 +1,119 @@
+//! Support bundles: the state of a consumer gathered into a single tar archive to attach to bug reports, see
+//! `TraceSession::diagnostics_dump`.
+//!
+//! ```no_run
+//! use libdtrace_rs::bundle::SupportBundle;
+//!
+//! let mut bundle = SupportBundle::new();
+//! bundle.add("program.d", "syscall:::entry { @[probefunc] = count(); }");
+//! bundle.write("dtrace-support.tar").unwrap();
+//! ```
+
+use crate::utils::Error;
+use std::io::Write;
+use std::path::Path;
+
+/// Size of the blocks of a tar archive.
+const BLOCK: usize = 512;
+
+/// The files of a support bundle, written as a tar archive.
+#[derive(Debug, Clone)]
+pub struct SupportBundle {
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl Default for SupportBundle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SupportBundle {
+    /// Creates a bundle holding `platform.txt`, see [`SupportBundle::platform`].
+    pub fn new() -> Self {
+        Self {
+            files: vec![("platform.txt".to_string(), Self::platform().into_bytes())],
+        }
+    }
+
+    /// Describes the platform the consumer runs on: the operating system, architecture, number of CPUs and the
+    /// versions of the crate and of the DTrace API.
+    pub fn platform() -> String {
+        let cpus = std::thread::available_parallelism().map_or(0, |cpus| cpus.get());
+        format!(
+            "os: {}\narch: {}\ncpus: {}\ncrate: {} {}\ndtrace api: {}\n",
+            std::env::consts::OS,
+            std::env::consts::ARCH,
+            cpus,
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            crate::DTRACE_VERSION
+        )
+    }
+
+    /// Adds the file `name` with `contents`, replacing the file of the same name if there is one. Names longer than
+    /// 100 bytes are truncated.
+    pub fn add(&mut self, name: &str, contents: impl Into<Vec<u8>>) -> &mut Self {
+        let contents = contents.into();
+        match self.files.iter_mut().find(|(file, _)| file == name) {
+            Some((_, file)) => *file = contents,
+            None => self.files.push((name.to_string(), contents)),
+        }
+        self
+    }
+
+    /// Returns the names of the files of the bundle, in the order they are archived.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.files.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Writes the bundle to `out` as a tar archive.
+    pub fn write_to(&self, mut out: impl Write) -> std::io::Result<()> {
+        let mtime = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        for (name, contents) in &self.files {
+            out.write_all(&header(name, contents.len() as u64, mtime))?;
+            out.write_all(contents)?;
+            let padding = (BLOCK - contents.len() % BLOCK) % BLOCK;
+            out.write_all(&[0u8; BLOCK][..padding])?;
+        }
+        // An archive ends with two empty blocks.
+        out.write_all(&[0u8; 2 * BLOCK])
+    }
+
+    /// Writes the bundle to the file at `path` as a tar archive, replacing the file if it exists.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let context = || format!("writing support bundle {}", path.display());
+        let file = std::fs::File::create(path).map_err(|err| Error::from(err).with_context(context()))?;
+        let mut out = std::io::BufWriter::new(file);
+        self.write_to(&mut out)
+            .and_then(|()| out.flush())
+            .map_err(|err| Error::from(err).with_context(context()))
+    }
+}
+
+/// Builds the ustar header of a regular file.
+fn header(name: &str, size: u64, mtime: u64) -> [u8; BLOCK] {
+    let mut header = [0u8; BLOCK];
+    let field = |header: &mut [u8; BLOCK], offset: usize, value: &[u8]| {
+        header[offset..offset + value.len()].copy_from_slice(value);
+    };
+    let name = name.as_bytes();
+    field(&mut header, 0, &name[..name.len().min(100)]);
+    field(&mut header, 100, b"0000644\0");
+    field(&mut header, 108, b"0000000\0");
+    field(&mut header, 116, b"0000000\0");
+    field(&mut header, 124, format!("{:011o}\0", size).as_bytes());
+    field(&mut header, 136, format!("{:011o}\0", mtime).as_bytes());
+    field(&mut header, 156, b"0");
+    field(&mut header, 257, b"ustar\0");
+    field(&mut header, 263, b"00");
+    // The checksum is computed with its own field filled with spaces.
+    field(&mut header, 148, b"        ");
+    let checksum: u32 = header.iter().map(|&byte| byte as u32).sum();
+    field(&mut header, 148, format!("{:06o}\0 ", checksum).as_bytes());
+    header
+}
@@ -0,0 +1,196 @@
+//! Broadcasting events to several subscribers, see `TraceSession::subscribe`.
+//!
+//! Every [`Subscriber`] receives every event published after it subscribed, shared through an [`Arc`]. Each
+//! subscriber queues a bounded number of events: a subscriber that falls behind loses its oldest events rather than
+//! slowing down the publisher or the other subscribers, and counts them, see [`Subscriber::lagged`].
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+/// Default number of events a subscriber queues before dropping the oldest.
+pub const SUBSCRIBER_CAPACITY: usize = 1024;
+
+/// Events queued for a subscriber.
+struct Queue<T> {
+    events: VecDeque<Arc<T>>,
+    capacity: usize,
+    /// Number of events dropped because the queue was full
+    lagged: u64,
+    /// Set once the bus published its last event, or was dropped
+    closed: bool,
+}
+
+/// A queue shared between the bus and a subscriber.
+struct Shared<T> {
+    queue: Mutex<Queue<T>>,
+    ready: Condvar,
+}
+
+impl<T> Shared<T> {
+    fn close(&self) {
+        self.queue.lock().unwrap().closed = true;
+        self.ready.notify_all();
+    }
+}
+
+/// Publishes events to every [`Subscriber`], see the [module](self) docs.
+pub struct EventBus<T> {
+    subscribers: Mutex<Vec<Weak<Shared<T>>>>,
+    /// Set by `close` and cleared by `reopen`, changed and read with `subscribers` locked
+    closed: AtomicBool,
+}
+
+impl<T> Default for EventBus<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> EventBus<T> {
+    /// Creates a bus without subscribers.
+    pub fn new() -> Self {
+        Self {
+            subscribers: Mutex::new(Vec::new()),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Subscribes to the events published from now on, queueing up to `capacity` of them. The subscription of a
+    /// closed bus is already ended.
+    pub fn subscribe(&self, capacity: usize) -> Subscriber<T> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let closed = self.closed.load(Ordering::Relaxed);
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                events: VecDeque::new(),
+                capacity: capacity.max(1),
+                lagged: 0,
+                closed,
+            }),
+            ready: Condvar::new(),
+        });
+        if !closed {
+            subscribers.push(Arc::downgrade(&shared));
+        }
+        Subscriber { shared }
+    }
+
+    /// Returns the number of subscribers that were not dropped.
+    pub fn subscriber_count(&self) -> usize {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| subscriber.strong_count() > 0);
+        subscribers.len()
+    }
+
+    /// Publishes `event` to every subscriber, dropping the oldest event of the subscribers whose queue is full.
+    pub fn publish(&self, event: Arc<T>) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| match subscriber.upgrade() {
+            Some(shared) => {
+                let mut queue = shared.queue.lock().unwrap();
+                if queue.events.len() >= queue.capacity {
+                    queue.events.pop_front();
+                    queue.lagged += 1;
+                }
+                queue.events.push_back(event.clone());
+                drop(queue);
+                shared.ready.notify_one();
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// Ends the subscriptions: subscribers receive the events they queued, then `None`. Later subscriptions end
+    /// right away, until the bus is reopened.
+    pub fn close(&self) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        self.closed.store(true, Ordering::Relaxed);
+        for shared in subscribers.drain(..).filter_map(|subscriber| subscriber.upgrade()) {
+            shared.close();
+        }
+    }
+
+    /// Reopens a closed bus, for later subscribers to receive the events published from then on.
+    pub fn reopen(&self) {
+        let _subscribers = self.subscribers.lock().unwrap();
+        self.closed.store(false, Ordering::Relaxed);
+    }
+}
+
+impl<T> Drop for EventBus<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Receives the events of an [`EventBus`], see the [module](self) docs.
+pub struct Subscriber<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Subscriber<T> {
+    /// Receives the next event, blocking until one is available.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`None`] once the bus was closed and all the queued events have been received.
+    pub fn recv(&self) -> Option<Arc<T>> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        loop {
+            if let Some(event) = queue.events.pop_front() {
+                return Some(event);
+            }
+            if queue.closed {
+                return None;
+            }
+            queue = self.shared.ready.wait(queue).unwrap();
+        }
+    }
+
+    /// Receives the next event if one is available.
+    pub fn try_recv(&self) -> Option<Arc<T>> {
+        self.shared.queue.lock().unwrap().events.pop_front()
+    }
+
+    /// Receives the next event, blocking for at most `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Arc<T>> {
+        let deadline = Instant::now() + timeout;
+        let mut queue = self.shared.queue.lock().unwrap();
+        loop {
+            if let Some(event) = queue.events.pop_front() {
+                return Some(event);
+            }
+            let now = Instant::now();
+            if queue.closed || now >= deadline {
+                return None;
+            }
+            queue = self.shared.ready.wait_timeout(queue, deadline - now).unwrap().0;
+        }
+    }
+
+    /// Returns the number of events dropped so far because this subscriber did not keep up.
+    pub fn lagged(&self) -> u64 {
+        self.shared.queue.lock().unwrap().lagged
+    }
+
+    /// Returns the number of events queued for this subscriber.
+    pub fn len(&self) -> usize {
+        self.shared.queue.lock().unwrap().events.len()
+    }
+
+    /// Returns `true` if no event is queued for this subscriber.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Iterator for Subscriber<T> {
+    type Item = Arc<T>;
+
+    fn next(&mut self) -> Option<Arc<T>> {
+        self.recv()
+    }
+}
@@ -57,3 +57,116 @@ pub unsafe extern "C" fn walk(
 
     return crate::DTRACE_AGGWALK_NEXT as ::core::ffi::c_int;
 }
+
+/// Buffered handler appending the output to the [`String`] its argument points to, to collect the output of a
+/// handle with `dtrace_hdl::dtrace_register_handler`.
+///
+/// # Safety
+///
+/// `bufdata` must point to the buffered output libdtrace passes to buffered handlers, and `arg` to a [`String`] that
+/// is not borrowed elsewhere.
+pub unsafe extern "C" fn buffered_to_string(
+    bufdata: *const crate::dtrace_bufdata_t,
+    arg: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    let out = &mut *(arg as *mut String);
+    out.push_str(&::core::ffi::CStr::from_ptr((*bufdata).dtbda_buffered).to_string_lossy());
+
+    crate::DTRACE_HANDLE_OK as ::core::ffi::c_int
+}
+
+/// Returns the column header dtrace(1) prints above the output of [`chew_default`] when the `quiet` option is not set.
+pub fn default_header() -> String {
+    format!("{:>3} {:>6} {:>32}", "CPU", "ID", "FUNCTION:NAME")
+}
+
+/// Formats the columns dtrace(1) prints before the records of a probe: CPU, probe ID and `FUNCTION:NAME`, or the
+/// indented function name with the `flowindent` option.
+///
+/// # Returns
+///
+/// Returns `None` if the `quiet` option is set, as dtrace(1) prints no columns then.
+pub fn default_columns(data: &crate::dtrace_probedata_t) -> Option<String> {
+    let flowindent = unsafe { option_set(data, crate::DTRACEOPT_FLOWINDENT) };
+    if !flowindent && unsafe { option_set(data, crate::DTRACEOPT_QUIET) } {
+        return None;
+    }
+
+    let pd = match unsafe { data.dtpda_pdesc.as_ref() } {
+        Some(pd) => pd,
+        None => return Some(format!("{:>3} ", data.dtpda_cpu)),
+    };
+    let func = crate::probe::field(&pd.dtpd_func);
+    let name = crate::probe::field(&pd.dtpd_name);
+    if !flowindent {
+        return Some(format!(
+            "{:>3} {:>6} {:>32} ",
+            data.dtpda_cpu,
+            pd.dtpd_id,
+            format!("{}:{}", func, name)
+        ));
+    }
+
+    let prefix = match data.dtpda_prefix.is_null() {
+        true => String::new(),
+        false => unsafe { ::core::ffi::CStr::from_ptr(data.dtpda_prefix) }
+            .to_string_lossy()
+            .into_owned(),
+    };
+    let indent = " ".repeat(data.dtpda_indent.max(0) as usize);
+    let label = match data.dtpda_flow as u32 {
+        crate::DTRACEFLOW_NONE => format!("{}{}{}:{}", indent, prefix, func, name),
+        _ => format!("{}{}{}", indent, prefix, func),
+    };
+    Some(format!("{:>3} {:<41} ", data.dtpda_cpu, label))
+}
+
+/// Returns `true` if the option `option` of the handle that produced `data` is set.
+unsafe fn option_set(data: &crate::dtrace_probedata_t, option: u32) -> bool {
+    match data.dtpda_handle.as_ref() {
+        Some(handle) => handle.dt_options[option as usize] != crate::DTRACEOPT_UNSET as crate::dtrace_optval_t,
+        None => false,
+    }
+}
+
+/// Probe callback printing the columns of dtrace(1), see [`default_columns`].
+///
+/// Pair it with [`chew_rec_default`] and the [`buffered`] handler, so the records are printed after the columns.
+///
+/// # Safety
+///
+/// `data` must be null or point to the probe data libdtrace passes to probe callbacks.
+pub unsafe extern "C" fn chew_default(
+    data: *const crate::dtrace_probedata_t,
+    _arg: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    if let Some(columns) = data.as_ref().and_then(default_columns) {
+        print!("{}", columns);
+    }
+
+    crate::DTRACE_CONSUME_THIS as ::core::ffi::c_int
+}
+
+/// Record callback ending the line of each probe like dtrace(1), unless the `quiet` option is set.
+///
+/// # Safety
+///
+/// `data` and `record` must be null or point to the probe data and record libdtrace passes to record callbacks.
+pub unsafe extern "C" fn chew_rec_default(
+    data: *const crate::dtrace_probedata_t,
+    record: *const crate::dtrace_recdesc_t,
+    _arg: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    if record.is_null() {
+        if data.as_ref().is_some_and(|data| !option_set(data, crate::DTRACEOPT_QUIET)) {
+            println!();
+        }
+        return crate::DTRACE_CONSUME_NEXT as ::core::ffi::c_int;
+    }
+
+    if (*record).dtrd_action == crate::DTRACEACT_EXIT as u16 {
+        return crate::DTRACE_CONSUME_NEXT as ::core::ffi::c_int;
+    }
+
+    crate::DTRACE_CONSUME_THIS as ::core::ffi::c_int
+}
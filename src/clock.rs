@@ -0,0 +1,45 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Converts the nanosecond values of the `timestamp` variable to wall-clock time, see `dtrace_hdl::clock`.
+///
+/// `timestamp` counts from an arbitrary point in the past, so the clock pairs one of its values with the wall-clock
+/// time it was read at. Conversions do not account for changes to the system clock made afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Clock {
+    wall: SystemTime,
+    timestamp: u64,
+}
+
+impl Clock {
+    /// Creates a clock where the `timestamp` value `timestamp` corresponds to the wall-clock time `wall`.
+    pub fn new(wall: SystemTime, timestamp: u64) -> Self {
+        Self { wall, timestamp }
+    }
+
+    /// Converts a value of the `timestamp` variable to wall-clock time.
+    pub fn to_system_time(&self, timestamp: u64) -> SystemTime {
+        if timestamp >= self.timestamp {
+            self.wall + Duration::from_nanos(timestamp - self.timestamp)
+        } else {
+            self.wall - Duration::from_nanos(self.timestamp - timestamp)
+        }
+    }
+
+    /// Converts a value of the `timestamp` variable to a `chrono` UTC datetime.
+    #[cfg(feature = "chrono")]
+    pub fn to_datetime(&self, timestamp: u64) -> chrono::DateTime<chrono::Utc> {
+        self.to_system_time(timestamp).into()
+    }
+
+    /// Converts a value of the `walltimestamp` variable, nanoseconds since the Unix epoch, to wall-clock time.
+    pub fn walltimestamp(walltimestamp: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_nanos(walltimestamp)
+    }
+
+    /// Converts a value of the `vtimestamp` variable, the time a thread has been running on a CPU, to a duration.
+    ///
+    /// `vtimestamp` does not advance while the thread is off CPU, so it cannot be converted to wall-clock time.
+    pub fn vtimestamp(vtimestamp: u64) -> Duration {
+        Duration::from_nanos(vtimestamp)
+    }
+}
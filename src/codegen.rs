@@ -0,0 +1,288 @@
+//! Generation of `#[repr(C)]` Rust structs from the CTF types of the modules, to reinterpret raw captures of kernel
+//! structs, e.g. `copyin()` or `tracemem()` records, in consumer code.
+//!
+//! The generated structs are packed and padded explicitly, so their layout matches the CTF layout byte for byte
+//! whatever the alignment of their members. Pointers become integers of their size, and members that cannot be
+//! represented, like bit-fields, anonymous structs and unions, become byte arrays.
+//!
+//! ```no_run
+//! use libdtrace_rs::codegen::StructGenerator;
+//!
+//! # let handle: libdtrace_rs::wrapper::dtrace_hdl = unimplemented!();
+//! let mut generator = StructGenerator::new(&handle);
+//! generator.add(Some("nt"), "struct _UNICODE_STRING").unwrap();
+//! std::fs::write("src/kernel_types.rs", generator.finish()).unwrap();
+//! ```
+
+use crate::typeinfo::{encoding, kind, members, resolve, type_name, type_size, TypeKind};
+use crate::utils::Error;
+use crate::wrapper::dtrace_hdl;
+use ::core::ffi::CStr;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+
+/// Rust keywords that cannot name a field as they are
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false", "fn", "for", "if",
+    "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "static", "struct", "trait",
+    "true", "type", "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "gen", "macro",
+    "override", "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+/// Generates the Rust source of `#[repr(C)]` structs matching CTF types, along with the structs they embed.
+pub struct StructGenerator<'a> {
+    handle: &'a dtrace_hdl,
+    /// Rust names of the structs generated so far
+    generated: HashSet<String>,
+    output: String,
+}
+
+/// Members of a struct packed into a run of bytes, starting at `start` and ending before `end`.
+struct Bitfield {
+    start: u64,
+    end: u64,
+    names: Vec<String>,
+}
+
+impl<'a> StructGenerator<'a> {
+    /// Creates a generator looking up types with `handle`.
+    pub fn new(handle: &'a dtrace_hdl) -> Self {
+        Self {
+            handle,
+            generated: HashSet::new(),
+            output: String::from("// Generated from CTF type data by libdtrace-rs.\n"),
+        }
+    }
+
+    /// Generates the struct for a struct or union type, and for the structs it embeds. Types already generated are
+    /// skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `object` - The module defining the type, or `None` to search every module.
+    /// * `name` - The name of the type, e.g. `struct _EPROCESS` or a typedef of a struct.
+    ///
+    /// # Returns
+    ///
+    /// Returns the name of the generated Rust struct, or an error if the type does not exist or is not a struct or
+    /// union.
+    pub fn add(&mut self, object: Option<&str>, name: &str) -> Result<String, Error> {
+        let info = self
+            .handle
+            .dtrace_lookup_by_type(object, name)
+            .map_err(|error| error.with_context(format!("looking up type `{}`", name)))?;
+        let module = if info.dtt_object.is_null() {
+            String::new()
+        } else {
+            unsafe { CStr::from_ptr(info.dtt_object) }.to_string_lossy().into_owned()
+        };
+        unsafe {
+            let id = resolve(info.dtt_ctfp, info.dtt_type);
+            match kind(info.dtt_ctfp, id) {
+                TypeKind::Struct | TypeKind::Union => {}
+                _ => return Err(Error::new(-1, format!("`{}` is not a struct or union", name))),
+            }
+            self.generate(info.dtt_ctfp, id, &module)
+                .ok_or_else(|| Error::new(-1, format!("`{}` has no name usable in Rust", name)))
+        }
+    }
+
+    /// Returns the source of all generated structs.
+    pub fn finish(self) -> String {
+        self.output
+    }
+
+    /// Generates the struct for the struct or union `id`, returning its Rust name, or `None` if it is anonymous.
+    unsafe fn generate(&mut self, ctfp: *mut crate::ctf_file_t, id: crate::ctf_id_t, module: &str) -> Option<String> {
+        let name = type_name(ctfp, id);
+        let rust_name = rust_type_name(&name)?;
+        if !self.generated.insert(rust_name.clone()) {
+            return Some(rust_name);
+        }
+        let size = type_size(ctfp, id);
+
+        // Embedded structs are generated first, so they precede this one in the output.
+        let mut source = format!("\n/// `{}` of `{}`, {} bytes\n#[repr(C, packed)]\n", name, module, size);
+        source.push_str("#[derive(Debug, Clone, Copy)]\n#[allow(non_camel_case_types, non_snake_case)]\n");
+        if kind(ctfp, id) == TypeKind::Union {
+            let _ = writeln!(source, "pub struct {}(pub [u8; {}]);", rust_name, size);
+        } else {
+            let _ = writeln!(source, "pub struct {} {{", rust_name);
+            for field in self.fields(ctfp, id, size, module) {
+                source.push_str(&field);
+            }
+            source.push_str("}\n");
+        }
+        let _ = write!(
+            source,
+            "
+impl {name} {{
+    /// Reads the struct from the start of a raw capture, e.g. a `copyin()` record.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {{
+        (bytes.len() >= std::mem::size_of::<Self>())
+            .then(|| unsafe {{ std::ptr::read_unaligned(bytes.as_ptr() as *const Self) }})
+    }}
+}}
+
+const _: () = assert!(std::mem::size_of::<{name}>() == {size});
+",
+            name = rust_name,
+            size = size
+        );
+        self.output.push_str(&source);
+        Some(rust_name)
+    }
+
+    /// Lays out the members of the struct `id` of `size` bytes as fields, padding the gaps between them.
+    unsafe fn fields(
+        &mut self,
+        ctfp: *mut crate::ctf_file_t,
+        id: crate::ctf_id_t,
+        size: u64,
+        module: &str,
+    ) -> Vec<String> {
+        let mut members = members(ctfp, id);
+        members.sort_by_key(|member| member.offset);
+
+        let mut fields = Vec::new();
+        let mut cursor = 0;
+        let mut bitfield: Option<Bitfield> = None;
+        for (index, member) in members.iter().enumerate() {
+            let name = if member.name.is_empty() {
+                format!("_anon{}", index)
+            } else {
+                member.name.clone()
+            };
+            let member_id = resolve(ctfp, member.id);
+            let member_size = type_size(ctfp, member_id);
+            let bits = match kind(ctfp, member_id) {
+                TypeKind::Integer => encoding(ctfp, member_id).map_or(member_size * 8, |enc| enc.cte_bits as u64),
+                _ => member_size * 8,
+            };
+            let start = member.offset / 8;
+            let end = (member.offset + bits).div_ceil(8);
+
+            if member.offset % 8 != 0 || bits != member_size * 8 {
+                match &mut bitfield {
+                    Some(run) if start < run.end => {
+                        run.end = run.end.max(end);
+                        run.names.push(name);
+                    }
+                    _ => {
+                        if let Some(run) = bitfield.take() {
+                            cursor = push_bitfield(&mut fields, run, cursor);
+                        }
+                        bitfield = Some(Bitfield {
+                            start: start.max(cursor),
+                            end,
+                            names: vec![name],
+                        });
+                    }
+                }
+                continue;
+            }
+            if let Some(run) = bitfield.take() {
+                cursor = push_bitfield(&mut fields, run, cursor);
+            }
+
+            if start < cursor {
+                fields.push(format!("    // `{}` overlaps the previous member\n", name));
+                continue;
+            }
+            push_padding(&mut fields, cursor, start);
+            let rust_type = self.field_type(ctfp, member_id, module);
+            fields.push(format!(
+                "    /// `{}`, offset {}\n    pub {}: {},\n",
+                type_name(ctfp, member.id),
+                start,
+                field_name(&name),
+                rust_type
+            ));
+            cursor = start + member_size;
+        }
+        if let Some(run) = bitfield.take() {
+            cursor = push_bitfield(&mut fields, run, cursor);
+        }
+        push_padding(&mut fields, cursor, size);
+        fields
+    }
+
+    /// Returns the Rust type of a member of type `id`, generating the structs it embeds.
+    unsafe fn field_type(&mut self, ctfp: *mut crate::ctf_file_t, id: crate::ctf_id_t, module: &str) -> String {
+        let id = resolve(ctfp, id);
+        let size = type_size(ctfp, id);
+        let integer = |signed: bool| match size {
+            1 | 2 | 4 | 8 => format!("{}{}", if signed { 'i' } else { 'u' }, size * 8),
+            _ => bytes(size),
+        };
+        match kind(ctfp, id) {
+            TypeKind::Integer => match encoding(ctfp, id) {
+                Some(enc) if enc.cte_bits as u64 == size * 8 => integer(enc.cte_format & crate::CTF_INT_SIGNED != 0),
+                _ => bytes(size),
+            },
+            TypeKind::Float => match size {
+                4 => "f32".to_string(),
+                8 => "f64".to_string(),
+                _ => bytes(size),
+            },
+            TypeKind::Pointer => integer(false),
+            TypeKind::Enum => integer(true),
+            TypeKind::Array => {
+                let mut info: crate::ctf_arinfo_t = std::mem::zeroed();
+                if crate::ctf_array_info(ctfp, id, &mut info) != 0 {
+                    return bytes(size);
+                }
+                format!("[{}; {}]", self.field_type(ctfp, info.ctr_contents, module), info.ctr_nelems)
+            }
+            TypeKind::Struct | TypeKind::Union => self.generate(ctfp, id, module).unwrap_or_else(|| bytes(size)),
+            _ => bytes(size),
+        }
+    }
+}
+
+fn bytes(size: u64) -> String {
+    format!("[u8; {}]", size)
+}
+
+/// Adds a padding field covering the bytes from `from` to `to`, if any.
+fn push_padding(fields: &mut Vec<String>, from: u64, to: u64) {
+    if to > from {
+        fields.push(format!("    pub _pad{}: [u8; {}],\n", from, to - from));
+    }
+}
+
+/// Adds the field holding a run of bit-fields, returning the offset following it.
+fn push_bitfield(fields: &mut Vec<String>, run: Bitfield, cursor: u64) -> u64 {
+    push_padding(fields, cursor, run.start);
+    let names: Vec<String> = run.names.iter().map(|name| format!("`{}`", name)).collect();
+    fields.push(format!(
+        "    /// Bit-fields {}, offset {}\n    pub _bitfield{}: [u8; {}],\n",
+        names.join(", "),
+        run.start,
+        run.start,
+        run.end - run.start
+    ));
+    run.end
+}
+
+/// Returns the Rust name of a named struct or union, e.g. `_EPROCESS` for `struct _EPROCESS`.
+fn rust_type_name(name: &str) -> Option<String> {
+    let name = name
+        .strip_prefix("struct ")
+        .or_else(|| name.strip_prefix("union "))
+        .unwrap_or(name)
+        .trim();
+    let valid = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then(|| name.to_string())
+}
+
+/// Returns a Rust field name for the member `name`.
+fn field_name(name: &str) -> String {
+    match name {
+        "self" | "Self" | "super" | "crate" => format!("{}_", name),
+        _ if KEYWORDS.contains(&name) => format!("r#{}", name),
+        _ => name.to_string(),
+    }
+}
@@ -0,0 +1,402 @@
+use crate::utils::{Error, ErrorKind};
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// How a D program is compiled, see `dtrace_hdl::compile`.
+///
+/// ```no_run
+/// use libdtrace_rs::compile::CompileOptions;
+/// use libdtrace_rs::wrapper::dtrace_hdl;
+///
+/// let handle = dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap();
+/// let options = CompileOptions::new().allow_zero_matches().arg("notepad.exe");
+/// let prog = handle
+///     .compile("syscall:::entry /execname == $$1/ { @[probefunc] = count(); }", &options)
+///     .unwrap();
+/// handle.dtrace_program_exec(prog, None).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileOptions {
+    /// What the probe descriptions of the program name when they are incomplete, e.g. `BEGIN`
+    pub spec: crate::types::ProbeSpec,
+    /// `DTRACE_C_*` flags
+    pub flags: u32,
+    /// Arguments of the program, referred to as `$1`, `$2`, ... in the program
+    pub args: Option<Vec<String>>,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            spec: crate::types::ProbeSpec::Name,
+            flags: 0,
+            args: None,
+        }
+    }
+}
+
+impl CompileOptions {
+    /// Creates options compiling incomplete probe descriptions as probe names, without flags or arguments.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets what incomplete probe descriptions name, [`ProbeSpec::Name`](crate::types::ProbeSpec::Name) by default.
+    pub fn spec(mut self, spec: crate::types::ProbeSpec) -> Self {
+        self.spec = spec;
+        self
+    }
+
+    /// Adds raw `DTRACE_C_*` flags.
+    pub fn flag(mut self, flag: u32) -> Self {
+        self.flags |= flag;
+        self
+    }
+
+    /// Permits probe descriptions that match no probe (`DTRACE_C_ZDEFS`, `dtrace -Z`), instead of failing.
+    pub fn allow_zero_matches(self) -> Self {
+        self.flag(crate::DTRACE_C_ZDEFS)
+    }
+
+    /// Runs the C preprocessor on the program before compiling it (`DTRACE_C_CPP`, `dtrace -C`).
+    pub fn use_cpp(self) -> Self {
+        self.flag(crate::DTRACE_C_CPP)
+    }
+
+    /// Does not process the D libraries of the system (`DTRACE_C_NOLIBS`), so their types and translators are not
+    /// available to the program.
+    pub fn no_libs(self) -> Self {
+        self.flag(crate::DTRACE_C_NOLIBS)
+    }
+
+    /// Shows the DIF instructions the program compiles to (`DTRACE_C_DIFV`, `dtrace -S`).
+    pub fn difv(self) -> Self {
+        self.flag(crate::DTRACE_C_DIFV)
+    }
+
+    /// Permits programs without any clause (`DTRACE_C_EMPTY`).
+    pub fn allow_empty(self) -> Self {
+        self.flag(crate::DTRACE_C_EMPTY)
+    }
+
+    /// Adds an argument of the program.
+    pub fn arg(mut self, arg: &str) -> Self {
+        self.args.get_or_insert_with(Vec::new).push(arg.to_string());
+        self
+    }
+
+    /// Adds several arguments of the program.
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.args
+            .get_or_insert_with(Vec::new)
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_string()));
+        self
+    }
+}
+
+/// A compile error of a D program, with where libdtrace located it, see `Error::compile_error`.
+///
+/// libdtrace prefixes the messages of the errors it locates with their line, e.g. `line 3: syntax error near "}"`,
+/// or `file.d, line 3: ...` for programs compiled from files. [`CompileError::render`] quotes that line of the source,
+/// marking the token the error is near, like rustc diagnostics:
+///
+/// ```no_run
+/// use libdtrace_rs::compile::CompileOptions;
+/// use libdtrace_rs::wrapper::dtrace_hdl;
+///
+/// let handle = dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap();
+/// let source = "BEGIN {\n    trace(arg0\n}";
+/// if let Err(err) = handle.compile(source, &CompileOptions::new()) {
+///     match err.compile_error() {
+///         Some(error) => eprintln!("{}", error.render(source)),
+///         None => eprintln!("{}", err),
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    /// The message, without its location
+    pub message: String,
+    /// The file the error is in, for programs compiled from files
+    pub file: Option<String>,
+    /// The line the error is at, 1-based
+    pub line: Option<usize>,
+}
+
+impl CompileError {
+    /// Parses the location out of the message of a compile error.
+    pub fn new(message: &str) -> Self {
+        // Errors in a region of the program, e.g. a translator, start with the region between brackets.
+        let located = match message.strip_prefix('[') {
+            Some(rest) => rest.split_once("] ").map_or(message, |(_, located)| located),
+            None => message,
+        };
+        let (file, rest) = match located.split_once(", line ") {
+            Some((file, rest)) => (Some(file.to_string()), rest),
+            None => (None, located.strip_prefix("line ").unwrap_or_default()),
+        };
+        let line = rest
+            .split_once(": ")
+            .and_then(|(line, message)| Some((line.parse::<usize>().ok()?, message)));
+        match line {
+            Some((line, located)) => Self {
+                message: located.to_string(),
+                file,
+                line: Some(line),
+            },
+            None => Self {
+                message: message.to_string(),
+                file: None,
+                line: None,
+            },
+        }
+    }
+
+    /// Returns the compile error `error` is, `None` if `error` is not a compile error, see
+    /// [`ErrorKind::Compile`](crate::utils::ErrorKind::Compile). Probe descriptions matching no probes are compile
+    /// errors too.
+    pub fn from_error(error: &Error) -> Option<Self> {
+        let kind = error.kind();
+        matches!(kind, ErrorKind::Compile | ErrorKind::NoMatch).then(|| Self::new(&error.message()))
+    }
+
+    /// Returns the token the error is near, e.g. `}` for `syntax error near "}"`.
+    pub fn near(&self) -> Option<&str> {
+        let (_, rest) = self.message.split_once("near \"")?;
+        rest.rfind('"').map(|end| &rest[..end]).filter(|token| !token.is_empty())
+    }
+
+    /// Renders the error with the line of `source` it is at, the source of the program, e.g.
+    ///
+    /// ```text
+    /// error: syntax error near "}"
+    ///  --> line 3
+    ///   |
+    /// 3 | }
+    ///   | ^
+    /// ```
+    ///
+    /// The token the error is near is marked, or the whole line if the message does not tell. Errors without a line,
+    /// or with a line `source` does not have, are rendered as their message.
+    pub fn render(&self, source: &str) -> String {
+        let mut rendered = format!("error: {}", self.message);
+        let (line, text) = match self.line.and_then(|line| Some((line, source.lines().nth(line.checked_sub(1)?)?))) {
+            Some(located) => located,
+            None => return rendered,
+        };
+        let code = text.trim_end();
+        let indent = code.len() - code.trim_start().len();
+        let (start, len) = match self.near().and_then(|token| Some((code.find(token)?, token.len()))) {
+            Some(token) => token,
+            None => (indent, code.len() - indent),
+        };
+        // Tabs are kept in front of the marker, so it lines up with the source however tabs are displayed.
+        let padding: String = code[..start].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        let marker = "^".repeat(code[start..start + len].chars().count().max(1));
+        let gutter = " ".repeat(line.to_string().len());
+        let location = match &self.file {
+            Some(file) => format!("{}, line {}", file, line),
+            None => format!("line {}", line),
+        };
+        rendered += &format!("\n{}--> {}", gutter, location);
+        rendered += &format!("\n{} |\n{} | {}", gutter, line, code);
+        rendered += &format!("\n{} | {}{}", gutter, padding, marker);
+        rendered
+    }
+}
+
+impl std::fmt::Display for CompileError {
+    /// Formats the error as libdtrace does, e.g. `line 3: syntax error near "}"`.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{}, line {}: {}", file, line, self.message),
+            (None, Some(line)) => write!(f, "line {}: {}", line, self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// State shared by a [`CompileTask`] and its thread.
+#[derive(Default)]
+struct Compilation {
+    /// The address of the compiled program, or the error, once the compilation ended
+    result: Mutex<Option<Result<usize, Error>>>,
+    done: Condvar,
+    cancelled: AtomicBool,
+}
+
+/// A D program being compiled on a dedicated thread, so the caller stays responsive while large programs are
+/// preprocessed and compiled.
+///
+/// libdtrace can not interrupt a compilation: a task that is cancelled, dropped or times out only stops waiting for
+/// it. The compilation goes on in the background, holding a reference to the handle, and its program is discarded. A
+/// task cancelled before its thread started compiling does not compile at all.
+///
+/// Once `wait` returned the program, neither the task nor its thread refer to the handle anymore, so the handle can
+/// be borrowed mutably again, e.g. with [`Arc::get_mut`] to start tracing.
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use libdtrace_rs::compile::{CompileOptions, CompileTask};
+/// use libdtrace_rs::wrapper::dtrace_hdl;
+///
+/// let mut handle = Arc::new(dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap());
+/// let options = CompileOptions::new().use_cpp();
+/// let task = CompileTask::spawn(&handle, "syscall:::entry { @[probefunc] = count(); }", &options)
+///     .unwrap()
+///     .with_timeout(Duration::from_secs(5));
+/// while !task.is_finished() {
+///     // Keep the UI responsive.
+///     std::thread::sleep(Duration::from_millis(10));
+/// }
+/// let mut prog = task.wait(&handle).unwrap();
+/// handle.dtrace_program_exec(&mut prog, None).unwrap();
+/// Arc::get_mut(&mut handle).unwrap().dtrace_go().unwrap();
+/// ```
+pub struct CompileTask {
+    handle: Arc<crate::wrapper::dtrace_hdl>,
+    compilation: Arc<Compilation>,
+    started: Instant,
+    timeout: Option<Duration>,
+}
+
+impl CompileTask {
+    /// Starts compiling `program` with `options` on a new thread, see `dtrace_hdl::compile`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the task, or an error if the thread could not be spawned.
+    pub fn spawn(
+        handle: &Arc<crate::wrapper::dtrace_hdl>,
+        program: &str,
+        options: &CompileOptions,
+    ) -> Result<Self, Error> {
+        let compilation = Arc::new(Compilation::default());
+        let (hdl, shared) = (handle.clone(), compilation.clone());
+        let (program, options) = (program.to_string(), options.clone());
+        std::thread::Builder::new()
+            .name("dtrace-compile".to_string())
+            .spawn(move || {
+                if shared.cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+                let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                    hdl.compile(&program, &options).map(|prog| prog as *mut crate::dtrace_prog as usize)
+                }))
+                .unwrap_or_else(|_| Err(Error::new(-1, "The compilation panicked")));
+                let mut stored = shared.result.lock().unwrap();
+                match result {
+                    // Nobody waits for the program anymore.
+                    Ok(prog) if shared.cancelled.load(Ordering::Relaxed) => unsafe {
+                        hdl.discard_program(&mut *(prog as *mut crate::dtrace_prog))
+                    },
+                    result => *stored = Some(result),
+                }
+                // Released before the result is seen, so `wait` returns with the task holding the only references.
+                drop(hdl);
+                drop(stored);
+                shared.done.notify_all();
+            })
+            .map_err(|err| Error::from(err).with_context("spawning the compilation thread"))?;
+
+        Ok(Self {
+            handle: handle.clone(),
+            compilation,
+            started: Instant::now(),
+            timeout: None,
+        })
+    }
+
+    /// Gives up on the compilation once `timeout` elapsed since the task was spawned, see `wait`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Stops waiting for the compilation, see [`CompileTask`].
+    pub fn cancel(self) {
+        drop(self);
+    }
+
+    /// Returns `true` once the compilation ended or timed out, so `wait` does not block.
+    pub fn is_finished(&self) -> bool {
+        self.compilation.result.lock().unwrap().is_some() || self.remaining() == Some(Duration::ZERO)
+    }
+
+    /// Returns the time left before the timeout, if any.
+    fn remaining(&self) -> Option<Duration> {
+        self.timeout.map(|timeout| timeout.saturating_sub(self.started.elapsed()))
+    }
+
+    /// Waits for the compilation to end, at most until the timeout of the task.
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - The handle the task compiles with, which owns the program.
+    ///
+    /// # Returns
+    ///
+    /// Returns the compiled program, the compilation error, or an error if the compilation timed out or `handle` is
+    /// not the handle of the task.
+    pub fn wait(self, handle: &crate::wrapper::dtrace_hdl) -> Result<CompiledProgram<'_>, Error> {
+        if !std::ptr::eq(&*self.handle, handle) {
+            self.cancel();
+            return Err(Error::new(-1, "The task compiles with another handle"));
+        }
+        let mut result = self.compilation.result.lock().unwrap();
+        while result.is_none() {
+            result = match self.remaining() {
+                Some(Duration::ZERO) => {
+                    self.compilation.cancelled.store(true, Ordering::Relaxed);
+                    let timeout = self.timeout.unwrap_or_default();
+                    return Err(Error::new(-1, format!("Compilation timed out after {:?}", timeout)));
+                }
+                Some(remaining) => self.compilation.done.wait_timeout(result, remaining).unwrap().0,
+                None => self.compilation.done.wait(result).unwrap(),
+            };
+        }
+        result.take().unwrap().map(|prog| CompiledProgram {
+            prog: prog as *mut crate::dtrace_prog,
+            handle: std::marker::PhantomData,
+        })
+    }
+}
+
+impl Drop for CompileTask {
+    /// Stops waiting for the compilation, discarding its program unless `wait` returned it.
+    fn drop(&mut self) {
+        let mut result = self.compilation.result.lock().unwrap();
+        self.compilation.cancelled.store(true, Ordering::Relaxed);
+        if let Some(Ok(prog)) = result.take() {
+            unsafe { self.handle.discard_program(&mut *(prog as *mut crate::dtrace_prog)) };
+        }
+    }
+}
+
+/// A program compiled by a [`CompileTask`], owned by the handle it was compiled with and dereferencing to the
+/// `dtrace_prog` the handle executes.
+pub struct CompiledProgram<'a> {
+    prog: *mut crate::dtrace_prog,
+    handle: std::marker::PhantomData<&'a crate::wrapper::dtrace_hdl>,
+}
+
+impl std::ops::Deref for CompiledProgram<'_> {
+    type Target = crate::dtrace_prog;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.prog }
+    }
+}
+
+impl std::ops::DerefMut for CompiledProgram<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.prog }
+    }
+}
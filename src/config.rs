@@ -0,0 +1,104 @@
+use crate::types::dtrace_handler_kind;
+use crate::utils::Error;
+use crate::wrapper::dtrace_hdl;
+use ::core::ffi::c_int;
+use std::sync::Arc;
+
+/// Registers a handler on a newly opened handle.
+pub(crate) type Installer = Arc<dyn Fn(&dtrace_hdl) -> Result<(), Error> + Send + Sync>;
+
+/// A D program compiled from a string and enabled on a handle.
+#[derive(Debug, Clone)]
+pub struct ProgramConfig {
+    /// Source of the program
+    pub source: String,
+    /// Probe specifier context the program was compiled with
+    pub spec: crate::types::ProbeSpec,
+    /// Flags the program was compiled with
+    pub flags: u32,
+    /// Arguments passed to the program
+    pub args: Option<Vec<String>>,
+}
+
+/// The configuration of a handle: how it was opened, the options set, the handlers registered and the programs
+/// enabled on it, see `dtrace_hdl::clone_config`.
+///
+/// Buffered output subscribers and programs compiled from files are not part of the configuration.
+#[derive(Clone)]
+pub struct ConsumerConfig {
+    /// DTrace version the handle was opened with
+    pub version: c_int,
+    /// Flags the handle was opened with
+    pub flags: c_int,
+    /// Options set on the handle, in the order they were set
+    pub options: Vec<(String, String)>,
+    /// Programs enabled on the handle, in the order they were enabled
+    pub programs: Vec<ProgramConfig>,
+    pub(crate) handlers: Vec<(dtrace_handler_kind, Installer)>,
+}
+
+impl Default for ConsumerConfig {
+    fn default() -> Self {
+        Self {
+            version: crate::DTRACE_VERSION as c_int,
+            flags: 0,
+            options: Vec::new(),
+            programs: Vec::new(),
+            handlers: Vec::new(),
+        }
+    }
+}
+
+impl ConsumerConfig {
+    /// Records `option` being set to `value`.
+    pub(crate) fn set_option(&mut self, option: &str, value: &str) {
+        self.options.push((option.to_string(), value.to_string()));
+    }
+
+    /// Records `option` being changed to `value` by a program with `setopt()`, or unset if `value` is `None`.
+    pub(crate) fn set_program_option(&mut self, option: &str, value: Option<crate::dtrace_optval_t>) {
+        match value {
+            Some(value) => self.set_option(option, &value.to_string()),
+            None => self.options.retain(|(set, _)| set != option),
+        }
+    }
+
+    /// Records the handler of `kind` being registered, replacing any previous one.
+    pub(crate) fn set_handler(&mut self, kind: dtrace_handler_kind, installer: Installer) {
+        self.remove_handler(kind);
+        self.handlers.push((kind, installer));
+    }
+
+    pub(crate) fn remove_handler(&mut self, kind: dtrace_handler_kind) {
+        self.handlers.retain(|(installed, _)| *installed != kind);
+    }
+
+    /// Opens a new handle with this configuration.
+    ///
+    /// The options are set and the handlers registered before the programs are compiled and enabled, tracing is
+    /// started with `dtrace_go` as usual.
+    ///
+    /// # Returns
+    ///
+    /// Returns the new handle, or the first error that occurred while applying the configuration, e.g. for a handler
+    /// registered with a raw argument, see `dtrace_hdl::clone_config`.
+    pub fn open(&self) -> Result<dtrace_hdl, Error> {
+        let mut handle = dtrace_hdl::dtrace_open(self.version, self.flags)?;
+        for (option, value) in &self.options {
+            handle.dtrace_setopt(option, value)?;
+        }
+        for (_, installer) in &self.handlers {
+            installer(&handle)?;
+        }
+        for program in &self.programs {
+            let options = crate::compile::CompileOptions {
+                spec: program.spec,
+                flags: program.flags,
+                args: program.args.clone(),
+            };
+            let prog = handle.compile(&program.source, &options)?;
+            handle.dtrace_program_exec(prog, None)?;
+        }
+        Ok(handle)
+    }
+}
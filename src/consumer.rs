@@ -0,0 +1,287 @@
+use crate::perf::{PerfCounters, Section};
+use crate::probe::ProbeDescription;
+use ::core::ffi::{c_int, c_void};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Firing statistics of a single probe, collected while consuming.
+#[derive(Debug, Clone)]
+pub struct ProbeStats {
+    /// Description of the probe
+    pub probe: ProbeDescription,
+    /// Number of times the probe fired
+    pub firings: u64,
+    /// Number of bytes recorded by the probe
+    pub bytes: u64,
+    /// When the last firing was consumed
+    pub last_seen: SystemTime,
+}
+
+/// Per-probe statistics kept by each `dtrace_hdl`.
+#[derive(Default)]
+pub(crate) struct ProbeStatsTable {
+    enabled: AtomicBool,
+    probes: Mutex<HashMap<crate::dtrace_id_t, ProbeStats>>,
+}
+
+impl ProbeStatsTable {
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.probes.lock().unwrap().clear();
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<ProbeStats> {
+        self.probes.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Accounts one firing of the probe described by `data`.
+    unsafe fn record(&self, data: *const crate::dtrace_probedata_t) {
+        let data = &*data;
+        if data.dtpda_pdesc.is_null() || data.dtpda_edesc.is_null() {
+            return;
+        }
+        let pdesc = &*data.dtpda_pdesc;
+        let size = (*data.dtpda_edesc).dtepd_size as u64;
+
+        let mut probes = self.probes.lock().unwrap();
+        let stats = probes.entry(pdesc.dtpd_id).or_insert_with(|| ProbeStats {
+            probe: ProbeDescription::from(pdesc),
+            firings: 0,
+            bytes: 0,
+            last_seen: SystemTime::now(),
+        });
+        stats.firings += 1;
+        stats.bytes += size;
+        stats.last_seen = SystemTime::now();
+    }
+}
+
+/// Statistics of the `dtrace_work` passes of a handle, to tell whether the consumer keeps up with the buffer switches,
+/// see `dtrace_hdl::consumption_stats`.
+///
+/// DTrace switches the principal buffers of every CPU at the `switchrate`, and a buffer that fills before the
+/// consumer reads it drops records. A consumer whose passes are further apart than the switch interval, or whose
+/// passes take most of it, is falling behind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConsumptionStats {
+    /// Number of passes
+    pub passes: u64,
+    /// Duration of the last pass
+    pub last_duration: Duration,
+    /// Duration of the longest pass
+    pub max_duration: Duration,
+    /// Time spent in passes
+    pub total_duration: Duration,
+    /// Number of bytes of the firings consumed by the last pass
+    pub last_bytes: u64,
+    /// Number of bytes of the firings consumed by the largest pass
+    pub max_bytes: u64,
+    /// Number of bytes of the firings consumed
+    pub total_bytes: u64,
+    /// Time between the starts of the last two passes, `None` before the second pass
+    pub last_interval: Option<Duration>,
+    /// Longest time between the starts of two passes
+    pub max_interval: Option<Duration>,
+    /// Time between the starts of the first and the last pass
+    pub total_interval: Duration,
+    /// The `switchrate` of the handle at the last pass, as the interval between buffer switches
+    pub switch_interval: Option<Duration>,
+}
+
+impl ConsumptionStats {
+    /// Returns the mean duration of a pass, `None` before the first pass.
+    pub fn mean_duration(&self) -> Option<Duration> {
+        (self.passes > 0).then(|| self.total_duration / self.passes as u32)
+    }
+
+    /// Returns the mean number of bytes consumed by a pass, `None` before the first pass.
+    pub fn mean_bytes(&self) -> Option<u64> {
+        (self.passes > 0).then(|| self.total_bytes / self.passes)
+    }
+
+    /// Returns the mean time between the starts of two passes, `None` before the second pass.
+    pub fn mean_interval(&self) -> Option<Duration> {
+        (self.passes > 1).then(|| self.total_interval / (self.passes - 1) as u32)
+    }
+
+    /// Returns the ratio of the mean interval between passes to the switch interval, above 1 if the consumer
+    /// consumes less often than the buffers switch.
+    pub fn lag(&self) -> Option<f64> {
+        match (self.mean_interval(), self.switch_interval) {
+            (Some(interval), Some(switch)) if !switch.is_zero() => Some(interval.as_secs_f64() / switch.as_secs_f64()),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the consumer is about to cause drops: the last interval between passes, or the last pass
+    /// itself, was longer than the switch interval.
+    pub fn is_lagging(&self) -> bool {
+        self.switch_interval.is_some_and(|switch| {
+            self.last_interval.is_some_and(|interval| interval > switch) || self.last_duration > switch
+        })
+    }
+
+    /// Accounts a pass that took `duration`, started `interval` after the previous one and consumed `bytes`.
+    fn record(&mut self, duration: Duration, interval: Option<Duration>, bytes: u64) {
+        self.passes += 1;
+        self.last_duration = duration;
+        self.max_duration = self.max_duration.max(duration);
+        self.total_duration += duration;
+        self.last_bytes = bytes;
+        self.max_bytes = self.max_bytes.max(bytes);
+        self.total_bytes = self.total_bytes.saturating_add(bytes);
+        self.last_interval = interval;
+        if let Some(interval) = interval {
+            self.max_interval = Some(self.max_interval.map_or(interval, |max| max.max(interval)));
+            self.total_interval += interval;
+        }
+    }
+}
+
+/// Consumption statistics kept by each `dtrace_hdl`.
+#[derive(Default)]
+pub(crate) struct ConsumptionTable {
+    enabled: AtomicBool,
+    /// Bytes of the firings consumed since the current pass started
+    bytes: AtomicU64,
+    /// Start of the last pass
+    last_start: Mutex<Option<Instant>>,
+    stats: Mutex<ConsumptionStats>,
+}
+
+impl ConsumptionTable {
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            *self.last_start.lock().unwrap() = None;
+            *self.stats.lock().unwrap() = ConsumptionStats::default();
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn snapshot(&self) -> ConsumptionStats {
+        *self.stats.lock().unwrap()
+    }
+
+    /// Starts timing a pass, `None` if the statistics are disabled.
+    pub(crate) fn begin(&self) -> Option<Instant> {
+        if !self.is_enabled() {
+            return None;
+        }
+        self.bytes.store(0, Ordering::Relaxed);
+        Some(Instant::now())
+    }
+
+    /// Accounts the pass started at `start`, with the switch interval of the handle.
+    pub(crate) fn end(&self, start: Instant, switch_interval: Option<Duration>) {
+        let duration = start.elapsed();
+        let interval = self.last_start.lock().unwrap().replace(start).map(|last| start - last);
+        let mut stats = self.stats.lock().unwrap();
+        stats.record(duration, interval, self.bytes.load(Ordering::Relaxed));
+        stats.switch_interval = switch_interval;
+    }
+
+    /// Accounts the firing described by `data` to the current pass.
+    unsafe fn record(&self, data: *const crate::dtrace_probedata_t) {
+        if let Some(edesc) = (*data).dtpda_edesc.as_ref() {
+            self.bytes.fetch_add(edesc.dtepd_size as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+/// State passed to the consume trampolines in place of the user's argument.
+///
+/// When the wrapper needs to observe consumption (e.g. to collect statistics or time the callbacks),
+/// `dtrace_consume` and `dtrace_work` are called with the trampolines below, which do the bookkeeping and then forward
+/// to the user's callbacks.
+pub(crate) struct ConsumeContext<'a> {
+    stats: &'a ProbeStatsTable,
+    consumption: &'a ConsumptionTable,
+    perf: &'a PerfCounters,
+    probe: crate::dtrace_consume_probe_f,
+    rec: crate::dtrace_consume_rec_f,
+    arg: *mut c_void,
+}
+
+impl<'a> ConsumeContext<'a> {
+    pub(crate) fn new(
+        stats: &'a ProbeStatsTable,
+        consumption: &'a ConsumptionTable,
+        perf: &'a PerfCounters,
+        probe: crate::dtrace_consume_probe_f,
+        rec: crate::dtrace_consume_rec_f,
+        arg: *mut c_void,
+    ) -> Self {
+        Self {
+            stats,
+            consumption,
+            perf,
+            probe,
+            rec,
+            arg,
+        }
+    }
+
+    /// Returns the callbacks and argument to hand to libdtrace.
+    ///
+    /// The context must outlive the consume call the returned values are used for.
+    pub(crate) fn callbacks(
+        &mut self,
+    ) -> (
+        crate::dtrace_consume_probe_f,
+        crate::dtrace_consume_rec_f,
+        *mut c_void,
+    ) {
+        if self.stats.is_enabled() || self.consumption.is_enabled() || cfg!(feature = "metrics") {
+            (
+                Some(probe_trampoline),
+                Some(rec_trampoline),
+                self as *mut Self as *mut c_void,
+            )
+        } else {
+            (self.probe, self.rec, self.arg)
+        }
+    }
+}
+
+unsafe extern "C" fn probe_trampoline(
+    data: *const crate::dtrace_probedata_t,
+    arg: *mut c_void,
+) -> c_int {
+    let ctx = &*(arg as *const ConsumeContext);
+    if ctx.stats.is_enabled() {
+        ctx.stats.record(data);
+    }
+    if ctx.consumption.is_enabled() {
+        ctx.consumption.record(data);
+    }
+
+    match ctx.probe {
+        Some(probe) => ctx.perf.time(Section::Callbacks, || probe(data, ctx.arg)),
+        None => crate::DTRACE_CONSUME_THIS as c_int,
+    }
+}
+
+unsafe extern "C" fn rec_trampoline(
+    data: *const crate::dtrace_probedata_t,
+    rec: *const crate::dtrace_recdesc_t,
+    arg: *mut c_void,
+) -> c_int {
+    let ctx = &*(arg as *const ConsumeContext);
+
+    match ctx.rec {
+        Some(handler) => ctx.perf.time(Section::Callbacks, || handler(data, rec, ctx.arg)),
+        None => crate::DTRACE_CONSUME_THIS as c_int,
+    }
+}
@@ -0,0 +1,68 @@
+//! The CPUs a consumer traces, to size per-CPU structures and validate the `cpu` option, see `dtrace_hdl::cpu_info`.
+//!
+//! ```no_run
+//! use libdtrace_rs::wrapper::dtrace_hdl;
+//!
+//! let mut handle = dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap();
+//! let cpu = handle.cpu_info().validate("1").unwrap();
+//! handle.dtrace_setopt("cpu", &cpu.to_string()).unwrap();
+//! ```
+
+use crate::utils::Error;
+
+/// The CPUs known to a handle.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CpuInfo {
+    /// One more than the highest CPU id, the size of tables indexed by CPU id
+    pub max_cpu: usize,
+    /// Ids of the online CPUs, in increasing order
+    pub cpus: Vec<crate::processorid_t>,
+    /// CPU the `cpu` option restricts tracing to, `None` when every CPU is traced
+    pub pinned: Option<crate::processorid_t>,
+}
+
+impl CpuInfo {
+    /// Describes `cpus`, with ids below `max_cpu`, optionally restricted to `pinned`.
+    pub fn new(max_cpu: usize, mut cpus: Vec<crate::processorid_t>, pinned: Option<crate::processorid_t>) -> Self {
+        cpus.sort_unstable();
+        cpus.dedup();
+        Self { max_cpu, cpus, pinned }
+    }
+
+    /// Returns the number of online CPUs.
+    pub fn count(&self) -> usize {
+        self.cpus.len()
+    }
+
+    /// Returns `true` if `cpu` is online.
+    pub fn is_online(&self, cpu: crate::processorid_t) -> bool {
+        self.cpus.binary_search(&cpu).is_ok()
+    }
+
+    /// Returns the ids of the CPUs producing data: the pinned CPU, or every online CPU.
+    pub fn traced(&self) -> Vec<crate::processorid_t> {
+        match self.pinned {
+            Some(cpu) => vec![cpu],
+            None => self.cpus.clone(),
+        }
+    }
+
+    /// Validates a value of the `cpu` option.
+    ///
+    /// # Returns
+    ///
+    /// Returns the CPU id, or an error if `value` is not an integer or names a CPU that is not online.
+    pub fn validate(&self, value: &str) -> Result<crate::processorid_t, Error> {
+        let cpu = value
+            .trim()
+            .parse::<crate::processorid_t>()
+            .map_err(|_| Error::new(-1, format!("Invalid cpu option `{}`, expected a CPU id", value)))?;
+        match self.is_online(cpu) {
+            true => Ok(cpu),
+            false => Err(Error::new(
+                -1,
+                format!("CPU {} is not online, the online CPUs are {:?}", cpu, self.cpus),
+            )),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Plugging custom decoding into the standard pipeline, for action kinds of private libdtrace extensions or
+//! conventions of an organization, e.g. records holding JSON in a string.
+//!
+//! A [`DecoderRegistry`] holds decoders keyed by action kind, for records, and by aggregation name, for aggregation
+//! entries. Once set on a handle with `dtrace_hdl::set_decoders`, or on a session with `TraceSession::with_decoders`,
+//! the values they decode are delivered to `RecordVisitor::visit_decoded` and `RecordVisitor::visit_decoded_aggregate`
+//! instead of the standard decoding.
+//!
+//! ```no_run
+//! use libdtrace_rs::decoders::DecoderRegistry;
+//! use libdtrace_rs::typed::TypedValue;
+//!
+//! // `@requests[json]` aggregates requests by a JSON description built in D; the decoder keeps the string as is.
+//! let registry = DecoderRegistry::new().on_aggregation("requests", |entry| match entry.key.0.first() {
+//!     Some(libdtrace_rs::aggregate::KeyItem::Str(json)) => Some(TypedValue::Str(json.clone())),
+//!     _ => None,
+//! });
+//! # let handle: libdtrace_rs::wrapper::dtrace_hdl = unimplemented!();
+//! handle.set_decoders(registry);
+//! ```
+
+use crate::aggregate::AggregateEntry;
+use crate::typed::TypedValue;
+use std::collections::HashMap;
+
+/// Decodes the bytes of a record, `None` to leave the record to the standard decoding.
+pub type RecordDecoder = Box<dyn Fn(&[u8]) -> Option<TypedValue> + Send + Sync>;
+
+/// Decodes an aggregation entry, `None` to leave the entry to the standard delivery.
+pub type AggregateDecoder = Box<dyn Fn(&AggregateEntry) -> Option<TypedValue> + Send + Sync>;
+
+/// Custom decoders by action kind and aggregation name, see the [module](self) docs.
+#[derive(Default)]
+pub struct DecoderRegistry {
+    actions: HashMap<crate::dtrace_actkind_t, RecordDecoder>,
+    aggregations: HashMap<String, AggregateDecoder>,
+}
+
+impl std::fmt::Debug for DecoderRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("DecoderRegistry")
+            .field("actions", &self.actions.keys().collect::<Vec<_>>())
+            .field("aggregations", &self.aggregations.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl DecoderRegistry {
+    /// Creates a registry without decoders.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the records of `action` with `decoder`, replacing the decoder of `action` if it had one.
+    ///
+    /// Records of the known action kinds can be decoded too, e.g. `DTRACEACT_DIFEXPR` for the records of `trace()`,
+    /// in which case the records `decoder` leaves are decoded as usual.
+    pub fn on_action(
+        mut self,
+        action: crate::dtrace_actkind_t,
+        decoder: impl Fn(&[u8]) -> Option<TypedValue> + Send + Sync + 'static,
+    ) -> Self {
+        self.actions.insert(action, Box::new(decoder));
+        self
+    }
+
+    /// Decodes the entries of the aggregation `name`, without the leading `@`, with `decoder`, replacing the decoder
+    /// of `name` if it had one.
+    pub fn on_aggregation(
+        mut self,
+        name: &str,
+        decoder: impl Fn(&AggregateEntry) -> Option<TypedValue> + Send + Sync + 'static,
+    ) -> Self {
+        self.aggregations.insert(name.to_string(), Box::new(decoder));
+        self
+    }
+
+    /// Returns `true` if there are no decoders.
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty() && self.aggregations.is_empty()
+    }
+
+    /// Decodes `bytes`, recorded by `action`, with the decoder of `action`.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`None`] if `action` has no decoder or its decoder left the record.
+    pub fn decode_record(&self, action: crate::dtrace_actkind_t, bytes: &[u8]) -> Option<TypedValue> {
+        self.actions.get(&action).and_then(|decoder| decoder(bytes))
+    }
+
+    /// Decodes `entry` with the decoder of its aggregation.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`None`] if the aggregation has no decoder or its decoder left the entry.
+    pub fn decode_aggregate(&self, entry: &AggregateEntry) -> Option<TypedValue> {
+        self.aggregations.get(&entry.name).and_then(|decoder| decoder(entry))
+    }
+}
+
+/// Adapts `decoder` of strings into a decoder of records holding a string, e.g. JSON built with `strjoin()`: the
+/// bytes up to the first NUL character are decoded as UTF-8, and records that are not are left.
+pub fn string_record(
+    decoder: impl Fn(&str) -> Option<TypedValue> + Send + Sync + 'static,
+) -> impl Fn(&[u8]) -> Option<TypedValue> + Send + Sync + 'static {
+    move |bytes| {
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        std::str::from_utf8(&bytes[..len]).ok().and_then(&decoder)
+    }
+}
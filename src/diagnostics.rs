@@ -0,0 +1,343 @@
+use crate::dtrace_dropkind_t::*;
+use ::core::ffi::{c_char, c_int, CStr};
+use crate::types::dtrace_status;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+
+/// Number of records dropped, by kind of drop, as counted by the default drop handler.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DropCounts {
+    /// Records dropped because the principal buffer was full
+    pub principal: u64,
+    /// Records dropped because the aggregation buffer was full
+    pub aggregation: u64,
+    /// Dynamic variable drops, including rinsing and dirty drops
+    pub dynamic: u64,
+    /// Dynamic variable drops that occurred with a non-empty rinsing list, included in `dynamic`
+    pub dynamic_rinse: u64,
+    /// Dynamic variable drops that occurred with a non-empty dirty list, included in `dynamic`
+    pub dynamic_dirty: u64,
+    /// Speculation drops, including busy and unavailable speculations
+    pub speculation: u64,
+    /// Records dropped because of stack or string overflows
+    pub overflow: u64,
+    /// Records dropped because of errors in the error handler
+    pub dblerror: u64,
+}
+
+impl DropCounts {
+    /// Returns the total number of dropped records.
+    pub fn total(&self) -> u64 {
+        self.principal + self.aggregation + self.dynamic + self.speculation + self.overflow + self.dblerror
+    }
+
+    pub(crate) fn add(&mut self, kind: crate::dtrace_dropkind_t, drops: u64) {
+        match kind {
+            DTRACEDROP_DYNRINSE => self.dynamic_rinse += drops,
+            DTRACEDROP_DYNDIRTY => self.dynamic_dirty += drops,
+            _ => {}
+        }
+        let counter = match kind {
+            DTRACEDROP_PRINCIPAL => &mut self.principal,
+            DTRACEDROP_AGGREGATION => &mut self.aggregation,
+            DTRACEDROP_DYNAMIC | DTRACEDROP_DYNRINSE | DTRACEDROP_DYNDIRTY => &mut self.dynamic,
+            DTRACEDROP_SPEC | DTRACEDROP_SPECBUSY | DTRACEDROP_SPECUNAVAIL => &mut self.speculation,
+            DTRACEDROP_STKSTROVERFLOW => &mut self.overflow,
+            DTRACEDROP_DBLERROR => &mut self.dblerror,
+        };
+        *counter += drops;
+    }
+}
+
+/// A change of the status of tracing, see `dtrace_hdl::watch_status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusEvent {
+    /// The status changed, e.g. from `Ok` to `Filled` once the fill buffer filled up. `from` is `None` for the first
+    /// status observed
+    Status {
+        from: Option<dtrace_status>,
+        to: dtrace_status,
+    },
+    /// Records were dropped since the previous status, with the counts of the default drop handler before and after
+    Drops { previous: DropCounts, current: DropCounts },
+}
+
+/// The watchers of the status of a handle, and the status they were last notified of.
+#[derive(Default)]
+pub(crate) struct StatusWatch {
+    watchers: Vec<Sender<StatusEvent>>,
+    status: Option<dtrace_status>,
+    drops: DropCounts,
+}
+
+impl StatusWatch {
+    /// Adds a watcher, notified of the changes following the last status.
+    pub(crate) fn subscribe(&mut self) -> Receiver<StatusEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.watchers.push(tx);
+        rx
+    }
+
+    pub(crate) fn is_watched(&self) -> bool {
+        !self.watchers.is_empty()
+    }
+
+    /// Notifies the watchers of the changes since the last status. `None` statuses, returned by libdtrace when asked
+    /// again within the status interval, change nothing.
+    pub(crate) fn update(&mut self, status: dtrace_status, drops: DropCounts) {
+        if status != dtrace_status::None && self.status != Some(status) {
+            let from = self.status.replace(status);
+            self.send(StatusEvent::Status { from, to: status });
+        }
+        if drops.total() > self.drops.total() {
+            self.send(StatusEvent::Drops {
+                previous: self.drops,
+                current: drops,
+            });
+        }
+        self.drops = drops;
+    }
+
+    /// Sends `event` to every watcher, forgetting the watchers that went away.
+    fn send(&mut self, event: StatusEvent) {
+        self.watchers.retain(|tx| tx.send(event).is_ok());
+    }
+}
+
+/// Default size of the dynamic variable space, used by the kernel when the `dynvarsize` option is not set
+pub const DEFAULT_DYNVARSIZE: u64 = 1024 * 1024;
+
+/// Default interval between cleanings of the dynamic variable space in nanoseconds, 101 Hz, used when the
+/// `cleanrate` option is not set
+pub const DEFAULT_CLEANRATE: u64 = 9_900_990;
+
+/// Pressure on the dynamic variable space (thread-local variables and associative arrays), see
+/// `dtrace_hdl::dynvar_pressure`.
+///
+/// Dynamic variable drops mean the space is too small for the variables alive at once, and are addressed by
+/// increasing the `dynvarsize` option. Drops occurring with non-empty rinsing or dirty lists mean the space is not
+/// cleaned often enough, and are addressed by increasing the `cleanrate` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DynvarPressure {
+    /// Current size of the dynamic variable space in bytes
+    pub dynvarsize: u64,
+    /// Current interval between cleanings of the dynamic variable space in nanoseconds
+    pub cleanrate: u64,
+    /// Dynamic variable drops caused by the space being full
+    pub drops: u64,
+    /// Dynamic variable drops caused by the space not being cleaned often enough
+    pub clean_drops: u64,
+    /// Suggested `dynvarsize` in bytes, if the space is too small
+    pub suggested_dynvarsize: Option<u64>,
+    /// Suggested `cleanrate` interval in nanoseconds, if the space is not cleaned often enough
+    pub suggested_cleanrate: Option<u64>,
+}
+
+impl DynvarPressure {
+    /// Assesses the pressure on the dynamic variable space from the drops counted so far.
+    ///
+    /// # Arguments
+    ///
+    /// * `counts` - The drops counted by the default drop handler.
+    /// * `dynvarsize` - The `dynvarsize` option, `None` if it is not set.
+    /// * `cleanrate` - The `cleanrate` option in nanoseconds, `None` if it is not set.
+    ///
+    /// # Returns
+    ///
+    /// Returns the pressure, suggesting twice the current size or cleaning rate when drops of the corresponding kind
+    /// were counted.
+    pub fn new(counts: &DropCounts, dynvarsize: Option<u64>, cleanrate: Option<u64>) -> Self {
+        let dynvarsize = dynvarsize.unwrap_or(DEFAULT_DYNVARSIZE);
+        let cleanrate = cleanrate.unwrap_or(DEFAULT_CLEANRATE);
+        let clean_drops = counts.dynamic_rinse + counts.dynamic_dirty;
+        let drops = counts.dynamic - clean_drops;
+        Self {
+            dynvarsize,
+            cleanrate,
+            drops,
+            clean_drops,
+            suggested_dynvarsize: (drops > 0).then(|| dynvarsize.saturating_mul(2)),
+            suggested_cleanrate: (clean_drops > 0).then(|| (cleanrate / 2).max(1)),
+        }
+    }
+
+    /// Returns `true` if dynamic variables were dropped.
+    pub fn is_under_pressure(&self) -> bool {
+        self.drops > 0 || self.clean_drops > 0
+    }
+
+    /// Returns the options to set to relieve the pressure, as `(option, value)` pairs for `dtrace_setopt`.
+    pub fn suggested_options(&self) -> Vec<(&'static str, String)> {
+        let mut options = Vec::new();
+        if let Some(size) = self.suggested_dynvarsize {
+            options.push(("dynvarsize", size.to_string()));
+        }
+        if let Some(interval) = self.suggested_cleanrate {
+            options.push(("cleanrate", format!("{}hz", 1_000_000_000 / interval)));
+        }
+        options
+    }
+}
+
+/// Converts a message provided by libdtrace, which ends with a newline.
+pub(crate) fn message(msg: *const c_char) -> String {
+    if msg.is_null() {
+        return String::new();
+    }
+    let msg = unsafe { CStr::from_ptr(msg) };
+    msg.to_string_lossy().trim_end().to_string()
+}
+
+/// Drop handler logging each drop as a warning and counting it, like dtrace(1) reports drops on stderr.
+pub(crate) fn log_drop(data: &crate::dtrace_dropdata_t, counts: &Mutex<DropCounts>) -> c_int {
+    counts.lock().unwrap().add(data.dtdda_kind, data.dtdda_drops);
+    log::warn!("{}", message(data.dtdda_msg));
+    crate::DTRACE_HANDLE_OK as c_int
+}
+
+/// A clause of an enabled program and the probes its ECB (enabling control block) enables, used to attribute faults
+/// to the D source, see `dtrace_hdl::fault`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StatementSource {
+    /// Index of the program among the programs enabled on the handle
+    pub program: usize,
+    /// Index of the ECB among the ECBs of the program, one per probe description of each clause
+    pub ecb: usize,
+    /// The probe description of the ECB as written, possibly with wildcards or empty fields
+    pub description: crate::probe::ProbeDescription,
+    /// Line the clause starts at in the program source, if the source is known
+    pub line: Option<usize>,
+    /// The probe descriptions and predicate of the clause, if the source is known
+    pub clause: Option<String>,
+    /// IDs of the probes matching the description when the program was enabled
+    pub probes: Vec<crate::dtrace_id_t>,
+}
+
+impl StatementSource {
+    /// Returns the number of probes the description matched when the program was enabled.
+    pub fn match_count(&self) -> usize {
+        self.probes.len()
+    }
+}
+
+impl std::fmt::Display for StatementSource {
+    /// Formats the description and the number of probes it matched, with the line of its clause if it is known,
+    /// e.g. `syscall::NtClose:entry at line 12 matched 1 probe`.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.description)?;
+        if let Some(line) = self.line {
+            write!(f, " at line {}", line)?;
+        }
+        match self.match_count() {
+            1 => write!(f, " matched 1 probe"),
+            count => write!(f, " matched {} probes", count),
+        }
+    }
+}
+
+/// A fault that occurred while executing a D program, see `dtrace_hdl::fault`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Fault {
+    /// The enabled probe ID (EPID) of the faulting ECB, if libdtrace knows it
+    pub epid: Option<crate::dtrace_epid_t>,
+    /// The probe that fired
+    pub probe: Option<crate::probe::ProbeDescription>,
+    /// CPU the fault occurred on
+    pub cpu: crate::processorid_t,
+    /// Description of the fault, e.g. `invalid address`
+    pub message: String,
+    /// The offending address, for faults caused by an address
+    pub address: Option<u64>,
+    /// Index of the faulting action of the statement, 1-based, or `0` for the predicate
+    pub action: i32,
+    /// Offset of the faulting instruction in the DIF object of the action
+    pub dif_offset: Option<i32>,
+    /// The clause the faulting ECB was compiled from, when it can be told apart from the other clauses enabling the
+    /// probe
+    pub source: Option<StatementSource>,
+}
+
+impl Fault {
+    /// Describes the fault reported to an error handler, attributing it to one of `sources`.
+    pub fn new(data: &crate::dtrace_errdata_t, sources: &[StatementSource]) -> Self {
+        let probe = unsafe { data.dteda_pdesc.as_ref() }.map(crate::probe::ProbeDescription::from);
+        let address = match data.dteda_fault as u32 {
+            crate::DTRACEFLT_BADADDR
+            | crate::DTRACEFLT_BADALIGN
+            | crate::DTRACEFLT_KPRIV
+            | crate::DTRACEFLT_UPRIV => Some(data.dteda_addr),
+            _ => None,
+        };
+        // A probe enabled by several clauses cannot be attributed to one of them.
+        let source = probe.as_ref().and_then(|probe| {
+            let mut matching = sources.iter().filter(|source| source.probes.contains(&probe.id));
+            match (matching.next(), matching.next()) {
+                (Some(source), None) => Some(source.clone()),
+                _ => None,
+            }
+        });
+        Self {
+            epid: unsafe { data.dteda_edesc.as_ref() }.map(|edesc| edesc.dtepd_epid),
+            probe,
+            cpu: data.dteda_cpu,
+            message: message(unsafe { crate::dtrace_faultstr(data.dteda_handle, data.dteda_fault) }),
+            address,
+            action: data.dteda_action,
+            dif_offset: (data.dteda_offset != -1).then_some(data.dteda_offset),
+            source,
+        }
+    }
+}
+
+impl std::fmt::Display for Fault {
+    /// Formats the fault like dtrace(1), followed by the clause it was compiled from if it is known, e.g.
+    /// `error on enabled probe ID 3 (ID 74: syscall::NtClose:entry): invalid address (0x0) in action #1 at DIF
+    /// offset 24, in clause at line 12: syscall::NtClose:entry`.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.epid {
+            Some(epid) => write!(f, "error on enabled probe ID {}", epid)?,
+            None => write!(f, "error on enabled probe ID ?")?,
+        }
+        if let Some(probe) = &self.probe {
+            write!(f, " (ID {}: {})", probe.id, probe)?;
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(address) = self.address {
+            write!(f, " (0x{:x})", address)?;
+        }
+        if self.action == 0 {
+            write!(f, " in predicate")?;
+        } else {
+            write!(f, " in action #{}", self.action)?;
+        }
+        if let Some(offset) = self.dif_offset {
+            write!(f, " at DIF offset {}", offset)?;
+        }
+        match &self.source {
+            Some(StatementSource {
+                line: Some(line),
+                clause: Some(clause),
+                ..
+            }) => write!(f, ", in clause at line {}: {}", line, clause),
+            Some(source) => write!(f, ", in clause {}", source.description),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Describes a fault that occurred while executing a D program, attributed to the offending probe.
+///
+/// The description has the same form as the one printed by dtrace(1), e.g. `error on enabled probe ID 3
+/// (ID 74: syscall::NtClose:entry): invalid address (0x0) in action #1 at DIF offset 24`. See [`Fault`] for a
+/// structured description attributed to the D source.
+pub fn describe_fault(data: &crate::dtrace_errdata_t) -> String {
+    Fault::new(data, &[]).to_string()
+}
+
+/// Error handler logging each fault as an error and counting it.
+pub(crate) fn log_error(data: &crate::dtrace_errdata_t, count: &AtomicU64) -> c_int {
+    count.fetch_add(1, Ordering::Relaxed);
+    log::error!("{}", describe_fault(data));
+    crate::DTRACE_HANDLE_OK as c_int
+}
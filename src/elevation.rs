@@ -0,0 +1,144 @@
+//! Telling whether the process is elevated, and relaunching it elevated when it is not.
+//!
+//! Opening DTrace on Windows requires running as administrator, so the first run of a consumer from a regular command
+//! prompt fails, with an error of kind [`ErrorKind::NotElevated`](crate::utils::ErrorKind::NotElevated).
+//! [`relaunch_elevated`] starts the consumer again with the same arguments through the UAC prompt:
+//!
+//! ```no_run
+//! use libdtrace_rs::elevation;
+//! use libdtrace_rs::wrapper::dtrace_hdl;
+//!
+//! let handle = match dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0) {
+//!     Ok(handle) => handle,
+//!     Err(err) if err.is_not_elevated() => match elevation::relaunch_elevated() {
+//!         Ok(code) => std::process::exit(code as i32),
+//!         Err(err) => panic!("{}", err),
+//!     },
+//!     Err(err) => panic!("{}", err),
+//! };
+//! ```
+
+use crate::utils::Error;
+#[cfg(windows)]
+use crate::win32;
+
+#[cfg(unix)]
+extern "C" {
+    fn geteuid() -> u32;
+}
+
+/// Returns `true` if the process runs elevated, i.e. as administrator on Windows and as root on Unix.
+pub fn is_elevated() -> bool {
+    #[cfg(windows)]
+    unsafe {
+        let mut token = std::ptr::null_mut();
+        if win32::OpenProcessToken(win32::GetCurrentProcess(), win32::TOKEN_QUERY, &mut token) == 0 {
+            return false;
+        }
+        let mut elevation: u32 = 0;
+        let mut len = 0;
+        let queried = win32::GetTokenInformation(
+            token,
+            win32::TOKEN_ELEVATION,
+            &mut elevation as *mut u32 as *mut ::core::ffi::c_void,
+            std::mem::size_of::<u32>() as u32,
+            &mut len,
+        );
+        win32::CloseHandle(token);
+        queried != 0 && elevation != 0
+    }
+    #[cfg(unix)]
+    unsafe {
+        geteuid() == 0
+    }
+    #[cfg(not(any(windows, unix)))]
+    true
+}
+
+/// Quotes `args` into a Windows command line, the way `CommandLineToArgvW` and the C runtime split it back into the
+/// same arguments: arguments holding whitespace or quotes are quoted, and the quotes and the backslashes preceding
+/// them escaped.
+pub fn command_line(args: impl IntoIterator<Item = impl AsRef<str>>) -> String {
+    let quote = |arg: &str| {
+        if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '\x0b', '"']) {
+            return arg.to_string();
+        }
+        let mut quoted = String::from('"');
+        let mut backslashes = 0;
+        for c in arg.chars() {
+            match c {
+                '\\' => {
+                    backslashes += 1;
+                    continue;
+                }
+                '"' => quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1)),
+                _ => quoted.extend(std::iter::repeat_n('\\', backslashes)),
+            }
+            backslashes = 0;
+            quoted.push(c);
+        }
+        // The closing quote must not be escaped by trailing backslashes.
+        quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+        quoted.push('"');
+        quoted
+    };
+    args.into_iter().map(|arg| quote(arg.as_ref())).collect::<Vec<_>>().join(" ")
+}
+
+/// Starts the executable of the process again as administrator, through the UAC prompt, with the same arguments and
+/// working directory, and waits for it to exit.
+///
+/// The elevated process gets a console of its own, so its output is not shown in the console of the caller.
+/// Arguments that are not valid Unicode are passed with their invalid parts replaced.
+///
+/// # Returns
+///
+/// Returns the exit code of the elevated process, or the Windows error, e.g. `ERROR_CANCELLED` if the user declined
+/// the prompt.
+pub fn relaunch_elevated() -> Result<u32, Error> {
+    #[cfg(windows)]
+    unsafe {
+        use std::os::windows::ffi::OsStrExt;
+
+        let context = "relaunching the process elevated";
+        let wide = |text: &std::ffi::OsStr| -> Vec<u16> { text.encode_wide().chain([0]).collect() };
+        let exe = std::env::current_exe().map_err(|err| Error::from(err).with_context(context))?;
+        let args: Vec<String> = std::env::args_os().skip(1).map(|arg| arg.to_string_lossy().into_owned()).collect();
+        let directory = std::env::current_dir().ok().map(|dir| wide(dir.as_os_str()));
+        let verb = wide("runas".as_ref());
+        let file = wide(exe.as_os_str());
+        let parameters = wide(command_line(&args).as_ref());
+
+        let mut info: win32::SHELLEXECUTEINFOW = std::mem::zeroed();
+        info.cbSize = std::mem::size_of::<win32::SHELLEXECUTEINFOW>() as u32;
+        info.fMask = win32::SEE_MASK_NOCLOSEPROCESS;
+        info.lpVerb = verb.as_ptr();
+        info.lpFile = file.as_ptr();
+        info.lpParameters = parameters.as_ptr();
+        info.lpDirectory = directory.as_ref().map_or(std::ptr::null(), |dir| dir.as_ptr());
+        info.nShow = win32::SW_SHOWNORMAL;
+        if win32::ShellExecuteExW(&mut info) == 0 {
+            let error = std::io::Error::last_os_error();
+            return Err(match error.raw_os_error() {
+                Some(win32::ERROR_CANCELLED) => Error::new(win32::ERROR_CANCELLED, "The elevation was declined"),
+                _ => Error::from(error),
+            }
+            .with_context(context));
+        }
+        if info.hProcess.is_null() {
+            return Err(Error::new(-1, "The elevated process cannot be waited for").with_context(context));
+        }
+
+        win32::WaitForSingleObject(info.hProcess, win32::INFINITE);
+        let mut code = 0;
+        let got = win32::GetExitCodeProcess(info.hProcess, &mut code);
+        let error = std::io::Error::last_os_error();
+        win32::CloseHandle(info.hProcess);
+        match got {
+            0 => Err(Error::from(error).with_context("getting the exit code of the elevated process")),
+            _ => Ok(code),
+        }
+    }
+    #[cfg(not(windows))]
+    Err(Error::new(-1, "Relaunching elevated is only available on Windows"))
+}
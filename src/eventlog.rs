@@ -0,0 +1,210 @@
+//! Reporting drops and faults to the Windows Event Log, so that data lost by a long-running tracing service does not
+//! go unnoticed, see `TraceSession::with_event_log_sink`.
+//!
+//! Events are written to the Application log under an event source, which is registered once, e.g. by the installer
+//! of the service, with [`EventLogSink::register`]. Drops are written as warnings with the event ID
+//! [`DROP_EVENT_ID`], faults as errors with the event ID [`FAULT_EVENT_ID`], with the message of the event as their
+//! description.
+//!
+//! ```no_run
+//! use libdtrace_rs::eventlog::EventLogSink;
+//! use libdtrace_rs::session::TraceSession;
+//!
+//! // Needs administrator rights, once per machine.
+//! EventLogSink::register("MyTracer").unwrap();
+//!
+//! let mut session = TraceSession::new("syscall:::entry { @[execname] = count(); }")
+//!     .with_event_log_sink(EventLogSink::open("MyTracer").unwrap());
+//! session.start().unwrap();
+//! ```
+
+use crate::session::{Event, Severity};
+use crate::utils::Error;
+#[cfg(windows)]
+use crate::win32;
+use ::core::ffi::c_void;
+
+/// Event ID of the drops written by an [`EventLogSink`]
+pub const DROP_EVENT_ID: u32 = 1;
+/// Event ID of the faults written by an [`EventLogSink`]
+pub const FAULT_EVENT_ID: u32 = 2;
+
+/// Message file of the sources registered by [`EventLogSink::register`], shipped with the .NET Framework, whose
+/// messages display the description of an event as is.
+pub const MESSAGE_FILE: &str = r"%SystemRoot%\Microsoft.NET\Framework64\v4.0.30319\EventLogMessages.dll";
+
+/// Registry key of the sources of the Application log, under `HKEY_LOCAL_MACHINE`.
+#[cfg_attr(not(windows), allow(dead_code))]
+const SOURCES_KEY: &str = r"SYSTEM\CurrentControlSet\Services\EventLog\Application";
+
+/// Encodes `text` as a NUL-terminated UTF-16 string.
+#[cfg(windows)]
+fn wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain([0]).collect()
+}
+
+/// An event source of the Windows Event Log, see the [module](self) docs.
+pub struct EventLogSink {
+    #[cfg_attr(not(windows), allow(dead_code))]
+    handle: *mut c_void,
+    source: String,
+}
+
+// Event source handles may be used and closed from any thread.
+unsafe impl Send for EventLogSink {}
+unsafe impl Sync for EventLogSink {}
+
+impl std::fmt::Debug for EventLogSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("EventLogSink").field("source", &self.source).finish()
+    }
+}
+
+impl EventLogSink {
+    /// Registers the event source `source` of the Application log, with [`MESSAGE_FILE`] as its message file.
+    /// Registering a source again updates it.
+    ///
+    /// Events of an unregistered source are still written, but the Event Viewer does not display their description
+    /// properly.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` once registered, or the Windows error, e.g. if the process is not running as administrator.
+    pub fn register(source: &str) -> Result<(), Error> {
+        #[cfg(windows)]
+        unsafe {
+            let context = || format!("registering event source {}", source);
+            let mut key = std::ptr::null_mut();
+            let status = win32::RegCreateKeyExW(
+                win32::HKEY_LOCAL_MACHINE,
+                wide(&format!(r"{}\{}", SOURCES_KEY, source)).as_ptr(),
+                0,
+                std::ptr::null_mut(),
+                win32::REG_OPTION_NON_VOLATILE,
+                win32::KEY_SET_VALUE,
+                std::ptr::null_mut(),
+                &mut key,
+                std::ptr::null_mut(),
+            );
+            if status != 0 {
+                return Err(Error::from(std::io::Error::from_raw_os_error(status)).with_context(context()));
+            }
+            let file = wide(MESSAGE_FILE);
+            let types = (win32::EVENTLOG_ERROR_TYPE | win32::EVENTLOG_WARNING_TYPE | win32::EVENTLOG_INFORMATION_TYPE)
+                as u32;
+            let mut status = win32::RegSetValueExW(
+                key,
+                wide("EventMessageFile").as_ptr(),
+                0,
+                win32::REG_EXPAND_SZ,
+                file.as_ptr() as *const u8,
+                (file.len() * 2) as u32,
+            );
+            if status == 0 {
+                status = win32::RegSetValueExW(
+                    key,
+                    wide("TypesSupported").as_ptr(),
+                    0,
+                    win32::REG_DWORD,
+                    &types as *const u32 as *const u8,
+                    4,
+                );
+            }
+            win32::RegCloseKey(key);
+            match status {
+                0 => Ok(()),
+                _ => Err(Error::from(std::io::Error::from_raw_os_error(status)).with_context(context())),
+            }
+        }
+        #[cfg(not(windows))]
+        Err(Error::new(
+            -1,
+            format!("Cannot register event source {}: the Event Log is only available on Windows", source),
+        ))
+    }
+
+    /// Opens the event source `source` of the local machine, registered or not, see `register`.
+    pub fn open(source: &str) -> Result<Self, Error> {
+        #[cfg(windows)]
+        unsafe {
+            let handle = win32::RegisterEventSourceW(std::ptr::null(), wide(source).as_ptr());
+            if handle.is_null() {
+                let error = std::io::Error::last_os_error();
+                return Err(Error::from(error).with_context(format!("opening event source {}", source)));
+            }
+            Ok(Self {
+                handle,
+                source: source.to_string(),
+            })
+        }
+        #[cfg(not(windows))]
+        Err(Error::new(
+            -1,
+            format!("Cannot open event source {}: the Event Log is only available on Windows", source),
+        ))
+    }
+
+    /// Returns the name of the event source.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Writes an event with the ID `event_id` and the description `message`, as an error, a warning or an information
+    /// event depending on `severity`.
+    pub fn report(&self, event_id: u32, severity: Severity, message: &str) -> Result<(), Error> {
+        #[cfg(windows)]
+        unsafe {
+            let kind = match severity {
+                Severity::Info => win32::EVENTLOG_INFORMATION_TYPE,
+                Severity::Warning => win32::EVENTLOG_WARNING_TYPE,
+                Severity::Error => win32::EVENTLOG_ERROR_TYPE,
+            };
+            let message = wide(message);
+            let strings = [message.as_ptr()];
+            let reported = win32::ReportEventW(
+                self.handle,
+                kind,
+                0,
+                event_id,
+                std::ptr::null_mut(),
+                1,
+                0,
+                strings.as_ptr(),
+                std::ptr::null_mut(),
+            );
+            if reported == 0 {
+                let error = std::io::Error::last_os_error();
+                return Err(Error::from(error).with_context(format!("writing to event source {}", self.source)));
+            }
+            Ok(())
+        }
+        #[cfg(not(windows))]
+        {
+            let _ = (event_id, severity, message);
+            Err(Error::new(-1, "The Event Log is only available on Windows"))
+        }
+    }
+
+    /// Writes `event` if it is a drop or a fault, other events are ignored.
+    ///
+    /// # Returns
+    ///
+    /// Returns whether `event` was written, or the error writing it.
+    pub fn report_event(&self, event: &Event) -> Result<bool, Error> {
+        match event {
+            Event::Drop(message) => self.report(DROP_EVENT_ID, Severity::Warning, message.trim_end())?,
+            Event::Fault(fault) => self.report(FAULT_EVENT_ID, Severity::Error, &fault.to_string())?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+impl Drop for EventLogSink {
+    fn drop(&mut self) {
+        #[cfg(windows)]
+        unsafe {
+            win32::DeregisterEventSource(self.handle);
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! Filtering the firings of probes in the consumer, so one broad enabling feeds several visitors that each see part
+//! of the trace data, without compiling a program per visitor.
+//!
+//! A [`ProbeFilter`] selects firings by provider, probe description, CPU and process. A [`Filtered`] visitor applies
+//! it before the records of a firing are decoded: the firings it rejects are skipped by libdtrace.
+//!
+//! ```no_run
+//! use libdtrace_rs::filter::{Filtered, ProbeFilter};
+//! use libdtrace_rs::visit::RecordVisitor;
+//!
+//! struct Printer;
+//!
+//! impl RecordVisitor for Printer {
+//!     fn visit_int(&mut self, value: i64) {
+//!         println!("{}", value);
+//!     }
+//! }
+//!
+//! # let mut handle: libdtrace_rs::wrapper::dtrace_hdl = unimplemented!();
+//! // The program traces `pid` first, e.g. `syscall:::entry { trace(pid); trace(arg0); }`.
+//! let filter = ProbeFilter::new().probe("NtClose:entry").pid(4242);
+//! let mut visitor = Filtered::new(Printer, filter);
+//! handle.work_with_visitor(&mut visitor).unwrap();
+//! ```
+
+use crate::aggregate::AggregateEntry;
+use crate::probe::{EnabledProbe, ProbeDescription};
+use crate::symbol::SymbolAction;
+use crate::visit::{RecordVisitor, UnknownActionPolicy};
+
+/// Selects firings of probes. A firing is selected if it matches one of the values of each kind of criterion given,
+/// e.g. one of the providers and one of the CPUs. Without criteria, every firing is selected.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProbeFilter {
+    providers: Vec<String>,
+    probes: Vec<String>,
+    pids: Vec<crate::pid_t>,
+    cpus: Vec<crate::processorid_t>,
+}
+
+impl ProbeFilter {
+    /// Creates a filter selecting every firing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Selects the probes of `provider`.
+    pub fn provider(mut self, provider: &str) -> Self {
+        self.providers.push(provider.to_string());
+        self
+    }
+
+    /// Selects the probes matching `pattern`, a probe description like those of D programs: up to four fields
+    /// `provider:module:function:name` with `*` and `?` wildcards, where leading fields can be left out and empty
+    /// fields match anything, e.g. `NtClose:entry` or `syscall::Nt*File:`.
+    pub fn probe(mut self, pattern: &str) -> Self {
+        self.probes.push(pattern.to_string());
+        self
+    }
+
+    /// Selects the firings of process `pid`, taken from the first record of each firing, which must be an integer
+    /// as recorded by `trace(pid)`. Firings that do not start with an integer are not selected.
+    pub fn pid(mut self, pid: crate::pid_t) -> Self {
+        self.pids.push(pid);
+        self
+    }
+
+    /// Selects the firings on CPU `cpu`.
+    pub fn cpu(mut self, cpu: crate::processorid_t) -> Self {
+        self.cpus.push(cpu);
+        self
+    }
+
+    /// Returns `true` if the filter selects firings by process, which is known only once the first record is decoded.
+    pub fn filters_pids(&self) -> bool {
+        !self.pids.is_empty()
+    }
+
+    /// Returns `true` if the firings of `probe` on `cpu` are selected, regardless of their process. Probes that are
+    /// not known only match filters without provider and probe criteria.
+    pub fn matches_probe(&self, cpu: crate::processorid_t, probe: Option<&ProbeDescription>) -> bool {
+        if !self.cpus.is_empty() && !self.cpus.contains(&cpu) {
+            return false;
+        }
+        if self.providers.is_empty() && self.probes.is_empty() {
+            return true;
+        }
+        let probe = match probe {
+            Some(probe) => probe,
+            None => return false,
+        };
+        (self.providers.is_empty() || self.providers.contains(&probe.provider))
+            && (self.probes.is_empty() || self.probes.iter().any(|pattern| matches_description(pattern, probe)))
+    }
+
+    /// Returns `true` if the firings of process `pid` are selected.
+    pub fn matches_pid(&self, pid: i64) -> bool {
+        self.pids.is_empty() || self.pids.iter().any(|&selected| selected as i64 == pid)
+    }
+}
+
+/// Returns `true` if `probe` matches the probe description `pattern`, see `ProbeFilter::probe`.
+fn matches_description(pattern: &str, probe: &ProbeDescription) -> bool {
+    let fields = [&probe.provider, &probe.module, &probe.function, &probe.name];
+    let parts: Vec<&str> = pattern.split(':').collect();
+    if parts.len() > fields.len() {
+        return false;
+    }
+    // Leading fields left out match anything, as in D.
+    let skipped = fields.len() - parts.len();
+    parts
+        .iter()
+        .zip(&fields[skipped..])
+        .all(|(part, field)| part.is_empty() || glob(part.as_bytes(), field.as_bytes()))
+}
+
+/// Matches `text` against `pattern`, where `*` matches any sequence and `?` any single character.
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` in the pattern and of the text it is matched up to.
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Whether the firing being visited by a [`Filtered`] is delivered.
+enum Firing {
+    /// The firing is delivered
+    Accepted,
+    /// The firing is dropped
+    Rejected,
+    /// The process of the firing is not known yet, with the arguments of `visit_probe`
+    Pending(crate::processorid_t, Option<EnabledProbe>),
+}
+
+/// A [`RecordVisitor`] delivering to the visitor it wraps only the firings selected by a [`ProbeFilter`], see the
+/// [module](self) docs.
+///
+/// Firings rejected by their probe or CPU are not decoded. When the filter selects processes, the probe is delivered
+/// to the wrapped visitor once the first record of the firing showed its process. Aggregation entries are always
+/// delivered.
+pub struct Filtered<V> {
+    visitor: V,
+    filter: ProbeFilter,
+    firing: Firing,
+}
+
+impl<V: RecordVisitor> Filtered<V> {
+    /// Wraps `visitor`, delivering the firings `filter` selects.
+    pub fn new(visitor: V, filter: ProbeFilter) -> Self {
+        Self {
+            visitor,
+            filter,
+            firing: Firing::Accepted,
+        }
+    }
+
+    /// Returns the filter.
+    pub fn filter(&self) -> &ProbeFilter {
+        &self.filter
+    }
+
+    /// Returns the wrapped visitor.
+    pub fn visitor(&mut self) -> &mut V {
+        &mut self.visitor
+    }
+
+    /// Returns the wrapped visitor.
+    pub fn into_inner(self) -> V {
+        self.visitor
+    }
+
+    /// Returns `true` if the current record is delivered, deciding a pending firing with the record, `pid` being the
+    /// value of an integer record.
+    fn deliver(&mut self, pid: Option<i64>) -> bool {
+        if let Firing::Pending(..) = self.firing {
+            let accepted = pid.is_some_and(|pid| self.filter.matches_pid(pid));
+            match std::mem::replace(&mut self.firing, Firing::Rejected) {
+                Firing::Pending(cpu, probe) if accepted => {
+                    self.visitor.visit_probe(cpu, probe.as_ref());
+                    self.firing = Firing::Accepted;
+                }
+                _ => {}
+            }
+        }
+        matches!(self.firing, Firing::Accepted)
+    }
+}
+
+impl<V: RecordVisitor> RecordVisitor for Filtered<V> {
+    fn accepts_probe(&mut self, cpu: crate::processorid_t, probe: Option<&EnabledProbe>) -> bool {
+        self.filter.matches_probe(cpu, probe.map(|probe| &probe.probe)) && self.visitor.accepts_probe(cpu, probe)
+    }
+
+    fn visit_probe(&mut self, cpu: crate::processorid_t, probe: Option<&EnabledProbe>) {
+        match self.filter.filters_pids() {
+            true => self.firing = Firing::Pending(cpu, probe.cloned()),
+            false => {
+                self.firing = Firing::Accepted;
+                self.visitor.visit_probe(cpu, probe);
+            }
+        }
+    }
+
+    fn visit_int(&mut self, value: i64) {
+        if self.deliver(Some(value)) {
+            self.visitor.visit_int(value);
+        }
+    }
+
+    fn visit_string(&mut self, value: &str) {
+        if self.deliver(None) {
+            self.visitor.visit_string(value);
+        }
+    }
+
+    fn visit_bytes(&mut self, value: &[u8]) {
+        if self.deliver(None) {
+            self.visitor.visit_bytes(value);
+        }
+    }
+
+    fn visit_stack(&mut self, frames: &[u64], pid: Option<crate::pid_t>) {
+        if self.deliver(None) {
+            self.visitor.visit_stack(frames, pid);
+        }
+    }
+
+    fn visit_stack_frames(&mut self, stack: &crate::symbol::Stack) {
+        if self.deliver(None) {
+            self.visitor.visit_stack_frames(stack);
+        }
+    }
+
+    fn visit_symbol(&mut self, action: SymbolAction, address: u64, pid: Option<crate::pid_t>) {
+        if self.deliver(None) {
+            self.visitor.visit_symbol(action, address, pid);
+        }
+    }
+
+    fn visit_printf(&mut self, output: &str) {
+        if self.deliver(None) {
+            self.visitor.visit_printf(output);
+        }
+    }
+
+    fn visit_exit(&mut self, status: i32) {
+        if self.deliver(None) {
+            self.visitor.visit_exit(status);
+        }
+    }
+
+    fn visit_aggregate(&mut self, entry: &AggregateEntry) {
+        self.visitor.visit_aggregate(entry);
+    }
+
+    fn visit_names(&mut self, names: &crate::names::Names) {
+        if self.deliver(None) {
+            self.visitor.visit_names(names);
+        }
+    }
+
+    fn visit_end(&mut self) {
+        if self.deliver(None) {
+            self.visitor.visit_end();
+        }
+        self.firing = Firing::Accepted;
+    }
+
+    fn visit_unknown(&mut self, action: crate::dtrace_actkind_t, bytes: &[u8]) {
+        if self.deliver(None) {
+            self.visitor.visit_unknown(action, bytes);
+        }
+    }
+
+    fn visit_decoded(&mut self, action: crate::dtrace_actkind_t, value: &crate::typed::TypedValue) {
+        if self.deliver(None) {
+            self.visitor.visit_decoded(action, value);
+        }
+    }
+
+    fn visit_decoded_aggregate(&mut self, entry: &AggregateEntry, value: &crate::typed::TypedValue) {
+        self.visitor.visit_decoded_aggregate(entry, value);
+    }
+
+    fn unknown_actions(&self) -> UnknownActionPolicy {
+        self.visitor.unknown_actions()
+    }
+}
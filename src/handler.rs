@@ -0,0 +1,159 @@
+use crate::types::{dtrace_handler, dtrace_handler_kind};
+use ::core::ffi::{c_int, c_void};
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// A handler function receiving a typed context instead of a raw `*mut c_void` argument.
+///
+/// Used with `dtrace_hdl::register_handler_with`, which keeps the context alive for as long as the handler is
+/// registered.
+pub enum Handler<T> {
+    /// Called for each buffered trace record
+    Buffered(fn(&crate::dtrace_bufdata_t, &T) -> c_int),
+    /// Called when trace records are dropped
+    Drop(fn(&crate::dtrace_dropdata_t, &T) -> c_int),
+    /// Called for errors such as accessing an invalid address or dividing by zero
+    Err(fn(&crate::dtrace_errdata_t, &T) -> c_int),
+    /// Called when a DTrace option is set from inside a D program
+    SetOpt(fn(&crate::dtrace_setoptdata_t, &T) -> c_int),
+}
+
+impl<T> Clone for Handler<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handler<T> {}
+
+impl<T> Handler<T> {
+    /// Returns the kind of the handler.
+    pub fn kind(&self) -> dtrace_handler_kind {
+        match self {
+            Handler::Buffered(_) => dtrace_handler_kind::Buffered,
+            Handler::Drop(_) => dtrace_handler_kind::Drop,
+            Handler::Err(_) => dtrace_handler_kind::Err,
+            Handler::SetOpt(_) => dtrace_handler_kind::SetOpt,
+        }
+    }
+}
+
+/// The handler function and context a trampoline forwards to.
+struct State<D, T> {
+    handler: fn(&D, &T) -> c_int,
+    ctx: Arc<T>,
+}
+
+unsafe extern "C" fn trampoline<D, T>(data: *const D, arg: *mut c_void) -> c_int {
+    let state = &*(arg as *const State<D, T>);
+    (state.handler)(&*data, &state.ctx)
+}
+
+/// Boxes the state of a typed handler, returning it along with the argument to register the trampoline with.
+fn boxed<D: 'static, T: Send + Sync + 'static>(
+    handler: fn(&D, &T) -> c_int,
+    ctx: Arc<T>,
+) -> (*mut c_void, Box<dyn Any + Send>) {
+    let state = Box::new(State { handler, ctx });
+    let arg = &*state as *const State<D, T> as *mut c_void;
+    (arg, state)
+}
+
+impl<T: Send + Sync + 'static> Handler<T> {
+    /// Converts the handler into a raw handler, the argument to register it with and the state backing that argument.
+    ///
+    /// The returned state must be kept alive for as long as the raw handler is registered.
+    pub(crate) fn into_raw(
+        self,
+        ctx: Arc<T>,
+    ) -> (dtrace_handler, *mut c_void, Box<dyn Any + Send>) {
+        match self {
+            Handler::Buffered(handler) => {
+                let (arg, state) = boxed(handler, ctx);
+                (dtrace_handler::Buffered(Some(trampoline::<crate::dtrace_bufdata_t, T>)), arg, state)
+            }
+            Handler::Drop(handler) => {
+                let (arg, state) = boxed(handler, ctx);
+                (dtrace_handler::Drop(Some(trampoline::<crate::dtrace_dropdata_t, T>)), arg, state)
+            }
+            Handler::Err(handler) => {
+                let (arg, state) = boxed(handler, ctx);
+                (dtrace_handler::Err(Some(trampoline::<crate::dtrace_errdata_t, T>)), arg, state)
+            }
+            Handler::SetOpt(handler) => {
+                let (arg, state) = boxed(handler, ctx);
+                (dtrace_handler::SetOpt(Some(trampoline::<crate::dtrace_setoptdata_t, T>)), arg, state)
+            }
+        }
+    }
+}
+
+/// The handlers installed on a `dtrace_hdl`, along with the state backing their arguments.
+#[derive(Default)]
+pub(crate) struct Handlers {
+    installed: Mutex<HashMap<dtrace_handler_kind, Option<Box<dyn Any + Send>>>>,
+}
+
+impl Handlers {
+    pub(crate) fn is_installed(&self, kind: dtrace_handler_kind) -> bool {
+        self.installed.lock().unwrap().contains_key(&kind)
+    }
+
+    /// Records that a handler of `kind` is installed, keeping `state` alive until it is removed.
+    pub(crate) fn install(&self, kind: dtrace_handler_kind, state: Option<Box<dyn Any + Send>>) {
+        self.installed.lock().unwrap().insert(kind, state);
+    }
+
+    /// Forgets the handler of `kind`, dropping its state.
+    pub(crate) fn remove(&self, kind: dtrace_handler_kind) -> bool {
+        self.installed.lock().unwrap().remove(&kind).is_some()
+    }
+}
+
+/// A subscriber to buffered output, see `dtrace_hdl::subscribe_buffered`.
+pub type BufferedSubscriber = Box<dyn FnMut(&crate::dtrace_bufdata_t) -> c_int + Send>;
+
+/// Buffered output subscribers sharing the single buffered handler libdtrace supports.
+#[derive(Default)]
+pub(crate) struct BufferedSubscribers {
+    next_id: AtomicUsize,
+    subscribers: Mutex<Vec<(usize, BufferedSubscriber)>>,
+}
+
+impl BufferedSubscribers {
+    /// Adds `subscriber`, returning its ID and whether it is the first subscriber.
+    pub(crate) fn add(&self, subscriber: BufferedSubscriber) -> (usize, bool) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.push((id, subscriber));
+        (id, subscribers.len() == 1)
+    }
+
+    /// Removes the subscriber with `id`, returning whether it existed and whether no subscribers are left.
+    pub(crate) fn remove(&self, id: usize) -> (bool, bool) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let len = subscribers.len();
+        subscribers.retain(|(sid, _)| *sid != id);
+        (subscribers.len() != len, subscribers.is_empty())
+    }
+
+    pub(crate) fn clear(&self) {
+        self.subscribers.lock().unwrap().clear();
+    }
+}
+
+/// Buffered handler fanning output out to every subscriber of the `BufferedSubscribers` passed as `arg`.
+///
+/// Aborts if any subscriber returns `DTRACE_HANDLE_ABORT`, after all subscribers have seen the data.
+pub(crate) unsafe extern "C" fn fan_out(bufdata: *const crate::dtrace_bufdata_t, arg: *mut c_void) -> c_int {
+    let subscribers = &*(arg as *const BufferedSubscribers);
+    let mut status = crate::DTRACE_HANDLE_OK as c_int;
+    for (_, subscriber) in subscribers.subscribers.lock().unwrap().iter_mut() {
+        if subscriber(&*bufdata) != crate::DTRACE_HANDLE_OK as c_int {
+            status = crate::DTRACE_HANDLE_ABORT as c_int;
+        }
+    }
+    status
+}
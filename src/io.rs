@@ -0,0 +1,220 @@
+//! Tracing file reads and writes by path, collected into per-file statistics by a [`FileIoTracker`].
+//!
+//! The reads and writes of Windows name the file by handle, so the tracker follows the handles of each process:
+//! [`FileIoTracker::PROGRAM`] records the path of the files as they are opened, the handle they are opened as, and the
+//! reads, writes and closes of the handles. Files opened before tracing started are reported as [`UNKNOWN_PATH`].
+//! Paths are decoded from string records, or from byte records holding UTF-16, see [`crate::strings`].
+//!
+//! ```no_run
+//! use libdtrace_rs::io::FileIoTracker;
+//! use libdtrace_rs::session::TraceSession;
+//! use std::sync::{Arc, Mutex};
+//!
+//! let tracker = Arc::new(Mutex::new(FileIoTracker::new()));
+//! let mut session = TraceSession::new(FileIoTracker::PROGRAM)
+//!     .with_option("quiet", "")
+//!     .with_visitor(tracker.clone());
+//! session.start().unwrap();
+//! std::thread::sleep(std::time::Duration::from_secs(10));
+//! session.stop();
+//! for (path, stats) in tracker.lock().unwrap().files() {
+//!     println!("{}: {} bytes read, {} bytes written", path, stats.read_bytes, stats.written_bytes);
+//! }
+//! ```
+
+use crate::aggregate::{Histogram, QUANTIZE_NBUCKETS, QUANTIZE_ZEROBUCKET};
+use crate::probe::EnabledProbe;
+use crate::strings::{StringEncoding, StringPolicy};
+use crate::visit::RecordVisitor;
+use std::collections::HashMap;
+
+/// Path the reads and writes of handles opened before tracing started are accounted to.
+pub const UNKNOWN_PATH: &str = "<unknown>";
+
+/// The reads and writes of a file, see [`FileIoTracker::files`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStats {
+    /// Number of reads
+    pub reads: u64,
+    /// Number of writes
+    pub writes: u64,
+    /// Number of bytes asked to be read
+    pub read_bytes: u64,
+    /// Number of bytes asked to be written
+    pub written_bytes: u64,
+    /// Number of reads and writes of each size, laid out like the data of `quantize()`
+    counts: Vec<i64>,
+}
+
+impl Default for FileStats {
+    fn default() -> Self {
+        Self {
+            reads: 0,
+            writes: 0,
+            read_bytes: 0,
+            written_bytes: 0,
+            counts: vec![0; QUANTIZE_NBUCKETS],
+        }
+    }
+}
+
+impl FileStats {
+    /// Adds a read, or a write, of `size` bytes.
+    fn add(&mut self, write: bool, size: u64) {
+        match write {
+            true => {
+                self.writes += 1;
+                self.written_bytes = self.written_bytes.saturating_add(size);
+            }
+            false => {
+                self.reads += 1;
+                self.read_bytes = self.read_bytes.saturating_add(size);
+            }
+        }
+        let bucket = match size {
+            0 => QUANTIZE_ZEROBUCKET,
+            _ => (QUANTIZE_ZEROBUCKET + 1 + size.ilog2() as usize).min(QUANTIZE_NBUCKETS - 1),
+        };
+        self.counts[bucket] += 1;
+    }
+
+    /// Returns the sizes of the reads and writes in bytes as a power-of-two histogram, like `quantize()`, e.g. to
+    /// print it with `render::histogram`.
+    pub fn sizes(&self) -> Histogram {
+        Histogram::quantize(&self.counts)
+    }
+}
+
+/// What a firing of [`FileIoTracker::PROGRAM`] records.
+#[derive(Debug, Clone, Copy)]
+enum Operation {
+    Open,
+    Read,
+    Write,
+    Close,
+}
+
+/// Follows the file handles of processes and collects the reads and writes of each file, see the [module](self)
+/// docs.
+#[derive(Debug, Default)]
+pub struct FileIoTracker {
+    /// Paths of the handles opened while tracing, by process ID and handle
+    handles: HashMap<(u64, u64), String>,
+    files: HashMap<String, FileStats>,
+    /// Operation, integers and strings recorded by the firing being visited
+    firing: Option<(Operation, Vec<i64>, Vec<String>)>,
+}
+
+impl FileIoTracker {
+    /// A D program recording what the tracker needs.
+    ///
+    /// A successful `NtCreateFile` or `NtOpenFile` records the process ID, the handle and the path of the file. A read
+    /// or write records the process ID, the handle and the size asked for, a close the process ID and the handle.
+    pub const PROGRAM: &'static str = r#"syscall::NtCreateFile:entry, syscall::NtOpenFile:entry {
+    self->phandle = arg0;
+    self->attr = arg2;
+}
+syscall::NtCreateFile:return, syscall::NtOpenFile:return /self->attr && arg0 == 0/ {
+    this->attr = (nt`_OBJECT_ATTRIBUTES *)copyin(self->attr, sizeof(nt`_OBJECT_ATTRIBUTES));
+    this->name = (nt`_UNICODE_STRING *)copyin((uintptr_t)this->attr->ObjectName, sizeof(nt`_UNICODE_STRING));
+    trace(pid);
+    trace(*(int64_t *)copyin(self->phandle, sizeof(int64_t)));
+    trace(wstr2str((wchar_t *)copyin((uintptr_t)this->name->Buffer, this->name->Length), this->name->Length / 2));
+}
+syscall::NtCreateFile:return, syscall::NtOpenFile:return { self->phandle = 0; self->attr = 0; }
+syscall::NtReadFile:entry, syscall::NtWriteFile:entry { trace(pid); trace(arg0); trace(arg6); }
+syscall::NtClose:entry { trace(pid); trace(arg0); }"#;
+
+    /// Creates a tracker without files.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that process `pid` opened `path` as `handle`. NT paths like `\??\C:\file.txt` are recorded as DOS paths.
+    pub fn open(&mut self, pid: u64, handle: u64, path: &str) {
+        let path = path.strip_prefix(r"\??\").unwrap_or(path);
+        self.handles.insert((pid, handle), path.to_string());
+    }
+
+    /// Records that process `pid` read, or wrote, `size` bytes through `handle`.
+    pub fn transfer(&mut self, pid: u64, handle: u64, write: bool, size: u64) {
+        let path = self.path(pid, handle).unwrap_or(UNKNOWN_PATH).to_string();
+        self.files.entry(path).or_default().add(write, size);
+    }
+
+    /// Records that process `pid` closed `handle`.
+    pub fn close(&mut self, pid: u64, handle: u64) {
+        self.handles.remove(&(pid, handle));
+    }
+
+    /// Returns the path of `handle` of process `pid`, if it was opened while tracing.
+    pub fn path(&self, pid: u64, handle: u64) -> Option<&str> {
+        self.handles.get(&(pid, handle)).map(String::as_str)
+    }
+
+    /// Forgets the handles of process `pid`, e.g. once it exited.
+    pub fn discard_process(&mut self, pid: u64) {
+        self.handles.retain(|(handle_pid, _), _| *handle_pid != pid);
+    }
+
+    /// Returns the statistics collected so far, by path.
+    pub fn files(&self) -> &HashMap<String, FileStats> {
+        &self.files
+    }
+
+    /// Returns the statistics collected so far and forgets them, e.g. to report them per interval. The handles are
+    /// kept.
+    pub fn take_files(&mut self) -> HashMap<String, FileStats> {
+        std::mem::take(&mut self.files)
+    }
+}
+
+impl RecordVisitor for FileIoTracker {
+    fn visit_probe(&mut self, _cpu: crate::processorid_t, probe: Option<&EnabledProbe>) {
+        let operation = probe.and_then(|probe| match (probe.probe.function.as_str(), probe.probe.name.as_str()) {
+            ("NtCreateFile" | "NtOpenFile", "return") => Some(Operation::Open),
+            ("NtReadFile", "entry") => Some(Operation::Read),
+            ("NtWriteFile", "entry") => Some(Operation::Write),
+            ("NtClose", "entry") => Some(Operation::Close),
+            _ => None,
+        });
+        self.firing = operation.map(|operation| (operation, Vec::new(), Vec::new()));
+    }
+
+    fn visit_int(&mut self, value: i64) {
+        if let Some((_, ints, _)) = &mut self.firing {
+            ints.push(value);
+        }
+    }
+
+    fn visit_string(&mut self, value: &str) {
+        if let Some((_, _, strings)) = &mut self.firing {
+            strings.push(value.to_string());
+        }
+    }
+
+    /// Decodes the bytes as a path copied from a `UNICODE_STRING` buffer, for programs recording the path without
+    /// converting it in D.
+    fn visit_bytes(&mut self, value: &[u8]) {
+        if let Some((_, _, strings)) = &mut self.firing {
+            let path = crate::strings::decode(value, StringEncoding::Utf16, StringPolicy::Lossy);
+            strings.extend(path.map(|path| path.to_string_lossy().into_owned()));
+        }
+    }
+
+    /// Accounts the firing if it recorded what [`FileIoTracker::PROGRAM`] does.
+    fn visit_end(&mut self) {
+        let (operation, ints, strings) = match self.firing.take() {
+            Some(firing) => firing,
+            None => return,
+        };
+        let ints: Vec<u64> = ints.into_iter().map(|value| value as u64).collect();
+        match (operation, &ints[..], &strings[..]) {
+            (Operation::Open, &[pid, handle, ..], [path, ..]) => self.open(pid, handle, path),
+            (Operation::Read, &[pid, handle, size, ..], _) => self.transfer(pid, handle, false, size),
+            (Operation::Write, &[pid, handle, size, ..], _) => self.transfer(pid, handle, true, size),
+            (Operation::Close, &[pid, handle, ..], _) => self.close(pid, handle),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Windows Job Objects, to tear down the process tree of a target when the tracing session ends, see
+//! `TargetCommand::kill_on_close`.
+//!
+//! A job created by [`JobObject::new`] kills the processes assigned to it, and the processes they create, once its
+//! last handle is closed. The handle is closed when the [`JobObject`] is dropped, and by Windows when the consumer
+//! exits, even if it crashes, so no instrumented process outlives the consumer.
+
+use crate::utils::Error;
+#[cfg(windows)]
+use crate::win32;
+use ::core::ffi::c_void;
+use std::collections::HashMap;
+
+/// A Job Object killing its processes when it is closed.
+pub struct JobObject {
+    #[cfg_attr(not(windows), allow(dead_code))]
+    handle: *mut c_void,
+    /// Completion port receiving the notifications of the job, see [`JobObject::poll`]
+    #[cfg_attr(not(windows), allow(dead_code))]
+    port: *mut c_void,
+}
+
+/// A notification of a Job Object about its processes, see [`JobObject::poll`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JobEvent {
+    /// A process was created in the job, or assigned to it
+    ProcessCreated(u32),
+    /// A process of the job exited, `abnormal` if it was terminated by an unhandled exception
+    ProcessExited { pid: u32, abnormal: bool },
+    /// The last process of the job exited
+    Empty,
+}
+
+// Job Object handles may be used and closed from any thread.
+unsafe impl Send for JobObject {}
+unsafe impl Sync for JobObject {}
+
+impl JobObject {
+    /// Creates an anonymous Job Object killing its processes when it is closed.
+    pub fn new() -> Result<Self, Error> {
+        #[cfg(windows)]
+        unsafe {
+            let handle = win32::CreateJobObjectW(std::ptr::null_mut(), std::ptr::null());
+            if handle.is_null() {
+                return Err(Error::from(std::io::Error::last_os_error()).with_context("creating job object"));
+            }
+            let port = win32::CreateIoCompletionPort(win32::INVALID_HANDLE_VALUE, std::ptr::null_mut(), 0, 1);
+            if port.is_null() {
+                let error = std::io::Error::last_os_error();
+                win32::CloseHandle(handle);
+                return Err(Error::from(error).with_context("creating job object completion port"));
+            }
+            let job = Self { handle, port };
+
+            let mut association = win32::JOBOBJECT_ASSOCIATE_COMPLETION_PORT {
+                CompletionKey: std::ptr::null_mut(),
+                CompletionPort: port,
+            };
+            let associated = win32::SetInformationJobObject(
+                job.handle,
+                win32::JOB_OBJECT_ASSOCIATE_COMPLETION_PORT_INFORMATION,
+                &mut association as *mut _ as *mut c_void,
+                std::mem::size_of::<win32::JOBOBJECT_ASSOCIATE_COMPLETION_PORT>() as u32,
+            );
+            if associated == 0 {
+                return Err(Error::from(std::io::Error::last_os_error()).with_context("associating job object port"));
+            }
+
+            let mut limits: win32::JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+            limits.BasicLimitInformation.LimitFlags = win32::JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            let set = win32::SetInformationJobObject(
+                job.handle,
+                win32::JOB_OBJECT_EXTENDED_LIMIT_INFORMATION,
+                &mut limits as *mut _ as *mut c_void,
+                std::mem::size_of::<win32::JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            );
+            if set == 0 {
+                return Err(Error::from(std::io::Error::last_os_error()).with_context("setting job object limits"));
+            }
+            Ok(job)
+        }
+        #[cfg(not(windows))]
+        Err(Error::new(-1, "Job objects are only available on Windows"))
+    }
+
+    /// Assigns the process `pid` to the job. The processes it creates from then on belong to the job as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The ID of the process, which must not belong to a job that forbids nesting.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the process was assigned, or the Windows error otherwise.
+    pub fn assign(&self, pid: u32) -> Result<(), Error> {
+        #[cfg(windows)]
+        unsafe {
+            let process = win32::OpenProcess(win32::PROCESS_SET_QUOTA | win32::PROCESS_TERMINATE, 0, pid);
+            if process.is_null() {
+                return Err(Error::from(std::io::Error::last_os_error()).with_context(format!("opening process {}", pid)));
+            }
+            let assigned = win32::AssignProcessToJobObject(self.handle, process);
+            let error = std::io::Error::last_os_error();
+            win32::CloseHandle(process);
+            if assigned == 0 {
+                return Err(Error::from(error).with_context(format!("assigning process {} to job object", pid)));
+            }
+            Ok(())
+        }
+        #[cfg(not(windows))]
+        Err(Error::new(-1, format!("Cannot assign process {} to a job object", pid)))
+    }
+
+    /// Returns the notifications of the job received since the last call, without waiting, in the order they occurred.
+    pub fn poll(&self) -> Vec<JobEvent> {
+        #[allow(unused_mut)]
+        let mut events = Vec::new();
+        #[cfg(windows)]
+        unsafe {
+            let mut message = 0u32;
+            let mut key = 0usize;
+            let mut pid: *mut c_void = std::ptr::null_mut();
+            while win32::GetQueuedCompletionStatus(self.port, &mut message, &mut key, &mut pid, 0) != 0 {
+                // The overlapped pointer of job notifications holds the ID of the process.
+                let pid = pid as usize as u32;
+                events.push(match message {
+                    win32::JOB_OBJECT_MSG_NEW_PROCESS => JobEvent::ProcessCreated(pid),
+                    win32::JOB_OBJECT_MSG_EXIT_PROCESS => JobEvent::ProcessExited { pid, abnormal: false },
+                    win32::JOB_OBJECT_MSG_ABNORMAL_EXIT_PROCESS => JobEvent::ProcessExited { pid, abnormal: true },
+                    win32::JOB_OBJECT_MSG_ACTIVE_PROCESS_ZERO => JobEvent::Empty,
+                    _ => continue,
+                });
+            }
+        }
+        events
+    }
+
+    /// Kills every process of the job now, with the exit code `code`.
+    pub fn terminate(&self, code: u32) -> Result<(), Error> {
+        #[cfg(windows)]
+        unsafe {
+            if win32::TerminateJobObject(self.handle, code) == 0 {
+                return Err(Error::from(std::io::Error::last_os_error()).with_context("terminating job object"));
+            }
+            Ok(())
+        }
+        #[cfg(not(windows))]
+        Err(Error::new(-1, format!("Cannot terminate a job object with exit code {}", code)))
+    }
+}
+
+impl Drop for JobObject {
+    fn drop(&mut self) {
+        #[cfg(windows)]
+        unsafe {
+            win32::CloseHandle(self.handle);
+            win32::CloseHandle(self.port);
+        }
+    }
+}
+
+/// Lists the running processes created by the current process, by ID, with the file name of their executable.
+///
+/// libdtrace does not report the ID of the processes it creates, which are found by comparing the children of the
+/// consumer before and after creating them.
+pub(crate) fn child_processes() -> Result<HashMap<u32, String>, Error> {
+    #[cfg(windows)]
+    unsafe {
+        let snapshot = win32::CreateToolhelp32Snapshot(win32::TH32CS_SNAPPROCESS, 0);
+        if snapshot == win32::INVALID_HANDLE_VALUE {
+            return Err(Error::from(std::io::Error::last_os_error()).with_context("listing processes"));
+        }
+        let current = win32::GetCurrentProcessId();
+        let mut children = HashMap::new();
+        let mut entry: win32::PROCESSENTRY32W = std::mem::zeroed();
+        entry.dwSize = std::mem::size_of::<win32::PROCESSENTRY32W>() as u32;
+        let mut found = win32::Process32FirstW(snapshot, &mut entry);
+        while found != 0 {
+            if entry.th32ParentProcessID == current {
+                let len = entry.szExeFile.iter().position(|&c| c == 0).unwrap_or(entry.szExeFile.len());
+                children.insert(entry.th32ProcessID, String::from_utf16_lossy(&entry.szExeFile[..len]));
+            }
+            found = win32::Process32NextW(snapshot, &mut entry);
+        }
+        win32::CloseHandle(snapshot);
+        Ok(children)
+    }
+    #[cfg(not(windows))]
+    Ok(HashMap::new())
+}
+
+/// Returns `true` if `exe`, the file name of the executable of a process, is the executable `file` a process was
+/// created with, which may omit the `.exe` extension.
+pub(crate) fn runs_file(exe: &str, file: &str) -> bool {
+    let name = file.rsplit(['\\', '/']).next().unwrap_or(file);
+    exe.eq_ignore_ascii_case(name) || exe.eq_ignore_ascii_case(&format!("{}.exe", name))
+}
@@ -0,0 +1,192 @@
+//! Pairing the entry and return of functions in the consumer to measure their latency, when measuring it in D with
+//! `self->ts` is awkward or the program cannot be changed.
+//!
+//! The [`LatencyCorrelator`] pairs the calls it is told about by process, thread and function, and collects the
+//! latencies of each function in a power-of-two histogram. As a [`RecordVisitor`] it pairs the firings of entry and
+//! return probes recording the process ID, thread ID and timestamp, like the clause of [`LatencyCorrelator::clause`].
+//!
+//! ```no_run
+//! use libdtrace_rs::latency::LatencyCorrelator;
+//! use libdtrace_rs::session::TraceSession;
+//! use std::sync::{Arc, Mutex};
+//!
+//! let latencies = Arc::new(Mutex::new(LatencyCorrelator::new()));
+//! let mut session = TraceSession::new(&LatencyCorrelator::clause("syscall::NtReadFile"))
+//!     .with_option("quiet", "")
+//!     .with_visitor(latencies.clone());
+//! session.start().unwrap();
+//! std::thread::sleep(std::time::Duration::from_secs(5));
+//! session.stop();
+//! for (function, stats) in latencies.lock().unwrap().latencies() {
+//!     println!("{}: {} calls, {:?} on average", function, stats.count, stats.mean());
+//! }
+//! ```
+
+use crate::aggregate::{Histogram, QUANTIZE_NBUCKETS, QUANTIZE_ZEROBUCKET};
+use crate::probe::EnabledProbe;
+use crate::visit::RecordVisitor;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// The latencies of the calls of a function, see [`LatencyCorrelator::latencies`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// Number of calls that returned
+    pub count: u64,
+    /// Sum of the latencies, in nanoseconds
+    pub total: u64,
+    /// Shortest latency, in nanoseconds
+    pub min: u64,
+    /// Longest latency, in nanoseconds
+    pub max: u64,
+    /// Number of latencies in each bucket, laid out like the data of `quantize()`
+    counts: Vec<i64>,
+}
+
+impl Default for LatencyStats {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            total: 0,
+            min: u64::MAX,
+            max: 0,
+            counts: vec![0; QUANTIZE_NBUCKETS],
+        }
+    }
+}
+
+impl LatencyStats {
+    /// Adds the latency `nanos` of a call.
+    fn add(&mut self, nanos: u64) {
+        self.count += 1;
+        self.total = self.total.saturating_add(nanos);
+        self.min = self.min.min(nanos);
+        self.max = self.max.max(nanos);
+        let bucket = match nanos {
+            0 => QUANTIZE_ZEROBUCKET,
+            _ => (QUANTIZE_ZEROBUCKET + 1 + nanos.ilog2() as usize).min(QUANTIZE_NBUCKETS - 1),
+        };
+        self.counts[bucket] += 1;
+    }
+
+    /// Returns the average latency.
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            count => Duration::from_nanos(self.total / count),
+        }
+    }
+
+    /// Returns the latencies in nanoseconds as a power-of-two histogram, like `quantize()`, e.g. to print it with
+    /// `render::histogram`.
+    pub fn histogram(&self) -> Histogram {
+        Histogram::quantize(&self.counts)
+    }
+}
+
+/// A call that entered a function and did not return yet.
+type CallKey = (u64, u64, String);
+
+/// Pairs the entries and returns of functions by process, thread and function, see the [module](self) docs.
+#[derive(Debug, Default)]
+pub struct LatencyCorrelator {
+    /// Entry timestamps of the calls in progress, innermost last for recursive calls
+    pending: HashMap<CallKey, Vec<u64>>,
+    latencies: HashMap<String, LatencyStats>,
+    /// Function, probe name and integers recorded by the firing being visited
+    firing: Option<(String, String, Vec<i64>)>,
+}
+
+impl LatencyCorrelator {
+    /// Creates a correlator without calls.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a D clause recording what the correlator needs on the entry and return probes of the functions
+    /// matching `description`, e.g. `syscall::NtReadFile` or `pid$target:ntdll.dll:Rtl*`.
+    pub fn clause(description: &str) -> String {
+        format!(
+            "{0}:entry, {0}:return {{ trace(pid); trace(tid); trace(timestamp); }}",
+            description
+        )
+    }
+
+    /// Records that thread `tid` of process `pid` entered `function` at `timestamp`, in nanoseconds.
+    pub fn enter(&mut self, pid: u64, tid: u64, function: &str, timestamp: u64) {
+        self.pending
+            .entry((pid, tid, function.to_string()))
+            .or_default()
+            .push(timestamp);
+    }
+
+    /// Records that thread `tid` of process `pid` returned from `function` at `timestamp`, in nanoseconds.
+    ///
+    /// # Returns
+    ///
+    /// Returns the latency of the call, or [`None`] if its entry was not seen, e.g. because it entered the function
+    /// before tracing started.
+    pub fn leave(&mut self, pid: u64, tid: u64, function: &str, timestamp: u64) -> Option<Duration> {
+        let key = (pid, tid, function.to_string());
+        let calls = self.pending.get_mut(&key)?;
+        let entered = calls.pop()?;
+        if calls.is_empty() {
+            self.pending.remove(&key);
+        }
+        let nanos = timestamp.saturating_sub(entered);
+        self.latencies.entry(key.2).or_default().add(nanos);
+        Some(Duration::from_nanos(nanos))
+    }
+
+    /// Forgets the calls in progress of the process `pid`, e.g. once it exited, as they never return.
+    pub fn discard_process(&mut self, pid: u64) {
+        self.pending.retain(|(call_pid, _, _), _| *call_pid != pid);
+    }
+
+    /// Returns the number of calls in progress.
+    pub fn pending(&self) -> usize {
+        self.pending.values().map(Vec::len).sum()
+    }
+
+    /// Returns the latencies collected so far, by function.
+    pub fn latencies(&self) -> &HashMap<String, LatencyStats> {
+        &self.latencies
+    }
+
+    /// Returns the latencies collected so far and forgets them, e.g. to report them per interval. Calls in progress
+    /// are kept.
+    pub fn take_latencies(&mut self) -> HashMap<String, LatencyStats> {
+        std::mem::take(&mut self.latencies)
+    }
+}
+
+impl RecordVisitor for LatencyCorrelator {
+    fn visit_probe(&mut self, _cpu: crate::processorid_t, probe: Option<&EnabledProbe>) {
+        self.firing = probe.map(|probe| (probe.probe.function.clone(), probe.probe.name.clone(), Vec::new()));
+    }
+
+    fn visit_int(&mut self, value: i64) {
+        if let Some((_, _, values)) = &mut self.firing {
+            values.push(value);
+        }
+    }
+
+    /// Pairs the firing if its first three records are the process ID, thread ID and timestamp.
+    fn visit_end(&mut self) {
+        let (function, name, values) = match self.firing.take() {
+            Some(firing) => firing,
+            None => return,
+        };
+        let (pid, tid, timestamp) = match values[..] {
+            [pid, tid, timestamp, ..] => (pid as u64, tid as u64, timestamp as u64),
+            _ => return,
+        };
+        match name.as_str() {
+            "entry" => self.enter(pid, tid, &function, timestamp),
+            "return" => {
+                self.leave(pid, tid, &function, timestamp);
+            }
+            _ => {}
+        }
+    }
+}
@@ -1,17 +1,71 @@
 #![allow(non_upper_case_globals)]
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
+#[cfg(not(feature = "stub"))]
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+#[cfg(feature = "stub")]
+mod stub;
+#[cfg(feature = "stub")]
+pub use stub::*;
 pub mod callbacks;
 pub mod wrapper;
 pub mod utils;
+pub mod output;
 pub mod types;
+pub mod aggregate;
+pub mod render;
+pub mod probe;
+pub mod consumer;
+pub mod handler;
+pub mod diagnostics;
+pub mod session;
+pub mod config;
+pub mod perf;
+pub mod target;
+pub mod pid;
+pub mod job;
+#[cfg(windows)]
+mod win32;
+pub mod clock;
+pub mod compile;
+pub mod open;
+pub mod script;
+pub mod symbol;
+pub mod strings;
+pub mod anonymous;
+pub mod typeinfo;
+pub mod typed;
+pub mod statement;
+pub mod visit;
+pub mod bundle;
+pub mod latency;
+pub mod names;
+pub mod cpu;
+pub mod filter;
+pub mod bus;
+pub mod raw;
+pub mod toolkit;
+pub mod process;
+pub mod io;
+pub mod net;
+pub mod decoders;
+pub mod eventlog;
+pub mod elevation;
+pub mod options;
+#[cfg(feature = "codegen")]
+pub mod codegen;
+#[cfg(feature = "scripts")]
+pub mod scripts;
+pub mod prelude;
+#[cfg(feature = "tui")]
+pub mod tui;
 
 #[cfg(test)]
 mod tests {
     use crate::*;
     use wrapper::dtrace_hdl;
     #[test]
+    #[cfg_attr(feature = "stub", ignore = "needs libdtrace")]
     fn dtrace_get_handle() {
         let handle = dtrace_hdl::dtrace_open(DTRACE_VERSION as i32, 0);
         match handle {
@@ -23,8 +77,9 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(feature = "stub", ignore = "needs libdtrace")]
     fn dtrace_set_option() {
-        let handle = dtrace_hdl::dtrace_open(DTRACE_VERSION as i32, 0).unwrap();
+        let mut handle = dtrace_hdl::dtrace_open(DTRACE_VERSION as i32, 0).unwrap();
         let status = handle.dtrace_setopt("bufsize", "4m");
         match status {
             Ok(_) => {}
@@ -35,6 +90,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(feature = "stub", ignore = "needs libdtrace")]
     fn dtrace_handle_buffered() {
         let handle = dtrace_hdl::dtrace_open(DTRACE_VERSION as i32, 0).unwrap();
         let status = handle.dtrace_register_handler(crate::types::dtrace_handler::Buffered(Some(callbacks::buffered)), None);
@@ -47,12 +103,13 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(feature = "stub", ignore = "needs libdtrace")]
     fn dtrace_compile_and_exec() {
         let handle = dtrace_hdl::dtrace_open(DTRACE_VERSION as i32, 0).unwrap();
         let prog = handle
                     .dtrace_program_strcompile(
                         "dtrace:::BEGIN {trace(\"Hello World\");} syscall:::entry { @num[execname] = count(); }", 
-                        dtrace_probespec::DTRACE_PROBESPEC_NAME, 
+                        types::ProbeSpec::Name, 
                         DTRACE_C_ZDEFS,
                         None);
         match prog {
@@ -68,6 +125,1257 @@ mod tests {
             Err(error) => {
                 panic!("{}", error);
             }
-        }                        
+        }
+    }
+
+    #[test]
+    #[cfg_attr(feature = "stub", ignore = "needs libdtrace")]
+    fn dtrace_concurrent_handles() {
+        let threads: Vec<_> = ["1m", "2m"]
+            .into_iter()
+            .map(|bufsize| {
+                std::thread::spawn(move || {
+                    let mut handle = dtrace_hdl::dtrace_open(DTRACE_VERSION as i32, 0).unwrap();
+                    handle.dtrace_setopt("bufsize", bufsize).unwrap();
+                    for _ in 0..10 {
+                        handle
+                            .compile(
+                                "syscall::NtClose:entry { @[execname] = count(); }",
+                                &compile::CompileOptions::new().allow_zero_matches(),
+                            )
+                            .unwrap();
+                    }
+                    handle.dtrace_getopt("bufsize").unwrap()
+                })
+            })
+            .collect();
+        let bufsizes: Vec<_> = threads.into_iter().map(|thread| thread.join().unwrap()).collect();
+        assert_eq!(bufsizes, [1 << 20, 2 << 20]);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "stub", ignore = "needs libdtrace")]
+    fn dtrace_compile_task() {
+        use compile::{CompileOptions, CompileTask};
+
+        let mut handle = std::sync::Arc::new(dtrace_hdl::dtrace_open(DTRACE_VERSION as i32, 0).unwrap());
+        let other = std::sync::Arc::new(dtrace_hdl::dtrace_open(DTRACE_VERSION as i32, 0).unwrap());
+        let options = CompileOptions::new().allow_zero_matches();
+        let program = "syscall::NtClose:entry { @[execname] = count(); }";
+
+        let task = CompileTask::spawn(&handle, program, &options).unwrap();
+        let mut prog = task.wait(&handle).unwrap();
+        handle.dtrace_program_exec(&mut prog, None).unwrap();
+        // The task and its thread released the handle, so tracing can start on it.
+        let tracing = std::sync::Arc::get_mut(&mut handle).unwrap();
+        tracing.dtrace_go().unwrap();
+        tracing.dtrace_stop().unwrap();
+
+        let task = CompileTask::spawn(&handle, "syscall::NtClose:entry {", &options).unwrap();
+        assert!(task.wait(&handle).err().unwrap().is_compile_error());
+
+        let task = CompileTask::spawn(&handle, program, &options).unwrap();
+        assert!(task.wait(&other).is_err());
+
+        let task = CompileTask::spawn(&handle, program, &options)
+            .unwrap()
+            .with_timeout(std::time::Duration::ZERO);
+        assert!(task.is_finished());
+        task.cancel();
+    }
+
+    #[test]
+    #[cfg_attr(feature = "stub", ignore = "needs libdtrace")]
+    fn dtrace_snapshot_aggregations() {
+        let mut handle = dtrace_hdl::dtrace_open(DTRACE_VERSION as i32, 0).unwrap();
+        let prog = handle
+            .compile("dtrace:::BEGIN { @a = count(); @b = sum(2); }", &compile::CompileOptions::new())
+            .unwrap();
+        handle.dtrace_program_exec(prog, None).unwrap();
+        handle.dtrace_go().unwrap();
+        handle.dtrace_sleep();
+        let snapshot = handle.snapshot_aggregations().unwrap().into_map();
+        handle.dtrace_stop().unwrap();
+        assert_eq!(snapshot["a"][&aggregate::AggKey::default()].as_i64(), 1);
+        assert_eq!(snapshot["b"][&aggregate::AggKey::default()].as_i64(), 2);
+    }
+
+    #[test]
+    fn raw_aggregate_records() {
+        use aggregate::{AggValue, KeyItem, RawAggregate};
+        use probe::RecordDescription;
+
+        let record = |action: u32, offset: u32, size: u32| RecordDescription {
+            action: action as dtrace_actkind_t,
+            size,
+            offset,
+            alignment: 8,
+            format: 0,
+            arg: 0,
+        };
+        // @bytes[execname, pid] = sum(...): the aggregation ID, a 16-byte string, a pid and the sum.
+        let description = aggregate::AggregationDescription {
+            id: 1,
+            varid: 2,
+            epid: 3,
+            name: "bytes".to_string(),
+            keys: vec![record(DTRACEACT_DIFEXPR, 8, 16), record(DTRACEACT_DIFEXPR, 24, 4)],
+            value: record(DTRACEAGG_SUM, 32, 8),
+        };
+        let mut data = vec![0u8; 40];
+        data[8..15].copy_from_slice(b"cmd.exe");
+        data[24..28].copy_from_slice(&42i32.to_ne_bytes());
+        data[32..40].copy_from_slice(&4096i64.to_ne_bytes());
+
+        let raw = RawAggregate::new(&description, &data, 0);
+        assert_eq!(raw.normal(), 1);
+        let keys: Vec<&[u8]> = raw.keys().map(|(_, bytes)| bytes).collect();
+        assert_eq!(keys, [&data[8..24], &data[24..28]]);
+        assert_eq!(raw.value(), &4096i64.to_ne_bytes());
+        let entry = raw.decode();
+        assert_eq!(entry.name, "bytes");
+        assert_eq!(entry.key.0, [KeyItem::Str("cmd.exe".to_string()), KeyItem::Int(42)]);
+        assert_eq!(entry.value, AggValue::Sum(4096));
+
+        let truncated = RawAggregate::new(&description, &data[..30], 1);
+        assert!(truncated.value().is_empty());
+        assert_eq!(truncated.decode().key.0[1], KeyItem::Int(42));
+        assert_eq!(truncated.decode().value, AggValue::Sum(0));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "stub", ignore = "needs libdtrace")]
+    fn session_flushes_on_shutdown() {
+        let mut session = session::TraceSession::new(
+            "BEGIN { @calls[\"begin\"] = count(); exit(0); } END { printf(\"end\\n\"); }",
+        )
+        .with_option("quiet", "")
+        .with_flush_timeout(std::time::Duration::from_secs(10));
+        session.start().unwrap();
+        let mut output = String::new();
+        while let Some(event) = session.recv() {
+            match event {
+                session::Event::Output(text) => output.push_str(&text),
+                session::Event::Stopped(reason) => {
+                    assert!(matches!(reason, session::StopReason::Done));
+                    break;
+                }
+                _ => {}
+            }
+        }
+        assert!(output.contains("end\n"));
+        assert!(output.contains("begin"));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "stub", ignore = "needs libdtrace")]
+    fn session_pause_reporting() {
+        use std::time::Duration;
+
+        let mut session = session::TraceSession::new("profile-97 { printf(\"tick\\n\"); }").with_option("quiet", "");
+        session.start().unwrap();
+        let ticked = |session: &session::TraceSession| {
+            std::iter::from_fn(|| session.recv_timeout(Duration::from_millis(500)))
+                .take(10)
+                .any(|event| matches!(event, session::Event::Output(text) if text.contains("tick")))
+        };
+        assert!(ticked(&session));
+
+        session.pause_reporting();
+        assert!(session.is_reporting_paused());
+        // Output consumed before the pause took effect is still delivered.
+        std::thread::sleep(Duration::from_millis(500));
+        while session.try_recv().is_some() {}
+        assert!(session.recv_timeout(Duration::from_secs(1)).is_none());
+        assert!(session.is_running());
+
+        session.resume_reporting();
+        assert!(ticked(&session));
+        session.stop();
+    }
+
+    #[test]
+    fn consumption_stats() {
+        use std::time::Duration;
+
+        let table = consumer::ConsumptionTable::default();
+        assert!(table.begin().is_none());
+        table.set_enabled(true);
+        let start = table.begin().unwrap();
+        table.end(start, Some(Duration::from_secs(1)));
+        let stats = table.snapshot();
+        assert_eq!(stats.passes, 1);
+        assert!(stats.last_interval.is_none() && stats.mean_interval().is_none() && stats.lag().is_none());
+
+        let stats = consumer::ConsumptionStats {
+            passes: 3,
+            last_duration: Duration::from_millis(10),
+            total_duration: Duration::from_millis(30),
+            total_bytes: 3000,
+            last_interval: Some(Duration::from_millis(1500)),
+            total_interval: Duration::from_secs(3),
+            switch_interval: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        assert_eq!(stats.mean_duration(), Some(Duration::from_millis(10)));
+        assert_eq!(stats.mean_bytes(), Some(1000));
+        assert_eq!(stats.lag(), Some(1.5));
+        assert!(stats.is_lagging());
+        assert!(!consumer::ConsumptionStats { last_interval: None, ..stats }.is_lagging());
+
+        table.set_enabled(false);
+        assert_eq!(table.snapshot(), consumer::ConsumptionStats::default());
+    }
+
+    #[test]
+    fn probe_specs() {
+        use types::ProbeSpec;
+
+        assert_eq!(compile::CompileOptions::new().spec, ProbeSpec::Name);
+        for spec in [ProbeSpec::Provider, ProbeSpec::Module, ProbeSpec::Function, ProbeSpec::Name] {
+            assert_eq!(ProbeSpec::try_from(dtrace_probespec::from(spec)).unwrap(), spec);
+        }
+        assert!(ProbeSpec::try_from(dtrace_probespec::DTRACE_PROBESPEC_NONE).is_err());
+    }
+
+    #[test]
+    fn option_values() {
+        use options::*;
+        use std::time::Duration;
+
+        assert_eq!(parse_size("4k").unwrap(), 4096);
+        assert_eq!(parse_size("2G").unwrap(), 2 << 30);
+        assert_eq!(parse_size("0x100").unwrap(), 256);
+        assert_eq!(parse_size("1000").unwrap(), 1000);
+        assert!(parse_size("4kb").is_err() && parse_size("").is_err() && parse_size("m").is_err());
+        for size in ["4k", "64m", "1000", "3t", "0"] {
+            assert_eq!(format_size(parse_size(size).unwrap()), size);
+        }
+
+        assert_eq!(parse_time("10ms").unwrap(), Duration::from_millis(10));
+        assert_eq!(parse_time("2MIN").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse_time("500").unwrap(), Duration::from_nanos(500));
+        assert!(parse_time("10hz").is_err());
+        assert_eq!(format_time(Duration::from_secs(7200)), "2h");
+        assert_eq!(format_time(Duration::from_micros(1500)), "1500us");
+
+        assert_eq!(parse_rate("10").unwrap(), Duration::from_millis(100));
+        assert_eq!(parse_rate("997hz").unwrap(), Duration::from_nanos(1_003_009));
+        assert_eq!(parse_rate("1s").unwrap(), Duration::from_secs(1));
+        assert!(parse_rate("0hz").is_err() && parse_rate("fast").is_err());
+        for rate in ["997hz", "100ms", "1s", "3ns"] {
+            assert_eq!(parse_rate(&format_rate(parse_rate(rate).unwrap())).unwrap(), parse_rate(rate).unwrap());
+        }
+        assert_eq!(format_rate(parse_rate("997hz").unwrap()), "997hz");
+        assert_eq!(format_rate(parse_rate("10hz").unwrap()), "100ms");
+    }
+
+    #[test]
+    fn compile_error_rendering() {
+        use compile::CompileError;
+
+        let source = "BEGIN\n{\n\ttrace(arg0 arg1);\n}";
+        let error = CompileError::new("line 3: syntax error near \"arg1\"");
+        assert_eq!((error.line, error.near()), (Some(3), Some("arg1")));
+        assert_eq!(error.to_string(), "line 3: syntax error near \"arg1\"");
+        assert_eq!(
+            error.render(source),
+            "error: syntax error near \"arg1\"\n --> line 3\n  |\n3 | \ttrace(arg0 arg1);\n  | \t           ^^^^"
+        );
+
+        let error = CompileError::new("[translator] probes.d, line 2: undefined function");
+        assert_eq!((error.file.as_deref(), error.line), (Some("probes.d"), Some(2)));
+        assert!(error.render(source).ends_with("2 | {\n  | ^"));
+
+        let error = CompileError::new("probe description syscall::NtNope:entry does not match any probes");
+        assert_eq!(error.line, None);
+        assert_eq!(error.render(source), "error: probe description syscall::NtNope:entry does not match any probes");
+        assert_eq!(utils::Error::new(EDT_COMPILER as i32, "line 9: oops").compile_error().unwrap().line, Some(9));
+        assert!(utils::Error::new(-1, "line 9: oops").compile_error().is_none());
+    }
+
+    #[test]
+    fn target_executables() {
+        assert!(job::runs_file("notepad.exe", r"C:\Windows\notepad.exe"));
+        assert!(job::runs_file("Notepad.exe", "notepad"));
+        assert!(!job::runs_file("notepad.exe", "cmd.exe"));
+    }
+
+    #[test]
+    fn named_pipe_paths() {
+        assert_eq!(output::pipe_path("trace"), r"\\.\pipe\trace");
+        assert_eq!(output::pipe_path(r"\\server\pipe\trace"), r"\\server\pipe\trace");
+        #[cfg(not(windows))]
+        assert!(output::NamedPipe::create("trace").is_err());
+    }
+
+    #[test]
+    fn open_builder_flags() {
+        let builder = open::OpenBuilder::new().no_device().force_64bit();
+        assert_eq!(builder.flags, DTRACE_O_NODEV | DTRACE_O_LP64);
+        assert!(builder.validate().is_ok());
+        assert!(builder.force_32bit().validate().is_err());
+        assert!(open::OpenBuilder::new().flag(1 << 8).open().is_err());
+    }
+
+    #[test]
+    fn probe_catalog_formats() {
+        let attributes = probe::Attributes {
+            name: "Evolving".to_string(),
+            data: "Evolving".to_string(),
+            class: "Common".to_string(),
+        };
+        let entry = probe::CatalogEntry {
+            listing: probe::ListedProbe {
+                probe: probe::ProbeDescription {
+                    id: 7,
+                    provider: "syscall".to_string(),
+                    module: String::new(),
+                    function: "Nt\"Close,".to_string(),
+                    name: "entry".to_string(),
+                },
+                attributes: attributes.clone(),
+                arg_attributes: attributes,
+            },
+            args: vec!["HANDLE".to_string(), String::new()],
+        };
+
+        let mut json = Vec::new();
+        entry.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.starts_with("{\"id\":7,\"provider\":\"syscall\",\"module\":\"\",\"function\":\"Nt\\\"Close,\""));
+        assert!(json.ends_with("\"args\":[\"HANDLE\",\"\"]}"));
+
+        let mut csv = Vec::new();
+        entry.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "7,syscall,,\"Nt\"\"Close,\",entry,Evolving,Evolving,Common,Evolving,Evolving,Common,HANDLE;"
+        );
+    }
+
+    #[test]
+    fn visit_records() {
+        #[derive(Default)]
+        struct Collect(Vec<String>);
+        impl visit::RecordVisitor for Collect {
+            fn visit_int(&mut self, value: i64) {
+                self.0.push(format!("int {}", value));
+            }
+            fn visit_string(&mut self, value: &str) {
+                self.0.push(format!("string {}", value));
+            }
+            fn visit_stack(&mut self, frames: &[u64], pid: Option<pid_t>) {
+                self.0.push(format!("stack {:?} {:?}", frames, pid));
+            }
+            fn visit_exit(&mut self, status: i32) {
+                self.0.push(format!("exit {}", status));
+            }
+            fn visit_end(&mut self) {
+                self.0.push("end".to_string());
+            }
+            fn visit_unknown(&mut self, action: dtrace_actkind_t, bytes: &[u8]) {
+                self.0.push(format!("unknown {} {}", action, bytes.len()));
+            }
+        }
+
+        let mut buffer = [0u8; 56];
+        buffer[0..4].copy_from_slice(&(-5i32).to_ne_bytes());
+        buffer[8..12].copy_from_slice(b"cmd\0");
+        for (i, word) in [42u64, 0x10, 0x20].iter().enumerate() {
+            buffer[24 + i * 8..32 + i * 8].copy_from_slice(&word.to_ne_bytes());
+        }
+        buffer[48..52].copy_from_slice(&3i32.to_ne_bytes());
+        let record = |action: u32, offset: u32, size: u32, arg: u64| {
+            let mut rec: dtrace_recdesc_t = unsafe { std::mem::zeroed() };
+            rec.dtrd_action = action as dtrace_actkind_t;
+            rec.dtrd_offset = offset;
+            rec.dtrd_size = size;
+            rec.dtrd_arg = arg;
+            rec
+        };
+        let records = [
+            record(DTRACEACT_DIFEXPR, 0, 4, 0),
+            record(DTRACEACT_DIFEXPR, 8, 16, 0),
+            record(DTRACEACT_USTACK, 24, 24, 2),
+            record(DTRACEACT_EXIT, 48, 4, 0),
+            record(0x7f00, 52, 4, 0),
+        ];
+        let mut data: dtrace_probedata_t = unsafe { std::mem::zeroed() };
+
+        let mut visitor = Collect::default();
+        let mut ctx = visit::VisitContext {
+            visitor: &mut visitor,
+            handle: std::ptr::null_mut(),
+            epids: Default::default(),
+            decoders: Default::default(),
+            output: Default::default(),
+            error: None,
+        };
+        let arg = &mut ctx as *mut visit::VisitContext as *mut ::core::ffi::c_void;
+        for rec in records.iter().map(|rec| rec as *const dtrace_recdesc_t).chain([std::ptr::null()]) {
+            // libdtrace points the data of the firing at the record it passes.
+            if let Some(rec) = unsafe { rec.as_ref() } {
+                data.dtpda_data = buffer[rec.dtrd_offset as usize..].as_mut_ptr() as caddr_t;
+            }
+            unsafe { visit::visit_rec(&data, rec, arg) };
+        }
+        assert_eq!(
+            visitor.0,
+            ["int -5", "string cmd", "stack [16, 32] Some(42)", "exit 3", "unknown 32512 4", "end"]
+        );
+    }
+
+    #[test]
+    fn custom_decoders() {
+        use aggregate::{AggValue, AggregateEntry, KeyItem};
+        use typed::TypedValue;
+
+        #[derive(Default)]
+        struct Collect(Vec<String>);
+        impl visit::RecordVisitor for Collect {
+            fn visit_int(&mut self, value: i64) {
+                self.0.push(format!("int {}", value));
+            }
+            fn visit_string(&mut self, value: &str) {
+                self.0.push(format!("string {}", value));
+            }
+            fn visit_aggregate(&mut self, entry: &AggregateEntry) {
+                self.0.push(format!("aggregate {}", entry.name));
+            }
+            fn visit_decoded(&mut self, action: dtrace_actkind_t, value: &TypedValue) {
+                self.0.push(format!("decoded {} {}", action, value));
+            }
+        }
+
+        let registry = decoders::DecoderRegistry::new()
+            .on_action(
+                DTRACEACT_DIFEXPR as dtrace_actkind_t,
+                decoders::string_record(|text| text.strip_prefix("json:").map(|json| TypedValue::Str(json.into()))),
+            )
+            .on_action(0x7f00, |bytes| Some(TypedValue::UInt(bytes.len() as u64)))
+            .on_aggregation("requests", |entry| match entry.key.0.first() {
+                Some(KeyItem::Int(value)) => Some(TypedValue::Int(*value)),
+                _ => None,
+            });
+
+        let mut buffer = [0u8; 48];
+        buffer[0..4].copy_from_slice(&7i32.to_ne_bytes());
+        buffer[8..20].copy_from_slice(b"json:{\"a\":1}");
+        buffer[24..27].copy_from_slice(b"cmd");
+        let record = |action: u32, offset: u32, size: u32| {
+            let mut rec: dtrace_recdesc_t = unsafe { std::mem::zeroed() };
+            rec.dtrd_action = action as dtrace_actkind_t;
+            rec.dtrd_offset = offset;
+            rec.dtrd_size = size;
+            rec
+        };
+        let records = [
+            record(DTRACEACT_DIFEXPR, 0, 4),
+            record(DTRACEACT_DIFEXPR, 8, 16),
+            record(DTRACEACT_DIFEXPR, 24, 16),
+            record(0x7f00, 40, 4),
+        ];
+        let mut data: dtrace_probedata_t = unsafe { std::mem::zeroed() };
+
+        let mut visitor = Collect::default();
+        let mut ctx = visit::VisitContext {
+            visitor: &mut visitor,
+            handle: std::ptr::null_mut(),
+            epids: Default::default(),
+            decoders: std::sync::Arc::new(registry),
+            output: Default::default(),
+            error: None,
+        };
+        let arg = &mut ctx as *mut visit::VisitContext as *mut ::core::ffi::c_void;
+        for rec in &records {
+            data.dtpda_data = buffer[rec.dtrd_offset as usize..].as_mut_ptr() as caddr_t;
+            unsafe { visit::visit_rec(&data, rec, arg) };
+        }
+        let entry = |name: &str, key: i64| AggregateEntry {
+            name: name.to_string(),
+            varid: 1,
+            key: key.into(),
+            value: AggValue::Count(1),
+            normal: 1,
+        };
+        let decoders = ctx.decoders.clone();
+        assert_eq!(decoders.decode_aggregate(&entry("requests", 5)), Some(TypedValue::Int(5)));
+        assert_eq!(decoders.decode_aggregate(&entry("other", 5)), None);
+        assert_eq!(visitor.0, ["int 7", "decoded 1 \"{\\\"a\\\":1}\"", "string cmd", "decoded 32512 0x4"]);
+    }
+
+    #[test]
+    fn error_messages() {
+        let error = utils::Error::from(-1);
+        assert_eq!(error.errno(), -1);
+        assert_eq!(error.message(), wrapper::dtrace_hdl::errno_message(-1));
+        assert_eq!(error.to_string(), format!("Error: {}", error.message()));
+
+        let error = utils::Error::new(-1, "no probes").with_context("compiling");
+        assert_eq!(error.to_string(), "Error: compiling: no probes");
+
+        assert_eq!(utils::excerpt(&"é".repeat(60)), "é".repeat(60));
+        assert_eq!(utils::excerpt(&"é".repeat(61)), format!("{}...", "é".repeat(60)));
+        assert_eq!(utils::excerpt("BEGIN\n{ exit(0); }"), "BEGIN...");
+    }
+
+    #[test]
+    fn anonymous_dof_values() {
+        let query = "\r\nHKEY_LOCAL_MACHINE\\SYSTEM\\CurrentControlSet\\Services\\dtrace\\Parameters\r\n    \
+                     dof-data-0    REG_BINARY    0102\r\n    dof-data-2    REG_BINARY    03\r\n    \
+                     dof-data-x    REG_SZ    x\r\n    Start    REG_DWORD    0x1\r\n\r\n";
+        assert_eq!(anonymous::dof_values(query), ["dof-data-0", "dof-data-2"]);
+    }
+
+    #[test]
+    fn event_bus_lag() {
+        use std::sync::Arc;
+
+        let bus = bus::EventBus::new();
+        let fast = bus.subscribe(8);
+        let slow = bus.subscribe(2);
+        for event in 0..4 {
+            bus.publish(Arc::new(event));
+        }
+        drop(bus.subscribe(1));
+        assert_eq!(bus.subscriber_count(), 2);
+        bus.close();
+
+        assert_eq!(fast.map(|event| *event).collect::<Vec<_>>(), [0, 1, 2, 3]);
+        assert_eq!(slow.lagged(), 2);
+        assert_eq!(slow.map(|event| *event).collect::<Vec<_>>(), [2, 3]);
+
+        let mut late = bus.subscribe(8);
+        assert!(late.next().is_none());
+        bus.reopen();
+        let reopened = bus.subscribe(8);
+        bus.publish(Arc::new(4));
+        assert_eq!(reopened.try_recv().as_deref(), Some(&4));
+    }
+
+    #[test]
+    fn raw_consumer() {
+        use raw::RawAction;
+
+        // The record follows another one, and libdtrace points the data of the firing at it.
+        let mut buffer = [0u8; 16];
+        buffer[8..].copy_from_slice(&7u64.to_ne_bytes());
+        let mut data: dtrace_probedata_t = unsafe { std::mem::zeroed() };
+        data.dtpda_data = buffer[8..].as_mut_ptr() as caddr_t;
+        let mut rec: dtrace_recdesc_t = unsafe { std::mem::zeroed() };
+        rec.dtrd_offset = 8;
+        rec.dtrd_size = 8;
+
+        let mut records = Vec::new();
+        let mut consumer = |data: &dtrace_probedata_t, rec: Option<&dtrace_recdesc_t>| match rec {
+            Some(rec) => {
+                records.push(unsafe { raw::record_bytes(data, rec) }.to_vec());
+                RawAction::Next
+            }
+            None => RawAction::Abort,
+        };
+        let (probe, record, arg) = raw::callbacks(&mut consumer);
+        unsafe {
+            assert_eq!(probe.unwrap()(&data, arg), DTRACE_CONSUME_THIS as i32);
+            assert_eq!(record.unwrap()(&data, &rec, arg), DTRACE_CONSUME_NEXT as i32);
+            assert_eq!(record.unwrap()(&data, std::ptr::null(), arg), DTRACE_CONSUME_ABORT as i32);
+        }
+        assert_eq!(records, [7u64.to_ne_bytes().to_vec()]);
+    }
+
+    #[test]
+    fn printa_chunks() {
+        use aggregate::{ChunkKind, PrintaChunk};
+
+        assert_eq!(PrintaChunk::kind_of(DTRACE_BUFDATA_AGGKEY), ChunkKind::Key);
+        assert_eq!(PrintaChunk::kind_of(DTRACE_BUFDATA_AGGVAL | DTRACE_BUFDATA_AGGLAST), ChunkKind::Value);
+        assert_eq!(PrintaChunk::kind_of(DTRACE_BUFDATA_AGGFORMAT), ChunkKind::Format);
+        assert_eq!(PrintaChunk::kind_of(0), ChunkKind::Other);
+
+        let descriptions = Default::default();
+        let mut chunks = Vec::new();
+        let mut callback = |chunk: &PrintaChunk| chunks.push((chunk.text.clone(), chunk.kind, chunk.entry.is_some()));
+        let mut ctx = aggregate::PrintaContext {
+            descriptions: &descriptions,
+            callback: &mut callback,
+            entry: None,
+        };
+        let mut bufdata: dtrace_bufdata_t = unsafe { std::mem::zeroed() };
+        bufdata.dtbda_buffered = c"\n".as_ptr();
+        // Without an entry, the flags are not trusted.
+        bufdata.dtbda_flags = DTRACE_BUFDATA_AGGFORMAT;
+        let arg = &mut ctx as *mut aggregate::PrintaContext as *mut ::core::ffi::c_void;
+        assert_eq!(unsafe { aggregate::printa_chunk(&bufdata, arg) }, DTRACE_HANDLE_OK as i32);
+        assert_eq!(chunks, [("\n".to_string(), ChunkKind::Other, false)]);
+    }
+
+    #[test]
+    fn toolkit_loading() {
+        use toolkit::Toolkit;
+
+        let dir = std::env::temp_dir().join(format!("toolkit-loading-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        let manifest = "# I/O\nname = iosnoop\nversion = 1.2\nauthor = someone\ncpp = true\n\n[options]\nquiet =\n";
+        std::fs::write(dir.join(toolkit::MANIFEST), manifest).unwrap();
+        std::fs::write(dir.join("main.d"), "syscall::NtReadFile:entry { trace(pid); }").unwrap();
+        std::fs::write(dir.join("lib/io.d"), "inline int IO_READ = 1;").unwrap();
+
+        let toolkit = Toolkit::load(&dir).unwrap();
+        assert_eq!(toolkit.metadata().name, "iosnoop");
+        assert_eq!(toolkit.metadata().version, "1.2");
+        assert_eq!(toolkit.metadata().extra["author"], "someone");
+        assert_eq!(toolkit.options(), [("quiet".to_string(), String::new())]);
+        assert_eq!(toolkit.libdir(), Some(dir.join("lib").as_path()));
+        assert!(toolkit.include_dirs().is_empty());
+        assert_eq!(toolkit.compile_options().flags, DTRACE_C_CPP);
+        assert!(toolkit.source().unwrap().contains("NtReadFile"));
+
+        std::fs::write(dir.join(toolkit::MANIFEST), "main = ../main.d\n").unwrap();
+        assert!(Toolkit::load(&dir).is_err());
+
+        let mut bundle = bundle::SupportBundle::new();
+        bundle.add("iosnoop/toolkit.conf", "description = Traces reads\n");
+        bundle.add("iosnoop/main.d", "syscall::NtReadFile:entry { trace(pid); }");
+        let archive = dir.join("reads.tar");
+        bundle.write(&archive).unwrap();
+        let toolkit = Toolkit::load(&archive).unwrap();
+        assert_eq!(toolkit.metadata().name, "iosnoop");
+        assert_eq!(toolkit.metadata().description, "Traces reads");
+        let extracted = toolkit.root().to_path_buf();
+        assert!(extracted.join("main.d").is_file());
+        drop(toolkit);
+        assert!(!extracted.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "scripts")]
+    fn script_builders() {
+        use scripts::{CpuProfile, ExecSnoop, FileIoLatency, SyscallCounts};
+
+        let counts = SyscallCounts::new().syscall("Nt*File").total().build();
+        assert_eq!(counts.source, "syscall::Nt*File:entry { @calls[probefunc] = count(); }");
+        let counts = SyscallCounts::new().process("note\"pad").pid(4).build();
+        assert!(counts.source.contains("/execname == \"note\\\"pad\" && pid == 4/"));
+
+        let latency = FileIoLatency::new().pid(4).min_latency(std::time::Duration::from_micros(1)).build();
+        let clauses = script::clauses(&latency.source);
+        assert_eq!(clauses.len(), 2);
+        assert!(clauses[0].header.ends_with("/pid == 4/"));
+        assert!(clauses[1].header.ends_with("/(self->ts && timestamp - self->ts >= 1000) && (pid == 4)/"));
+
+        let snoop = ExecSnoop::new().parent("explorer.exe").build();
+        assert_eq!(snoop.options, [("quiet".to_string(), String::new())]);
+        assert_eq!(script::clauses(&snoop.source)[0].descriptions, 1);
+
+        let filter = script::ProcessFilter::new().pid(4).tid(8);
+        let counts = SyscallCounts::new().process("ignored.exe").filter(filter.clone()).build();
+        assert_eq!(counts.source, "syscall:::entry /pid == 4 && tid == 8/ { @calls[execname, probefunc] = count(); }");
+        assert!(script::ProcessFilter::new().is_empty());
+
+        let profile = CpuProfile::new().rate(99).kernel().tid(8).build();
+        assert_eq!(profile.source, "profile-99 /(arg0) && (tid == 8)/ { @samples[execname, stack()] = count(); }");
+        assert_eq!(CpuProfile::new().build().source, scripts::CPU_PROFILE);
+    }
+
+    #[test]
+    #[cfg(feature = "scripts")]
+    #[cfg_attr(feature = "stub", ignore = "needs libdtrace")]
+    fn dtrace_compile_scripts() {
+        let handle = dtrace_hdl::dtrace_open(DTRACE_VERSION as i32, 0).unwrap();
+        let options = compile::CompileOptions::new().allow_zero_matches();
+        for script in [scripts::SYSCALL_COUNTS, scripts::FILE_IO_LATENCY, scripts::CPU_PROFILE] {
+            handle.compile(script, &options).unwrap();
+        }
+        handle.compile(&scripts::FileIoLatency::new().process("notepad.exe").build().source, &options).unwrap();
+    }
+
+    #[test]
+    fn probe_filters() {
+        use filter::{Filtered, ProbeFilter};
+        use visit::RecordVisitor;
+
+        #[derive(Default)]
+        struct Ints(Vec<i64>);
+        impl RecordVisitor for Ints {
+            fn visit_int(&mut self, value: i64) {
+                self.0.push(value);
+            }
+        }
+
+        let probe = |function: &str| probe::ProbeDescription {
+            id: 1,
+            provider: "syscall".to_string(),
+            module: String::new(),
+            function: function.to_string(),
+            name: "entry".to_string(),
+        };
+        let filter = ProbeFilter::new().probe("Nt*File:entry").cpu(2);
+        assert!(filter.matches_probe(2, Some(&probe("NtReadFile"))));
+        assert!(!filter.matches_probe(1, Some(&probe("NtReadFile"))));
+        assert!(!filter.matches_probe(2, Some(&probe("NtClose"))));
+        assert!(ProbeFilter::new().probe("syscall:::").matches_probe(0, Some(&probe("NtClose"))));
+        assert!(!ProbeFilter::new().provider("fbt").matches_probe(0, Some(&probe("NtClose"))));
+        assert!(!ProbeFilter::new().provider("fbt").matches_probe(0, None));
+
+        let mut filtered = Filtered::new(Ints::default(), ProbeFilter::new().pid(42));
+        for (pid, value) in [(42, 1), (7, 2), (42, 3)] {
+            assert!(filtered.accepts_probe(0, None));
+            filtered.visit_probe(0, None);
+            filtered.visit_int(pid);
+            filtered.visit_int(value);
+            filtered.visit_end();
+        }
+        assert_eq!(filtered.into_inner().0, [42, 1, 42, 3]);
+    }
+
+    #[test]
+    fn statement_match_counts() {
+        let statement = |function: &str, line: Option<usize>, probes: Vec<dtrace_id_t>| diagnostics::StatementSource {
+            program: 0,
+            ecb: 0,
+            description: probe::ProbeDescription {
+                id: 0,
+                provider: "syscall".to_string(),
+                module: String::new(),
+                function: function.to_string(),
+                name: "entry".to_string(),
+            },
+            line,
+            clause: None,
+            probes,
+        };
+        let matched = statement("NtClose", Some(3), vec![74]);
+        assert_eq!(matched.to_string(), "syscall::NtClose:entry at line 3 matched 1 probe");
+        let unmatched = statement("NtNope", None, Vec::new());
+        assert_eq!(unmatched.match_count(), 0);
+        assert_eq!(unmatched.to_string(), "syscall::NtNope:entry matched 0 probes");
+    }
+
+    #[test]
+    fn error_kinds() {
+        use utils::{Error, ErrorKind};
+
+        let syntax = Error::new(EDT_COMPILER as i32, "syntax error near \"}\"");
+        assert!(syntax.is_compile_error() && !syntax.is_no_match());
+        let unmatched = Error::new(
+            EDT_COMPILER as i32,
+            "probe description syscall::NtNope:entry does not match any probes",
+        );
+        assert_eq!(unmatched.kind(), ErrorKind::NoMatch);
+        assert!(Error::new(EDT_NOPROBES as i32, "no probes").is_no_match());
+        assert!(Error::new(EDT_DESTRUCTIVE as i32, "destructive actions not allowed").is_permission_error());
+        assert_eq!(Error::new(-1, "wrapper error").kind(), ErrorKind::Other);
+        let not_elevated = Error::new(13, "Permission denied").with_kind(ErrorKind::NotElevated);
+        assert!(not_elevated.is_not_elevated() && not_elevated.is_permission_error());
+    }
+
+    #[test]
+    fn elevated_command_line() {
+        assert_eq!(elevation::command_line(["-s", "trace.d"]), "-s trace.d");
+        assert_eq!(elevation::command_line(["-o", r"C:\My Traces\"]), r#"-o "C:\My Traces\\""#);
+        assert_eq!(elevation::command_line(["", r#"say "hi""#]), r#""" "say \"hi\"""#);
+    }
+
+    #[test]
+    fn stack_truncation() {
+        let data: Vec<u8> = [0x10u64, 0x20, 0x30, 0].iter().flat_map(|pc| pc.to_ne_bytes()).collect();
+        let full = symbol::Stack::decode(&data, 2, Some(4));
+        assert_eq!((full.frames.as_slice(), full.truncated), ([0x10, 0x20].as_slice(), true));
+
+        let partial = symbol::Stack::decode(&data, 4, None);
+        assert_eq!((partial.frames.len(), partial.depth, partial.truncated), (3, 4, false));
+    }
+
+    #[test]
+    fn aggregate_snapshot_delta() {
+        use aggregate::{AggKey, AggValue, AggregateEntry, AggregateSnapshot, Histogram};
+        let entry = |key: &str, value: AggValue| AggregateEntry {
+            name: "calls".to_string(),
+            varid: 1,
+            key: AggKey::from(key),
+            value,
+            normal: 1,
+        };
+        let mut counts = vec![0i64; aggregate::QUANTIZE_NBUCKETS];
+        counts[aggregate::QUANTIZE_ZEROBUCKET + 1] = 4;
+        let previous = AggregateSnapshot::from(vec![
+            entry("read", AggValue::Count(10)),
+            entry("gone", AggValue::Count(1)),
+            entry("sizes", AggValue::Quantize(Histogram::quantize(&counts))),
+        ]);
+        counts[aggregate::QUANTIZE_ZEROBUCKET + 1] = 7;
+        counts[aggregate::QUANTIZE_ZEROBUCKET + 2] = 2;
+        let current = AggregateSnapshot::from(vec![
+            entry("read", AggValue::Count(25)),
+            entry("write", AggValue::Count(3)),
+            entry("sizes", AggValue::Quantize(Histogram::quantize(&counts))),
+        ]);
+
+        let delta = current.delta(&previous);
+        assert_eq!(delta.entries.len(), 3);
+        assert_eq!(delta.get("calls", &AggKey::from("read")), Some(&AggValue::Count(15)));
+        assert_eq!(delta.get("calls", &AggKey::from("write")), Some(&AggValue::Count(3)));
+        let sizes = delta.get("calls", &AggKey::from("sizes")).and_then(AggValue::histogram).unwrap();
+        assert_eq!(sizes.total(), 5);
+        assert!(sizes.buckets.iter().any(|bucket| bucket.value == 1 && bucket.count == 3));
+
+        // The underflow bucket shares its value with the first bucket of linear and log-linear histograms.
+        let (previous, current) = ([4, 5, 0, 0, 0, 0], [8, 6, 0, 0, 0, 0]);
+        let lquantize = 4 << 32 | 10 << 48;
+        let llquantize = 10 | 1 << 16 | 10 << 48;
+        for delta in [
+            Histogram::lquantize(lquantize, &current).delta(&Histogram::lquantize(lquantize, &previous)),
+            Histogram::llquantize(llquantize, &current).delta(&Histogram::llquantize(llquantize, &previous)),
+        ] {
+            assert_eq!(delta.buckets[0].value, delta.buckets[1].value);
+            assert_eq!((delta.buckets[0].count, delta.buckets[1].count), (4, 1));
+        }
+    }
+
+    #[test]
+    fn latency_pairing() {
+        let mut latencies = latency::LatencyCorrelator::new();
+        latencies.enter(1, 10, "NtReadFile", 1_000);
+        latencies.enter(1, 10, "NtReadFile", 1_500);
+        latencies.enter(1, 11, "NtReadFile", 2_000);
+        assert_eq!(latencies.leave(1, 10, "NtReadFile", 1_600), Some(std::time::Duration::from_nanos(100)));
+        assert_eq!(latencies.leave(1, 10, "NtReadFile", 3_000), Some(std::time::Duration::from_nanos(2_000)));
+        assert_eq!(latencies.leave(1, 10, "NtReadFile", 4_000), None);
+        assert_eq!(latencies.pending(), 1);
+        latencies.discard_process(1);
+        assert_eq!(latencies.pending(), 0);
+
+        let stats = &latencies.latencies()["NtReadFile"];
+        assert_eq!((stats.count, stats.min, stats.max), (2, 100, 2_000));
+        assert_eq!(stats.mean(), std::time::Duration::from_nanos(1_050));
+        let histogram = stats.histogram();
+        assert_eq!(histogram.total(), 2);
+        assert!(histogram.buckets.iter().any(|bucket| bucket.value == 64 && bucket.count == 1));
+    }
+
+    #[test]
+    fn process_events() {
+        use process::{ProcessEvent, ProcessExited, ProcessMonitor, ProcessStarted};
+        use visit::RecordVisitor;
+
+        let probe = |provider: &str, function: &str| probe::EnabledProbe {
+            epid: 1,
+            uarg: 0,
+            size: 0,
+            nrecs: 0,
+            records: Vec::new(),
+            probe: probe::ProbeDescription {
+                id: 1,
+                provider: provider.to_string(),
+                module: String::new(),
+                function: function.to_string(),
+                name: "entry".to_string(),
+            },
+        };
+        let mut monitor = ProcessMonitor::new();
+        monitor.visit_probe(0, Some(&probe("fbt", "PspInsertProcess")));
+        monitor.visit_int(42);
+        monitor.visit_int(4);
+        monitor.visit_string("C:\\Windows\\notepad.exe");
+        monitor.visit_string("notepad.exe a.txt");
+        monitor.visit_end();
+        monitor.visit_probe(0, Some(&probe("syscall", "NtCreateUserProcess")));
+        monitor.visit_end();
+        monitor.visit_probe(0, Some(&probe("syscall", "NtTerminateProcess")));
+        monitor.visit_int(42);
+        monitor.visit_int(3);
+        monitor.visit_end();
+        assert_eq!(
+            monitor.take_events(),
+            [
+                ProcessEvent::Started(ProcessStarted {
+                    pid: 42,
+                    ppid: 4,
+                    image: "C:\\Windows\\notepad.exe".to_string(),
+                    cmdline: "notepad.exe a.txt".to_string(),
+                }),
+                ProcessEvent::Exited(ProcessExited { pid: 42, code: 3 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn file_io_tracking() {
+        use visit::RecordVisitor;
+
+        let probe = |function: &str, name: &str| probe::EnabledProbe {
+            epid: 1,
+            uarg: 0,
+            size: 0,
+            nrecs: 0,
+            records: Vec::new(),
+            probe: probe::ProbeDescription {
+                id: 1,
+                provider: "syscall".to_string(),
+                module: String::new(),
+                function: function.to_string(),
+                name: name.to_string(),
+            },
+        };
+        let mut tracker = io::FileIoTracker::new();
+        tracker.visit_probe(0, Some(&probe("NtCreateFile", "return")));
+        tracker.visit_int(4);
+        tracker.visit_int(0x40);
+        let path: Vec<u8> = r"\??\C:\a.txt".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        tracker.visit_bytes(&path);
+        tracker.visit_end();
+        assert_eq!(tracker.path(4, 0x40), Some(r"C:\a.txt"));
+
+        for (function, size) in [("NtReadFile", 100), ("NtWriteFile", 10), ("NtReadFile", 100)] {
+            tracker.visit_probe(0, Some(&probe(function, "entry")));
+            for value in [4, 0x40, size] {
+                tracker.visit_int(value);
+            }
+            tracker.visit_end();
+        }
+        tracker.visit_probe(0, Some(&probe("NtClose", "entry")));
+        tracker.visit_int(4);
+        tracker.visit_int(0x40);
+        tracker.visit_end();
+        tracker.transfer(4, 0x40, false, 1);
+
+        let files = tracker.take_files();
+        let stats = &files[r"C:\a.txt"];
+        assert_eq!((stats.reads, stats.read_bytes, stats.writes, stats.written_bytes), (2, 200, 1, 10));
+        assert_eq!(stats.sizes().total(), 3);
+        assert_eq!(files[io::UNKNOWN_PATH].reads, 1);
+        assert!(tracker.files().is_empty());
+    }
+
+    #[test]
+    fn socket_tracking() {
+        use net::{SocketEvent, IOCTL_AFD_CONNECT, IOCTL_AFD_RECV, IOCTL_AFD_SEND};
+        use visit::RecordVisitor;
+
+        let probe = |function: &str| probe::EnabledProbe {
+            epid: 1,
+            uarg: 0,
+            size: 0,
+            nrecs: 0,
+            records: Vec::new(),
+            probe: probe::ProbeDescription {
+                id: 1,
+                provider: "syscall".to_string(),
+                module: String::new(),
+                function: function.to_string(),
+                name: "entry".to_string(),
+            },
+        };
+        let fire = |tracker: &mut net::SocketTracker, function: &str, values: &[i64]| {
+            tracker.visit_probe(0, Some(&probe(function)));
+            for &value in values {
+                tracker.visit_int(value);
+            }
+            tracker.visit_end();
+        };
+        let remote: std::net::SocketAddr = "10.0.0.1:443".parse().unwrap();
+        let ip: std::net::Ipv6Addr = "2001:db8::1".parse().unwrap();
+        assert_eq!(net::socket_address(2, [1, 187], &[10, 0, 0, 1]), Some(remote));
+        assert_eq!(net::socket_address(23, [0, 80], &ip.octets()), Some((ip, 80).into()));
+        assert_eq!(net::socket_address(2, [1, 187], &[10, 0]), None);
+
+        let (connect, send, recv) = (IOCTL_AFD_CONNECT as i64, IOCTL_AFD_SEND as i64, IOCTL_AFD_RECV as i64);
+        let mut tracker = net::SocketTracker::new();
+        let port = i64::from(u16::from_le_bytes([1, 187]));
+        let address = i64::from(u32::from_le_bytes([10, 0, 0, 1]));
+        fire(&mut tracker, "NtDeviceIoControlFile", &[4, 0x40, connect, 2, port, address]);
+        assert_eq!(tracker.remote(4, 0x40), Some(remote));
+        fire(&mut tracker, "NtDeviceIoControlFile", &[4, 0x40, send, 100]);
+        fire(&mut tracker, "NtDeviceIoControlFile", &[4, 0x40, recv, 4096]);
+        let words: Vec<i64> = ip.octets().chunks(8).map(|word| i64::from_le_bytes(word.try_into().unwrap())).collect();
+        fire(&mut tracker, "NtDeviceIoControlFile", &[4, 0x44, connect, 23, 0x5000, 0, words[0], words[1]]);
+        assert_eq!(tracker.remote(4, 0x44), Some((ip, 80).into()));
+        fire(&mut tracker, "NtClose", &[4, 0x40]);
+        fire(&mut tracker, "NtDeviceIoControlFile", &[4, 0x40, send, 10]);
+        fire(&mut tracker, "NtDeviceIoControlFile", &[4, 0x40, 0x12345, 10]);
+
+        assert_eq!(
+            tracker.take_events(),
+            vec![
+                SocketEvent::Connect { pid: 4, handle: 0x40, remote: Some(remote) },
+                SocketEvent::Send { pid: 4, handle: 0x40, remote: Some(remote), bytes: 100 },
+                SocketEvent::Recv { pid: 4, handle: 0x40, remote: Some(remote), bytes: 4096 },
+                SocketEvent::Connect { pid: 4, handle: 0x44, remote: Some((ip, 80).into()) },
+                SocketEvent::Send { pid: 4, handle: 0x40, remote: None, bytes: 10 },
+            ]
+        );
+        let endpoints = tracker.endpoints();
+        let stats = &endpoints[&Some(remote)];
+        assert_eq!((stats.connects, stats.sends, stats.sent_bytes), (1, 1, 100));
+        assert_eq!((stats.recvs, stats.received_bytes), (1, 4096));
+        assert_eq!(endpoints[&None].sent_bytes, 10);
+    }
+
+    #[test]
+    fn name_enrichment() {
+        use visit::RecordVisitor;
+
+        #[derive(Default)]
+        struct Collect(Vec<names::Names>);
+        impl RecordVisitor for Collect {
+            fn visit_names(&mut self, names: &names::Names) {
+                self.0.push(names.clone());
+            }
+        }
+
+        let mut enricher = names::Enricher::new(Collect::default()).with_records(1, 2);
+        enricher.names().set_process_name(4, "notepad.exe");
+        enricher.names().set_thread_name(4, 8, "worker");
+        enricher.visit_probe(0, None);
+        for value in [100, 4, 8] {
+            enricher.visit_int(value);
+        }
+        enricher.visit_end();
+        enricher.visit_probe(0, None);
+        enricher.visit_int(100);
+        enricher.visit_end();
+        let names = &enricher.visitor().0;
+        assert_eq!(names.len(), 1);
+        assert_eq!((names[0].pid, names[0].tid), (4, 8));
+        assert_eq!(names[0].process.as_deref(), Some("notepad.exe"));
+        assert_eq!(names[0].thread.as_deref(), Some("worker"));
+
+        enricher.names().observe(&target::TargetEvent::Exited { pid: Some(4), code: Some(0) });
+        assert!(enricher.names().is_empty());
+    }
+
+    #[test]
+    fn format_conversions() {
+        assert_eq!(statement::conversions("%d %s\n"), 2);
+        assert_eq!(statement::conversions("100%% of %@d"), 1);
+        assert_eq!(statement::conversions("trailing %"), 0);
+    }
+
+    #[test]
+    fn log_entry_display() {
+        let entry = session::LogEntry {
+            time: std::time::UNIX_EPOCH + std::time::Duration::from_millis(1_500),
+            severity: session::Severity::Warning,
+            message: "3 dynamic variable drops".to_string(),
+        };
+        assert_eq!(entry.to_string(), "1.500000000 warning: 3 dynamic variable drops");
+        assert!(session::Severity::Error > session::Severity::Warning);
+        assert!(session::TraceSession::new("BEGIN {}").events().is_empty());
+    }
+
+    #[test]
+    fn support_bundle_archive() {
+        let mut bundle = bundle::SupportBundle::new();
+        bundle.add("program.d", "BEGIN { exit(0); }");
+        let mut archive = Vec::new();
+        bundle.write_to(&mut archive).unwrap();
+        assert_eq!(bundle.names().collect::<Vec<_>>(), ["platform.txt", "program.d"]);
+        assert_eq!(archive.len() % 512, 0);
+        assert!(archive.starts_with(b"platform.txt\0"));
+        assert_eq!(&archive[257..263], b"ustar\0");
+        let checksum: u32 = archive[..512]
+            .iter()
+            .enumerate()
+            .map(|(i, &byte)| if (148..156).contains(&i) { b' ' as u32 } else { byte as u32 })
+            .sum();
+        let recorded = std::str::from_utf8(&archive[148..154]).unwrap();
+        assert_eq!(u32::from_str_radix(recorded, 8).unwrap(), checksum);
+        assert!(archive.ends_with(&[0u8; 1024]));
+    }
+
+    #[test]
+    fn program_option_changes() {
+        let mut config = config::ConsumerConfig::default();
+        config.set_option("bufsize", "4m");
+        config.set_program_option("switchrate", Some(10));
+        assert_eq!(config.options.last(), Some(&("switchrate".to_string(), "10".to_string())));
+        config.set_program_option("bufsize", None);
+        assert_eq!(config.options, [("switchrate".to_string(), "10".to_string())]);
+    }
+
+    #[test]
+    fn aggregation_routing() {
+        use std::sync::{Arc, Mutex};
+
+        let latencies = Arc::new(Mutex::new(Vec::new()));
+        let others = Arc::new(Mutex::new(Vec::new()));
+        let (seen, other) = (latencies.clone(), others.clone());
+        let mut router = aggregate::AggregationRouter::new()
+            .on_aggregation("@latency", move |entry| seen.lock().unwrap().push(entry.value.as_i64()))
+            .on_other(move |entry| other.lock().unwrap().push(entry.name.clone()));
+        assert_eq!(router.names().collect::<Vec<_>>(), ["latency"]);
+
+        let entry = |name: &str, value: i64| aggregate::AggregateEntry {
+            name: name.to_string(),
+            varid: 1,
+            key: aggregate::AggKey::from("NtClose"),
+            value: aggregate::AggValue::Count(value as u64),
+            normal: 1,
+        };
+        visit::RecordVisitor::visit_aggregate(&mut router, &entry("latency", 7));
+        assert!(router.dispatch(&entry("bytes", 1)));
+        assert_eq!(*latencies.lock().unwrap(), [7]);
+        assert_eq!(*others.lock().unwrap(), ["bytes"]);
+    }
+
+    #[test]
+    fn cpu_option_validation() {
+        let info = cpu::CpuInfo::new(8, vec![3, 0, 1, 3], None);
+        assert_eq!((info.count(), info.max_cpu), (3, 8));
+        assert_eq!(info.traced(), [0, 1, 3]);
+        assert_eq!(info.validate(" 1 ").unwrap(), 1);
+        assert!(info.validate("2").is_err());
+        assert!(info.validate("all").is_err());
+
+        let pinned = cpu::CpuInfo::new(8, vec![0, 1], Some(1));
+        assert_eq!(pinned.traced(), [1]);
+    }
+
+    #[test]
+    fn script_with_predicate() {
+        let program = "#pragma D option quiet\n/* entry */\nsyscall::NtClose:entry\n{ @[\"}\"] = count(); }\nsyscall::NtClose:return /arg0 != 0/ { trace(arg0 / 2); }\n";
+        let rewritten = script::with_predicate(program, "pid == $target");
+        assert_eq!(
+            rewritten,
+            "#pragma D option quiet\n/* entry */\nsyscall::NtClose:entry /pid == $target/\n{ @[\"}\"] = count(); }\nsyscall::NtClose:return /(arg0 != 0) && (pid == $target)/ { trace(arg0 / 2); }\n"
+        );
+
+        let program = "BEGIN { start = timestamp; }\nsyscall:::entry { @ = count(); }\ndtrace:::END, ERROR { printa(@); }\n";
+        assert_eq!(
+            script::with_predicate(program, "pid == 42"),
+            "BEGIN { start = timestamp; }\nsyscall:::entry /pid == 42/ { @ = count(); }\ndtrace:::END, ERROR { printa(@); }\n"
+        );
+        assert_eq!(
+            script::with_predicate("syscall:::entry /pid == 1/ /* a/b */ { }", "tid == 2"),
+            "syscall:::entry /(pid == 1) && (tid == 2)/ /* a/b */ { }"
+        );
+        assert_eq!(
+            script::with_predicate("syscall:::entry // note\n/execname == \"a/b\"/ { }", "tid == 2"),
+            "syscall:::entry // note\n/(execname == \"a/b\") && (tid == 2)/ { }"
+        );
+        assert_eq!(
+            script::with_predicate("syscall:::entry /* note */\n{ }", "tid == 2"),
+            "syscall:::entry /tid == 2/ /* note */\n{ }"
+        );
+
+        let program = "#pragma D option quiet\n/* entry */\nsyscall::NtClose:entry\n{ @[\"}\"] = count(); }\nsyscall::NtClose:return /arg0 != 0/ { trace(arg0 / 2); }\n";
+        let clauses = script::clauses(program);
+        assert_eq!(clauses.len(), 2);
+        assert_eq!((clauses[0].line, clauses[0].descriptions), (3, 1));
+        assert_eq!(clauses[1].header, "syscall::NtClose:return /arg0 != 0/");
+        assert_eq!(clauses[1].line, 5);
+    }
+
+    #[test]
+    fn render_quantize_histogram() {
+        let mut counts = vec![0i64; aggregate::QUANTIZE_NBUCKETS];
+        counts[aggregate::QUANTIZE_ZEROBUCKET + 1] = 1;
+        counts[aggregate::QUANTIZE_ZEROBUCKET + 3] = 3;
+        let hist = aggregate::Histogram::quantize(&counts);
+        assert_eq!(hist.total(), 4);
+
+        let output = render::histogram(&hist);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[1].trim_start().starts_with("0 |"));
+        assert!(lines[2].contains(&format!("|{}{} 1", "@".repeat(10), " ".repeat(30))));
+        assert!(lines[4].contains(&format!("|{}{} 3", "@".repeat(30), " ".repeat(10))));
+        assert!(lines[5].trim_start().starts_with("8 |"));
+    }
+
+    #[test]
+    fn histogram_slo() {
+        use aggregate::{Histogram, Slo};
+
+        // 90 values in [1024, 2048), 10 in [4096, 8192)
+        let mut counts = vec![0i64; aggregate::QUANTIZE_NBUCKETS];
+        counts[aggregate::QUANTIZE_ZEROBUCKET + 11] = 90;
+        counts[aggregate::QUANTIZE_ZEROBUCKET + 13] = 10;
+        let hist = Histogram::quantize(&counts);
+        assert!((hist.percentile(50.0).unwrap() - (1024.0 + 1024.0 * 50.0 / 90.0)).abs() < 1e-9);
+        assert_eq!(hist.percentile(90.0), Some(2048.0));
+        assert_eq!(hist.percentile(95.0), Some(4096.0 + 4096.0 * 0.5));
+        assert_eq!(hist.count_above(4096), 10);
+        assert_eq!(hist.count_above(2048), 10);
+        assert_eq!(hist.count_above(0), 100);
+
+        let report = hist.evaluate(&Slo::new(90.0, 2048));
+        assert!(report.passed);
+        assert_eq!((report.total, report.above), (100, 10));
+        let report = hist.evaluate(&Slo::new(99.0, 4096));
+        assert!(!report.passed);
+        assert_eq!(report.to_string(), "FAIL: p99 = 7782 (target 4096), 10 of 100 at or above target");
+
+        let empty = Histogram::quantize(&[0; aggregate::QUANTIZE_NBUCKETS]);
+        assert_eq!(empty.percentile(99.0), None);
+        assert!(empty.evaluate(&Slo::new(99.0, 1)).passed);
+    }
+
+    #[test]
+    fn histogram_arithmetic() {
+        use aggregate::Histogram;
+
+        let a = Histogram::lquantize((10 << 48) | (5 << 32), &[1, 2, 3, 4, 5, 6, 7]);
+        let b = Histogram::lquantize((10 << 48) | (5 << 32), &[1, 1, 1, 1, 1, 1, 1]);
+        let counts = |hist: &Histogram| hist.buckets.iter().map(|bucket| bucket.count).collect::<Vec<_>>();
+        assert!(a.is_compatible(&b));
+        assert_eq!(counts(&a.merge(&b).unwrap()), [2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(counts(&a.subtract(&b).unwrap()), [0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(counts(&a.scale(0.5)), [1, 1, 2, 2, 3, 3, 4]);
+        assert_eq!(a.merge(&b).unwrap().subtract(&b).unwrap(), a);
+
+        let other_step = Histogram::lquantize((20 << 48) | (5 << 32), &[1, 2, 3, 4, 5, 6, 7]);
+        assert!(!a.is_compatible(&other_step));
+        assert!(a.merge(&other_step).is_err());
+        let quantize = Histogram::quantize(&[0; aggregate::QUANTIZE_NBUCKETS]);
+        assert!(quantize.subtract(&a).is_err());
+    }
+
+    #[test]
+    fn decode_wide_string() {
+        use strings::{decode, DecodedString, StringEncoding, StringPolicy};
+
+        let bytes: Vec<u8> = "Ünïcode\0junk".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!(
+            decode(&bytes, StringEncoding::Utf16, StringPolicy::Strict),
+            Ok(DecodedString::Text("Ünïcode".to_string()))
+        );
+
+        // An unpaired surrogate
+        let invalid = [0x41, 0x00, 0x00, 0xd8, 0x42, 0x00];
+        assert_eq!(
+            decode(&invalid, StringEncoding::Utf16, StringPolicy::Strict).unwrap_err().offset,
+            2
+        );
+        assert_eq!(
+            decode(&invalid, StringEncoding::Utf16, StringPolicy::Lossy),
+            Ok(DecodedString::Text("A\u{fffd}B".to_string()))
+        );
+    }
+
+    #[test]
+    fn symbol_map_perf_map() {
+        use symbol::{MappedSymbol, SymbolMap};
+
+        assert_eq!(symbol::parse_uaddr("ntdll.dll`NtClose+0x14"), Some(("ntdll.dll", "NtClose", 0x14)));
+        assert_eq!(symbol::parse_uaddr("ntdll.dll`0x7ffe0000"), None);
+
+        let map = SymbolMap {
+            pid: Some(42),
+            symbols: vec![MappedSymbol {
+                start: 0x1000,
+                size: 0x20,
+                module: "app.exe".to_string(),
+                name: "main".to_string(),
+            }],
+        };
+        assert_eq!(map.lookup(0x101f).map(|symbol| symbol.name.as_str()), Some("main"));
+        assert!(map.lookup(0x1020).is_none() && map.lookup(0xfff).is_none());
+
+        let mut output = Vec::new();
+        map.write_perf_map(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "1000 20 app.exe`main\n");
     }
 }
@@ -0,0 +1,295 @@
+//! Resolving the IDs of processes and threads recorded by a program to their names, so consumers do not have to.
+//!
+//! A [`NameCache`] resolves the image name of a process, e.g. `notepad.exe`, and the description of a thread set with
+//! `SetThreadDescription`, and remembers them until the process exits. As IDs are reused, the names of a process are
+//! forgotten when it is reported to exit with [`NameCache::observe`], or explicitly with
+//! [`NameCache::forget_process`].
+//!
+//! An [`Enricher`] wraps a [`RecordVisitor`] to deliver the names of the process and thread of each firing to
+//! `RecordVisitor::visit_names`:
+//!
+//! ```no_run
+//! use libdtrace_rs::names::{Enricher, Names};
+//! use libdtrace_rs::session::TraceSession;
+//! use libdtrace_rs::visit::RecordVisitor;
+//! use std::sync::{Arc, Mutex};
+//!
+//! struct Printer;
+//!
+//! impl RecordVisitor for Printer {
+//!     fn visit_names(&mut self, names: &Names) {
+//!         println!("{} ({})", names.process.as_deref().unwrap_or("?"), names.tid);
+//!     }
+//! }
+//!
+//! let mut session = TraceSession::new("syscall::NtClose:entry { trace(pid); trace(tid); }")
+//!     .with_option("quiet", "")
+//!     .with_visitor(Arc::new(Mutex::new(Enricher::new(Printer))));
+//! session.start().unwrap();
+//! ```
+
+use crate::aggregate::AggregateEntry;
+use crate::probe::EnabledProbe;
+use crate::symbol::SymbolAction;
+use crate::target::TargetEvent;
+use crate::visit::{RecordVisitor, UnknownActionPolicy};
+use std::collections::HashMap;
+
+/// The process and thread a firing recorded, with their names if they could be resolved, see
+/// `RecordVisitor::visit_names`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Names {
+    pub pid: u32,
+    pub tid: u32,
+    /// Image name of the process, e.g. `notepad.exe`
+    pub process: Option<String>,
+    /// Description of the thread, if it has one
+    pub thread: Option<String>,
+}
+
+/// Caches the names of processes and threads by ID, see the [module](self) docs.
+#[derive(Debug, Default)]
+pub struct NameCache {
+    /// Image names by process, `None` if the process could not be queried
+    processes: HashMap<u32, Option<String>>,
+    /// Descriptions by process and thread, `None` if the thread has none or could not be queried
+    threads: HashMap<(u32, u32), Option<String>>,
+}
+
+impl NameCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the image name of the process `pid`, resolving it on the first call.
+    pub fn process_name(&mut self, pid: u32) -> Option<&str> {
+        self.processes.entry(pid).or_insert_with(|| query_process_name(pid)).as_deref()
+    }
+
+    /// Returns the description of the thread `tid` of the process `pid`, resolving it on the first call.
+    pub fn thread_name(&mut self, pid: u32, tid: u32) -> Option<&str> {
+        self.threads.entry((pid, tid)).or_insert_with(|| query_thread_name(tid)).as_deref()
+    }
+
+    /// Sets the name of the process `pid`, e.g. from a recorded `execname`, instead of resolving it.
+    pub fn set_process_name(&mut self, pid: u32, name: &str) {
+        self.processes.insert(pid, Some(name.to_string()));
+    }
+
+    /// Sets the name of the thread `tid` of the process `pid` instead of resolving it.
+    pub fn set_thread_name(&mut self, pid: u32, tid: u32, name: &str) {
+        self.threads.insert((pid, tid), Some(name.to_string()));
+    }
+
+    /// Returns the names of the thread `tid` of the process `pid`.
+    pub fn names(&mut self, pid: u32, tid: u32) -> Names {
+        Names {
+            pid,
+            tid,
+            process: self.process_name(pid).map(str::to_string),
+            thread: self.thread_name(pid, tid).map(str::to_string),
+        }
+    }
+
+    /// Forgets the names of the process `pid` and its threads, e.g. once it exited, as its ID may be reused.
+    pub fn forget_process(&mut self, pid: u32) {
+        self.processes.remove(&pid);
+        self.threads.retain(|(thread_pid, _), _| *thread_pid != pid);
+    }
+
+    /// Forgets the names of the target of a session when it exits, see `session::Event::Target`.
+    pub fn observe(&mut self, event: &TargetEvent) {
+        if let TargetEvent::Exited { pid: Some(pid), .. } = event {
+            self.forget_process(*pid);
+        }
+    }
+
+    /// Returns the number of processes and threads whose names are cached.
+    pub fn len(&self) -> usize {
+        self.processes.len() + self.threads.len()
+    }
+
+    /// Returns `true` if no name is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Returns the image name of the process `pid`, without its directory.
+fn query_process_name(pid: u32) -> Option<String> {
+    #[cfg(windows)]
+    unsafe {
+        use crate::win32;
+        let process = win32::OpenProcess(win32::PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if process.is_null() {
+            return None;
+        }
+        let mut path = [0u16; 1024];
+        let mut size = path.len() as u32;
+        let queried = win32::QueryFullProcessImageNameW(process, 0, path.as_mut_ptr(), &mut size);
+        win32::CloseHandle(process);
+        if queried == 0 {
+            return None;
+        }
+        let path = String::from_utf16_lossy(&path[..size as usize]);
+        path.rsplit(['\\', '/']).next().map(str::to_string)
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = pid;
+        None
+    }
+}
+
+/// Returns the description of the thread `tid`, or `None` if it has none.
+fn query_thread_name(tid: u32) -> Option<String> {
+    #[cfg(windows)]
+    unsafe {
+        use crate::win32;
+        let thread = win32::OpenThread(win32::THREAD_QUERY_LIMITED_INFORMATION, 0, tid);
+        if thread.is_null() {
+            return None;
+        }
+        let mut description: *mut u16 = std::ptr::null_mut();
+        let queried = win32::GetThreadDescription(thread, &mut description);
+        win32::CloseHandle(thread);
+        if queried < 0 || description.is_null() {
+            return None;
+        }
+        let len = (0..).take_while(|&i| *description.add(i) != 0).count();
+        let name = String::from_utf16_lossy(std::slice::from_raw_parts(description, len));
+        win32::LocalFree(description as *mut ::core::ffi::c_void);
+        (!name.is_empty()).then_some(name)
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = tid;
+        None
+    }
+}
+
+/// A [`RecordVisitor`] delivering the names of the process and thread of each firing to the visitor it wraps.
+///
+/// The IDs are taken from the integer records of each firing, by default the first two, as recorded by
+/// `trace(pid); trace(tid);`. Every record is forwarded to the wrapped visitor, then `visit_names` is called before
+/// `visit_end` if the firing recorded both IDs.
+pub struct Enricher<V> {
+    visitor: V,
+    names: NameCache,
+    /// Indexes of the integer records holding the process and thread IDs
+    records: (usize, usize),
+    /// Integers recorded by the firing being visited
+    values: Vec<i64>,
+}
+
+impl<V: RecordVisitor> Enricher<V> {
+    /// Wraps `visitor`, taking the process and thread IDs from the first two integer records.
+    pub fn new(visitor: V) -> Self {
+        Self {
+            visitor,
+            names: NameCache::new(),
+            records: (0, 1),
+            values: Vec::new(),
+        }
+    }
+
+    /// Takes the process and thread IDs from the integer records at `pid` and `tid` instead, counting from 0.
+    pub fn with_records(mut self, pid: usize, tid: usize) -> Self {
+        self.records = (pid, tid);
+        self
+    }
+
+    /// Returns the cache of names, e.g. to forget the processes that exited.
+    pub fn names(&mut self) -> &mut NameCache {
+        &mut self.names
+    }
+
+    /// Returns the wrapped visitor.
+    pub fn visitor(&mut self) -> &mut V {
+        &mut self.visitor
+    }
+
+    /// Returns the wrapped visitor, dropping the cache.
+    pub fn into_inner(self) -> V {
+        self.visitor
+    }
+}
+
+impl<V: RecordVisitor> RecordVisitor for Enricher<V> {
+    fn accepts_probe(&mut self, cpu: crate::processorid_t, probe: Option<&EnabledProbe>) -> bool {
+        self.visitor.accepts_probe(cpu, probe)
+    }
+
+    fn visit_probe(&mut self, cpu: crate::processorid_t, probe: Option<&EnabledProbe>) {
+        self.values.clear();
+        self.visitor.visit_probe(cpu, probe);
+    }
+
+    fn visit_int(&mut self, value: i64) {
+        self.values.push(value);
+        self.visitor.visit_int(value);
+    }
+
+    fn visit_string(&mut self, value: &str) {
+        self.visitor.visit_string(value);
+    }
+
+    fn visit_bytes(&mut self, value: &[u8]) {
+        self.visitor.visit_bytes(value);
+    }
+
+    fn visit_stack(&mut self, frames: &[u64], pid: Option<crate::pid_t>) {
+        self.visitor.visit_stack(frames, pid);
+    }
+
+    fn visit_stack_frames(&mut self, stack: &crate::symbol::Stack) {
+        self.visitor.visit_stack_frames(stack);
+    }
+
+    fn visit_symbol(&mut self, action: SymbolAction, address: u64, pid: Option<crate::pid_t>) {
+        self.visitor.visit_symbol(action, address, pid);
+    }
+
+    fn visit_printf(&mut self, output: &str) {
+        self.visitor.visit_printf(output);
+    }
+
+    fn visit_exit(&mut self, status: i32) {
+        self.visitor.visit_exit(status);
+    }
+
+    fn visit_aggregate(&mut self, entry: &AggregateEntry) {
+        self.visitor.visit_aggregate(entry);
+    }
+
+    fn visit_names(&mut self, names: &Names) {
+        self.visitor.visit_names(names);
+    }
+
+    fn visit_end(&mut self) {
+        let (pid, tid) = self.records;
+        if let (Some(&pid), Some(&tid)) = (self.values.get(pid), self.values.get(tid)) {
+            let names = self.names.names(pid as u32, tid as u32);
+            self.visitor.visit_names(&names);
+        }
+        self.values.clear();
+        self.visitor.visit_end();
+    }
+
+    fn visit_unknown(&mut self, action: crate::dtrace_actkind_t, bytes: &[u8]) {
+        self.visitor.visit_unknown(action, bytes);
+    }
+
+    fn visit_decoded(&mut self, action: crate::dtrace_actkind_t, value: &crate::typed::TypedValue) {
+        self.visitor.visit_decoded(action, value);
+    }
+
+    fn visit_decoded_aggregate(&mut self, entry: &AggregateEntry, value: &crate::typed::TypedValue) {
+        self.visitor.visit_decoded_aggregate(entry, value);
+    }
+
+    fn unknown_actions(&self) -> UnknownActionPolicy {
+        self.visitor.unknown_actions()
+    }
+}
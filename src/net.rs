@@ -0,0 +1,286 @@
+//! Tracing the socket operations of processes, delivered as typed [`SocketEvent`]s and collected into per-endpoint
+//! statistics by a [`SocketTracker`].
+//!
+//! Windows sockets are driven by `NtDeviceIoControlFile` requests to the AFD driver, so
+//! [`SocketTracker::PROGRAM`] enables those with the control codes of connecting, accepting, sending and receiving,
+//! along with `NtClose` to forget the sockets closed. The remote address of a connection is copied from the request
+//! where it holds one; sends and receives are accounted to the address their socket connected to, or to `None` when
+//! it is not known, e.g. for accepted sockets and sockets connected before tracing started.
+//!
+//! ```no_run
+//! use libdtrace_rs::net::SocketTracker;
+//! use libdtrace_rs::session::TraceSession;
+//! use std::sync::{Arc, Mutex};
+//!
+//! let tracker = Arc::new(Mutex::new(SocketTracker::new()));
+//! let mut session = TraceSession::new(SocketTracker::PROGRAM)
+//!     .with_option("quiet", "")
+//!     .with_visitor(tracker.clone());
+//! session.start().unwrap();
+//! std::thread::sleep(std::time::Duration::from_secs(10));
+//! session.stop();
+//! for (endpoint, stats) in tracker.lock().unwrap().endpoints() {
+//!     println!("{:?}: {} bytes sent, {} bytes received", endpoint, stats.sent_bytes, stats.received_bytes);
+//! }
+//! ```
+
+use crate::probe::EnabledProbe;
+use crate::visit::RecordVisitor;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Control code of the AFD request connecting a socket
+pub const IOCTL_AFD_CONNECT: u32 = 0x12007;
+/// Control code of the AFD request accepting a connection on a listening socket
+pub const IOCTL_AFD_ACCEPT: u32 = 0x12010;
+/// Control code of the AFD request receiving on a socket
+pub const IOCTL_AFD_RECV: u32 = 0x12017;
+/// Control code of the AFD request sending on a socket
+pub const IOCTL_AFD_SEND: u32 = 0x1201f;
+
+/// Address family of IPv4 socket addresses on Windows
+const AF_INET: u16 = 2;
+/// Address family of IPv6 socket addresses on Windows
+const AF_INET6: u16 = 23;
+
+/// Decodes a `SOCKADDR_IN` or `SOCKADDR_IN6`, given its family, its port and its address as they are laid out in
+/// memory: the port in network byte order, the address starting at offset 4 for IPv4 and at offset 8 for IPv6.
+///
+/// # Returns
+///
+/// Returns the address, or [`None`] for other families or if `address` is too short.
+pub fn socket_address(family: u16, port: [u8; 2], address: &[u8]) -> Option<SocketAddr> {
+    let ip = match family {
+        AF_INET => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(address.get(..4)?).ok()?)),
+        AF_INET6 => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(address.get(..16)?).ok()?)),
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, u16::from_be_bytes(port)))
+}
+
+/// A socket operation of a process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SocketEvent {
+    /// The socket `handle` connected, to `remote` if the request held its address
+    Connect {
+        pid: crate::pid_t,
+        handle: u64,
+        remote: Option<SocketAddr>,
+    },
+    /// The listening socket `handle` accepted a connection
+    Accept { pid: crate::pid_t, handle: u64 },
+    /// `bytes` were sent on the socket `handle`, to its remote endpoint if known
+    Send {
+        pid: crate::pid_t,
+        handle: u64,
+        remote: Option<SocketAddr>,
+        bytes: u64,
+    },
+    /// `bytes` were received on the socket `handle`, from its remote endpoint if known
+    Recv {
+        pid: crate::pid_t,
+        handle: u64,
+        remote: Option<SocketAddr>,
+        bytes: u64,
+    },
+}
+
+/// The traffic with an endpoint, see [`SocketTracker::endpoints`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EndpointStats {
+    /// Number of connections made to the endpoint
+    pub connects: u64,
+    /// Number of sends
+    pub sends: u64,
+    /// Number of receives
+    pub recvs: u64,
+    /// Number of bytes asked to be sent
+    pub sent_bytes: u64,
+    /// Size of the buffers receives were asked into
+    pub received_bytes: u64,
+}
+
+/// A callback receiving the events of a [`SocketTracker`], see `on_event`.
+type SocketEventCallback = Box<dyn FnMut(&SocketEvent) + Send>;
+
+/// Follows the sockets of processes and decodes their operations, see the [module](self) docs.
+///
+/// The events are kept until taken with `take_events`, unless a callback receives them, see `on_event`.
+#[derive(Default)]
+pub struct SocketTracker {
+    /// Remote addresses of the sockets connected while tracing, by process ID and handle
+    sockets: HashMap<(u64, u64), SocketAddr>,
+    endpoints: HashMap<Option<SocketAddr>, EndpointStats>,
+    events: Vec<SocketEvent>,
+    callback: Option<SocketEventCallback>,
+    /// Integers recorded by the firing being visited, `None` if it is not a firing of the program
+    firing: Option<(&'static str, Vec<i64>)>,
+}
+
+impl SocketTracker {
+    /// A D program recording what the tracker needs.
+    ///
+    /// A request records the process ID, the socket handle and the control code. A connection adds the family, the
+    /// port and the address of the remote endpoint, as 16-bit, 16-bit, 32-bit and for IPv6 two 64-bit integers read
+    /// from the `SOCKADDR` at offset 24 of the request on 64-bit Windows. A send or receive adds the size of its first
+    /// buffer. A close records the process ID and the handle.
+    pub const PROGRAM: &'static str = r#"syscall::NtDeviceIoControlFile:entry /arg5 == 0x12007 && arg7 >= 48/ {
+    this->addr = (uint8_t *)copyin(arg6 + 24, 24);
+    trace(pid); trace(arg0); trace(arg5);
+    trace(*(uint16_t *)this->addr); trace(*(uint16_t *)(this->addr + 2)); trace(*(uint32_t *)(this->addr + 4));
+    trace(*(uint64_t *)(this->addr + 8)); trace(*(uint64_t *)(this->addr + 16));
+}
+syscall::NtDeviceIoControlFile:entry /arg5 == 0x12007 && arg7 >= 40 && arg7 < 48/ {
+    this->addr = (uint8_t *)copyin(arg6 + 24, 16);
+    trace(pid); trace(arg0); trace(arg5);
+    trace(*(uint16_t *)this->addr); trace(*(uint16_t *)(this->addr + 2)); trace(*(uint32_t *)(this->addr + 4));
+}
+syscall::NtDeviceIoControlFile:entry /arg5 == 0x12010/ { trace(pid); trace(arg0); trace(arg5); }
+syscall::NtDeviceIoControlFile:entry /(arg5 == 0x12017 || arg5 == 0x1201f) && arg7 >= 12/ {
+    this->info = (uint64_t *)copyin(arg6, 12);
+    trace(pid); trace(arg0); trace(arg5);
+    trace(*(uint32_t *)((uintptr_t)this->info + 8) > 0 ? *(uint32_t *)copyin(this->info[0], 4) : 0);
+}
+syscall::NtClose:entry { trace(pid); trace(arg0); }"#;
+
+    /// Creates a tracker without sockets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hands the events to `callback` as they are decoded, instead of keeping them.
+    pub fn on_event(mut self, callback: impl FnMut(&SocketEvent) + Send + 'static) -> Self {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Returns the events kept so far and forgets them.
+    pub fn take_events(&mut self) -> Vec<SocketEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Returns the traffic collected so far, by remote endpoint.
+    pub fn endpoints(&self) -> &HashMap<Option<SocketAddr>, EndpointStats> {
+        &self.endpoints
+    }
+
+    /// Returns the traffic collected so far and forgets it, e.g. to report it per interval. The sockets are kept.
+    pub fn take_endpoints(&mut self) -> HashMap<Option<SocketAddr>, EndpointStats> {
+        std::mem::take(&mut self.endpoints)
+    }
+
+    /// Returns the remote endpoint of socket `handle` of process `pid`, if it connected while tracing.
+    pub fn remote(&self, pid: u64, handle: u64) -> Option<SocketAddr> {
+        self.sockets.get(&(pid, handle)).copied()
+    }
+
+    /// Accounts `event` and delivers it.
+    pub fn record(&mut self, event: SocketEvent) {
+        match event {
+            SocketEvent::Connect { pid, handle, remote } => {
+                let key = (pid as u64, handle);
+                match remote {
+                    Some(remote) => self.sockets.insert(key, remote),
+                    None => self.sockets.remove(&key),
+                };
+                self.endpoints.entry(remote).or_default().connects += 1;
+            }
+            SocketEvent::Accept { .. } => {}
+            SocketEvent::Send { remote, bytes, .. } => {
+                let stats = self.endpoints.entry(remote).or_default();
+                stats.sends += 1;
+                stats.sent_bytes = stats.sent_bytes.saturating_add(bytes);
+            }
+            SocketEvent::Recv { remote, bytes, .. } => {
+                let stats = self.endpoints.entry(remote).or_default();
+                stats.recvs += 1;
+                stats.received_bytes = stats.received_bytes.saturating_add(bytes);
+            }
+        }
+        match &mut self.callback {
+            Some(callback) => callback(&event),
+            None => self.events.push(event),
+        }
+    }
+
+    /// Forgets the socket `handle` of process `pid`, once closed.
+    pub fn close(&mut self, pid: u64, handle: u64) {
+        self.sockets.remove(&(pid, handle));
+    }
+
+    /// Forgets the sockets of process `pid`, e.g. once it exited.
+    pub fn discard_process(&mut self, pid: u64) {
+        self.sockets.retain(|(socket_pid, _), _| *socket_pid != pid);
+    }
+
+    /// Decodes the integers recorded by a request of [`SocketTracker::PROGRAM`].
+    fn request(&self, values: &[i64]) -> Option<SocketEvent> {
+        let (pid, handle, code) = match values {
+            &[pid, handle, code, ..] => (pid as crate::pid_t, handle as u64, code as u32),
+            _ => return None,
+        };
+        let remote = self.remote(pid as u64, handle);
+        let event = match (code, &values[3..]) {
+            (IOCTL_AFD_CONNECT, &[family, port, v4, ref v6 @ ..]) => {
+                let address: Vec<u8> = match v6 {
+                    [high, low] => [high, low].iter().flat_map(|word| word.to_le_bytes()).collect(),
+                    _ => (v4 as u32).to_le_bytes().to_vec(),
+                };
+                SocketEvent::Connect {
+                    pid,
+                    handle,
+                    remote: socket_address(family as u16, (port as u16).to_le_bytes(), &address),
+                }
+            }
+            (IOCTL_AFD_ACCEPT, _) => SocketEvent::Accept { pid, handle },
+            (IOCTL_AFD_SEND, &[bytes, ..]) => SocketEvent::Send {
+                pid,
+                handle,
+                remote,
+                bytes: bytes as u64,
+            },
+            (IOCTL_AFD_RECV, &[bytes, ..]) => SocketEvent::Recv {
+                pid,
+                handle,
+                remote,
+                bytes: bytes as u64,
+            },
+            _ => return None,
+        };
+        Some(event)
+    }
+}
+
+impl RecordVisitor for SocketTracker {
+    fn visit_probe(&mut self, _cpu: crate::processorid_t, probe: Option<&EnabledProbe>) {
+        let function = probe.and_then(|probe| match probe.probe.function.as_str() {
+            "NtDeviceIoControlFile" => Some("NtDeviceIoControlFile"),
+            "NtClose" => Some("NtClose"),
+            _ => None,
+        });
+        self.firing = function.map(|function| (function, Vec::new()));
+    }
+
+    fn visit_int(&mut self, value: i64) {
+        if let Some((_, values)) = &mut self.firing {
+            values.push(value);
+        }
+    }
+
+    /// Decodes the firing if it recorded what [`SocketTracker::PROGRAM`] does.
+    fn visit_end(&mut self) {
+        let (function, values) = match self.firing.take() {
+            Some(firing) => firing,
+            None => return,
+        };
+        match (function, &values[..]) {
+            ("NtClose", &[pid, handle, ..]) => self.close(pid as u64, handle as u64),
+            ("NtDeviceIoControlFile", values) => {
+                if let Some(event) = self.request(values) {
+                    self.record(event);
+                }
+            }
+            _ => {}
+        }
+    }
+}
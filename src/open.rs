@@ -0,0 +1,92 @@
+use crate::utils::Error;
+
+/// How a DTrace instance is opened, replacing the `DTRACE_O_*` flags of `dtrace_hdl::dtrace_open`.
+///
+/// ```no_run
+/// use libdtrace_rs::open::OpenBuilder;
+///
+/// // Compile programs for 32-bit processes without enabling any provider.
+/// let handle = OpenBuilder::new().no_providers().force_32bit().open().unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenBuilder {
+    /// DTrace version to open, `DTRACE_VERSION` by default
+    pub version: i32,
+    /// `DTRACE_O_*` flags
+    pub flags: u32,
+}
+
+impl Default for OpenBuilder {
+    fn default() -> Self {
+        Self {
+            version: crate::DTRACE_VERSION as i32,
+            flags: 0,
+        }
+    }
+}
+
+impl OpenBuilder {
+    /// Creates a builder opening the current DTrace version without flags.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the DTrace version to open. Any version other than the current one fails to open.
+    pub fn version(mut self, version: i32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Adds raw `DTRACE_O_*` flags.
+    pub fn flag(mut self, flag: u32) -> Self {
+        self.flags |= flag;
+        self
+    }
+
+    /// Does not open the DTrace device (`DTRACE_O_NODEV`), e.g. to compile programs without the driver.
+    pub fn no_device(self) -> Self {
+        self.flag(crate::DTRACE_O_NODEV)
+    }
+
+    /// Does not enable any provider (`DTRACE_O_NOSYS`).
+    pub fn no_providers(self) -> Self {
+        self.flag(crate::DTRACE_O_NOSYS)
+    }
+
+    /// Compiles programs for 64-bit processes (`DTRACE_O_LP64`), whatever the data model of the consumer.
+    pub fn force_64bit(self) -> Self {
+        self.flag(crate::DTRACE_O_LP64)
+    }
+
+    /// Compiles programs for 32-bit processes (`DTRACE_O_ILP32`), whatever the data model of the consumer.
+    pub fn force_32bit(self) -> Self {
+        self.flag(crate::DTRACE_O_ILP32)
+    }
+
+    /// Checks that the flags are known and do not contradict each other.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the flags are valid, or an error naming the invalid flags otherwise.
+    pub fn validate(&self) -> Result<(), Error> {
+        let unknown = self.flags & !crate::DTRACE_O_MASK;
+        if unknown != 0 {
+            return Err(Error::new(-1, format!("Unknown open flags {:#x}", unknown)));
+        }
+        let models = crate::DTRACE_O_LP64 | crate::DTRACE_O_ILP32;
+        if self.flags & models == models {
+            return Err(Error::new(-1, "Cannot force both the 64-bit and the 32-bit data model"));
+        }
+        Ok(())
+    }
+
+    /// Opens a DTrace instance with the version and flags of the builder, see `dtrace_hdl::dtrace_open`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the handle, or the error of validating the flags or opening the instance.
+    pub fn open(&self) -> Result<crate::wrapper::dtrace_hdl, Error> {
+        self.validate()?;
+        crate::wrapper::dtrace_hdl::dtrace_open(self.version, self.flags as i32)
+    }
+}
@@ -0,0 +1,137 @@
+//! Parsing and formatting the values of DTrace options the way dtrace(1) does, for applications accepting tuning
+//! strings from their users, e.g. a `--bufsize 4m` or `--switchrate 997hz` command-line flag.
+//!
+//! * Sizes, e.g. `bufsize`, are a number of bytes with an optional binary suffix: `k`, `m`, `g` or `t`, in any case.
+//! * Rates, e.g. `switchrate`, are a frequency in hertz, with or without the `hz` suffix, or the interval between two
+//!   events with a time suffix. libdtrace keeps them as intervals.
+//! * Times are an interval with a time suffix: `ns`, `us`, `ms`, `s`, `m`, `h` or `d`, or their long forms `nsec`,
+//!   `usec`, `msec`, `sec`, `min`, `hour` and `day`, in any case. A number without suffix is a number of nanoseconds.
+//!
+//! Numbers are decimal, hexadecimal with a `0x` prefix or octal with a `0` prefix. The formatters use the largest
+//! suffix that keeps the value exact, so their strings parse back to the same value.
+//!
+//! ```
+//! use libdtrace_rs::options::{format_rate, format_size, parse_rate, parse_size, parse_time};
+//! use std::time::Duration;
+//!
+//! assert_eq!(parse_size("64m").unwrap(), 64 << 20);
+//! assert_eq!(format_size(4096), "4k");
+//! assert_eq!(parse_time("10ms").unwrap(), Duration::from_millis(10));
+//! let rate = parse_rate("997hz").unwrap();
+//! assert_eq!(format_rate(rate), "997hz");
+//! ```
+
+use crate::utils::Error;
+use std::time::Duration;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Suffixes of sizes, with the number of bits they shift by, largest first.
+const SIZE_SUFFIXES: [(char, u32); 4] = [('t', 40), ('g', 30), ('m', 20), ('k', 10)];
+
+/// Suffixes of times, with the number of nanoseconds of their unit, largest first.
+const TIME_SUFFIXES: [(&str, &str, u64); 7] = [
+    ("d", "day", 24 * 60 * 60 * NANOS_PER_SEC),
+    ("h", "hour", 60 * 60 * NANOS_PER_SEC),
+    ("m", "min", 60 * NANOS_PER_SEC),
+    ("s", "sec", NANOS_PER_SEC),
+    ("ms", "msec", 1_000_000),
+    ("us", "usec", 1_000),
+    ("ns", "nsec", 1),
+];
+
+/// Splits `value` into its number, parsed like `strtoull` with base 0, and its suffix.
+fn split_number(value: &str) -> Option<(u64, &str)> {
+    let (radix, digits) = if let Some(hex) = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        (16, hex)
+    } else if value.starts_with('0') {
+        // The leading zero is kept as a digit, so a lone `0` is zero.
+        (8, value)
+    } else {
+        (10, value)
+    };
+    let end = digits.find(|c: char| !c.is_digit(radix)).unwrap_or(digits.len());
+    let number = u64::from_str_radix(&digits[..end], radix).ok()?;
+    Some((number, &digits[end..]))
+}
+
+/// Returns the error of an option value `value` that cannot be parsed as `what`.
+fn invalid(value: &str, what: &str) -> Error {
+    Error::new(-1, format!("Invalid {} {:?}", what, value))
+}
+
+/// Parses a size, e.g. `4k` or `64m`, into a number of bytes, see the [module](self) docs.
+pub fn parse_size(value: &str) -> Result<u64, Error> {
+    let (number, suffix) = split_number(value).ok_or_else(|| invalid(value, "size"))?;
+    let shift = match suffix.to_ascii_lowercase().as_str() {
+        "" => 0,
+        suffix => SIZE_SUFFIXES
+            .iter()
+            .find(|(name, _)| suffix.len() == 1 && suffix.starts_with(*name))
+            .map(|&(_, shift)| shift)
+            .ok_or_else(|| invalid(value, "size"))?,
+    };
+    number.checked_mul(1 << shift).ok_or_else(|| invalid(value, "size"))
+}
+
+/// Formats `size`, a number of bytes, with the largest suffix dividing it, e.g. `4k` for 4096.
+pub fn format_size(size: u64) -> String {
+    SIZE_SUFFIXES
+        .iter()
+        .find(|&&(_, shift)| size != 0 && size.is_multiple_of(1 << shift))
+        .map_or_else(|| size.to_string(), |&(name, shift)| format!("{}{}", size >> shift, name))
+}
+
+/// Parses the number of nanoseconds of a time with a suffix, `None` if `suffix` is not a time suffix.
+fn time_nanos(number: u64, suffix: &str) -> Option<u64> {
+    let suffix = suffix.to_ascii_lowercase();
+    let (_, _, unit) = TIME_SUFFIXES.iter().find(|(short, long, _)| suffix == *short || suffix == *long)?;
+    number.checked_mul(*unit)
+}
+
+/// Parses a time, e.g. `10ms`, see the [module](self) docs.
+pub fn parse_time(value: &str) -> Result<Duration, Error> {
+    match split_number(value) {
+        Some((number, "")) => Ok(Duration::from_nanos(number)),
+        Some((number, suffix)) => time_nanos(number, suffix)
+            .map(Duration::from_nanos)
+            .ok_or_else(|| invalid(value, "time")),
+        None => Err(invalid(value, "time")),
+    }
+}
+
+/// Formats `time` with the largest unit dividing it, e.g. `10ms`. Times are formatted to the nanosecond.
+pub fn format_time(time: Duration) -> String {
+    let nanos = time.as_nanos() as u64;
+    let (short, _, unit) = TIME_SUFFIXES
+        .iter()
+        .find(|&&(_, _, unit)| nanos != 0 && nanos.is_multiple_of(unit))
+        .unwrap_or(&TIME_SUFFIXES[TIME_SUFFIXES.len() - 1]);
+    format!("{}{}", nanos / unit, short)
+}
+
+/// Parses a rate, e.g. `997hz`, `10` or `100ms`, into the interval between two events, as libdtrace keeps it, see the
+/// [module](self) docs.
+pub fn parse_rate(value: &str) -> Result<Duration, Error> {
+    let (number, suffix) = split_number(value).ok_or_else(|| invalid(value, "rate"))?;
+    let nanos = match suffix.eq_ignore_ascii_case("hz") || suffix.is_empty() {
+        true if number == 0 => None,
+        true => Some(NANOS_PER_SEC / number),
+        false => time_nanos(number, suffix),
+    };
+    nanos.map(Duration::from_nanos).ok_or_else(|| invalid(value, "rate"))
+}
+
+/// Formats `interval`, the interval between two events, as a rate: with a time unit if a microsecond or a larger unit
+/// divides it, e.g. `100ms`, as a frequency if it is an exact number of nanoseconds in hertz, e.g. `997hz`, or in
+/// nanoseconds otherwise.
+pub fn format_rate(interval: Duration) -> String {
+    let nanos = interval.as_nanos() as u64;
+    if nanos != 0 && nanos.is_multiple_of(1_000) {
+        return format_time(interval);
+    }
+    match NANOS_PER_SEC.checked_div(nanos) {
+        Some(hertz) if hertz != 0 && NANOS_PER_SEC / hertz == nanos => format!("{}hz", hertz),
+        _ => format_time(interval),
+    }
+}
@@ -0,0 +1,173 @@
+//! Destinations of the output libdtrace formats, e.g. for `printf()` or `printa()`, see [`OutputSink`].
+//!
+//! The wrapper never hands a `FILE *` to libdtrace: output meant for a sink is received by a buffered handler
+//! installed for the duration of the call, and written to the sink.
+//!
+//! ```no_run
+//! use libdtrace_rs::wrapper::dtrace_hdl;
+//!
+//! # let mut handle: dtrace_hdl = unimplemented!();
+//! let mut output = std::fs::File::create("output.txt").unwrap();
+//! handle.dtrace_work(Some(&mut output), None, None, None).unwrap();
+//!
+//! let mut aggregations = Vec::new();
+//! handle.dtrace_aggregate_print(Some(&mut aggregations), None).unwrap();
+//! ```
+//!
+//! On Windows, the output can be consumed live by another local process, e.g. a viewer, through a [`NamedPipe`]:
+//!
+//! ```no_run
+//! use libdtrace_rs::output::NamedPipe;
+//! use libdtrace_rs::session::{Event, TraceSession};
+//! use std::io::Write;
+//!
+//! let mut session = TraceSession::new("syscall:::entry { printf(\"%s\\n\", probefunc); }");
+//! session.start().unwrap();
+//! // Waits for the viewer to open \\.\pipe\dtrace-output.
+//! let mut pipe = NamedPipe::create("dtrace-output").unwrap();
+//! while let Some(event) = session.recv() {
+//!     if let Event::Output(text) = event {
+//!         pipe.write_all(text.as_bytes()).unwrap();
+//!     }
+//! }
+//! ```
+
+use crate::utils::Error;
+use ::core::ffi::{c_int, c_void, CStr};
+
+/// Receives the output formatted by libdtrace.
+///
+/// Implemented for every [`std::io::Write`], e.g. files, standard output or an in-memory `Vec<u8>`.
+pub trait OutputSink {
+    /// Writes a piece of output, which may end in the middle of a line.
+    fn write_output(&mut self, output: &str) -> std::io::Result<()>;
+}
+
+impl<W: std::io::Write> OutputSink for W {
+    fn write_output(&mut self, output: &str) -> std::io::Result<()> {
+        self.write_all(output.as_bytes())
+    }
+}
+
+/// State of [`buffered_to_sink`]: the sink, and the error that stopped writing to it.
+pub(crate) struct SinkBridge<'a> {
+    pub(crate) sink: &'a mut dyn OutputSink,
+    pub(crate) error: Option<std::io::Error>,
+}
+
+impl<'a> SinkBridge<'a> {
+    pub(crate) fn new(sink: &'a mut dyn OutputSink) -> Self {
+        Self { sink, error: None }
+    }
+}
+
+/// Buffered handler writing the output to the [`SinkBridge`] passed as `arg`, aborting at the first error.
+pub(crate) unsafe extern "C" fn buffered_to_sink(bufdata: *const crate::dtrace_bufdata_t, arg: *mut c_void) -> c_int {
+    let bridge = &mut *(arg as *mut SinkBridge);
+    let output = CStr::from_ptr((*bufdata).dtbda_buffered).to_string_lossy();
+    match bridge.sink.write_output(&output) {
+        Ok(()) => crate::DTRACE_HANDLE_OK as c_int,
+        Err(err) => {
+            bridge.error = Some(err);
+            crate::DTRACE_HANDLE_ABORT as c_int
+        }
+    }
+}
+
+/// Prefix of the paths of the named pipes of the local machine.
+pub const PIPE_PREFIX: &str = r"\\.\pipe\";
+
+/// Returns the path of the named pipe `name`: `name` itself if it is a path, e.g. `\\server\pipe\trace`, or the path
+/// of the local pipe `name` otherwise, e.g. `\\.\pipe\trace` for `trace`.
+pub fn pipe_path(name: &str) -> String {
+    match name.starts_with(r"\\") {
+        true => name.to_string(),
+        false => format!("{}{}", PIPE_PREFIX, name),
+    }
+}
+
+/// A Windows named pipe the output is written to, as the server creating it or as a client of another process.
+///
+/// Writes fail once the other end closes the pipe, which stops the writing of the output, see [`OutputSink`].
+#[derive(Debug)]
+pub struct NamedPipe {
+    file: std::fs::File,
+    path: String,
+}
+
+impl NamedPipe {
+    /// Creates the pipe `name`, see [`pipe_path`], and waits for a local process to connect to it.
+    ///
+    /// # Returns
+    ///
+    /// Returns the pipe once a client is connected, or the Windows error, e.g. if the pipe already exists.
+    pub fn create(name: &str) -> Result<Self, Error> {
+        let path = pipe_path(name);
+        #[cfg(windows)]
+        unsafe {
+            use std::os::windows::io::FromRawHandle;
+
+            let wide: Vec<u16> = path.encode_utf16().chain([0]).collect();
+            let handle = crate::win32::CreateNamedPipeW(
+                wide.as_ptr(),
+                crate::win32::PIPE_ACCESS_OUTBOUND,
+                crate::win32::PIPE_TYPE_BYTE | crate::win32::PIPE_WAIT | crate::win32::PIPE_REJECT_REMOTE_CLIENTS,
+                1,
+                64 * 1024,
+                0,
+                0,
+                std::ptr::null_mut(),
+            );
+            if handle == crate::win32::INVALID_HANDLE_VALUE {
+                let error = std::io::Error::last_os_error();
+                return Err(Error::from(error).with_context(format!("creating named pipe {}", path)));
+            }
+            // The file closes the handle from now on.
+            let file = std::fs::File::from_raw_handle(handle);
+            if crate::win32::ConnectNamedPipe(handle, std::ptr::null_mut()) == 0 {
+                let error = std::io::Error::last_os_error();
+                // A client connecting between the creation and the wait is connected as well.
+                if error.raw_os_error() != Some(crate::win32::ERROR_PIPE_CONNECTED) {
+                    return Err(Error::from(error).with_context(format!("waiting for a client of {}", path)));
+                }
+            }
+            Ok(Self { file, path })
+        }
+        #[cfg(not(windows))]
+        Err(Error::new(-1, format!("Cannot create named pipe {}: named pipes are only available on Windows", path)))
+    }
+
+    /// Connects to the pipe `name`, see [`pipe_path`], created by another process, e.g. a viewer.
+    ///
+    /// # Returns
+    ///
+    /// Returns the pipe, or the Windows error, e.g. if the pipe does not exist or all of its instances are busy.
+    pub fn connect(name: &str) -> Result<Self, Error> {
+        let path = pipe_path(name);
+        #[cfg(windows)]
+        {
+            let file = std::fs::OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .map_err(|err| Error::from(err).with_context(format!("connecting to named pipe {}", path)))?;
+            Ok(Self { file, path })
+        }
+        #[cfg(not(windows))]
+        Err(Error::new(-1, format!("Cannot connect to named pipe {}: named pipes are only available on Windows", path)))
+    }
+
+    /// Returns the path of the pipe.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl std::io::Write for NamedPipe {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
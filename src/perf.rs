@@ -0,0 +1,105 @@
+#[cfg(feature = "metrics")]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+/// Time spent by a handle in each layer, see `dtrace_hdl::perf_report`.
+///
+/// Times of concurrent calls on the same handle are approximate, as nested time cannot be attributed to the call it
+/// was spent in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PerfReport {
+    /// Time spent inside libdtrace, excluding the callbacks it made
+    pub libdtrace: Duration,
+    /// Time spent in the probe and record callbacks passed to `dtrace_consume` and `dtrace_work`
+    pub callbacks: Duration,
+    /// Time spent decoding trace data into Rust types, e.g. by `aggregate_entries`
+    pub decoding: Duration,
+    /// Number of libdtrace calls timed
+    pub calls: u64,
+}
+
+/// What time is accounted to.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Section {
+    Callbacks,
+    Decoding,
+}
+
+/// Time counters of a handle, only updated with the `metrics` feature.
+#[derive(Default)]
+pub(crate) struct PerfCounters {
+    #[cfg(feature = "metrics")]
+    libdtrace: AtomicU64,
+    #[cfg(feature = "metrics")]
+    callbacks: AtomicU64,
+    #[cfg(feature = "metrics")]
+    decoding: AtomicU64,
+    #[cfg(feature = "metrics")]
+    calls: AtomicU64,
+}
+
+#[cfg(feature = "metrics")]
+impl PerfCounters {
+    fn counter(&self, section: Section) -> &AtomicU64 {
+        match section {
+            Section::Callbacks => &self.callbacks,
+            Section::Decoding => &self.decoding,
+        }
+    }
+
+    fn nested(&self) -> u64 {
+        self.callbacks.load(Ordering::Relaxed) + self.decoding.load(Ordering::Relaxed)
+    }
+
+    /// Runs `f`, accounting the time it takes to `section`.
+    pub(crate) fn time<T>(&self, section: Section, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.counter(section)
+            .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        result
+    }
+
+    /// Runs the libdtrace call `f`, accounting the time it takes, minus the time accounted to other sections meanwhile.
+    pub(crate) fn time_call<T>(&self, f: impl FnOnce() -> T) -> T {
+        let nested = self.nested();
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed().as_nanos() as u64;
+        let nested = self.nested().saturating_sub(nested);
+        self.libdtrace
+            .fetch_add(elapsed.saturating_sub(nested), Ordering::Relaxed);
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    pub(crate) fn report(&self) -> PerfReport {
+        PerfReport {
+            libdtrace: Duration::from_nanos(self.libdtrace.load(Ordering::Relaxed)),
+            callbacks: Duration::from_nanos(self.callbacks.load(Ordering::Relaxed)),
+            decoding: Duration::from_nanos(self.decoding.load(Ordering::Relaxed)),
+            calls: self.calls.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        for counter in [&self.libdtrace, &self.callbacks, &self.decoding, &self.calls] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+impl PerfCounters {
+    #[inline(always)]
+    pub(crate) fn time<T>(&self, _section: Section, f: impl FnOnce() -> T) -> T {
+        f()
+    }
+
+    #[inline(always)]
+    pub(crate) fn time_call<T>(&self, f: impl FnOnce() -> T) -> T {
+        f()
+    }
+}
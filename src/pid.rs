@@ -0,0 +1,117 @@
+//! Probe descriptions for the pid provider, which instruments the functions of user processes.
+//!
+//! ```
+//! use libdtrace_rs::pid::PidProbe;
+//!
+//! let probe = PidProbe::target("ntdll.dll", "NtCreateFile").offset(0x12);
+//! assert_eq!(probe.to_string(), "pid$target:ntdll.dll:NtCreateFile:12");
+//! ```
+//!
+//! A clause can be limited to some threads of the process, see [`PidProbe::clause`]:
+//!
+//! ```
+//! use libdtrace_rs::pid::PidProbe;
+//! use libdtrace_rs::script::ProcessFilter;
+//!
+//! let clause = PidProbe::process(42, "ntdll.dll", "NtCreateFile").clause(&ProcessFilter::new().tid(7), "ustack();");
+//! assert_eq!(clause, "pid42:ntdll.dll:NtCreateFile:entry /tid == 7/ { ustack(); }");
+//! ```
+
+/// Where a pid probe fires in its function.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PidSite {
+    /// When the function is called
+    #[default]
+    Entry,
+    /// When the function returns
+    Return,
+    /// When the instruction at the given offset from the start of the function executes
+    Offset(u64),
+}
+
+impl std::fmt::Display for PidSite {
+    /// Formats the site as the name of the probe. Offsets are hexadecimal, without prefix.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PidSite::Entry => write!(f, "entry"),
+            PidSite::Return => write!(f, "return"),
+            PidSite::Offset(offset) => write!(f, "{:x}", offset),
+        }
+    }
+}
+
+impl PidSite {
+    /// Parses the name of a pid probe, e.g. `entry` or `1a`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "entry" => Some(PidSite::Entry),
+            "return" => Some(PidSite::Return),
+            _ => u64::from_str_radix(name, 16).ok().map(PidSite::Offset),
+        }
+    }
+}
+
+/// A pid provider probe description, formatted as `pid<process>:<module>:<function>:<site>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PidProbe {
+    /// The traced process, or `None` for the `$target` of the program
+    pub pid: Option<crate::pid_t>,
+    /// The module defining the function, e.g. `ntdll.dll`, or empty for every module
+    pub module: String,
+    /// The function, possibly with wildcards
+    pub function: String,
+    /// Where the probe fires in the function
+    pub site: PidSite,
+}
+
+impl PidProbe {
+    /// Describes the entry of `function` of `module` in the `$target` process of the program.
+    pub fn target(module: &str, function: &str) -> Self {
+        Self {
+            pid: None,
+            module: module.to_string(),
+            function: function.to_string(),
+            site: PidSite::Entry,
+        }
+    }
+
+    /// Describes the entry of `function` of `module` in the process `pid`.
+    pub fn process(pid: crate::pid_t, module: &str, function: &str) -> Self {
+        Self {
+            pid: Some(pid),
+            ..Self::target(module, function)
+        }
+    }
+
+    /// Fires when the function returns instead.
+    pub fn returns(mut self) -> Self {
+        self.site = PidSite::Return;
+        self
+    }
+
+    /// Fires when the instruction at `offset` bytes from the start of the function executes instead. The offset must
+    /// be the start of an instruction, see `dtrace_hdl::pid_offsets`.
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.site = PidSite::Offset(offset);
+        self
+    }
+
+    /// Returns a clause running `actions` when the probe fires in the threads selected by `filter`, e.g. a thread of
+    /// the process, the other firings being discarded in the kernel.
+    pub fn clause(&self, filter: &crate::script::ProcessFilter, actions: &str) -> String {
+        match filter.predicate() {
+            Some(predicate) => format!("{} /{}/ {{ {} }}", self, predicate, actions),
+            None => format!("{} {{ {} }}", self, actions),
+        }
+    }
+}
+
+impl std::fmt::Display for PidProbe {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.pid {
+            Some(pid) => write!(f, "pid{}", pid)?,
+            None => write!(f, "pid$target")?,
+        }
+        write!(f, ":{}:{}:{}", self.module, self.function, self.site)
+    }
+}
@@ -0,0 +1,21 @@
+//! The types most applications need, to import with `use libdtrace_rs::prelude::*`.
+
+pub use crate::aggregate::{
+    AggKey, AggValue, AggregateEntry, AggregateSnapshot, AggregationDescription, Histogram, KeyItem,
+};
+pub use crate::compile::CompileOptions;
+pub use crate::config::{ConsumerConfig, ProgramConfig};
+pub use crate::open::OpenBuilder;
+pub use crate::handler::Handler;
+pub use crate::probe::ProbeDescription;
+pub use crate::session::{
+    Event, Events, LogEntry, MemoryPolicy, OutputPrefix, SessionSummary, Severity, StopReason, SupervisionPolicy,
+    ThreadPriority, TraceSession,
+};
+pub use crate::symbol::{StackFormat, StackOptions};
+pub use crate::target::{AttachTarget, TargetCommand};
+pub use crate::types::{dtrace_aggwalk_order, dtrace_handler, dtrace_handler_kind, dtrace_status, ProbeSpec};
+pub use crate::utils::{Error, ErrorKind};
+pub use crate::visit::RecordVisitor;
+pub use crate::wrapper::dtrace_hdl;
+pub use crate::{dtrace_probespec, dtrace_prog, dtrace_workstatus_t, DTRACE_VERSION};
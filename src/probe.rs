@@ -0,0 +1,383 @@
+use ::core::ffi::{c_char, c_int, c_void};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Owned description of a probe (`provider:module:function:name`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProbeDescription {
+    /// Probe ID
+    pub id: crate::dtrace_id_t,
+    /// Provider name, e.g. `syscall`
+    pub provider: String,
+    /// Module name
+    pub module: String,
+    /// Function name, e.g. `NtCreateFile`
+    pub function: String,
+    /// Probe name, e.g. `entry`
+    pub name: String,
+}
+
+impl From<&crate::dtrace_probedesc_t> for ProbeDescription {
+    fn from(pd: &crate::dtrace_probedesc_t) -> Self {
+        Self {
+            id: pd.dtpd_id,
+            provider: field(&pd.dtpd_provider),
+            module: field(&pd.dtpd_mod),
+            function: field(&pd.dtpd_func),
+            name: field(&pd.dtpd_name),
+        }
+    }
+}
+
+impl ProbeDescription {
+    /// Converts the description back to the form libdtrace takes, truncating names that do not fit.
+    pub(crate) fn to_raw(&self) -> crate::dtrace_probedesc_t {
+        let mut pd: crate::dtrace_probedesc_t = unsafe { std::mem::zeroed() };
+        pd.dtpd_id = self.id;
+        set_field(&mut pd.dtpd_provider, &self.provider);
+        set_field(&mut pd.dtpd_mod, &self.module);
+        set_field(&mut pd.dtpd_func, &self.function);
+        set_field(&mut pd.dtpd_name, &self.name);
+        pd
+    }
+}
+
+impl std::fmt::Display for ProbeDescription {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}:{}",
+            self.provider, self.module, self.function, self.name
+        )
+    }
+}
+
+/// Converts a fixed-size, NUL-terminated name field of a probe description.
+pub(crate) fn field(name: &[c_char]) -> String {
+    let bytes: Vec<u8> = name
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as u8)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Fills a fixed-size name field of a probe description, keeping the terminating NUL.
+fn set_field(field: &mut [c_char], name: &str) {
+    let len = field.len() - 1;
+    for (dst, &src) in field.iter_mut().zip(name.as_bytes().iter().take(len)) {
+        *dst = src as c_char;
+    }
+}
+
+/// Layout of a record of the data recorded on a probe firing or of an aggregation entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RecordDescription {
+    /// Action recording the data, e.g. `DTRACEACT_DIFEXPR`
+    pub action: crate::dtrace_actkind_t,
+    /// Size of the record in bytes
+    pub size: u32,
+    /// Offset of the record from the start of the data
+    pub offset: u32,
+    /// Alignment of the record
+    pub alignment: u16,
+    /// Format string index of the record, `0` if it has none
+    pub format: u16,
+    /// Argument of the action, e.g. the number of frames of a stack
+    pub arg: u64,
+}
+
+impl From<&crate::dtrace_recdesc_t> for RecordDescription {
+    fn from(rec: &crate::dtrace_recdesc_t) -> Self {
+        Self {
+            action: rec.dtrd_action,
+            size: rec.dtrd_size,
+            offset: rec.dtrd_offset,
+            alignment: rec.dtrd_alignment,
+            format: rec.dtrd_format,
+            arg: rec.dtrd_arg,
+        }
+    }
+}
+
+/// An enabled probe, i.e. a clause of a D program bound to a probe, identified by its EPID.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EnabledProbe {
+    /// Enabled probe ID
+    pub epid: crate::dtrace_epid_t,
+    /// User argument of the enabling
+    pub uarg: u64,
+    /// Size of the data recorded on each firing
+    pub size: u32,
+    /// Number of records recorded on each firing
+    pub nrecs: usize,
+    /// Layout of the records recorded on each firing
+    pub records: Vec<RecordDescription>,
+    /// Description of the probe
+    pub probe: ProbeDescription,
+}
+
+impl EnabledProbe {
+    /// Builds an enabled probe from its enabling and probe descriptions.
+    ///
+    /// # Safety
+    ///
+    /// `edesc` and `pdesc` must be valid pointers owned by a DTrace instance.
+    pub unsafe fn from_raw(
+        edesc: *const crate::dtrace_eprobedesc_t,
+        pdesc: *const crate::dtrace_probedesc_t,
+    ) -> Self {
+        let edesc = &*edesc;
+        let nrecs = edesc.dtepd_nrecs.max(0) as usize;
+        // The records are allocated past the end of the description.
+        let records = std::slice::from_raw_parts(edesc.dtepd_rec.as_ptr(), nrecs);
+        Self {
+            epid: edesc.dtepd_epid,
+            uarg: edesc.dtepd_uarg,
+            size: edesc.dtepd_size,
+            nrecs,
+            records: records.iter().map(RecordDescription::from).collect(),
+            probe: ProbeDescription::from(&*pdesc),
+        }
+    }
+}
+
+/// EPID to enabled probe lookup cache kept by each `dtrace_hdl`.
+#[derive(Default)]
+pub(crate) struct EpidCache {
+    probes: Mutex<HashMap<crate::dtrace_epid_t, Arc<EnabledProbe>>>,
+}
+
+impl EpidCache {
+    /// Looks up `epid` in the cache, falling back to the descriptions known to `handle`.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be a valid DTrace handle.
+    pub(crate) unsafe fn lookup(
+        &self,
+        handle: *mut crate::dtrace_hdl_t,
+        epid: crate::dtrace_epid_t,
+    ) -> Option<Arc<EnabledProbe>> {
+        let mut probes = self.probes.lock().unwrap();
+        if let Some(probe) = probes.get(&epid) {
+            return Some(probe.clone());
+        }
+
+        let hdl = &*handle;
+        if epid >= hdl.dt_maxprobe || hdl.dt_edesc.is_null() || hdl.dt_pdesc.is_null() {
+            return None;
+        }
+        let edesc = *hdl.dt_edesc.add(epid as usize);
+        let pdesc = *hdl.dt_pdesc.add(epid as usize);
+        if edesc.is_null() || pdesc.is_null() {
+            return None;
+        }
+
+        let probe = Arc::new(EnabledProbe::from_raw(edesc, pdesc));
+        probes.insert(epid, probe.clone());
+        Some(probe)
+    }
+}
+
+/// Modules that were loaded or unloaded since the previous update, see `dtrace_hdl::update_modules`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ModuleChanges {
+    /// Names of the modules that were loaded
+    pub loaded: Vec<String>,
+    /// Names of the modules that were unloaded
+    pub unloaded: Vec<String>,
+}
+
+impl ModuleChanges {
+    /// Returns `true` if no module was loaded or unloaded.
+    pub fn is_empty(&self) -> bool {
+        self.loaded.is_empty() && self.unloaded.is_empty()
+    }
+}
+
+/// Stability attributes, as listed by `dtrace -lv`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Attributes {
+    /// Stability of the names, e.g. `Evolving`
+    pub name: String,
+    /// Stability of the data, e.g. `Evolving`
+    pub data: String,
+    /// Dependency class, e.g. `Common` or `ISA`
+    pub class: String,
+}
+
+impl From<&crate::dtrace_attribute_t> for Attributes {
+    fn from(attr: &crate::dtrace_attribute_t) -> Self {
+        let name = |ptr: *const c_char| crate::diagnostics::message(ptr);
+        unsafe {
+            Self {
+                name: name(crate::dtrace_stability_name(attr.dtat_name)),
+                data: name(crate::dtrace_stability_name(attr.dtat_data)),
+                class: name(crate::dtrace_class_name(attr.dtat_class)),
+            }
+        }
+    }
+}
+
+/// A probe matched by a statement, see [`StatementListing`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ListedProbe {
+    /// Description of the probe
+    pub probe: ProbeDescription,
+    /// Stability of the probe
+    pub attributes: Attributes,
+    /// Stability of the arguments of the probe
+    pub arg_attributes: Attributes,
+}
+
+/// The probes a statement of a program enables, see `dtrace_hdl::program_listing`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StatementListing {
+    /// The probe description of the statement as written, possibly with wildcards or empty fields
+    pub description: ProbeDescription,
+    /// Stability of the probe description
+    pub attributes: Attributes,
+    /// The probes matching the description
+    pub probes: Vec<ListedProbe>,
+}
+
+/// Format of a probe catalog, see `dtrace_hdl::export_probe_catalog`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CatalogFormat {
+    /// A JSON array with one object per probe
+    Json,
+    /// CSV with a header row and one row per probe, the argument types separated by `;`
+    Csv,
+}
+
+/// A probe of the catalog of a DTrace instance, see `dtrace_hdl::probe_catalog`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CatalogEntry {
+    /// The probe with its stability
+    pub listing: ListedProbe,
+    /// Names of the native types of the arguments of the probe, empty for arguments without type data
+    pub args: Vec<String>,
+}
+
+impl CatalogEntry {
+    /// Writes the entry as a JSON object, without trailing newline.
+    pub fn write_json(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        let attributes = |attr: &Attributes| {
+            format!(
+                "{{\"name\":{},\"data\":{},\"class\":{}}}",
+                json_string(&attr.name),
+                json_string(&attr.data),
+                json_string(&attr.class)
+            )
+        };
+        let probe = &self.listing.probe;
+        let args: Vec<String> = self.args.iter().map(|arg| json_string(arg)).collect();
+        write!(
+            writer,
+            "{{\"id\":{},\"provider\":{},\"module\":{},\"function\":{},\"name\":{},\"attributes\":{},\"arg_attributes\":{},\"args\":[{}]}}",
+            probe.id,
+            json_string(&probe.provider),
+            json_string(&probe.module),
+            json_string(&probe.function),
+            json_string(&probe.name),
+            attributes(&self.listing.attributes),
+            attributes(&self.listing.arg_attributes),
+            args.join(",")
+        )
+    }
+
+    /// Header row of [`CatalogEntry::write_csv`], without trailing newline.
+    pub const CSV_HEADER: &'static str =
+        "id,provider,module,function,name,name_stability,data_stability,class,arg_name_stability,arg_data_stability,arg_class,args";
+
+    /// Writes the entry as a CSV row, without trailing newline.
+    pub fn write_csv(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        let probe = &self.listing.probe;
+        let (attr, arga) = (&self.listing.attributes, &self.listing.arg_attributes);
+        let fields = [
+            &probe.provider,
+            &probe.module,
+            &probe.function,
+            &probe.name,
+            &attr.name,
+            &attr.data,
+            &attr.class,
+            &arga.name,
+            &arga.data,
+            &arga.class,
+            &self.args.join(";"),
+        ];
+        write!(writer, "{}", probe.id)?;
+        for field in fields {
+            write!(writer, ",{}", csv_field(field))?;
+        }
+        Ok(())
+    }
+}
+
+/// Quotes `value` as a JSON string.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Quotes `value` as a CSV field if it contains a separator, a quote or a line break.
+fn csv_field(value: &str) -> std::borrow::Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\"")).into()
+    } else {
+        value.into()
+    }
+}
+
+/// Statement iterator appending the probe description and its attributes of every statement to the
+/// `Vec<(dtrace_probedesc_t, dtrace_attribute_t)>` passed as `arg`.
+pub(crate) unsafe extern "C" fn collect_stmt_probe(
+    _handle: *mut crate::dtrace_hdl_t,
+    _program: *mut crate::dtrace_prog_t,
+    stmt: *mut crate::dtrace_stmtdesc_t,
+    arg: *mut c_void,
+) -> c_int {
+    let descs = &mut *(arg as *mut Vec<(crate::dtrace_probedesc_t, crate::dtrace_attribute_t)>);
+    descs.push(((*(*stmt).dtsd_ecbdesc).dted_probe, (*stmt).dtsd_descattr));
+    0
+}
+
+/// Statement iterator appending the ECB description and the probe description of every statement to the
+/// `Vec<(usize, dtrace_probedesc_t)>` passed as `arg`. Statements compiled from the same probe description of a
+/// clause share their ECB description.
+pub(crate) unsafe extern "C" fn collect_stmt_ecb(
+    _handle: *mut crate::dtrace_hdl_t,
+    _program: *mut crate::dtrace_prog_t,
+    stmt: *mut crate::dtrace_stmtdesc_t,
+    arg: *mut c_void,
+) -> c_int {
+    let ecbs = &mut *(arg as *mut Vec<(usize, crate::dtrace_probedesc_t)>);
+    ecbs.push(((*stmt).dtsd_ecbdesc as usize, (*(*stmt).dtsd_ecbdesc).dted_probe));
+    0
+}
+
+/// Probe iterator appending every matched probe to the `Vec<dtrace_probedesc_t>` passed as `arg`.
+pub(crate) unsafe extern "C" fn collect_probe(
+    _handle: *mut crate::dtrace_hdl_t,
+    probe: *const crate::dtrace_probedesc_t,
+    arg: *mut c_void,
+) -> c_int {
+    let probes = &mut *(arg as *mut Vec<crate::dtrace_probedesc_t>);
+    probes.push(*probe);
+    0
+}
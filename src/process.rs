@@ -0,0 +1,174 @@
+//! Monitoring the processes started and exiting, delivered as typed events by a [`ProcessMonitor`].
+//!
+//! Windows has no `proc` provider: the monitor enables the creation of processes in the kernel, `PspInsertProcess`,
+//! within the `NtCreateUserProcess` system call of their parent, and the exits of processes through
+//! `NtTerminateProcess`, see [`ProcessMonitor::PROGRAM`]. The program needs the symbols of the kernel, see the
+//! `sympath` option.
+//!
+//! ```no_run
+//! use libdtrace_rs::process::{ProcessEvent, ProcessMonitor};
+//! use libdtrace_rs::session::TraceSession;
+//! use std::sync::{Arc, Mutex};
+//!
+//! let monitor = ProcessMonitor::new().on_event(|event| match event {
+//!     ProcessEvent::Started(started) => println!("{} started {} ({})", started.ppid, started.pid, started.cmdline),
+//!     ProcessEvent::Exited(exited) => println!("{} exited with {}", exited.pid, exited.code),
+//! });
+//! let mut session = TraceSession::new(ProcessMonitor::PROGRAM)
+//!     .with_option("quiet", "")
+//!     .with_visitor(Arc::new(Mutex::new(monitor)));
+//! session.start().unwrap();
+//! std::thread::sleep(std::time::Duration::from_secs(60));
+//! session.stop();
+//! ```
+
+use crate::probe::EnabledProbe;
+use crate::visit::RecordVisitor;
+
+/// A process was created.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProcessStarted {
+    /// ID of the new process
+    pub pid: crate::pid_t,
+    /// ID of the process that created it
+    pub ppid: crate::pid_t,
+    /// Path of the image of the new process
+    pub image: String,
+    /// Command line of the new process
+    pub cmdline: String,
+}
+
+/// A process exited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProcessExited {
+    /// ID of the process
+    pub pid: crate::pid_t,
+    /// Exit code of the process
+    pub code: i32,
+}
+
+/// What a [`ProcessMonitor`] saw happen.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ProcessEvent {
+    /// A process was created
+    Started(ProcessStarted),
+    /// A process exited
+    Exited(ProcessExited),
+}
+
+/// The records of the firing being visited, by kind.
+#[derive(Debug, Default)]
+struct Firing {
+    ints: Vec<i64>,
+    strings: Vec<String>,
+}
+
+/// A callback receiving the events of a [`ProcessMonitor`], see `on_event`.
+type ProcessEventCallback = Box<dyn FnMut(&ProcessEvent) + Send>;
+
+/// Decodes the firings of [`ProcessMonitor::PROGRAM`] into [`ProcessEvent`]s, see the [module](self) docs.
+///
+/// The events are kept until taken with `take_events`, unless a callback receives them, see `on_event`.
+#[derive(Default)]
+pub struct ProcessMonitor {
+    events: Vec<ProcessEvent>,
+    callback: Option<ProcessEventCallback>,
+    /// The kind of event the firing being visited describes, with its records
+    firing: Option<(Kind, Firing)>,
+}
+
+/// The kind of event a probe of [`ProcessMonitor::PROGRAM`] describes.
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Started,
+    Exited,
+}
+
+impl ProcessMonitor {
+    /// A D program recording the processes created and exiting, as decoded by the monitor.
+    ///
+    /// A process creation records the ID of the new process, the ID of its parent, then the image path and the command
+    /// line the parent passed. An exit records the ID of the process, then its exit code. Processes terminated by
+    /// another process are not reported.
+    pub const PROGRAM: &'static str = r#"syscall::NtCreateUserProcess:entry { self->params = arg8; }
+fbt:nt:PspInsertProcess:entry /self->params/ {
+    this->params = (nt`_RTL_USER_PROCESS_PARAMETERS *)copyin(self->params, sizeof(nt`_RTL_USER_PROCESS_PARAMETERS));
+    trace((int64_t)((nt`_EPROCESS *)arg0)->UniqueProcessId);
+    trace(pid);
+    trace(wstr2str((wchar_t *)copyin((uintptr_t)this->params->ImagePathName.Buffer,
+        this->params->ImagePathName.Length), this->params->ImagePathName.Length / 2));
+    trace(wstr2str((wchar_t *)copyin((uintptr_t)this->params->CommandLine.Buffer,
+        this->params->CommandLine.Length), this->params->CommandLine.Length / 2));
+}
+syscall::NtCreateUserProcess:return { self->params = 0; }
+syscall::NtTerminateProcess:entry /(int64_t)arg0 == -1/ { trace(pid); trace((int)arg1); }"#;
+
+    /// Creates a monitor without events.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hands the events to `callback` as they are decoded, instead of keeping them.
+    pub fn on_event(mut self, callback: impl FnMut(&ProcessEvent) + Send + 'static) -> Self {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Returns the events kept so far and forgets them.
+    pub fn take_events(&mut self) -> Vec<ProcessEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Delivers `event`.
+    fn emit(&mut self, event: ProcessEvent) {
+        match &mut self.callback {
+            Some(callback) => callback(&event),
+            None => self.events.push(event),
+        }
+    }
+}
+
+impl RecordVisitor for ProcessMonitor {
+    fn visit_probe(&mut self, _cpu: crate::processorid_t, probe: Option<&EnabledProbe>) {
+        let kind = match probe.map(|probe| probe.probe.function.as_str()) {
+            Some("PspInsertProcess") => Some(Kind::Started),
+            Some("NtTerminateProcess") => Some(Kind::Exited),
+            _ => None,
+        };
+        self.firing = kind.map(|kind| (kind, Firing::default()));
+    }
+
+    fn visit_int(&mut self, value: i64) {
+        if let Some((_, firing)) = &mut self.firing {
+            firing.ints.push(value);
+        }
+    }
+
+    fn visit_string(&mut self, value: &str) {
+        if let Some((_, firing)) = &mut self.firing {
+            firing.strings.push(value.to_string());
+        }
+    }
+
+    /// Delivers the event of the firing if it recorded what [`ProcessMonitor::PROGRAM`] does.
+    fn visit_end(&mut self) {
+        let (kind, mut firing) = match self.firing.take() {
+            Some(firing) => firing,
+            None => return,
+        };
+        let event = match (kind, &firing.ints[..], &mut firing.strings[..]) {
+            (Kind::Started, &[pid, ppid, ..], [image, cmdline, ..]) => ProcessEvent::Started(ProcessStarted {
+                pid: pid as crate::pid_t,
+                ppid: ppid as crate::pid_t,
+                image: std::mem::take(image),
+                cmdline: std::mem::take(cmdline),
+            }),
+            (Kind::Exited, &[pid, code, ..], _) => ProcessEvent::Exited(ProcessExited {
+                pid: pid as crate::pid_t,
+                code: code as i32,
+            }),
+            _ => return,
+        };
+        self.emit(event);
+    }
+}
@@ -0,0 +1,94 @@
+//! Consuming without decoding, for consumers that parse the records themselves and need the least overhead, see
+//! `dtrace_hdl::work_raw`.
+//!
+//! A [`RawConsumer`] receives the `dtrace_probedata_t` and `dtrace_recdesc_t` of libdtrace as they are, borrowed for
+//! the duration of the callback. Nothing is looked up, decoded, counted or timed on the way: the probe statistics and
+//! performance counters of the handle do not account raw consumption.
+//!
+//! ```no_run
+//! use libdtrace_rs::raw::{self, RawAction};
+//!
+//! # let mut handle: libdtrace_rs::wrapper::dtrace_hdl = unimplemented!();
+//! let mut bytes = 0;
+//! handle
+//!     .work_raw(&mut |data: &libdtrace_rs::dtrace_probedata_t, rec: Option<&libdtrace_rs::dtrace_recdesc_t>| {
+//!         if let Some(rec) = rec {
+//!             bytes += unsafe { raw::record_bytes(data, rec) }.len();
+//!         }
+//!         RawAction::Next
+//!     })
+//!     .unwrap();
+//! ```
+
+use ::core::ffi::{c_int, c_void};
+
+/// What libdtrace does after a raw callback returns.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawAction {
+    /// Process the firing or the record as usual, formatting its output
+    This = crate::DTRACE_CONSUME_THIS as i32,
+    /// Skip the firing, from the probe callback, or the record, from the record callback
+    Next = crate::DTRACE_CONSUME_NEXT as i32,
+    /// Stop consuming, failing the call with `EDT_DIRABORT`
+    Abort = crate::DTRACE_CONSUME_ABORT as i32,
+}
+
+/// Receives the firings consumed by `dtrace_hdl::work_raw` and `dtrace_hdl::consume_raw`, see the [module](self)
+/// docs.
+///
+/// Closures taking the probe data and the record description implement it as record callbacks.
+pub trait RawConsumer {
+    /// Called for each firing, before its records.
+    fn probe(&mut self, _data: &crate::dtrace_probedata_t) -> RawAction {
+        RawAction::This
+    }
+
+    /// Called for each record of a firing, then once with `None` at the end of the firing.
+    fn record(&mut self, data: &crate::dtrace_probedata_t, rec: Option<&crate::dtrace_recdesc_t>) -> RawAction;
+}
+
+impl<F> RawConsumer for F
+where
+    F: FnMut(&crate::dtrace_probedata_t, Option<&crate::dtrace_recdesc_t>) -> RawAction,
+{
+    fn record(&mut self, data: &crate::dtrace_probedata_t, rec: Option<&crate::dtrace_recdesc_t>) -> RawAction {
+        self(data, rec)
+    }
+}
+
+/// Returns the bytes of `rec` in the data of the firing. libdtrace points `dtpda_data` at the record it passes, so
+/// the record starts there rather than at `dtrd_offset`.
+///
+/// # Safety
+///
+/// `data` and `rec` must be the ones handed to a [`RawConsumer`] by libdtrace, or `dtpda_data` must point at
+/// `dtrd_size` readable bytes all the same.
+pub unsafe fn record_bytes<'a>(data: &'a crate::dtrace_probedata_t, rec: &crate::dtrace_recdesc_t) -> &'a [u8] {
+    std::slice::from_raw_parts(data.dtpda_data as *const u8, rec.dtrd_size as usize)
+}
+
+/// Returns the callbacks and argument handing the firings to `consumer`.
+pub(crate) fn callbacks<C: RawConsumer>(
+    consumer: &mut C,
+) -> (crate::dtrace_consume_probe_f, crate::dtrace_consume_rec_f, *mut c_void) {
+    (
+        Some(raw_probe::<C>),
+        Some(raw_rec::<C>),
+        consumer as *mut C as *mut c_void,
+    )
+}
+
+unsafe extern "C" fn raw_probe<C: RawConsumer>(data: *const crate::dtrace_probedata_t, arg: *mut c_void) -> c_int {
+    let consumer = &mut *(arg as *mut C);
+    consumer.probe(&*data) as c_int
+}
+
+unsafe extern "C" fn raw_rec<C: RawConsumer>(
+    data: *const crate::dtrace_probedata_t,
+    rec: *const crate::dtrace_recdesc_t,
+    arg: *mut c_void,
+) -> c_int {
+    let consumer = &mut *(arg as *mut C);
+    consumer.record(&*data, rec.as_ref()) as c_int
+}
@@ -0,0 +1,110 @@
+use crate::aggregate::{AggKey, AggValue, AggregateEntry, Histogram, HistogramKind, KeyItem};
+use std::fmt::Write;
+
+/// Width of the distribution bar drawn by [`histogram`]
+const BAR_WIDTH: usize = 40;
+
+/// Renders aggregation entries the way `dtrace(1)` prints them with `printa()`.
+///
+/// Scalar aggregations (`count()`, `sum()`, `avg()`, ...) are printed as a table with one line
+/// per key and a right-aligned value column, quantizing aggregations are printed as the key
+/// followed by an `@` histogram.
+///
+/// # Arguments
+///
+/// * `entries` - The entries to render, usually all entries of one aggregation variable.
+///
+/// # Returns
+///
+/// Returns the rendered output as a [`String`].
+pub fn aggregation(entries: &[AggregateEntry]) -> String {
+    let mut out = String::from("\n");
+    for entry in entries {
+        match Histogram::from_value(&entry.value) {
+            Some(hist) => {
+                out.push_str(&keys(&entry.key));
+                out.push('\n');
+                out.push_str(&histogram(&hist));
+                out.push('\n');
+            }
+            None => {
+                out.push_str(&keys(&entry.key));
+                let _ = writeln!(out, " {:>16}", scalar(&entry.value));
+            }
+        }
+    }
+    out
+}
+
+/// Renders a histogram like `dtrace(1)` prints `quantize()`, `lquantize()` and `llquantize()`
+/// aggregations.
+///
+/// Leading and trailing empty buckets are trimmed, keeping one empty bucket on each side.
+///
+/// # Arguments
+///
+/// * `hist` - The histogram to render.
+///
+/// # Returns
+///
+/// Returns the rendered output as a [`String`].
+pub fn histogram(hist: &Histogram) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{:>16} {:>41} {:<9}",
+        "value", "------------- Distribution -------------", "count"
+    );
+
+    let buckets = &hist.buckets;
+    if buckets.is_empty() {
+        return out;
+    }
+
+    let (first, last) = match buckets.iter().position(|bucket| bucket.count != 0) {
+        Some(first) => {
+            let last = buckets.iter().rposition(|bucket| bucket.count != 0).unwrap();
+            (first.saturating_sub(1), (last + 1).min(buckets.len() - 1))
+        }
+        None => (0, buckets.len() - 1),
+    };
+
+    let total: i64 = buckets[first..=last].iter().map(|bucket| bucket.count.abs()).sum();
+    for (i, bucket) in buckets.iter().enumerate().take(last + 1).skip(first) {
+        let label = match hist.kind {
+            HistogramKind::Quantize => bucket.value.to_string(),
+            _ if i == 0 => format!("< {}", bucket.value),
+            _ if i == buckets.len() - 1 => format!(">= {}", bucket.value),
+            _ => bucket.value.to_string(),
+        };
+        let depth = if total == 0 {
+            0
+        } else {
+            ((bucket.count.abs() as f64 * BAR_WIDTH as f64) / total as f64 + 0.5) as usize
+        };
+        let _ = writeln!(
+            out,
+            "{:>16} |{}{} {:<9}",
+            label,
+            "@".repeat(depth),
+            " ".repeat(BAR_WIDTH - depth),
+            bucket.count
+        );
+    }
+    out
+}
+
+fn keys(key: &AggKey) -> String {
+    let mut out = String::new();
+    for item in &key.0 {
+        let _ = match item {
+            KeyItem::Int(value) => write!(out, " {:>16}", value),
+            _ => write!(out, "  {:<50}", item.to_string()),
+        };
+    }
+    out
+}
+
+pub(crate) fn scalar(value: &AggValue) -> i64 {
+    value.as_i64()
+}
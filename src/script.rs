@@ -0,0 +1,360 @@
+/// Keywords starting top-level declarations whose braces do not delimit a clause body.
+const DECLARATIONS: [&str; 7] = [
+    "struct",
+    "union",
+    "enum",
+    "translator",
+    "typedef",
+    "inline",
+    "provider",
+];
+
+/// Adds `predicate` to every clause of the D program `program`.
+///
+/// Clauses without a predicate gain `/predicate/`, clauses with one have it combined as
+/// `/(existing) && (predicate)/`, so e.g. a whole script can be limited to one process with `pid == $target`.
+/// Comments, strings, preprocessor lines and declarations are left untouched, and so are the clauses of the `BEGIN`,
+/// `END` and `ERROR` probes, which fire in the consumer rather than in the traced processes.
+///
+/// # Arguments
+///
+/// * `program` - The source of the D program.
+/// * `predicate` - The D expression every clause should additionally require.
+///
+/// # Returns
+///
+/// Returns the rewritten source.
+pub fn with_predicate(program: &str, predicate: &str) -> String {
+    let mut out = String::with_capacity(program.len() + predicate.len() * 4);
+    let mut copied = 0;
+    for header in clause_headers(program) {
+        let text = &program[header.clone()];
+        if is_dtrace_clause(text) {
+            continue;
+        }
+        out.push_str(&program[copied..header.start]);
+        out.push_str(&add_predicate(text, predicate));
+        copied = header.end;
+    }
+    out.push_str(&program[copied.min(program.len())..]);
+    out
+}
+
+/// Selects the firings of a process or a thread with a predicate, so that the other firings are discarded in the kernel
+/// before anything is recorded, instead of being consumed and filtered out, see `filter::ProbeFilter`.
+///
+/// ```
+/// use libdtrace_rs::script::ProcessFilter;
+///
+/// let filter = ProcessFilter::new().execname("notepad.exe").tid(42);
+/// assert_eq!(filter.predicate().unwrap(), "execname == \"notepad.exe\" && tid == 42");
+/// assert_eq!(filter.apply("syscall:::entry { @[probefunc] = count(); }"),
+///     "syscall:::entry /execname == \"notepad.exe\" && tid == 42/ { @[probefunc] = count(); }");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ProcessFilter {
+    /// Name of the selected processes, compared to `execname`
+    pub execname: Option<String>,
+    /// ID of the selected process
+    pub pid: Option<crate::pid_t>,
+    /// ID of the selected thread
+    pub tid: Option<u64>,
+}
+
+impl ProcessFilter {
+    /// Creates a filter selecting every firing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Selects the firings in the processes named `execname`, e.g. `notepad.exe`.
+    pub fn execname(mut self, execname: &str) -> Self {
+        self.execname = Some(execname.to_string());
+        self
+    }
+
+    /// Selects the firings in process `pid`.
+    pub fn pid(mut self, pid: crate::pid_t) -> Self {
+        self.pid = Some(pid);
+        self
+    }
+
+    /// Selects the firings in thread `tid`.
+    pub fn tid(mut self, tid: u64) -> Self {
+        self.tid = Some(tid);
+        self
+    }
+
+    /// Returns `true` if the filter selects every firing.
+    pub fn is_empty(&self) -> bool {
+        self.predicate().is_none()
+    }
+
+    /// Returns the D expression selecting the firings, `None` if the filter selects every firing.
+    pub fn predicate(&self) -> Option<String> {
+        let mut predicates = Vec::new();
+        if let Some(execname) = &self.execname {
+            predicates.push(format!("execname == \"{}\"", execname.replace('\\', "\\\\").replace('"', "\\\"")));
+        }
+        if let Some(pid) = self.pid {
+            predicates.push(format!("pid == {}", pid));
+        }
+        if let Some(tid) = self.tid {
+            predicates.push(format!("tid == {}", tid));
+        }
+        (!predicates.is_empty()).then(|| predicates.join(" && "))
+    }
+
+    /// Returns the D program `program` with every clause limited to the selected firings, see [`with_predicate`].
+    pub fn apply(&self, program: &str) -> String {
+        match self.predicate() {
+            Some(predicate) => with_predicate(program, &predicate),
+            None => program.to_string(),
+        }
+    }
+}
+
+/// A clause of a D program, see [`clauses`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Clause {
+    /// Line the clause starts at, 1-based
+    pub line: usize,
+    /// The probe descriptions and predicate of the clause on one line, without comments
+    pub header: String,
+    /// Number of probe descriptions of the clause
+    pub descriptions: usize,
+}
+
+/// Lists the clauses of the D program `program`, in source order.
+///
+/// libdtrace enables each probe description of a clause as one ECB (enabling control block) in that order, which
+/// allows attributing the ECBs of a program back to its source.
+pub fn clauses(program: &str) -> Vec<Clause> {
+    clause_headers(program)
+        .into_iter()
+        .map(|header| {
+            let text = &program[header.clone()];
+            let start = header.start + code_start(text.as_bytes());
+            let header = strip_comments(text).split_whitespace().collect::<Vec<_>>().join(" ");
+            let descriptions = header[..predicate_start(&header).unwrap_or(header.len())]
+                .split(',')
+                .filter(|desc| !desc.trim().is_empty())
+                .count();
+            Clause {
+                line: program[..start].matches('\n').count() + 1,
+                header,
+                descriptions,
+            }
+        })
+        .collect()
+}
+
+/// Returns the index of the first character of `text` that is neither whitespace nor part of a comment.
+fn code_start(text: &[u8]) -> usize {
+    let mut i = 0;
+    while i < text.len() {
+        match text[i] {
+            b'/' if text.get(i + 1) == Some(&b'/') => i = skip_until(text, i, b"\n"),
+            b'/' if text.get(i + 1) == Some(&b'*') => i = skip_until(text, i + 2, b"*/") + 2,
+            c if c.is_ascii_whitespace() => i += 1,
+            _ => return i,
+        }
+    }
+    text.len()
+}
+
+/// Returns the ranges of the headers of the clauses of `program`, the text between the previous top-level item and
+/// the `{` opening the clause body.
+fn clause_headers(program: &str) -> Vec<std::ops::Range<usize>> {
+    let bytes = program.as_bytes();
+    let mut headers = Vec::new();
+    let mut header_start = 0;
+    let mut depth = 0usize;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = skip_until(bytes, i, b"\n");
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = skip_until(bytes, i + 2, b"*/") + 2;
+                continue;
+            }
+            b'"' | b'\'' => {
+                i = skip_literal(bytes, i);
+                continue;
+            }
+            b'#' if depth == 0 && is_line_start(bytes, i) => {
+                i = skip_until(bytes, i, b"\n");
+                header_start = i;
+                continue;
+            }
+            b';' if depth == 0 => header_start = i + 1,
+            b'{' => {
+                if depth == 0 && is_clause(&program[header_start..i]) {
+                    headers.push(header_start..i);
+                }
+                depth += 1;
+            }
+            b'}' => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    header_start = i + 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    headers
+}
+
+/// Returns the index of the start of `end` at or after `from`, or the end of `bytes`.
+fn skip_until(bytes: &[u8], from: usize, end: &[u8]) -> usize {
+    (from..bytes.len())
+        .find(|&i| bytes[i..].starts_with(end))
+        .unwrap_or(bytes.len())
+}
+
+/// Returns the index after the string or character literal starting at `from`.
+fn skip_literal(bytes: &[u8], from: usize) -> usize {
+    let quote = bytes[from];
+    let mut i = from + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            c if c == quote => return i + 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+fn is_line_start(bytes: &[u8], at: usize) -> bool {
+    bytes[..at]
+        .iter()
+        .rev()
+        .take_while(|&&c| c != b'\n')
+        .all(|c| c.is_ascii_whitespace())
+}
+
+/// Returns `true` if `header`, the text between the previous top-level item and a `{`, introduces a clause.
+fn is_clause(header: &str) -> bool {
+    let header = strip_comments(header);
+    match header.split_whitespace().next() {
+        Some(word) => !DECLARATIONS.contains(&word),
+        None => false,
+    }
+}
+
+/// Returns the ranges of `text` outside of `/* */` and `//` comments. String and character literals are kept whole,
+/// so comment markers inside them are not taken for comments.
+fn code_ranges(text: &str) -> Vec<std::ops::Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut ranges = Vec::new();
+    let mut code_start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let end = if bytes[i..].starts_with(b"//") {
+            skip_until(bytes, i, b"\n")
+        } else if bytes[i..].starts_with(b"/*") {
+            (skip_until(bytes, i + 2, b"*/") + 2).min(bytes.len())
+        } else {
+            i = match bytes[i] {
+                b'"' | b'\'' => skip_literal(bytes, i),
+                _ => i + 1,
+            };
+            continue;
+        };
+        if code_start < i {
+            ranges.push(code_start..i);
+        }
+        code_start = end;
+        i = end;
+    }
+    if code_start < bytes.len() {
+        ranges.push(code_start..bytes.len());
+    }
+    ranges
+}
+
+fn strip_comments(text: &str) -> String {
+    code_ranges(text).into_iter().map(|range| &text[range]).collect()
+}
+
+/// Returns the indices of the '/' characters of `header` outside of comments and literals.
+fn slashes(header: &str) -> Vec<usize> {
+    let bytes = header.as_bytes();
+    let mut slashes = Vec::new();
+    for range in code_ranges(header) {
+        let mut i = range.start;
+        while i < range.end {
+            match bytes[i] {
+                b'"' | b'\'' => i = skip_literal(bytes, i),
+                b'/' => {
+                    slashes.push(i);
+                    i += 1;
+                }
+                _ => i += 1,
+            }
+        }
+    }
+    slashes
+}
+
+/// Adds `predicate` to the clause header `header`, made of probe descriptions and an optional predicate.
+fn add_predicate(header: &str, predicate: &str) -> String {
+    let slashes = slashes(header);
+    if let (Some(&open), Some(&close)) = (slashes.first(), slashes.last()) {
+        if open < close {
+            return format!(
+                "{}/({}) && ({})/{}",
+                &header[..open],
+                &header[open + 1..close],
+                predicate,
+                &header[close + 1..]
+            );
+        }
+    }
+
+    // Without a predicate, it follows the last probe description, before any trailing comment.
+    let end = code_ranges(header)
+        .into_iter()
+        .rev()
+        .find_map(|range| {
+            let code = &header[range.clone()];
+            let trimmed = code.trim_end();
+            (!trimmed.is_empty()).then(|| range.start + trimmed.len())
+        })
+        .unwrap_or(0);
+    format!("{} /{}/{}", &header[..end], predicate, &header[end..])
+}
+
+/// Returns the index of the '/' opening the predicate of a clause header. Probe descriptions cannot contain a '/', so
+/// it is the first one outside of comments.
+fn predicate_start(header: &str) -> Option<usize> {
+    slashes(header).first().copied()
+}
+
+/// Returns `true` if every probe description of the clause header `header` names a probe of the `dtrace` provider,
+/// `BEGIN`, `END` or `ERROR`.
+fn is_dtrace_clause(header: &str) -> bool {
+    let header = strip_comments(header);
+    let descriptions = &header[..predicate_start(&header).unwrap_or(header.len())];
+    let mut descriptions = descriptions.split(',').map(str::trim).filter(|desc| !desc.is_empty()).peekable();
+    descriptions.peek().is_some()
+        && descriptions.all(|desc| {
+            let fields: Vec<&str> = desc.split(':').collect();
+            let (name, rest) = fields.split_last().unwrap();
+            let provider_ok = match rest.split_first() {
+                Some((provider, rest)) => {
+                    (provider.is_empty() || *provider == "dtrace") && rest.iter().all(|field| field.is_empty())
+                }
+                None => true,
+            };
+            provider_ok && ["BEGIN", "END", "ERROR"].contains(name)
+        })
+}
@@ -0,0 +1,314 @@
+//! Ready-made D scripts answering common questions, to get data out of a system without writing D first.
+//!
+//! Each script is available as a constant tracing the whole system, and as a builder narrowing it down to a process or
+//! a thread, which produces a [`Script`]: the source along with the options it runs with. The process and thread are
+//! selected by the predicates of the script, see [`ProcessFilter`], so nothing is recorded for the others.
+//!
+//! ```no_run
+//! use libdtrace_rs::scripts::SyscallCounts;
+//!
+//! let mut session = SyscallCounts::new().process("notepad.exe").build().session();
+//! session.start().unwrap();
+//! std::thread::sleep(std::time::Duration::from_secs(5));
+//! session.stop();
+//! ```
+
+use crate::compile::CompileOptions;
+use crate::script::ProcessFilter;
+use crate::session::TraceSession;
+use std::time::Duration;
+
+/// Counts the system calls of each process.
+pub const SYSCALL_COUNTS: &str = "syscall:::entry { @calls[execname, probefunc] = count(); }";
+
+/// Measures the latency of file reads and writes, in nanoseconds.
+pub const FILE_IO_LATENCY: &str = "\
+syscall::NtReadFile:entry, syscall::NtWriteFile:entry { self->ts = timestamp; }
+syscall::NtReadFile:return, syscall::NtWriteFile:return /self->ts/ {
+    @latency[execname, probefunc] = quantize(timestamp - self->ts);
+    self->ts = 0;
+}";
+
+/// Prints the processes created, with their parent. Needs the symbols of the kernel, see the `sympath` option.
+pub const EXEC_SNOOP: &str = r#"syscall::NtCreateUserProcess:entry {
+    this->params = (nt`_RTL_USER_PROCESS_PARAMETERS *)copyin(arg8, sizeof(nt`_RTL_USER_PROCESS_PARAMETERS));
+    this->image = wstr2str((wchar_t *)copyin((uintptr_t)this->params->ImagePathName.Buffer,
+        this->params->ImagePathName.Length), this->params->ImagePathName.Length / 2);
+    printf("%d %s %s\n", pid, execname, this->image);
+}"#;
+
+/// Samples the user stacks of the processes running on the CPUs, 997 times per second per CPU.
+pub const CPU_PROFILE: &str = "profile-997 /arg1/ { @samples[execname, ustack()] = count(); }";
+
+/// A D script with the options it runs with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Script {
+    /// Source of the script
+    pub source: String,
+    /// Options the script runs with, in the order they are set
+    pub options: Vec<(String, String)>,
+}
+
+impl Script {
+    /// Returns a session running the script, compiled with [`CompileOptions::allow_zero_matches`] so it also runs
+    /// where some of its probes do not exist.
+    pub fn session(&self) -> TraceSession {
+        self.options.iter().fold(
+            TraceSession::new(&self.source).with_compile_options(CompileOptions::new().allow_zero_matches()),
+            |session, (option, value)| session.with_option(option, value),
+        )
+    }
+}
+
+/// Builds a script counting system calls, see [`SYSCALL_COUNTS`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyscallCounts {
+    target: ProcessFilter,
+    syscall: String,
+    by_process: bool,
+}
+
+impl Default for SyscallCounts {
+    fn default() -> Self {
+        Self {
+            target: ProcessFilter::default(),
+            syscall: String::new(),
+            by_process: true,
+        }
+    }
+}
+
+impl SyscallCounts {
+    /// Counts every system call of every process, by process.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts the system calls of the processes named `execname`.
+    pub fn process(mut self, execname: &str) -> Self {
+        self.target.execname = Some(execname.to_string());
+        self
+    }
+
+    /// Counts the system calls of process `pid`.
+    pub fn pid(mut self, pid: crate::pid_t) -> Self {
+        self.target.pid = Some(pid);
+        self
+    }
+
+    /// Counts the system calls of thread `tid`.
+    pub fn tid(mut self, tid: u64) -> Self {
+        self.target.tid = Some(tid);
+        self
+    }
+
+    /// Counts the system calls selected by `filter`, replacing the process and thread selected so far.
+    pub fn filter(mut self, filter: ProcessFilter) -> Self {
+        self.target = filter;
+        self
+    }
+
+    /// Counts the system calls matching `pattern`, e.g. `Nt*File`.
+    pub fn syscall(mut self, pattern: &str) -> Self {
+        self.syscall = pattern.to_string();
+        self
+    }
+
+    /// Counts the system calls of all processes together instead of by process.
+    pub fn total(mut self) -> Self {
+        self.by_process = false;
+        self
+    }
+
+    /// Returns the script.
+    pub fn build(&self) -> Script {
+        let key = match self.by_process {
+            true => "execname, probefunc",
+            false => "probefunc",
+        };
+        let source = format!("syscall::{}:entry {{ @calls[{}] = count(); }}", self.syscall, key);
+        Script {
+            source: self.target.apply(&source),
+            options: Vec::new(),
+        }
+    }
+}
+
+/// Builds a script measuring the latency of file reads and writes, see [`FILE_IO_LATENCY`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileIoLatency {
+    target: ProcessFilter,
+    min_latency: Option<Duration>,
+}
+
+impl FileIoLatency {
+    /// Measures the reads and writes of every process.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Measures the reads and writes of the processes named `execname`.
+    pub fn process(mut self, execname: &str) -> Self {
+        self.target.execname = Some(execname.to_string());
+        self
+    }
+
+    /// Measures the reads and writes of process `pid`.
+    pub fn pid(mut self, pid: crate::pid_t) -> Self {
+        self.target.pid = Some(pid);
+        self
+    }
+
+    /// Measures the reads and writes of thread `tid`.
+    pub fn tid(mut self, tid: u64) -> Self {
+        self.target.tid = Some(tid);
+        self
+    }
+
+    /// Measures the reads and writes selected by `filter`, replacing the process and thread selected so far.
+    pub fn filter(mut self, filter: ProcessFilter) -> Self {
+        self.target = filter;
+        self
+    }
+
+    /// Leaves out the reads and writes faster than `latency`.
+    pub fn min_latency(mut self, latency: Duration) -> Self {
+        self.min_latency = Some(latency);
+        self
+    }
+
+    /// Returns the script.
+    pub fn build(&self) -> Script {
+        let source = match self.min_latency {
+            Some(latency) => FILE_IO_LATENCY.replace(
+                "/self->ts/",
+                &format!("/self->ts && timestamp - self->ts >= {}/", latency.as_nanos()),
+            ),
+            None => FILE_IO_LATENCY.to_string(),
+        };
+        Script {
+            source: self.target.apply(&source),
+            options: Vec::new(),
+        }
+    }
+}
+
+/// Builds a script printing the processes created, see [`EXEC_SNOOP`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecSnoop {
+    target: ProcessFilter,
+}
+
+impl ExecSnoop {
+    /// Prints the processes created by every process.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Prints the processes created by the processes named `execname`.
+    pub fn parent(mut self, execname: &str) -> Self {
+        self.target.execname = Some(execname.to_string());
+        self
+    }
+
+    /// Prints the processes created by process `pid`.
+    pub fn parent_pid(mut self, pid: crate::pid_t) -> Self {
+        self.target.pid = Some(pid);
+        self
+    }
+
+    /// Prints the processes created by thread `tid`.
+    pub fn parent_tid(mut self, tid: u64) -> Self {
+        self.target.tid = Some(tid);
+        self
+    }
+
+    /// Prints the processes created by the processes and threads selected by `filter`, replacing the parent selected
+    /// so far.
+    pub fn filter(mut self, filter: ProcessFilter) -> Self {
+        self.target = filter;
+        self
+    }
+
+    /// Returns the script, which runs quietly so only its lines are printed.
+    pub fn build(&self) -> Script {
+        Script {
+            source: self.target.apply(EXEC_SNOOP),
+            options: vec![("quiet".to_string(), String::new())],
+        }
+    }
+}
+
+/// Builds a script sampling what the CPUs run, see [`CPU_PROFILE`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuProfile {
+    target: ProcessFilter,
+    rate: u32,
+    kernel: bool,
+}
+
+impl Default for CpuProfile {
+    fn default() -> Self {
+        Self {
+            target: ProcessFilter::default(),
+            rate: 997,
+            kernel: false,
+        }
+    }
+}
+
+impl CpuProfile {
+    /// Samples the user stacks of every process, 997 times per second per CPU.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Samples the processes named `execname`.
+    pub fn process(mut self, execname: &str) -> Self {
+        self.target.execname = Some(execname.to_string());
+        self
+    }
+
+    /// Samples process `pid`.
+    pub fn pid(mut self, pid: crate::pid_t) -> Self {
+        self.target.pid = Some(pid);
+        self
+    }
+
+    /// Samples thread `tid`.
+    pub fn tid(mut self, tid: u64) -> Self {
+        self.target.tid = Some(tid);
+        self
+    }
+
+    /// Samples the processes and threads selected by `filter`, replacing the process and thread selected so far.
+    pub fn filter(mut self, filter: ProcessFilter) -> Self {
+        self.target = filter;
+        self
+    }
+
+    /// Samples `rate` times per second per CPU. Rates that are not a multiple of the timer rate, e.g. 997, avoid
+    /// sampling in lockstep with periodic activity.
+    pub fn rate(mut self, rate: u32) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    /// Samples the kernel stacks instead of the user stacks, keeping the samples taken while the CPU runs kernel code.
+    pub fn kernel(mut self) -> Self {
+        self.kernel = true;
+        self
+    }
+
+    /// Returns the script.
+    pub fn build(&self) -> Script {
+        let source = match self.kernel {
+            true => format!("profile-{} /arg0/ {{ @samples[execname, stack()] = count(); }}", self.rate),
+            false => format!("profile-{} /arg1/ {{ @samples[execname, ustack()] = count(); }}", self.rate),
+        };
+        Script {
+            source: self.target.apply(&source),
+            options: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,1788 @@
+use crate::compile::CompileOptions;
+use crate::handler::Handler;
+use crate::target::{Target, TargetEvent};
+use crate::utils::Error;
+use crate::wrapper::dtrace_hdl;
+use ::core::ffi::c_int;
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+/// An event produced by a running [`TraceSession`].
+#[derive(Debug)]
+pub enum Event {
+    /// Output of a `printf`, `trace` or similar action
+    Output(String),
+    /// Trace records were dropped, with the message describing the drop
+    Drop(String),
+    /// A fault occurred while executing the D program, with the clause it occurred in when it can be resolved
+    Fault(Box<crate::diagnostics::Fault>),
+    /// Output of a CPU was produced for the first time, further output of that CPU is delivered to the stream, see
+    /// `TraceSession::with_cpu_streams`
+    CpuStream(CpuStream),
+    /// Modules were loaded or unloaded, see `TraceSession::with_module_updates`
+    Modules(crate::probe::ModuleChanges),
+    /// The target process of the session started, exited or changed state, see `TraceSession::with_target`
+    Target(TargetEvent),
+    /// The program changed an option with `setopt()`, to `value`, or unset it if `value` is `None`. The change is
+    /// recorded in the configuration of the handle, see `dtrace_hdl::clone_config`
+    OptionChanged {
+        name: String,
+        value: Option<crate::dtrace_optval_t>,
+    },
+    /// The program was reloaded, see `TraceSession::reload`. On error the previous program keeps running
+    Reloaded(Result<(), Error>),
+    /// Consuming failed and the session reopened DTrace and enabled its program again, see
+    /// `TraceSession::with_supervision`. Trace data produced meanwhile is lost
+    Restarted {
+        /// The error consuming failed with
+        error: Error,
+        /// Number of times the session was restarted so far, including this one
+        restarts: u32,
+    },
+    /// The session stopped tracing, this is the last event of a session
+    Stopped(StopReason),
+}
+
+/// Why a [`TraceSession`] stopped tracing.
+#[derive(Debug, Clone)]
+pub enum StopReason {
+    /// The D program finished, e.g. by calling `exit()`
+    Done,
+    /// The session was stopped with `TraceSession::stop`
+    Stopped,
+    /// The session ran for longer than its timeout
+    Timeout,
+    /// The session produced as many events as its event limit
+    EventLimit,
+    /// Pending events exceeded the memory limit of the session, with [`MemoryPolicy::Stop`]
+    MemoryLimit,
+    /// The target process of the session exited
+    TargetExited,
+    /// Consuming trace data failed
+    Error(Error),
+}
+
+/// The outcome of a run of a [`TraceSession`], returned once its worker thread exited, see `TraceSession::stop`.
+#[derive(Debug, Clone)]
+pub struct SessionSummary {
+    /// Why the session stopped tracing, as reported by [`Event::Stopped`]
+    pub reason: StopReason,
+    /// Exit code of the target process, if it exited and the code could be retrieved
+    pub exit_code: Option<u32>,
+    /// Number of events produced, including events discarded by the memory limit
+    pub events: u64,
+    /// Number of records dropped by DTrace, by kind of drop
+    pub drops: crate::diagnostics::DropCounts,
+    /// Time spent tracing, from enabling the program to stopping
+    pub duration: Duration,
+    /// Number of times the session was restarted after consuming failed, see `TraceSession::with_supervision`
+    pub restarts: u32,
+}
+
+impl Event {
+    /// Copies the event for the subscribers of a session, `None` for [`Event::CpuStream`] whose stream has a single
+    /// receiver.
+    fn try_clone(&self) -> Option<Event> {
+        Some(match self {
+            Event::Output(text) => Event::Output(text.clone()),
+            Event::Drop(text) => Event::Drop(text.clone()),
+            Event::Fault(fault) => Event::Fault(fault.clone()),
+            Event::CpuStream(_) => return None,
+            Event::Modules(changes) => Event::Modules(changes.clone()),
+            Event::Target(event) => Event::Target(event.clone()),
+            Event::OptionChanged { name, value } => Event::OptionChanged {
+                name: name.clone(),
+                value: *value,
+            },
+            Event::Reloaded(result) => Event::Reloaded(result.clone()),
+            Event::Restarted { error, restarts } => Event::Restarted {
+                error: error.clone(),
+                restarts: *restarts,
+            },
+            Event::Stopped(reason) => Event::Stopped(reason.clone()),
+        })
+    }
+
+    /// Approximate number of bytes the event occupies while pending.
+    fn size(&self) -> usize {
+        let heap = match self {
+            Event::Output(text) | Event::Drop(text) => text.capacity(),
+            Event::Fault(fault) => {
+                std::mem::size_of::<crate::diagnostics::Fault>()
+                    + fault.message.capacity()
+                    + fault
+                        .source
+                        .as_ref()
+                        .and_then(|source| source.clause.as_ref())
+                        .map_or(0, |clause| clause.capacity())
+            }
+            Event::Modules(changes) => changes
+                .loaded
+                .iter()
+                .chain(&changes.unloaded)
+                .map(String::capacity)
+                .sum(),
+            Event::Target(TargetEvent::Notice { message, .. }) => message.capacity(),
+            Event::OptionChanged { name, .. } => name.capacity(),
+            Event::Target(_) | Event::CpuStream(_) | Event::Reloaded(_) | Event::Restarted { .. } | Event::Stopped(_) => 0,
+        };
+        std::mem::size_of::<Event>() + heap
+    }
+}
+
+/// How serious a [`LogEntry`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Something expected happened, e.g. an option was set or the program was reloaded
+    Info,
+    /// Something went wrong without stopping the session, e.g. records were dropped
+    Warning,
+    /// Something failed, e.g. the program faulted or consuming failed
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Something that happened to a [`TraceSession`], see `TraceSession::events`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    /// When it happened
+    pub time: SystemTime,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl std::fmt::Display for LogEntry {
+    /// Formats the entry as a line of a bug report, e.g. `1700000000.123456789 warning: 3 dynamic variable drops`.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let since_epoch = self.time.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        write!(
+            f,
+            "{}.{:09} {}: {}",
+            since_epoch.as_secs(),
+            since_epoch.subsec_nanos(),
+            self.severity,
+            self.message
+        )
+    }
+}
+
+/// Number of entries kept by the log of a session unless set with `TraceSession::with_event_log`.
+pub const EVENT_LOG_CAPACITY: usize = 1024;
+
+/// Time a session spends at most consuming the last data of a run, unless set with
+/// `TraceSession::with_flush_timeout`.
+pub const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time the worker of a paused session waits between two checks of DTrace, see `TraceSession::pause_reporting`.
+const PAUSE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The last entries logged by a session, shared by the session and its worker across runs.
+struct EventLog {
+    capacity: usize,
+    entries: Mutex<VecDeque<LogEntry>>,
+}
+
+impl EventLog {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity.min(EVENT_LOG_CAPACITY))),
+        }
+    }
+
+    /// Appends an entry, forgetting the oldest one once the log is full.
+    fn log(&self, severity: Severity, message: impl Into<String>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(LogEntry {
+            time: SystemTime::now(),
+            severity,
+            message: message.into(),
+        });
+    }
+
+    /// Logs what `event` tells about the session, if anything. Output is not logged.
+    fn log_event(&self, event: &Event) {
+        match event {
+            Event::Output(_) | Event::CpuStream(_) => {}
+            Event::Drop(message) => self.log(Severity::Warning, message.trim_end()),
+            Event::Fault(fault) => self.log(Severity::Error, fault.to_string()),
+            Event::Modules(changes) => self.log(
+                Severity::Info,
+                format!("Modules loaded: {:?}, unloaded: {:?}", changes.loaded, changes.unloaded),
+            ),
+            Event::Target(event) => self.log(Severity::Info, event.to_string()),
+            Event::OptionChanged { name, value } => self.log(
+                Severity::Info,
+                format!("Option {} changed to {} by the program", name, option_value(*value)),
+            ),
+            Event::Reloaded(Ok(())) => self.log(Severity::Info, "Reloaded the program"),
+            Event::Reloaded(Err(err)) => self.log(Severity::Error, format!("Reloading the program failed: {}", err)),
+            Event::Restarted { error, restarts } => self.log(
+                Severity::Warning,
+                format!("Restarted ({}) after consuming failed: {}", restarts, error),
+            ),
+            Event::Stopped(StopReason::Error(err)) => self.log(Severity::Error, format!("Stopped: {}", err)),
+            Event::Stopped(reason) => self.log(Severity::Info, format!("Stopped: {:?}", reason)),
+        }
+    }
+
+    fn entries(&self) -> Vec<LogEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// What is known about a piece of output when it is formatted, see `TraceSession::with_formatter`.
+#[derive(Debug, Clone)]
+pub struct OutputContext {
+    /// When the output was consumed
+    pub time: SystemTime,
+    /// The CPU the probe that produced the output fired on
+    pub cpu: Option<crate::processorid_t>,
+    /// The probe that produced the output
+    pub probe: Option<crate::probe::ProbeDescription>,
+}
+
+/// Formats the output of a session before it is delivered, see `TraceSession::with_formatter`.
+pub type OutputFormatter = Arc<dyn Fn(&OutputContext, &str) -> String + Send + Sync>;
+
+/// A column prefixed to each line of output, see `TraceSession::with_prefixes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputPrefix {
+    /// Wall-clock time the output was consumed at, as seconds since the Unix epoch
+    Timestamp,
+    /// The CPU the probe fired on
+    Cpu,
+    /// The description of the probe, `provider:module:function:name`
+    Probe,
+}
+
+impl OutputPrefix {
+    fn format(&self, context: &OutputContext) -> String {
+        match self {
+            OutputPrefix::Timestamp => {
+                let since_epoch = context
+                    .time
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default();
+                format!("{}.{:09}", since_epoch.as_secs(), since_epoch.subsec_nanos())
+            }
+            OutputPrefix::Cpu => context.cpu.map(|cpu| cpu.to_string()).unwrap_or_else(|| "-".to_string()),
+            OutputPrefix::Probe => context
+                .probe
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "-".to_string()),
+        }
+    }
+
+    /// Returns a formatter prefixing each line of output with the columns `prefixes`, separated by spaces.
+    pub fn formatter(prefixes: &[OutputPrefix]) -> OutputFormatter {
+        let prefixes = prefixes.to_vec();
+        // Output may end in the middle of a line, continued by the next output.
+        let line_start = Mutex::new(true);
+        Arc::new(move |context, output| {
+            let prefix: String = prefixes
+                .iter()
+                .map(|prefix| prefix.format(context) + " ")
+                .collect();
+            let mut line_start = line_start.lock().unwrap();
+            let mut formatted = String::with_capacity(output.len() + prefix.len());
+            for line in output.split_inclusive('\n') {
+                if *line_start {
+                    formatted.push_str(&prefix);
+                }
+                formatted.push_str(line);
+                *line_start = line.ends_with('\n');
+            }
+            formatted
+        })
+    }
+}
+
+/// What a [`TraceSession`] does with new events while its pending events exceed its memory limit.
+#[derive(Debug, Clone)]
+pub enum MemoryPolicy {
+    /// Discard new events, counting them
+    Drop,
+    /// Append new events to the file at the given path instead of delivering them
+    Spill(PathBuf),
+    /// Discard new events and stop tracing
+    Stop,
+}
+
+/// How a [`TraceSession`] recovers when consuming fails, e.g. because the DTrace service was restarted, see
+/// `TraceSession::with_supervision`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SupervisionPolicy {
+    /// Number of times DTrace is reopened after a failure before the session stops with the error
+    pub attempts: u32,
+    /// Time to wait before each attempt, giving the driver time to come back
+    pub delay: Duration,
+}
+
+impl Default for SupervisionPolicy {
+    /// Makes 10 attempts, one second apart.
+    fn default() -> Self {
+        Self {
+            attempts: 10,
+            delay: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Scheduling priority of the worker thread of a [`TraceSession`], see `TraceSession::with_thread_priority`.
+///
+/// The priorities are the thread priorities of Windows, relative to the priority class of the process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ThreadPriority {
+    Idle,
+    Lowest,
+    BelowNormal,
+    Normal,
+    AboveNormal,
+    Highest,
+    TimeCritical,
+}
+
+impl ThreadPriority {
+    /// Returns the `THREAD_PRIORITY_*` value of the priority.
+    fn value(self) -> i32 {
+        match self {
+            ThreadPriority::Idle => -15,
+            ThreadPriority::Lowest => -2,
+            ThreadPriority::BelowNormal => -1,
+            ThreadPriority::Normal => 0,
+            ThreadPriority::AboveNormal => 1,
+            ThreadPriority::Highest => 2,
+            ThreadPriority::TimeCritical => 15,
+        }
+    }
+}
+
+/// How the worker thread of a session is set up.
+#[derive(Debug, Clone)]
+struct ThreadOptions {
+    name: String,
+    priority: Option<ThreadPriority>,
+    /// Mask of the CPUs the thread may run on
+    affinity: Option<u64>,
+}
+
+impl ThreadOptions {
+    /// Applies the priority and affinity to the current thread.
+    fn apply(&self) -> Result<(), Error> {
+        #[cfg(windows)]
+        unsafe {
+            let thread = crate::win32::GetCurrentThread();
+            if let Some(priority) = self.priority {
+                if crate::win32::SetThreadPriority(thread, priority.value()) == 0 {
+                    return Err(Error::from(std::io::Error::last_os_error())
+                        .with_context(format!("setting the worker thread priority to {:?}", priority)));
+                }
+            }
+            if let Some(affinity) = self.affinity {
+                if crate::win32::SetThreadAffinityMask(thread, affinity as usize) == 0 {
+                    return Err(Error::from(std::io::Error::last_os_error())
+                        .with_context(format!("setting the worker thread affinity to {:#x}", affinity)));
+                }
+            }
+        }
+        #[cfg(not(windows))]
+        if let Some(priority) = self.priority {
+            log::warn!("Ignoring worker thread priority {} outside Windows", priority.value());
+        }
+        Ok(())
+    }
+}
+
+/// Memory used by the events of a [`TraceSession`] that were produced but not yet received.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Approximate number of bytes used by pending events
+    pub used: usize,
+    /// The memory limit of the session, if any
+    pub limit: Option<usize>,
+    /// Number of events discarded because of the limit
+    pub dropped: u64,
+    /// Number of events written to the spill file because of the limit
+    pub spilled: u64,
+}
+
+/// Accounts the memory used by pending events and enforces the memory limit of a session.
+struct Budget {
+    limit: Option<(usize, MemoryPolicy)>,
+    used: AtomicUsize,
+    dropped: AtomicU64,
+    spilled: AtomicU64,
+    exceeded: AtomicBool,
+    spill: Mutex<Option<std::fs::File>>,
+}
+
+impl Budget {
+    fn new(limit: Option<(usize, MemoryPolicy)>) -> Self {
+        Self {
+            limit,
+            used: AtomicUsize::new(0),
+            dropped: AtomicU64::new(0),
+            spilled: AtomicU64::new(0),
+            exceeded: AtomicBool::new(false),
+            spill: Mutex::new(None),
+        }
+    }
+
+    /// Returns `true` if `event` may be delivered, applying the memory policy otherwise.
+    fn admit(&self, event: &Event) -> bool {
+        let size = event.size();
+        let (limit, policy) = match &self.limit {
+            Some((limit, policy)) => (*limit, policy),
+            None => {
+                self.used.fetch_add(size, Ordering::Relaxed);
+                return true;
+            }
+        };
+        // The last event of a session, new streams and changes of the target are always delivered.
+        if matches!(event, Event::Stopped(_) | Event::CpuStream(_) | Event::Target(_)) || self.used.load(Ordering::Relaxed) + size <= limit {
+            self.used.fetch_add(size, Ordering::Relaxed);
+            return true;
+        }
+
+        match policy {
+            MemoryPolicy::Drop => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            MemoryPolicy::Stop => {
+                self.exceeded.store(true, Ordering::Relaxed);
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            MemoryPolicy::Spill(path) => match self.spill(path, event) {
+                Ok(()) => {
+                    self.spilled.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(err) => {
+                    log::warn!("Failed to spill event to {}: {}", path.display(), err);
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            },
+        }
+        false
+    }
+
+    fn spill(&self, path: &std::path::Path, event: &Event) -> std::io::Result<()> {
+        let mut file = self.spill.lock().unwrap();
+        if file.is_none() {
+            *file = Some(
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?,
+            );
+        }
+        let file = file.as_mut().unwrap();
+        match event {
+            Event::Output(output) => file.write_all(output.as_bytes()),
+            Event::Drop(message) => writeln!(file, "drop: {}", message),
+            Event::Fault(fault) => writeln!(file, "{}", fault),
+            Event::Modules(changes) => writeln!(
+                file,
+                "modules loaded: {:?}, unloaded: {:?}",
+                changes.loaded, changes.unloaded
+            ),
+            Event::Target(event) => writeln!(file, "{}", event),
+            Event::OptionChanged { name, value } => writeln!(file, "option {} = {}", name, option_value(*value)),
+            Event::Reloaded(Ok(())) => writeln!(file, "reloaded"),
+            Event::Reloaded(Err(err)) => writeln!(file, "reload failed: {}", err),
+            Event::Restarted { error, restarts } => writeln!(file, "restarted ({}) after: {}", restarts, error),
+            Event::CpuStream(_) | Event::Stopped(_) => Ok(()),
+        }
+    }
+
+    /// Accounts `event` as received.
+    fn release(&self, event: &Event) {
+        self.used.fetch_sub(event.size(), Ordering::Relaxed);
+    }
+
+    fn usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            used: self.used.load(Ordering::Relaxed),
+            limit: self.limit.as_ref().map(|(limit, _)| *limit),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            spilled: self.spilled.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The output of a single CPU of a [`TraceSession`], in the order it was produced on that CPU.
+///
+/// The stream ends once the session stopped and all of its output was received.
+pub struct CpuStream {
+    cpu: crate::processorid_t,
+    /// Behind a mutex so [`Event`] is `Sync`, as subscribers share events, see `TraceSession::subscribe`
+    events: Mutex<Receiver<Event>>,
+    budget: Arc<Budget>,
+}
+
+impl CpuStream {
+    /// Returns the CPU the output of the stream was produced on.
+    pub fn cpu(&self) -> crate::processorid_t {
+        self.cpu
+    }
+
+    /// Receives the next output, blocking until one is available.
+    pub fn recv(&self) -> Option<Event> {
+        let event = self.events.lock().unwrap().recv().ok()?;
+        self.budget.release(&event);
+        Some(event)
+    }
+
+    /// Receives the next output if one is available.
+    pub fn try_recv(&self) -> Option<Event> {
+        let event = self.events.lock().unwrap().try_recv().ok()?;
+        self.budget.release(&event);
+        Some(event)
+    }
+
+    /// Receives the next output, blocking for at most `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Event> {
+        let event = self.events.lock().unwrap().recv_timeout(timeout).ok()?;
+        self.budget.release(&event);
+        Some(event)
+    }
+}
+
+impl std::fmt::Debug for CpuStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("CpuStream").field("cpu", &self.cpu).finish()
+    }
+}
+
+/// A record visitor shared between a session and its worker, see `TraceSession::with_visitor`.
+type SharedVisitor = Arc<Mutex<dyn crate::visit::RecordVisitor + Send>>;
+
+/// Sends events of a session to its receiver, counting them.
+struct Sink {
+    /// Sender of the events received through the session, `None` with `TraceSession::with_subscribers_only`
+    tx: Option<Sender<Event>>,
+    /// Subscribers of the session, see `TraceSession::subscribe`
+    bus: Arc<crate::bus::EventBus<Event>>,
+    sent: AtomicU64,
+    /// Records dropped by DTrace, reported in the summary of the session
+    drops: Mutex<crate::diagnostics::DropCounts>,
+    budget: Arc<Budget>,
+    cpus: Option<Mutex<HashMap<crate::processorid_t, Sender<Event>>>>,
+    formatter: Option<OutputFormatter>,
+    log: Arc<EventLog>,
+    /// Number of faults of the program
+    faults: AtomicU64,
+    /// Probes enabled by the program, for support bundles
+    probes: Mutex<Vec<crate::probe::ProbeDescription>>,
+    /// Event source the drops and faults are written to, see `TraceSession::with_event_log_sink`
+    event_log: Option<Arc<crate::eventlog::EventLogSink>>,
+    /// Statistics on the passes of the current handle, see `TraceSession::with_consumption_stats`
+    consumption: Mutex<crate::consumer::ConsumptionStats>,
+}
+
+impl Sink {
+    fn send(&self, event: Event) {
+        self.log.log_event(&event);
+        if let Some(event_log) = &self.event_log {
+            if let Err(err) = event_log.report_event(&event) {
+                log::warn!("Failed to write to the event log: {}", err);
+            }
+        }
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        self.publish(&event);
+        let tx = match &self.tx {
+            Some(tx) => tx,
+            None => return,
+        };
+        if !self.budget.admit(&event) {
+            return;
+        }
+        // The receiver only goes away with the session, which stops the worker.
+        let _ = tx.send(event);
+    }
+
+    /// Publishes a copy of `event` to the subscribers of the session, ending their subscriptions with the last event.
+    fn publish(&self, event: &Event) {
+        if self.bus.subscriber_count() > 0 {
+            if let Some(copy) = event.try_clone() {
+                self.bus.publish(Arc::new(copy));
+            }
+        }
+        // Closing even without subscribers ends the subscriptions made after the session stopped.
+        if let Event::Stopped(_) = event {
+            self.bus.close();
+        }
+    }
+
+    /// Formats and sends the output in `bufdata`, to the stream of its CPU if the session has per-CPU streams.
+    fn send_buffered(&self, bufdata: &crate::dtrace_bufdata_t) {
+        let output = unsafe { ::core::ffi::CStr::from_ptr(bufdata.dtbda_buffered) }.to_string_lossy();
+        let data = unsafe { bufdata.dtbda_probe.as_ref() };
+        let cpu = data.map(|data| data.dtpda_cpu);
+        let output = match &self.formatter {
+            Some(formatter) => {
+                let context = OutputContext {
+                    time: SystemTime::now(),
+                    cpu,
+                    probe: data
+                        .and_then(|data| unsafe { data.dtpda_pdesc.as_ref() })
+                        .map(crate::probe::ProbeDescription::from),
+                };
+                formatter(&context, &output)
+            }
+            None => output.into_owned(),
+        };
+        self.send_output(cpu, output);
+    }
+
+    /// Sends the output of a probe that fired on `cpu`, to the stream of the CPU if the session has per-CPU streams.
+    fn send_output(&self, cpu: Option<crate::processorid_t>, output: String) {
+        let (cpus, cpu) = match (&self.cpus, cpu) {
+            (Some(cpus), Some(cpu)) => (cpus, cpu),
+            _ => return self.send(Event::Output(output)),
+        };
+        let event = Event::Output(output);
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        self.publish(&event);
+        if self.tx.is_none() || !self.budget.admit(&event) {
+            return;
+        }
+        let mut cpus = cpus.lock().unwrap();
+        let tx = cpus.entry(cpu).or_insert_with(|| {
+            let (tx, events) = mpsc::channel();
+            let stream = CpuStream {
+                cpu,
+                events: Mutex::new(events),
+                budget: self.budget.clone(),
+            };
+            self.send(Event::CpuStream(stream));
+            tx
+        });
+        let _ = tx.send(event);
+    }
+}
+
+fn send_drop(data: &crate::dtrace_dropdata_t, sink: &Sink) -> c_int {
+    sink.drops.lock().unwrap().add(data.dtdda_kind, data.dtdda_drops);
+    sink.send(Event::Drop(crate::diagnostics::message(data.dtdda_msg)));
+    crate::DTRACE_HANDLE_OK as c_int
+}
+
+/// Formats the value of an option for the log of a session.
+fn option_value(value: Option<crate::dtrace_optval_t>) -> String {
+    value.map_or_else(|| "unset".to_string(), |value| value.to_string())
+}
+
+/// Context of the setopt handler of a session, with the configuration of the handle to record the changes in.
+struct OptionSink {
+    sink: Arc<Sink>,
+    config: Arc<Mutex<crate::config::ConsumerConfig>>,
+    program_options: ProgramOptions,
+}
+
+/// The options changed by the program of a session with `setopt()`, to set them again on the handles opened to
+/// restart or recover the session. An option the program unset is `None`.
+type ProgramOptions = Arc<Mutex<Vec<(String, Option<crate::dtrace_optval_t>)>>>;
+
+/// Records and sends the options changed by the program with `setopt()`.
+fn send_option_change(data: &crate::dtrace_setoptdata_t, options: &OptionSink) -> c_int {
+    let name = crate::diagnostics::message(data.dtsda_option);
+    let value = (data.dtsda_newval != crate::DTRACEOPT_UNSET).then_some(data.dtsda_newval);
+    options.config.lock().unwrap().set_program_option(&name, value);
+    let mut program_options = options.program_options.lock().unwrap();
+    program_options.retain(|(set, _)| *set != name);
+    program_options.push((name.clone(), value));
+    drop(program_options);
+    options.sink.send(Event::OptionChanged { name, value });
+    crate::DTRACE_HANDLE_OK as c_int
+}
+
+/// Context of the error handler of a session, with the ECBs of the handle to attribute faults to their clause.
+struct FaultSink {
+    sink: Arc<Sink>,
+    statements: Arc<Mutex<Vec<crate::diagnostics::StatementSource>>>,
+}
+
+fn send_fault(data: &crate::dtrace_errdata_t, faults: &FaultSink) -> c_int {
+    let fault = crate::diagnostics::Fault::new(data, &faults.statements.lock().unwrap());
+    faults.sink.faults.fetch_add(1, Ordering::Relaxed);
+    faults.sink.send(Event::Fault(Box::new(fault)));
+    crate::DTRACE_HANDLE_OK as c_int
+}
+
+/// A managed consumer, running a D program on a worker thread and delivering its output as [`Event`]s.
+///
+/// ```no_run
+/// use libdtrace_rs::session::{Event, TraceSession};
+/// use std::time::Duration;
+///
+/// let mut session = TraceSession::new("syscall:::entry { printf(\"%s\\n\", execname); }")
+///     .with_option("bufsize", "4m")
+///     .with_timeout(Duration::from_secs(10));
+/// session.start().unwrap();
+/// while let Some(event) = session.recv() {
+///     if let Event::Output(output) = event {
+///         print!("{}", output);
+///     }
+/// }
+/// ```
+pub struct TraceSession {
+    program: String,
+    script: Option<PathBuf>,
+    hot_reload: bool,
+    reload: Arc<Mutex<Option<String>>>,
+    compile: CompileOptions,
+    options: Vec<(String, String)>,
+    /// Options changed by the program with `setopt()`, kept by `restart`
+    program_options: ProgramOptions,
+    timeout: Option<Duration>,
+    flush_timeout: Duration,
+    event_limit: Option<u64>,
+    memory_limit: Option<(usize, MemoryPolicy)>,
+    module_updates: Option<Duration>,
+    supervision: Option<SupervisionPolicy>,
+    cpu_streams: bool,
+    formatter: Option<OutputFormatter>,
+    visitor: Option<SharedVisitor>,
+    decoders: Arc<crate::decoders::DecoderRegistry>,
+    consumption_stats: bool,
+    event_log: Option<Arc<crate::eventlog::EventLogSink>>,
+    target: Option<Target>,
+    thread: ThreadOptions,
+    budget: Arc<Budget>,
+    log: Arc<EventLog>,
+    /// Sink of the last run, for support bundles
+    sink: Option<Arc<Sink>>,
+    bus: Arc<crate::bus::EventBus<Event>>,
+    subscribers_only: bool,
+    events: Option<Receiver<Event>>,
+    stop: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
+    worker: Option<JoinHandle<Option<SessionSummary>>>,
+}
+
+impl TraceSession {
+    /// Creates a session for the D program `program`, compiled with `DTRACE_C_ZDEFS`.
+    pub fn new(program: &str) -> Self {
+        Self {
+            program: program.to_string(),
+            script: None,
+            hot_reload: false,
+            reload: Arc::new(Mutex::new(None)),
+            compile: CompileOptions::new().allow_zero_matches(),
+            options: Vec::new(),
+            program_options: Default::default(),
+            timeout: None,
+            flush_timeout: FLUSH_TIMEOUT,
+            event_limit: None,
+            memory_limit: None,
+            module_updates: None,
+            supervision: None,
+            cpu_streams: false,
+            formatter: None,
+            visitor: None,
+            decoders: Default::default(),
+            consumption_stats: false,
+            event_log: None,
+            target: None,
+            thread: ThreadOptions {
+                name: "dtrace-session".to_string(),
+                priority: None,
+                affinity: None,
+            },
+            budget: Arc::new(Budget::new(None)),
+            log: Arc::new(EventLog::new(EVENT_LOG_CAPACITY)),
+            sink: None,
+            bus: Arc::new(crate::bus::EventBus::new()),
+            subscribers_only: false,
+            events: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            paused: Arc::new(AtomicBool::new(false)),
+            worker: None,
+        }
+    }
+
+    /// Creates a session for the D script at `path`, compiled with `DTRACE_C_ZDEFS`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut session = Self::new(&std::fs::read_to_string(path)?);
+        session.script = Some(path.to_path_buf());
+        Ok(session)
+    }
+
+    /// Watches the script of a session created with `from_file`, reloading the program whenever the script changes.
+    ///
+    /// See `reload` for how the program is replaced.
+    pub fn with_hot_reload(mut self) -> Self {
+        self.hot_reload = true;
+        self
+    }
+
+    /// Sets the flags the program is compiled with, see `dtrace_program_strcompile`.
+    pub fn with_flags(mut self, flags: u32) -> Self {
+        self.compile.flags = flags;
+        self
+    }
+
+    /// Sets how the program is compiled, replacing the default `DTRACE_C_ZDEFS` flag.
+    pub fn with_compile_options(mut self, options: CompileOptions) -> Self {
+        self.compile = options;
+        self
+    }
+
+    /// Sets a DTrace option before the program is compiled, see `dtrace_setopt`.
+    pub fn with_option(mut self, option: &str, value: &str) -> Self {
+        self.options.push((option.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sets the process traced as `$target`: a [`TargetCommand`](crate::target::TargetCommand) to run, like
+    /// `dtrace -c`, or an [`AttachTarget`](crate::target::AttachTarget) to attach to, like `dtrace -p`.
+    ///
+    /// The process is created or grabbed before the program is compiled, so the program can refer to it as
+    /// `$target`, and resumed once tracing started. Tracing stops when the process exits. When the session stops
+    /// first, a command is killed and an attached process resumes running untraced. Changes of the state of the
+    /// process are delivered as [`Event::Target`].
+    pub fn with_target(mut self, target: impl Into<Target>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Stops tracing once the session has been running for `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Spends at most `timeout` consuming the last data of a run instead of [`FLUSH_TIMEOUT`].
+    ///
+    /// When a run ends, except after an error, the session shuts down in this order so the last interval of data is
+    /// delivered before [`Event::Stopped`]:
+    ///
+    /// 1. tracing is stopped, which fires the `END` probe;
+    /// 2. the trace buffers are switched and consumed, delivering the output of the last interval and of `END`;
+    /// 3. the aggregations are snapshotted a last time, visited by the visitor of the session, see `with_visitor`,
+    ///    and printed like dtrace(1) does on exit;
+    /// 4. [`Event::Stopped`] is sent, after which the receivers and subscribers of the session are drained;
+    /// 5. DTrace is closed.
+    ///
+    /// Steps 2 and 3 are skipped once `timeout` elapsed, which is logged as a warning, see `events`.
+    pub fn with_flush_timeout(mut self, timeout: Duration) -> Self {
+        self.flush_timeout = timeout;
+        self
+    }
+
+    /// Stops tracing once the session has produced `limit` events.
+    pub fn with_event_limit(mut self, limit: u64) -> Self {
+        self.event_limit = Some(limit);
+        self
+    }
+
+    /// Limits the memory used by events that were produced but not yet received to about `limit` bytes, applying
+    /// `policy` to new events while the limit is exceeded.
+    pub fn with_memory_limit(mut self, limit: usize, policy: MemoryPolicy) -> Self {
+        self.memory_limit = Some((limit, policy));
+        self
+    }
+
+    /// Checks for loaded and unloaded modules every `interval`, updating the DTrace instance and reporting changes as
+    /// [`Event::Modules`].
+    ///
+    /// Probes of modules loaded later are enabled once the program is reloaded or the session restarted.
+    pub fn with_module_updates(mut self, interval: Duration) -> Self {
+        self.module_updates = Some(interval);
+        self
+    }
+
+    /// Recovers from failures to consume, e.g. when the DTrace service is restarted, by reopening DTrace and enabling
+    /// the program again according to `policy`, reporting each recovery as [`Event::Restarted`]. The options the program
+    /// changed with `setopt()` are set again on the new handle.
+    ///
+    /// Without supervision the session stops with [`StopReason::Error`]. Sessions with a target are not restarted.
+    pub fn with_supervision(mut self, policy: SupervisionPolicy) -> Self {
+        self.supervision = Some(policy);
+        self
+    }
+
+    /// Delivers the output of each CPU to its own [`CpuStream`] instead of interleaving it with the output of the other
+    /// CPUs, preserving the order it was produced in on each CPU.
+    ///
+    /// Each stream is announced by an [`Event::CpuStream`] when its CPU first produces output. Output not attributed to
+    /// a probe and all other events are still delivered by the session.
+    pub fn with_cpu_streams(mut self) -> Self {
+        self.cpu_streams = true;
+        self
+    }
+
+    /// Formats each output with `formatter` before it is delivered, e.g. to add columns to every line instead of
+    /// printing them in every clause of the program.
+    ///
+    /// The formatter is called on the worker thread, in the order the output is consumed.
+    pub fn with_formatter(
+        mut self,
+        formatter: impl Fn(&OutputContext, &str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.formatter = Some(Arc::new(formatter));
+        self
+    }
+
+    /// Prefixes each line of output with the columns `prefixes`, see [`OutputPrefix::formatter`].
+    pub fn with_prefixes(mut self, prefixes: &[OutputPrefix]) -> Self {
+        self.formatter = Some(OutputPrefix::formatter(prefixes));
+        self
+    }
+
+    /// Decodes the records of each firing for `visitor` on the worker thread, in addition to delivering the output of
+    /// the session as events, see [`RecordVisitor`](crate::visit::RecordVisitor).
+    ///
+    /// The visitor is kept across restarts of the session and can be shared with the caller to retrieve its results.
+    pub fn with_visitor(mut self, visitor: Arc<Mutex<dyn crate::visit::RecordVisitor + Send>>) -> Self {
+        self.visitor = Some(visitor);
+        self
+    }
+
+    /// Decodes the records and aggregation entries handed to the visitor of the session with the decoders of
+    /// `registry`, see `with_visitor` and [`DecoderRegistry`](crate::decoders::DecoderRegistry).
+    pub fn with_decoders(mut self, registry: crate::decoders::DecoderRegistry) -> Self {
+        self.decoders = Arc::new(registry);
+        self
+    }
+
+    /// Collects statistics on the passes consuming trace data, to tell whether the worker keeps up with the buffer
+    /// switches, see `consumption_stats`.
+    pub fn with_consumption_stats(mut self) -> Self {
+        self.consumption_stats = true;
+        self
+    }
+
+    /// Writes the drops and faults of the session to the Windows Event Log through `sink`, besides delivering them as
+    /// events, see [`EventLogSink`](crate::eventlog::EventLogSink).
+    pub fn with_event_log_sink(mut self, sink: crate::eventlog::EventLogSink) -> Self {
+        self.event_log = Some(Arc::new(sink));
+        self
+    }
+
+    /// Delivers the events to the subscribers of the session only, see `subscribe`, so they are not kept for the
+    /// receiving methods of the session, which return [`None`]. [`Event::CpuStream`] is not delivered to subscribers,
+    /// so the output of per-CPU streams is lost.
+    pub fn with_subscribers_only(mut self) -> Self {
+        self.subscribers_only = true;
+        self
+    }
+
+    /// Keeps the last `capacity` entries of the log of the session instead of [`EVENT_LOG_CAPACITY`], see `events`.
+    pub fn with_event_log(mut self, capacity: usize) -> Self {
+        self.log = Arc::new(EventLog::new(capacity));
+        self
+    }
+
+    /// Names the worker thread `name`, as shown by debuggers and profilers. The default name is `dtrace-session`.
+    pub fn with_thread_name(mut self, name: &str) -> Self {
+        self.thread.name = name.to_string();
+        self
+    }
+
+    /// Sets the scheduling priority of the worker thread.
+    ///
+    /// Consuming trace data competes with the traced workload for CPU time: a lower priority limits the interference
+    /// with the workload, at the cost of more drops when the buffers fill up faster than they are consumed.
+    pub fn with_thread_priority(mut self, priority: ThreadPriority) -> Self {
+        self.thread.priority = Some(priority);
+        self
+    }
+
+    /// Restricts the worker thread to the CPUs of the mask `affinity`, bit `n` standing for CPU `n` of the processor
+    /// group of the process, e.g. to keep it off the CPUs the measured workload is pinned to.
+    pub fn with_thread_affinity(mut self, affinity: u64) -> Self {
+        self.thread.affinity = Some(affinity);
+        self
+    }
+
+    /// Starts tracing on a worker thread.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` once the program is running, or the error that prevented setting up the worker thread,
+    /// opening DTrace, setting an option or compiling and enabling the program.
+    pub fn start(&mut self) -> Result<(), Error> {
+        self.start_with(Vec::new())
+    }
+
+    /// Starts tracing on a worker thread with `program_options` set after the options of the session.
+    fn start_with(&mut self, program_options: Vec<(String, Option<crate::dtrace_optval_t>)>) -> Result<(), Error> {
+        if self.worker.is_some() {
+            return Err(Error::new(-1, "The session is already started"));
+        }
+        *self.program_options.lock().unwrap() = program_options;
+
+        let (tx, rx) = mpsc::channel();
+        let (started_tx, started_rx) = mpsc::sync_channel(1);
+        self.budget = Arc::new(Budget::new(self.memory_limit.clone()));
+        *self.reload.lock().unwrap() = None;
+        self.bus.reopen();
+        let script = match &self.script {
+            Some(path) if self.hot_reload => {
+                let modified = std::fs::metadata(path).and_then(|meta| meta.modified()).ok();
+                Some((path.clone(), modified))
+            }
+            _ => None,
+        };
+        let sink = Arc::new(Sink {
+            tx: (!self.subscribers_only).then_some(tx),
+            bus: self.bus.clone(),
+            sent: AtomicU64::new(0),
+            drops: Default::default(),
+            budget: self.budget.clone(),
+            cpus: self.cpu_streams.then(Default::default),
+            formatter: self.formatter.clone(),
+            log: self.log.clone(),
+            faults: AtomicU64::new(0),
+            probes: Default::default(),
+            event_log: self.event_log.clone(),
+            consumption: Default::default(),
+        });
+        self.sink = Some(sink.clone());
+        let worker = Worker {
+            program: self.program.clone(),
+            script,
+            reload: self.reload.clone(),
+            compile: self.compile.clone(),
+            options: self.options.clone(),
+            program_options: self.program_options.clone(),
+            timeout: self.timeout,
+            flush_timeout: self.flush_timeout,
+            event_limit: self.event_limit,
+            module_updates: self.module_updates,
+            supervision: self.supervision,
+            restarts: 0,
+            target: self.target.clone(),
+            target_state: Default::default(),
+            target_pid: None,
+            process: None,
+            job: None,
+            exit_code: None,
+            visitor: self.visitor.clone(),
+            decoders: self.decoders.clone(),
+            consumption_stats: self.consumption_stats,
+            stop: self.stop.clone(),
+            paused: self.paused.clone(),
+            sink,
+        };
+        self.stop.store(false, Ordering::Relaxed);
+        let thread = self.thread.clone();
+        let handle = std::thread::Builder::new()
+            .name(thread.name.clone())
+            .spawn(move || match thread.apply() {
+                Ok(()) => worker.run(started_tx),
+                Err(err) => {
+                    let _ = started_tx.send(Err(err));
+                    None
+                }
+            })
+            .map_err(|err| Error::from(err).with_context("spawning the session worker"))?;
+
+        match started_rx.recv() {
+            Ok(Ok(())) => {
+                self.events = Some(rx);
+                self.worker = Some(handle);
+                Ok(())
+            }
+            Ok(Err(err)) => {
+                let _ = handle.join();
+                self.log.log(Severity::Error, format!("Starting failed: {}", err));
+                Err(err)
+            }
+            Err(_) => {
+                let _ = handle.join();
+                Err(Error::new(-1, "The session worker exited before starting"))
+            }
+        }
+    }
+
+    /// Starts tracing and delivers the events of the session to `callback` on a thread of `scope`, so the callback can
+    /// borrow from the caller.
+    ///
+    /// The thread exits after delivering [`Event::Stopped`] and joining the worker thread, so the scope does not end
+    /// before the session stopped. Events are not available through `recv` and the other receiving methods meanwhile,
+    /// and the summary of the run is returned by the thread instead of `stop`.
+    ///
+    /// ```no_run
+    /// use libdtrace_rs::session::{Event, TraceSession};
+    ///
+    /// let mut lines = Vec::new();
+    /// let mut session = TraceSession::new("BEGIN { printf(\"hello\\n\"); exit(0); }");
+    /// std::thread::scope(|scope| {
+    ///     session
+    ///         .run_scoped(scope, |event| {
+    ///             if let Event::Output(output) = event {
+    ///                 lines.push(output);
+    ///             }
+    ///         })
+    ///         .unwrap();
+    /// });
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// Returns the handle of the thread calling `callback`, which returns the summary of the run, or the error that
+    /// prevented starting, see `start`.
+    pub fn run_scoped<'scope>(
+        &mut self,
+        scope: &'scope std::thread::Scope<'scope, '_>,
+        mut callback: impl FnMut(Event) + Send + 'scope,
+    ) -> Result<std::thread::ScopedJoinHandle<'scope, Option<SessionSummary>>, Error> {
+        self.start()?;
+        let events = self.events.take().expect("a started session has events");
+        let worker = self.worker.take().expect("a started session has a worker");
+        let budget = self.budget.clone();
+        Ok(scope.spawn(move || {
+            for event in events {
+                budget.release(&event);
+                callback(event);
+            }
+            worker.join().ok().flatten()
+        }))
+    }
+
+    /// Stops tracing, if the session is running, and starts it again on a new handle with the same program, options
+    /// and limits. The options the program changed with `setopt()` (see [`Event::OptionChanged`]) are set again too.
+    ///
+    /// Events of the previous run that were not received yet are discarded.
+    pub fn restart(&mut self) -> Result<(), Error> {
+        self.stop();
+        self.events = None;
+        let program_options = std::mem::take(&mut *self.program_options.lock().unwrap());
+        self.start_with(program_options)
+    }
+
+    /// Replaces the program of the session with `program`.
+    ///
+    /// A running session compiles and enables the new program on a new handle, then stops the previous one and
+    /// continues consuming with the same events, handlers and limits, reporting the outcome as [`Event::Reloaded`].
+    /// Sessions with a target cannot be reloaded while running.
+    pub fn reload(&mut self, program: &str) {
+        self.program = program.to_string();
+        if self.worker.is_some() {
+            *self.reload.lock().unwrap() = Some(program.to_string());
+        }
+    }
+
+    /// Receives the next event, blocking until one is available.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`None`] once the session is not running and all of its events have been received.
+    pub fn recv(&self) -> Option<Event> {
+        let event = self.events.as_ref()?.recv().ok()?;
+        self.budget.release(&event);
+        Some(event)
+    }
+
+    /// Receives the next event if one is available.
+    pub fn try_recv(&self) -> Option<Event> {
+        let event = self.events.as_ref()?.try_recv().ok()?;
+        self.budget.release(&event);
+        Some(event)
+    }
+
+    /// Receives the next event, blocking for at most `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Event> {
+        let event = self.events.as_ref()?.recv_timeout(timeout).ok()?;
+        self.budget.release(&event);
+        Some(event)
+    }
+
+    /// Subscribes to the events of the session, in addition to the receiving methods of the session and the other
+    /// subscribers, queueing up to [`SUBSCRIBER_CAPACITY`](crate::bus::SUBSCRIBER_CAPACITY) events.
+    ///
+    /// The subscriber receives every event produced from now on except [`Event::CpuStream`], until the subscription
+    /// ends with [`Event::Stopped`]. A subscription made after the session stopped ends right away, until the session
+    /// is started again. A subscriber that falls behind loses its oldest events rather than holding up the session, see
+    /// [`Subscriber::lagged`](crate::bus::Subscriber::lagged).
+    ///
+    /// ```no_run
+    /// use libdtrace_rs::session::{Event, TraceSession};
+    ///
+    /// let mut session = TraceSession::new("syscall::NtClose:entry { printf(\"%d\\n\", pid); }");
+    /// let subscriber = session.subscribe();
+    /// std::thread::spawn(move || {
+    ///     for event in subscriber {
+    ///         if let Event::Output(output) = &*event {
+    ///             print!("{}", output);
+    ///         }
+    ///     }
+    /// });
+    /// session.start().unwrap();
+    /// ```
+    pub fn subscribe(&self) -> crate::bus::Subscriber<Event> {
+        self.bus.subscribe(crate::bus::SUBSCRIBER_CAPACITY)
+    }
+
+    /// Subscribes to the events of the session like `subscribe`, queueing up to `capacity` events.
+    pub fn subscribe_with_capacity(&self, capacity: usize) -> crate::bus::Subscriber<Event> {
+        self.bus.subscribe(capacity)
+    }
+
+    /// Returns the last entries of the log of the session, oldest first: the options it set, drops, faults, restarts,
+    /// reloads, changes of the target and why it stopped, across all of its runs. Meant to be attached to bug
+    /// reports, see [`LogEntry`].
+    pub fn events(&self) -> Vec<LogEntry> {
+        self.log.entries()
+    }
+
+    /// Writes a support bundle to `path`, a tar archive to attach to bug reports, see
+    /// [`SupportBundle`](crate::bundle::SupportBundle).
+    ///
+    /// The bundle holds the platform, the options and program of the session, the probes enabled by the last run,
+    /// its counters of events, faults and drops, and the log of the session, see `events`.
+    pub fn diagnostics_dump(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let mut bundle = crate::bundle::SupportBundle::new();
+
+        let mut options = String::new();
+        for (option, value) in &self.options {
+            options.push_str(&format!("{}={}\n", option, value));
+        }
+        options.push_str(&format!("compile flags: {:#x}\n", self.compile.flags));
+        if let Some(args) = &self.compile.args {
+            options.push_str(&format!("arguments: {:?}\n", args));
+        }
+        bundle.add("options.txt", options);
+
+        let program = match &self.script {
+            Some(path) => format!("/* {} */\n{}", path.display(), self.program),
+            None => self.program.clone(),
+        };
+        bundle.add("program.d", program);
+
+        let mut status = format!("running: {}\n", self.is_running());
+        let usage = self.memory_usage();
+        status.push_str(&format!(
+            "pending bytes: {}, memory limit: {:?}, discarded: {}, spilled: {}\n",
+            usage.used, usage.limit, usage.dropped, usage.spilled
+        ));
+        if let Some(sink) = &self.sink {
+            status.push_str(&format!("events: {}\n", sink.sent.load(Ordering::Relaxed)));
+            status.push_str(&format!("faults: {}\n", sink.faults.load(Ordering::Relaxed)));
+            status.push_str(&format!("drops: {:?}\n", *sink.drops.lock().unwrap()));
+            let probes: String = sink
+                .probes
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|probe| format!("{:>6} {}\n", probe.id, probe))
+                .collect();
+            bundle.add("probes.txt", probes);
+        }
+        bundle.add("status.txt", status);
+
+        let log: String = self.events().iter().map(|entry| format!("{}\n", entry)).collect();
+        bundle.add("log.txt", log);
+        bundle.write(path)
+    }
+
+    /// Retrieves the memory used by pending events and the effect of the memory limit, see `with_memory_limit`.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.budget.usage()
+    }
+
+    /// Retrieves the statistics on the passes consuming trace data of the current or last run, `None` unless enabled
+    /// with `with_consumption_stats` or before the session started. The statistics start over when the session is
+    /// restarted or reopens DTrace.
+    pub fn consumption_stats(&self) -> Option<crate::consumer::ConsumptionStats> {
+        let sink = self.sink.as_ref().filter(|_| self.consumption_stats)?;
+        Some(*sink.consumption.lock().unwrap())
+    }
+
+    /// Returns `true` if the worker thread is tracing.
+    pub fn is_running(&self) -> bool {
+        self.worker.as_ref().is_some_and(|worker| !worker.is_finished())
+    }
+
+    /// Suspends the consumption of the trace data, e.g. to freeze a display, until `resume_reporting`.
+    ///
+    /// DTrace keeps tracing: the data accumulates in the buffers, as their policy allows, see the `bufpolicy` option,
+    /// and is delivered once reporting resumes or the session stops. No events are delivered meanwhile, and exits of
+    /// the program or limits based on the events are noticed only once reporting resumes. A session started while
+    /// reporting is paused starts paused.
+    pub fn pause_reporting(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Resumes the consumption of the trace data suspended by `pause_reporting`.
+    pub fn resume_reporting(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if reporting is paused, see `pause_reporting`.
+    pub fn is_reporting_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Asks the worker thread to stop tracing without waiting for it.
+    ///
+    /// The worker stops at its next safe point, between two passes over the trace data, and stops DTrace and
+    /// releases the target process itself before exiting. Events of the session can no longer be received.
+    pub fn cancel(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.events = None;
+        // The worker exits on its own once it sees the stop request, which must not be reset by a later start.
+        self.worker = None;
+        self.stop = Arc::new(AtomicBool::new(false));
+    }
+
+    /// Consumes the session, returning an iterator over its events that cancels the session when dropped, see
+    /// `cancel`.
+    ///
+    /// This allows handing the events to an asynchronous runtime, e.g. polling them from a blocking task, without
+    /// blocking the runtime when the task is cancelled.
+    pub fn into_events(self) -> Events {
+        Events { session: self }
+    }
+
+    /// Stops tracing and waits for the worker thread to exit.
+    ///
+    /// Events produced before stopping, ending with [`Event::Stopped`], can still be received.
+    ///
+    /// # Returns
+    ///
+    /// Returns the summary of the run, or [`None`] if the session was not started, was cancelled or its worker
+    /// thread panicked.
+    pub fn stop(&mut self) -> Option<SessionSummary> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.wait()
+    }
+
+    /// Waits for the session to stop on its own, e.g. once the program calls `exit()` or a limit is reached, without
+    /// asking it to stop.
+    ///
+    /// The events of the session must be received meanwhile when it has no memory limit, see `with_memory_limit`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the summary of the run, or [`None`] if the session was not started, was cancelled or its worker
+    /// thread panicked.
+    pub fn wait(&mut self) -> Option<SessionSummary> {
+        self.worker.take()?.join().ok().flatten()
+    }
+}
+
+impl Drop for TraceSession {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// The events of a session, owning the session, see `TraceSession::into_events`.
+///
+/// Dropping the events cancels the session without waiting for the worker thread, e.g. when the task polling them
+/// is cancelled.
+pub struct Events {
+    session: TraceSession,
+}
+
+impl Events {
+    /// Returns the session the events are produced by.
+    pub fn session(&self) -> &TraceSession {
+        &self.session
+    }
+}
+
+impl Iterator for Events {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.session.recv()
+    }
+}
+
+impl Drop for Events {
+    fn drop(&mut self) {
+        self.session.cancel();
+    }
+}
+
+/// Why the worker stopped consuming.
+enum Pass {
+    Stop(StopReason),
+    Reload(String),
+}
+
+/// State of the worker thread of a session.
+struct Worker {
+    program: String,
+    script: Option<(PathBuf, Option<SystemTime>)>,
+    reload: Arc<Mutex<Option<String>>>,
+    compile: CompileOptions,
+    options: Vec<(String, String)>,
+    program_options: ProgramOptions,
+    timeout: Option<Duration>,
+    flush_timeout: Duration,
+    event_limit: Option<u64>,
+    module_updates: Option<Duration>,
+    supervision: Option<SupervisionPolicy>,
+    /// Number of times DTrace was reopened after consuming failed
+    restarts: u32,
+    target: Option<Target>,
+    target_state: Arc<crate::target::TargetState>,
+    /// ID of the target process, if it is known
+    target_pid: Option<u32>,
+    /// The target process, to retrieve its exit code
+    process: Option<crate::target::TargetProcess>,
+    /// Job of the target process, see `TargetCommand::kill_on_close`, closed when the worker exits
+    job: Option<crate::job::JobObject>,
+    /// Exit code of the target process once it exited
+    exit_code: Option<u32>,
+    visitor: Option<SharedVisitor>,
+    decoders: Arc<crate::decoders::DecoderRegistry>,
+    consumption_stats: bool,
+    stop: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
+    sink: Arc<Sink>,
+}
+
+impl Worker {
+    fn run(mut self, started: mpsc::SyncSender<Result<(), Error>>) -> Option<SessionSummary> {
+        let (mut handle, mut proc) = match self.open() {
+            Ok(opened) => {
+                let _ = started.send(Ok(()));
+                opened
+            }
+            Err(err) => {
+                let _ = started.send(Err(err));
+                return None;
+            }
+        };
+
+        let started = Instant::now();
+        let reason = loop {
+            match self.consume(&mut handle, started) {
+                Pass::Stop(StopReason::Error(error)) if self.supervision.is_some() && self.target.is_none() => {
+                    match self.recover() {
+                        Some(Ok((new_handle, new_proc))) => {
+                            let old = std::mem::replace(&mut handle, new_handle);
+                            self.finish(old, std::mem::replace(&mut proc, new_proc), false);
+                            self.restarts += 1;
+                            let restarts = self.restarts;
+                            self.sink.send(Event::Restarted { error, restarts });
+                        }
+                        Some(Err(_)) => break StopReason::Error(error),
+                        None => break StopReason::Stopped,
+                    }
+                }
+                Pass::Stop(reason) => break reason,
+                Pass::Reload(program) => match self.reload(program) {
+                    Ok((new_handle, new_proc)) => {
+                        let old = std::mem::replace(&mut handle, new_handle);
+                        self.finish(old, std::mem::replace(&mut proc, new_proc), true);
+                        self.sink.send(Event::Reloaded(Ok(())));
+                    }
+                    Err(err) => self.sink.send(Event::Reloaded(Err(err))),
+                },
+            }
+        };
+
+        let exited = matches!(reason, StopReason::TargetExited);
+        self.finish(handle, proc, !matches!(reason, StopReason::Error(_)));
+        if proc.is_some() && !exited {
+            self.sink.send(Event::Target(TargetEvent::Stopped { pid: self.target_pid }));
+        }
+        let duration = started.elapsed();
+        self.sink.send(Event::Stopped(reason.clone()));
+        Some(SessionSummary {
+            reason,
+            exit_code: self.exit_code,
+            events: self.sink.sent.load(Ordering::Relaxed),
+            drops: *self.sink.drops.lock().unwrap(),
+            duration,
+            restarts: self.restarts,
+        })
+    }
+
+    /// Stops tracing on `handle`, flushing what was traced since the last pass if `drain` is set, see
+    /// `TraceSession::with_flush_timeout`.
+    fn finish(&self, mut handle: dtrace_hdl, proc: Option<*mut crate::ps_prochandle>, drain: bool) {
+        let _ = handle.dtrace_stop();
+        if drain {
+            if let Err(err) = self.flush(&mut handle) {
+                self.sink.log.log(Severity::Warning, format!("Flushing the last data failed: {}", err));
+            }
+        }
+        if let Some(proc) = proc {
+            // The process was returned by this handle and is released once.
+            unsafe { handle.dtrace_proc_release(proc) };
+        }
+    }
+
+    /// Consumes the data traced on the stopped `handle` until libdtrace reports it is done, then delivers the last
+    /// snapshot of the aggregations, giving up after the flush timeout.
+    fn flush(&self, handle: &mut dtrace_hdl) -> Result<(), Error> {
+        let deadline = Instant::now() + self.flush_timeout;
+        let timed_out = || Error::new(-1, format!("Timed out after {:?}", self.flush_timeout));
+        // Consuming after stopping also delivers the output of the END clauses.
+        while !matches!(self.work(handle)?, crate::dtrace_workstatus_t::DTRACE_WORKSTATUS_DONE) {
+            if Instant::now() >= deadline {
+                return Err(timed_out());
+            }
+            handle.dtrace_sleep();
+        }
+        if Instant::now() >= deadline {
+            return Err(timed_out());
+        }
+        if let Some(visitor) = &self.visitor {
+            handle.visit_aggregates(crate::types::dtrace_aggwalk_order::Sorted, &mut *visitor.lock().unwrap())?;
+        }
+        // The output goes to the buffered handler of the session, as events.
+        handle.dtrace_aggregate_print(None, None)
+    }
+
+    /// Consumes the trace data of `handle`, decoding it for the visitor of the session if it has one.
+    fn work(&self, handle: &mut dtrace_hdl) -> Result<crate::dtrace_workstatus_t, Error> {
+        match &self.visitor {
+            Some(visitor) => handle.work_with_visitor(&mut *visitor.lock().unwrap()),
+            None => handle.dtrace_work(
+                None,
+                Some(crate::callbacks::chew),
+                Some(crate::callbacks::chew_rec),
+                None,
+            ),
+        }
+    }
+
+    /// Sends the changes of the target since the last poll, returning `true` once the target exited.
+    fn poll_target(&mut self) -> bool {
+        let pid = self.target_pid;
+        for message in std::mem::take(&mut *self.target_state.notices.lock().unwrap()) {
+            self.sink.send(Event::Target(TargetEvent::Notice { pid, message }));
+        }
+        for event in self.job.iter().flat_map(|job| job.poll()) {
+            match event {
+                crate::job::JobEvent::ProcessCreated(child) if Some(child) != pid => {
+                    self.sink.send(Event::Target(TargetEvent::Forked { pid, child }))
+                }
+                _ => {}
+            }
+        }
+        if !self.target_state.exited.load(Ordering::Relaxed) {
+            return false;
+        }
+        let code = self.process.as_ref().and_then(|process| process.exit_code());
+        self.exit_code = code;
+        self.sink.send(Event::Target(TargetEvent::Exited { pid, code }));
+        true
+    }
+
+    /// Reopens DTrace and enables the program again according to the supervision policy of the session.
+    ///
+    /// Returns [`None`] if the session was stopped meanwhile, or the error of the last attempt.
+    fn recover(&mut self) -> Option<Result<(dtrace_hdl, Option<*mut crate::ps_prochandle>), Error>> {
+        let policy = self.supervision?;
+        let mut result = Err(Error::new(-1, "The supervision policy makes no attempts"));
+        for attempt in 1..=policy.attempts {
+            std::thread::sleep(policy.delay);
+            if self.stop.load(Ordering::Relaxed) {
+                return None;
+            }
+            result = self.open();
+            match &result {
+                Ok(_) => break,
+                Err(err) => log::warn!("Reopening DTrace failed (attempt {}): {}", attempt, err),
+            }
+        }
+        Some(result)
+    }
+
+    /// Enables `program` on a new handle, keeping the current program if that fails.
+    fn reload(&mut self, program: String) -> Result<(dtrace_hdl, Option<*mut crate::ps_prochandle>), Error> {
+        if self.target.is_some() {
+            return Err(Error::new(-1, "A session with a target cannot be reloaded while running"));
+        }
+        // The options changed by the previous program do not apply to the new one.
+        let previous = std::mem::replace(&mut self.program, program);
+        let program_options = std::mem::take(&mut *self.program_options.lock().unwrap());
+        self.open().inspect_err(|_| {
+            self.program = previous;
+            *self.program_options.lock().unwrap() = program_options;
+        })
+    }
+
+    /// Returns the program to reload, if it was replaced through the session or its script changed.
+    fn take_reload(&mut self) -> Option<String> {
+        if let Some(program) = self.reload.lock().unwrap().take() {
+            return Some(program);
+        }
+
+        let (path, modified) = self.script.as_mut()?;
+        let now = std::fs::metadata(&*path).and_then(|meta| meta.modified()).ok()?;
+        if *modified == Some(now) {
+            return None;
+        }
+        *modified = Some(now);
+        match std::fs::read_to_string(&*path) {
+            Ok(program) => Some(program),
+            Err(err) => {
+                log::warn!("Failed to read {}: {}", path.display(), err);
+                None
+            }
+        }
+    }
+
+    /// Opens DTrace, creates or grabs the target process if any and enables the program.
+    fn open(&mut self) -> Result<(dtrace_hdl, Option<*mut crate::ps_prochandle>), Error> {
+        let mut handle = dtrace_hdl::dtrace_open(crate::DTRACE_VERSION as i32, 0)?;
+        handle.set_decoders(self.decoders.clone());
+        handle.set_consumption_stats(self.consumption_stats);
+        let program_options = self.program_options.lock().unwrap().clone();
+        for (option, value) in &self.options {
+            // Options the program unset keep their default.
+            if program_options.iter().any(|(changed, value)| changed == option && value.is_none()) {
+                continue;
+            }
+            handle.dtrace_setopt(option, value)?;
+            self.sink.log.log(Severity::Info, format!("Set option {} to {:?}", option, value));
+        }
+        for (option, value) in &program_options {
+            if let Some(value) = value {
+                handle.dtrace_setopt(option, &value.to_string())?;
+                self.sink.log.log(Severity::Info, format!("Set option {} to {} as the program did", option, value));
+            }
+        }
+
+        let job = match &self.target {
+            Some(Target::Command(command)) if command.kill_on_close => Some(crate::job::JobObject::new()?),
+            _ => None,
+        };
+        let (proc, pid) = match &self.target {
+            Some(target) => {
+                handle.dtrace_register_handler(
+                    crate::types::dtrace_handler::Proc(Some(crate::target::notify)),
+                    Some(Arc::as_ptr(&self.target_state) as *mut ::core::ffi::c_void),
+                )?;
+                let (proc, pid) = match target {
+                    Target::Command(command) => {
+                        handle.proc_create_with_pid(&command.program, &command.args, job.as_ref())?
+                    }
+                    Target::Attach(attach) => {
+                        if let Some(evaltime) = &attach.evaltime {
+                            handle.dtrace_setopt("evaltime", evaltime)?;
+                        }
+                        (handle.dtrace_proc_grab(attach.pid, 0)?, Some(attach.pid as u32))
+                    }
+                };
+                (Some(proc), pid)
+            }
+            None => (None, None),
+        };
+        if job.is_some() {
+            self.job = job;
+        }
+        // The process is opened while it is stopped, so its ID cannot be reused before it is.
+        self.target_pid = pid;
+        self.process = pid.and_then(crate::target::TargetProcess::open);
+
+        let sink = self.sink.clone();
+        handle.subscribe_buffered(move |bufdata| {
+            sink.send_buffered(bufdata);
+            crate::DTRACE_HANDLE_OK as c_int
+        })?;
+        handle.register_handler_with(Handler::Drop(send_drop), self.sink.clone())?;
+        let faults = FaultSink {
+            sink: self.sink.clone(),
+            statements: handle.statement_sources(),
+        };
+        handle.register_handler_with(Handler::Err(send_fault), Arc::new(faults))?;
+        let options = OptionSink {
+            sink: self.sink.clone(),
+            config: handle.shared_config(),
+            program_options: self.program_options.clone(),
+        };
+        handle.register_handler_with(Handler::SetOpt(send_option_change), Arc::new(options))?;
+
+        let prog = handle.compile(&self.program, &self.compile)?;
+        *self.sink.probes.lock().unwrap() = handle.program_probes(prog).unwrap_or_default();
+        handle.dtrace_program_exec(prog, None)?;
+        handle.dtrace_go()?;
+        self.sink.log.log(Severity::Info, "Started tracing");
+        if let Some(proc) = proc {
+            unsafe { handle.dtrace_proc_continue(proc) };
+            self.sink.send(Event::Target(TargetEvent::Started { pid }));
+        }
+        Ok((handle, proc))
+    }
+
+    /// Consumes trace data until the program finishes, one of the limits of the session is reached or the program
+    /// is to be reloaded.
+    fn consume(&mut self, handle: &mut dtrace_hdl, started: Instant) -> Pass {
+        let mut updated = Instant::now();
+        loop {
+            if self.module_updates.is_some_and(|interval| updated.elapsed() >= interval) {
+                updated = Instant::now();
+                let changes = handle.update_modules();
+                if !changes.is_empty() {
+                    self.sink.send(Event::Modules(changes));
+                }
+            }
+            if self.stop.load(Ordering::Relaxed) {
+                return Pass::Stop(StopReason::Stopped);
+            }
+            if let Some(program) = self.take_reload() {
+                return Pass::Reload(program);
+            }
+            if self.timeout.is_some_and(|timeout| started.elapsed() >= timeout) {
+                return Pass::Stop(StopReason::Timeout);
+            }
+            if self.target.is_some() && self.poll_target() {
+                return Pass::Stop(StopReason::TargetExited);
+            }
+            if self.sink.budget.exceeded.load(Ordering::Relaxed) {
+                return Pass::Stop(StopReason::MemoryLimit);
+            }
+            if self
+                .event_limit
+                .is_some_and(|limit| self.sink.sent.load(Ordering::Relaxed) >= limit)
+            {
+                return Pass::Stop(StopReason::EventLimit);
+            }
+            if self.paused.load(Ordering::Relaxed) {
+                // Reading the status keeps DTrace from giving up on the consumer while the buffers fill.
+                if let Err(err) = handle.dtrace_status() {
+                    return Pass::Stop(StopReason::Error(err));
+                }
+                std::thread::sleep(PAUSE_INTERVAL);
+                continue;
+            }
+
+            handle.dtrace_sleep();
+            let status = self.work(handle);
+            if self.consumption_stats {
+                *self.sink.consumption.lock().unwrap() = handle.consumption_stats();
+            }
+            match status {
+                Ok(crate::dtrace_workstatus_t::DTRACE_WORKSTATUS_DONE) => return Pass::Stop(StopReason::Done),
+                Ok(_) => {}
+                Err(err) => return Pass::Stop(StopReason::Error(err)),
+            }
+        }
+    }
+}
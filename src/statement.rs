@@ -0,0 +1,217 @@
+//! The statements of a compiled program, to adjust them before the program is enabled, see
+//! `dtrace_hdl::program_statements`.
+//!
+//! libdtrace reads the format strings and the arguments of the actions from the statements when the program is
+//! enabled, so changing them between `dtrace_hdl::compile` and `dtrace_hdl::dtrace_program_exec` changes the program
+//! without compiling it again. Only the changes that keep the records of the program valid are permitted.
+//!
+//! ```no_run
+//! use libdtrace_rs::compile::CompileOptions;
+//! use libdtrace_rs::wrapper::dtrace_hdl;
+//!
+//! let handle = dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap();
+//! let prog = handle
+//!     .compile("syscall::NtClose:entry { printf(\"%d\\n\", pid); stack(); }", &CompileOptions::new())
+//!     .unwrap();
+//! for mut statement in handle.program_statements(prog).unwrap() {
+//!     if statement.format().is_some() {
+//!         statement.set_format("closed by %d\n").unwrap();
+//!     }
+//! }
+//! handle.dtrace_program_exec(prog, None).unwrap();
+//! ```
+
+use crate::probe::ProbeDescription;
+use crate::utils::Error;
+use crate::wrapper::dtrace_hdl;
+use std::marker::PhantomData;
+
+/// An action of a statement, see [`Statement::actions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ActionDescription {
+    /// Kind of the action, a `DTRACEACT_*` constant
+    pub kind: crate::dtrace_actkind_t,
+    /// Argument of the action, e.g. the number of frames of `stack()`
+    pub arg: u64,
+    /// Number of elements of the key of an aggregating action
+    pub ntuple: u32,
+}
+
+/// A statement of a compiled program, borrowing the program until it is enabled.
+pub struct Statement<'a> {
+    handle: &'a dtrace_hdl,
+    stmt: *mut crate::dtrace_stmtdesc_t,
+    _program: PhantomData<&'a mut crate::dtrace_prog>,
+}
+
+impl<'a> Statement<'a> {
+    /// Wraps the statement `stmt` of a program compiled by `handle`.
+    ///
+    /// # Safety
+    ///
+    /// `stmt` must be a statement of a program of `handle` that is not destroyed during `'a`.
+    pub(crate) unsafe fn new(handle: &'a dtrace_hdl, stmt: *mut crate::dtrace_stmtdesc_t) -> Self {
+        Self {
+            handle,
+            stmt,
+            _program: PhantomData,
+        }
+    }
+
+    /// Returns the probe description of the statement as written, possibly with wildcards or empty fields.
+    pub fn probe(&self) -> ProbeDescription {
+        unsafe { ProbeDescription::from(&(*(*self.stmt).dtsd_ecbdesc).dted_probe) }
+    }
+
+    /// Returns the action descriptions of the statement, in order.
+    fn action_descs(&self) -> Vec<*mut crate::dtrace_actdesc_t> {
+        let mut descs = Vec::new();
+        unsafe {
+            let last = (*self.stmt).dtsd_action_last;
+            let mut action = (*self.stmt).dtsd_action;
+            while !action.is_null() {
+                descs.push(action);
+                if action == last {
+                    break;
+                }
+                action = (*action).dtad_next;
+            }
+        }
+        descs
+    }
+
+    /// Returns the actions of the statement, in order.
+    pub fn actions(&self) -> Vec<ActionDescription> {
+        self.action_descs()
+            .into_iter()
+            .map(|action| unsafe {
+                ActionDescription {
+                    kind: (*action).dtad_kind,
+                    arg: (*action).dtad_arg,
+                    ntuple: (*action).dtad_ntuple,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the format string of a `printf()`, `printa()`, `system()` or `freopen()` statement, or `None` for
+    /// other statements.
+    pub fn format(&self) -> Option<String> {
+        unsafe {
+            let fmtdata = (*self.stmt).dtsd_fmtdata;
+            if fmtdata.is_null() {
+                return None;
+            }
+            let handle = self.handle.as_raw();
+            let len = crate::dtrace_printf_format(handle, fmtdata, std::ptr::null_mut(), 0);
+            let mut buf = vec![0u8; len + 1];
+            crate::dtrace_printf_format(handle, fmtdata, buf.as_mut_ptr() as *mut ::core::ffi::c_char, buf.len());
+            let format = ::core::ffi::CStr::from_bytes_until_nul(&buf).ok()?;
+            Some(format.to_string_lossy().into_owned())
+        }
+    }
+
+    /// Replaces the format string of a `printf()`, `printa()`, `system()` or `freopen()` statement.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The new format string, which must have as many conversions as the current one, as the records
+    ///   formatted by the statement do not change.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the statement has no format string, the number of conversions differs, or libdtrace
+    /// cannot parse `format`.
+    pub fn set_format(&mut self, format: &str) -> Result<(), Error> {
+        let current = self
+            .format()
+            .ok_or_else(|| Error::new(-1, format!("Statement `{}` has no format string", self.probe())))?;
+        if conversions(format) != conversions(&current) {
+            return Err(Error::new(
+                -1,
+                format!(
+                    "Format `{}` has {} conversions, `{}` has {}",
+                    format,
+                    conversions(format),
+                    current,
+                    conversions(&current)
+                ),
+            ));
+        }
+        let printa = self
+            .action_descs()
+            .first()
+            .is_some_and(|&action| unsafe { (*action).dtad_kind as u32 == crate::DTRACEACT_PRINTA });
+        let c_format = std::ffi::CString::new(format)
+            .map_err(|_| Error::new(-1, "Format strings cannot contain NUL characters"))?;
+        unsafe {
+            let handle = self.handle.as_raw();
+            let fmtdata = match printa {
+                true => crate::dtrace_printa_create(handle, c_format.as_ptr()),
+                false => crate::dtrace_printf_create(handle, c_format.as_ptr()),
+            };
+            if fmtdata.is_null() {
+                return Err(Error::from(self.handle).with_context(format!("parsing format `{}`", format)));
+            }
+            crate::dtrace_printf_destroy((*self.stmt).dtsd_fmtdata);
+            (*self.stmt).dtsd_fmtdata = fmtdata;
+        }
+        Ok(())
+    }
+
+    /// Sets the number of frames recorded by the `stack()`, `ustack()` or `jstack()` action at `index`, counting the
+    /// actions of the statement from 0. The string space of `ustack()` and `jstack()` is kept.
+    pub fn set_stack_frames(&mut self, index: usize, frames: u32) -> Result<(), Error> {
+        let action = *self
+            .action_descs()
+            .get(index)
+            .ok_or_else(|| Error::new(-1, format!("Statement `{}` has no action {}", self.probe(), index)))?;
+        unsafe {
+            match (*action).dtad_kind as u32 {
+                crate::DTRACEACT_STACK => (*action).dtad_arg = frames as u64,
+                // The frames are the low 32 bits, the string space the high 32 bits, see DTRACE_USTACK_ARG.
+                crate::DTRACEACT_USTACK | crate::DTRACEACT_JSTACK => {
+                    (*action).dtad_arg = ((*action).dtad_arg & !0xffff_ffff) | frames as u64
+                }
+                kind => {
+                    return Err(Error::new(-1, format!("Action {} of kind {} does not record a stack", index, kind)))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Statement<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Statement")
+            .field("probe", &self.probe())
+            .field("actions", &self.actions())
+            .field("format", &self.format())
+            .finish()
+    }
+}
+
+/// Counts the conversions of a format string, `%%` excepted.
+pub(crate) fn conversions(format: &str) -> usize {
+    let mut count = 0;
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' && chars.next().is_some_and(|next| next != '%') {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Statement iterator appending every statement to the `Vec<*mut dtrace_stmtdesc_t>` passed as `arg`.
+pub(crate) unsafe extern "C" fn collect_stmt(
+    _handle: *mut crate::dtrace_hdl_t,
+    _program: *mut crate::dtrace_prog_t,
+    stmt: *mut crate::dtrace_stmtdesc_t,
+    arg: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    let stmts = &mut *(arg as *mut Vec<*mut crate::dtrace_stmtdesc_t>);
+    stmts.push(stmt);
+    0
+}
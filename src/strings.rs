@@ -0,0 +1,134 @@
+use std::borrow::Cow;
+
+/// How the bytes of string records are decoded, see [`decode`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum StringPolicy {
+    /// Fail on strings that are not valid in their encoding
+    Strict,
+    /// Replace invalid sequences with `U+FFFD`, like dtrace(1) would print them
+    #[default]
+    Lossy,
+    /// Keep the bytes as recorded, without decoding them
+    Raw,
+}
+
+/// Encoding of the strings of a record.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum StringEncoding {
+    /// Strings recorded by D, e.g. `execname` or `copyinstr()`
+    #[default]
+    Utf8,
+    /// Wide strings copied from a Windows process with `copyin()`, e.g. `UNICODE_STRING` buffers
+    Utf16,
+}
+
+/// A decoded string record.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DecodedString {
+    /// The decoded text
+    Text(String),
+    /// The bytes as recorded, with [`StringPolicy::Raw`]
+    Bytes(Vec<u8>),
+}
+
+impl DecodedString {
+    /// Returns the text, replacing invalid UTF-8 in raw bytes.
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        match self {
+            DecodedString::Text(text) => Cow::Borrowed(text),
+            DecodedString::Bytes(bytes) => String::from_utf8_lossy(bytes),
+        }
+    }
+}
+
+impl std::fmt::Display for DecodedString {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.to_string_lossy())
+    }
+}
+
+/// Error of strict decoding, the position of the first invalid sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InvalidString {
+    /// Offset in bytes of the first invalid sequence
+    pub offset: usize,
+}
+
+impl std::fmt::Display for InvalidString {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "invalid string at byte {}", self.offset)
+    }
+}
+
+impl std::error::Error for InvalidString {}
+
+/// Decodes a string record, which ends at its first NUL character or at the end of the record.
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes of the record.
+/// * `encoding` - The encoding of the string. UTF-16 strings are little-endian and end at their first NUL code unit.
+/// * `policy` - How invalid strings are handled.
+///
+/// # Returns
+///
+/// Returns the decoded string, or the position of the first invalid sequence with [`StringPolicy::Strict`].
+pub fn decode(bytes: &[u8], encoding: StringEncoding, policy: StringPolicy) -> Result<DecodedString, InvalidString> {
+    let bytes = match encoding {
+        StringEncoding::Utf8 => &bytes[..bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len())],
+        StringEncoding::Utf16 => {
+            let len = bytes
+                .chunks_exact(2)
+                .position(|unit| unit == [0, 0])
+                .map_or(bytes.len() & !1, |units| units * 2);
+            &bytes[..len]
+        }
+    };
+
+    match (encoding, policy) {
+        (_, StringPolicy::Raw) => Ok(DecodedString::Bytes(bytes.to_vec())),
+        (StringEncoding::Utf8, StringPolicy::Strict) => std::str::from_utf8(bytes)
+            .map(|text| DecodedString::Text(text.to_string()))
+            .map_err(|error| InvalidString {
+                offset: error.valid_up_to(),
+            }),
+        (StringEncoding::Utf8, StringPolicy::Lossy) => {
+            Ok(DecodedString::Text(String::from_utf8_lossy(bytes).into_owned()))
+        }
+        (StringEncoding::Utf16, _) => {
+            let units = bytes.chunks_exact(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]]));
+            let mut text = String::new();
+            let mut offset = 0;
+            for c in char::decode_utf16(units) {
+                match c {
+                    Ok(c) => {
+                        text.push(c);
+                        offset += c.len_utf16() * 2;
+                    }
+                    Err(_) if policy == StringPolicy::Lossy => {
+                        text.push(char::REPLACEMENT_CHARACTER);
+                        offset += 2;
+                    }
+                    Err(_) => return Err(InvalidString { offset }),
+                }
+            }
+            Ok(DecodedString::Text(text))
+        }
+    }
+}
+
+/// Decodes the string of a record passed to a record callback, see [`decode`].
+///
+/// # Safety
+///
+/// `data` and `rec` must be the arguments of a record callback.
+pub unsafe fn decode_record(
+    data: &crate::dtrace_probedata_t,
+    rec: &crate::dtrace_recdesc_t,
+    encoding: StringEncoding,
+    policy: StringPolicy,
+) -> Result<DecodedString, InvalidString> {
+    // libdtrace points the data of the firing at the record it passes.
+    let bytes = std::slice::from_raw_parts(data.dtpda_data as *const u8, rec.dtrd_size as usize);
+    decode(bytes, encoding, policy)
+}
@@ -0,0 +1,1200 @@
+//! Stand-ins for the libdtrace bindings, used instead of the generated bindings with the `stub` feature.
+//!
+//! The declarations cover what this crate uses. Every libdtrace call fails: `dtrace_open` returns no handle and
+//! reports [`STUB_ERRNO`], so the wrapper returns errors instead of tracing.
+#![allow(clippy::missing_safety_doc)]
+
+/// Error number reported by every failing call of a stub build.
+pub const STUB_ERRNO: ::core::ffi::c_int = -1;
+
+/// Message of all errors of a stub build.
+const STUB_MESSAGE: &::core::ffi::CStr = c"libdtrace is not available in this build (stub feature)";
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct _iobuf {
+    pub _Placeholder: *mut ::core::ffi::c_void,
+}
+pub type FILE = _iobuf;
+
+pub const DTRACE_VERSION: u32 = 3;
+pub const DTRACE_O_NODEV: u32 = 1;
+pub const DTRACE_O_NOSYS: u32 = 2;
+pub const DTRACE_O_LP64: u32 = 4;
+pub const DTRACE_O_ILP32: u32 = 8;
+pub const DTRACE_O_MASK: u32 = 15;
+
+pub const DTRACE_C_DIFV: u32 = 1;
+pub const DTRACE_C_EMPTY: u32 = 2;
+pub const DTRACE_C_ZDEFS: u32 = 4;
+pub const DTRACE_D_STRIP: u32 = 1;
+pub const DTRACE_D_PROBES: u32 = 2;
+pub const DTRACE_D_MASK: u32 = 3;
+pub const DTRACE_C_EATTR: u32 = 8;
+pub const DTRACE_C_CPP: u32 = 16;
+pub const DTRACE_C_KNODEF: u32 = 32;
+pub const DTRACE_C_UNODEF: u32 = 64;
+pub const DTRACE_C_PSPEC: u32 = 128;
+pub const DTRACE_C_ETAGS: u32 = 256;
+pub const DTRACE_C_ARGREF: u32 = 512;
+pub const DTRACE_C_DEFARG: u32 = 2048;
+pub const DTRACE_C_NOLIBS: u32 = 4096;
+pub const DTRACE_C_CTL: u32 = 8192;
+pub const DTRACE_C_MASK: u32 = 16383;
+
+pub const DTRACE_HANDLE_ABORT: u32 = 1;
+pub const DTRACE_HANDLE_OK: u32 = 0;
+
+pub const DTRACE_CONSUME_ERROR: i32 = -1;
+pub const DTRACE_CONSUME_THIS: u32 = 0;
+pub const DTRACE_CONSUME_NEXT: u32 = 1;
+pub const DTRACE_CONSUME_ABORT: u32 = 2;
+
+pub const DTRACE_BUFDATA_AGGKEY: u32 = 1;
+pub const DTRACE_BUFDATA_AGGVAL: u32 = 2;
+pub const DTRACE_BUFDATA_AGGFORMAT: u32 = 4;
+pub const DTRACE_BUFDATA_AGGLAST: u32 = 8;
+
+pub const DTRACE_AGGWALK_ERROR: i32 = -1;
+pub const DTRACE_AGGWALK_NEXT: u32 = 0;
+pub const DTRACE_AGGWALK_ABORT: u32 = 1;
+pub const DTRACE_AGGWALK_CLEAR: u32 = 2;
+pub const DTRACE_AGGWALK_NORMALIZE: u32 = 3;
+pub const DTRACE_AGGWALK_DENORMALIZE: u32 = 4;
+pub const DTRACE_AGGWALK_REMOVE: u32 = 5;
+
+pub const DTRACE_STATUS_NONE: u32 = 0;
+pub const DTRACE_STATUS_OKAY: u32 = 1;
+pub const DTRACE_STATUS_EXITED: u32 = 2;
+pub const DTRACE_STATUS_FILLED: u32 = 3;
+pub const DTRACE_STATUS_STOPPED: u32 = 4;
+
+pub const DTRACE_PROVNAMELEN: u32 = 64;
+pub const DTRACE_MODNAMELEN: u32 = 64;
+pub const DTRACE_FUNCNAMELEN: u32 = 192;
+pub const DTRACE_NAMELEN: u32 = 64;
+pub const DTRACE_ARGTYPELEN: u32 = 128;
+
+pub const DTRACEACT_NONE: u32 = 0;
+pub const DTRACEACT_DIFEXPR: u32 = 1;
+pub const DTRACEACT_EXIT: u32 = 2;
+pub const DTRACEACT_PRINTF: u32 = 3;
+pub const DTRACEACT_PRINTA: u32 = 4;
+pub const DTRACEACT_LIBACT: u32 = 5;
+pub const DTRACEACT_TRACEMEM: u32 = 6;
+pub const DTRACEACT_TRACEMEM_DYNSIZE: u32 = 7;
+pub const DTRACEACT_PROC: u32 = 256;
+pub const DTRACEACT_USTACK: u32 = 257;
+pub const DTRACEACT_JSTACK: u32 = 258;
+pub const DTRACEACT_USYM: u32 = 259;
+pub const DTRACEACT_UMOD: u32 = 260;
+pub const DTRACEACT_UADDR: u32 = 261;
+pub const DTRACEACT_PROC_DESTRUCTIVE: u32 = 512;
+pub const DTRACEACT_STOP: u32 = 513;
+pub const DTRACEACT_RAISE: u32 = 514;
+pub const DTRACEACT_SYSTEM: u32 = 515;
+pub const DTRACEACT_FREOPEN: u32 = 516;
+pub const DTRACEACT_PROC_CONTROL: u32 = 768;
+pub const DTRACEACT_KERNEL: u32 = 1024;
+pub const DTRACEACT_STACK: u32 = 1025;
+pub const DTRACEACT_SYM: u32 = 1026;
+pub const DTRACEACT_MOD: u32 = 1027;
+pub const DTRACEACT_KERNEL_DESTRUCTIVE: u32 = 1280;
+pub const DTRACEACT_BREAKPOINT: u32 = 1281;
+pub const DTRACEACT_PANIC: u32 = 1282;
+pub const DTRACEACT_CHILL: u32 = 1283;
+pub const DTRACEACT_SPECULATIVE: u32 = 1536;
+pub const DTRACEACT_SPECULATE: u32 = 1537;
+pub const DTRACEACT_COMMIT: u32 = 1538;
+pub const DTRACEACT_DISCARD: u32 = 1539;
+pub const DTRACEACT_AGGREGATION: u32 = 1792;
+pub const DTRACEAGG_COUNT: u32 = 1793;
+pub const DTRACEAGG_MIN: u32 = 1794;
+pub const DTRACEAGG_MAX: u32 = 1795;
+pub const DTRACEAGG_AVG: u32 = 1796;
+pub const DTRACEAGG_SUM: u32 = 1797;
+pub const DTRACEAGG_STDDEV: u32 = 1798;
+pub const DTRACEAGG_QUANTIZE: u32 = 1799;
+pub const DTRACEAGG_LQUANTIZE: u32 = 1800;
+pub const DTRACEAGG_LLQUANTIZE: u32 = 1801;
+
+pub const DTRACEFLOW_ENTRY: u32 = 1;
+pub const DTRACEFLOW_RETURN: u32 = 2;
+pub const DTRACEFLOW_NONE: u32 = 3;
+
+pub const DTRACEFLT_UNKNOWN: u32 = 0;
+pub const DTRACEFLT_BADADDR: u32 = 1;
+pub const DTRACEFLT_BADALIGN: u32 = 2;
+pub const DTRACEFLT_ILLOP: u32 = 3;
+pub const DTRACEFLT_DIVZERO: u32 = 4;
+pub const DTRACEFLT_NOSCRATCH: u32 = 5;
+pub const DTRACEFLT_KPRIV: u32 = 6;
+pub const DTRACEFLT_UPRIV: u32 = 7;
+pub const DTRACEFLT_TUPOFLOW: u32 = 8;
+pub const DTRACEFLT_BADSTACK: u32 = 9;
+pub const DTRACEFLT_LIBRARY: u32 = 1000;
+
+pub const DTRACEOPT_BUFSIZE: u32 = 0;
+pub const DTRACEOPT_BUFPOLICY: u32 = 1;
+pub const DTRACEOPT_DYNVARSIZE: u32 = 2;
+pub const DTRACEOPT_AGGSIZE: u32 = 3;
+pub const DTRACEOPT_SPECSIZE: u32 = 4;
+pub const DTRACEOPT_NSPEC: u32 = 5;
+pub const DTRACEOPT_STRSIZE: u32 = 6;
+pub const DTRACEOPT_CLEANRATE: u32 = 7;
+pub const DTRACEOPT_CPU: u32 = 8;
+pub const DTRACEOPT_BUFRESIZE: u32 = 9;
+pub const DTRACEOPT_GRABANON: u32 = 10;
+pub const DTRACEOPT_FLOWINDENT: u32 = 11;
+pub const DTRACEOPT_QUIET: u32 = 12;
+pub const DTRACEOPT_STACKFRAMES: u32 = 13;
+pub const DTRACEOPT_USTACKFRAMES: u32 = 14;
+pub const DTRACEOPT_AGGRATE: u32 = 15;
+pub const DTRACEOPT_SWITCHRATE: u32 = 16;
+pub const DTRACEOPT_STATUSRATE: u32 = 17;
+pub const DTRACEOPT_DESTRUCTIVE: u32 = 18;
+pub const DTRACEOPT_STACKINDENT: u32 = 19;
+pub const DTRACEOPT_RAWBYTES: u32 = 20;
+pub const DTRACEOPT_JSTACKFRAMES: u32 = 21;
+pub const DTRACEOPT_JSTACKSTRSIZE: u32 = 22;
+pub const DTRACEOPT_AGGSORTKEY: u32 = 23;
+pub const DTRACEOPT_AGGSORTREV: u32 = 24;
+pub const DTRACEOPT_AGGSORTPOS: u32 = 25;
+pub const DTRACEOPT_AGGSORTKEYPOS: u32 = 26;
+pub const DTRACEOPT_TEMPORAL: u32 = 27;
+pub const DTRACEOPT_MAX: u32 = 28;
+pub const DTRACEOPT_UNSET: i64 = -2;
+
+pub const EDT_BASE: u32 = 1000;
+pub const EDT_COMPILER: u32 = 1005;
+pub const EDT_NOPROBE: u32 = 1012;
+pub const EDT_CPPFORK: u32 = 1041;
+pub const EDT_CPPEXEC: u32 = 1042;
+pub const EDT_CPPENT: u32 = 1043;
+pub const EDT_CPPERR: u32 = 1044;
+pub const EDT_DESTRUCTIVE: u32 = 1047;
+pub const EDT_ACCESS: u32 = 1062;
+pub const EDT_NOPROBES: u32 = 1074;
+
+pub type dtrace_optval_t = i64;
+pub type dtrace_epid_t = u32;
+pub type dtrace_id_t = u32;
+pub type dtrace_aggid_t = u32;
+pub type dtrace_aggvarid_t = i64;
+pub type dtrace_actkind_t = u16;
+pub type dtrace_stability_t = u8;
+pub type dtrace_class_t = u8;
+pub type processorid_t = ::core::ffi::c_int;
+pub type caddr_t = *mut ::core::ffi::c_char;
+pub type pid_t = ::core::ffi::c_int;
+pub type hrtime_t = ::core::ffi::c_longlong;
+pub type proc_child_func = ::core::option::Option<unsafe extern "C" fn(arg1: *mut ::core::ffi::c_void)>;
+pub type ctf_id_t = ::core::ffi::c_long;
+pub type ssize_t = ::core::ffi::c_longlong;
+pub const CTF_K_UNKNOWN: u32 = 0;
+pub const CTF_K_INTEGER: u32 = 1;
+pub const CTF_K_FLOAT: u32 = 2;
+pub const CTF_K_POINTER: u32 = 3;
+pub const CTF_K_ARRAY: u32 = 4;
+pub const CTF_K_FUNCTION: u32 = 5;
+pub const CTF_K_STRUCT: u32 = 6;
+pub const CTF_K_UNION: u32 = 7;
+pub const CTF_K_ENUM: u32 = 8;
+pub const CTF_K_FORWARD: u32 = 9;
+pub const CTF_K_TYPEDEF: u32 = 10;
+pub const CTF_K_VOLATILE: u32 = 11;
+pub const CTF_K_CONST: u32 = 12;
+pub const CTF_K_RESTRICT: u32 = 13;
+pub const CTF_INT_SIGNED: u32 = 1;
+pub const CTF_INT_CHAR: u32 = 2;
+pub const CTF_INT_BOOL: u32 = 4;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ctf_encoding {
+    pub cte_format: ::core::ffi::c_uint,
+    pub cte_offset: ::core::ffi::c_uint,
+    pub cte_bits: ::core::ffi::c_uint,
+}
+pub type ctf_encoding_t = ctf_encoding;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ctf_arinfo {
+    pub ctr_contents: ctf_id_t,
+    pub ctr_index: ctf_id_t,
+    pub ctr_nelems: ::core::ffi::c_uint,
+}
+pub type ctf_arinfo_t = ctf_arinfo;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ctf_file {
+    _unused: [u8; 0],
+}
+pub type ctf_file_t = ctf_file;
+pub type ctf_member_f = ::core::option::Option<
+    unsafe extern "C" fn(
+        arg1: *const ::core::ffi::c_char,
+        arg2: ctf_id_t,
+        arg3: ::core::ffi::c_ulong,
+        arg4: *mut ::core::ffi::c_void,
+    ) -> ::core::ffi::c_int,
+>;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_typeinfo {
+    pub dtt_object: *const ::core::ffi::c_char,
+    pub dtt_ctfp: *mut ctf_file_t,
+    pub dtt_type: ctf_id_t,
+    pub dtt_flags: ::core::ffi::c_uint,
+}
+pub type dtrace_typeinfo_t = dtrace_typeinfo;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_hdl {
+    pub dt_errno: ::core::ffi::c_int,
+    pub dt_options: [dtrace_optval_t; 28usize],
+    pub dt_maxprobe: dtrace_epid_t,
+    pub dt_edesc: *mut *mut dtrace_eprobedesc_t,
+    pub dt_pdesc: *mut *mut dtrace_probedesc_t,
+    pub dt_maxagg: usize,
+    pub dt_aggdesc: *mut *mut dtrace_aggdesc_t,
+    pub dt_maxformat: ::core::ffi::c_int,
+    pub dt_formats: *mut *mut ::core::ffi::c_void,
+    pub dt_maxstrdata: ::core::ffi::c_int,
+    pub dt_strdata: *mut *mut ::core::ffi::c_char,
+    pub dt_errhdlr: dtrace_handle_err_f,
+    pub dt_errarg: *mut ::core::ffi::c_void,
+    pub dt_drophdlr: dtrace_handle_drop_f,
+    pub dt_droparg: *mut ::core::ffi::c_void,
+    pub dt_prochdlr: dtrace_handle_proc_f,
+    pub dt_procarg: *mut ::core::ffi::c_void,
+    pub dt_setopthdlr: dtrace_handle_setopt_f,
+    pub dt_setoptarg: *mut ::core::ffi::c_void,
+    pub dt_bufhdlr: dtrace_handle_buffered_f,
+    pub dt_bufarg: *mut ::core::ffi::c_void,
+    pub dt_modlist: dt_list_t,
+    pub dt_nmods: ::core::ffi::c_uint,
+    pub dt_laststatus: hrtime_t,
+    pub dt_aggregate: dt_aggregate_t,
+}
+pub type dtrace_hdl_t = dtrace_hdl;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dt_aggregate {
+    pub dtat_flags: ::core::ffi::c_int,
+    pub dtat_cpu: processorid_t,
+    pub dtat_ncpus: ::core::ffi::c_int,
+    pub dtat_cpus: *mut processorid_t,
+    pub dtat_ncpu: processorid_t,
+    pub dtat_maxcpu: processorid_t,
+}
+pub type dt_aggregate_t = dt_aggregate;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dt_list {
+    pub dl_prev: *mut dt_list,
+    pub dl_next: *mut dt_list,
+}
+pub type dt_list_t = dt_list;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dt_module {
+    pub dm_list: dt_list_t,
+    pub dm_name: [::core::ffi::c_char; 64usize],
+}
+pub type dt_module_t = dt_module;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_prog {
+    _unused: [u8; 0],
+}
+pub type dtrace_prog_t = dtrace_prog;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dof_hdr {
+    pub dofh_ident: [u8; 16usize],
+    pub dofh_flags: u32,
+    pub dofh_hdrsize: u32,
+    pub dofh_secsize: u32,
+    pub dofh_secnum: u32,
+    pub dofh_secoff: u64,
+    pub dofh_loadsz: u64,
+    pub dofh_filesz: u64,
+    pub dofh_pad: u64,
+}
+pub type dof_hdr_t = dof_hdr;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ps_prochandle {
+    _unused: [u8; 0],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_attribute {
+    pub dtat_name: dtrace_stability_t,
+    pub dtat_data: dtrace_stability_t,
+    pub dtat_class: dtrace_class_t,
+}
+pub type dtrace_attribute_t = dtrace_attribute;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_proginfo {
+    pub dpi_descattr: dtrace_attribute_t,
+    pub dpi_stmtattr: dtrace_attribute_t,
+    pub dpi_aggregates: ::core::ffi::c_uint,
+    pub dpi_recgens: ::core::ffi::c_uint,
+    pub dpi_matches: ::core::ffi::c_uint,
+    pub dpi_speculations: ::core::ffi::c_uint,
+}
+pub type dtrace_proginfo_t = dtrace_proginfo;
+
+#[repr(u32)]
+#[derive(Copy, Clone, Hash, PartialEq, Eq)]
+pub enum dtrace_probespec {
+    DTRACE_PROBESPEC_NONE = 4294967295,
+    DTRACE_PROBESPEC_PROVIDER = 0,
+    DTRACE_PROBESPEC_MOD = 1,
+    DTRACE_PROBESPEC_FUNC = 2,
+    DTRACE_PROBESPEC_NAME = 3,
+}
+pub use self::dtrace_probespec as dtrace_probespec_t;
+
+#[repr(i32)]
+#[derive(Copy, Clone, Hash, PartialEq, Eq)]
+pub enum dtrace_workstatus_t {
+    DTRACE_WORKSTATUS_ERROR = -1,
+    DTRACE_WORKSTATUS_OKAY = 0,
+    DTRACE_WORKSTATUS_DONE = 1,
+}
+
+#[repr(u32)]
+#[derive(Copy, Clone, Hash, PartialEq, Eq)]
+pub enum dtrace_dropkind_t {
+    DTRACEDROP_PRINCIPAL = 0,
+    DTRACEDROP_AGGREGATION = 1,
+    DTRACEDROP_DYNAMIC = 2,
+    DTRACEDROP_DYNRINSE = 3,
+    DTRACEDROP_DYNDIRTY = 4,
+    DTRACEDROP_SPEC = 5,
+    DTRACEDROP_SPECBUSY = 6,
+    DTRACEDROP_SPECUNAVAIL = 7,
+    DTRACEDROP_STKSTROVERFLOW = 8,
+    DTRACEDROP_DBLERROR = 9,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_recdesc {
+    pub dtrd_action: dtrace_actkind_t,
+    pub dtrd_size: u32,
+    pub dtrd_offset: u32,
+    pub dtrd_alignment: u16,
+    pub dtrd_format: u16,
+    pub dtrd_arg: u64,
+    pub dtrd_uarg: u64,
+}
+pub type dtrace_recdesc_t = dtrace_recdesc;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_eprobedesc {
+    pub dtepd_epid: dtrace_epid_t,
+    pub dtepd_probeid: dtrace_id_t,
+    pub dtepd_uarg: u64,
+    pub dtepd_size: u32,
+    pub dtepd_nrecs: ::core::ffi::c_int,
+    pub dtepd_rec: [dtrace_recdesc_t; 1usize],
+}
+pub type dtrace_eprobedesc_t = dtrace_eprobedesc;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_probedesc {
+    pub dtpd_id: dtrace_id_t,
+    pub dtpd_provider: [::core::ffi::c_char; 64usize],
+    pub dtpd_mod: [::core::ffi::c_char; 64usize],
+    pub dtpd_func: [::core::ffi::c_char; 192usize],
+    pub dtpd_name: [::core::ffi::c_char; 64usize],
+}
+pub type dtrace_probedesc_t = dtrace_probedesc;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_aggdesc {
+    pub dtagd_name: *mut ::core::ffi::c_char,
+    pub dtagd_varid: dtrace_aggvarid_t,
+    pub dtagd_flags: ::core::ffi::c_int,
+    pub dtagd_id: dtrace_aggid_t,
+    pub dtagd_epid: dtrace_epid_t,
+    pub dtagd_size: u32,
+    pub dtagd_nrecs: u32,
+    pub dtagd_pad: u32,
+    pub dtagd_rec: [dtrace_recdesc_t; 1usize],
+}
+pub type dtrace_aggdesc_t = dtrace_aggdesc;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_probedata {
+    pub dtpda_handle: *mut dtrace_hdl_t,
+    pub dtpda_edesc: *mut dtrace_eprobedesc_t,
+    pub dtpda_pdesc: *mut dtrace_probedesc_t,
+    pub dtpda_cpu: processorid_t,
+    pub dtpda_data: caddr_t,
+    pub dtpda_flow: ::core::ffi::c_int,
+    pub dtpda_prefix: *const ::core::ffi::c_char,
+    pub dtpda_indent: ::core::ffi::c_int,
+}
+pub type dtrace_probedata_t = dtrace_probedata;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_aggdata {
+    pub dtada_handle: *mut dtrace_hdl_t,
+    pub dtada_desc: *mut dtrace_aggdesc_t,
+    pub dtada_edesc: *mut dtrace_eprobedesc_t,
+    pub dtada_pdesc: *mut dtrace_probedesc_t,
+    pub dtada_data: caddr_t,
+    pub dtada_normal: u64,
+    pub dtada_size: usize,
+    pub dtada_delta: caddr_t,
+    pub dtada_percpu: *mut caddr_t,
+    pub dtada_percpu_delta: *mut caddr_t,
+}
+pub type dtrace_aggdata_t = dtrace_aggdata;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_bufdata {
+    pub dtbda_handle: *mut dtrace_hdl_t,
+    pub dtbda_buffered: *const ::core::ffi::c_char,
+    pub dtbda_probe: *mut dtrace_probedata_t,
+    pub dtbda_recdesc: *const dtrace_recdesc_t,
+    pub dtbda_aggdata: *const dtrace_aggdata_t,
+    pub dtbda_flags: u32,
+}
+pub type dtrace_bufdata_t = dtrace_bufdata;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_errdata {
+    pub dteda_handle: *mut dtrace_hdl_t,
+    pub dteda_edesc: *mut dtrace_eprobedesc_t,
+    pub dteda_pdesc: *mut dtrace_probedesc_t,
+    pub dteda_cpu: processorid_t,
+    pub dteda_action: ::core::ffi::c_int,
+    pub dteda_offset: ::core::ffi::c_int,
+    pub dteda_fault: ::core::ffi::c_int,
+    pub dteda_addr: u64,
+    pub dteda_msg: *const ::core::ffi::c_char,
+}
+pub type dtrace_errdata_t = dtrace_errdata;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_dropdata {
+    pub dtdda_handle: *mut dtrace_hdl_t,
+    pub dtdda_cpu: processorid_t,
+    pub dtdda_kind: dtrace_dropkind_t,
+    pub dtdda_drops: u64,
+    pub dtdda_total: u64,
+    pub dtdda_msg: *const ::core::ffi::c_char,
+}
+pub type dtrace_dropdata_t = dtrace_dropdata;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_setoptdata {
+    pub dtsda_handle: *mut dtrace_hdl_t,
+    pub dtsda_probe: *const dtrace_probedata_t,
+    pub dtsda_option: *const ::core::ffi::c_char,
+    pub dtsda_oldval: dtrace_optval_t,
+    pub dtsda_newval: dtrace_optval_t,
+}
+pub type dtrace_setoptdata_t = dtrace_setoptdata;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_stmtdesc {
+    pub dtsd_ecbdesc: *mut dtrace_ecbdesc_t,
+    pub dtsd_action: *mut dtrace_actdesc_t,
+    pub dtsd_action_last: *mut dtrace_actdesc_t,
+    pub dtsd_aggdata: *mut ::core::ffi::c_void,
+    pub dtsd_fmtdata: *mut ::core::ffi::c_void,
+    pub dtsd_strdata: *mut ::core::ffi::c_void,
+    pub dtsd_callback: ::core::option::Option<unsafe extern "C" fn()>,
+    pub dtsd_data: *mut ::core::ffi::c_void,
+    pub dtsd_descattr: dtrace_attribute_t,
+    pub dtsd_stmtattr: dtrace_attribute_t,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_actdesc {
+    pub dtad_difo: *mut ::core::ffi::c_void,
+    pub dtad_next: *mut dtrace_actdesc,
+    pub dtad_kind: dtrace_actkind_t,
+    pub dtad_ntuple: u32,
+    pub dtad_arg: u64,
+    pub dtad_uarg: u64,
+    pub dtad_refcnt: ::core::ffi::c_int,
+}
+pub type dtrace_actdesc_t = dtrace_actdesc;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_ecbdesc {
+    pub dted_action: *mut ::core::ffi::c_void,
+    pub dted_pred: [u64; 2],
+    pub dted_probe: dtrace_probedesc_t,
+    pub dted_uarg: u64,
+    pub dted_refcnt: ::core::ffi::c_int,
+}
+pub type dtrace_ecbdesc_t = dtrace_ecbdesc;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct GElf_Sym {
+    pub st_name: u32,
+    pub st_info: ::core::ffi::c_uchar,
+    pub st_other: ::core::ffi::c_uchar,
+    pub st_shndx: u16,
+    pub st_value: u64,
+    pub st_size: u64,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_syminfo {
+    pub dts_object: *const ::core::ffi::c_char,
+    pub dts_name: *const ::core::ffi::c_char,
+    pub dts_id: ::core::ffi::c_ulong,
+}
+pub type dtrace_syminfo_t = dtrace_syminfo;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct dtrace_probeinfo {
+    pub dtp_attr: dtrace_attribute_t,
+    pub dtp_arga: dtrace_attribute_t,
+    pub dtp_argv: *const dtrace_typeinfo_t,
+    pub dtp_argc: ::core::ffi::c_int,
+}
+pub type dtrace_probeinfo_t = dtrace_probeinfo;
+
+pub type dtrace_probe_f = ::core::option::Option<
+    unsafe extern "C" fn(
+        arg1: *mut dtrace_hdl_t,
+        arg2: *const dtrace_probedesc_t,
+        arg3: *mut ::core::ffi::c_void,
+    ) -> ::core::ffi::c_int,
+>;
+pub type dtrace_stmtdesc_t = dtrace_stmtdesc;
+
+pub type dtrace_handle_err_f = ::core::option::Option<
+    unsafe extern "C" fn(
+        arg1: *const dtrace_errdata_t,
+        arg2: *mut ::core::ffi::c_void,
+    ) -> ::core::ffi::c_int,
+>;
+pub type dtrace_handle_drop_f = ::core::option::Option<
+    unsafe extern "C" fn(
+        arg1: *const dtrace_dropdata_t,
+        arg2: *mut ::core::ffi::c_void,
+    ) -> ::core::ffi::c_int,
+>;
+pub type dtrace_handle_proc_f = ::core::option::Option<
+    unsafe extern "C" fn(
+        arg1: *mut ps_prochandle,
+        arg2: *const ::core::ffi::c_char,
+        arg3: *mut ::core::ffi::c_void,
+    ),
+>;
+pub type dtrace_handle_setopt_f = ::core::option::Option<
+    unsafe extern "C" fn(
+        arg1: *const dtrace_setoptdata_t,
+        arg2: *mut ::core::ffi::c_void,
+    ) -> ::core::ffi::c_int,
+>;
+pub type dtrace_handle_buffered_f = ::core::option::Option<
+    unsafe extern "C" fn(
+        arg1: *const dtrace_bufdata_t,
+        arg2: *mut ::core::ffi::c_void,
+    ) -> ::core::ffi::c_int,
+>;
+pub type dtrace_consume_probe_f = ::core::option::Option<
+    unsafe extern "C" fn(
+        arg1: *const dtrace_probedata_t,
+        arg2: *mut ::core::ffi::c_void,
+    ) -> ::core::ffi::c_int,
+>;
+pub type dtrace_consume_rec_f = ::core::option::Option<
+    unsafe extern "C" fn(
+        arg1: *const dtrace_probedata_t,
+        arg2: *const dtrace_recdesc_t,
+        arg3: *mut ::core::ffi::c_void,
+    ) -> ::core::ffi::c_int,
+>;
+pub type dtrace_aggregate_f = ::core::option::Option<
+    unsafe extern "C" fn(
+        arg1: *const dtrace_aggdata_t,
+        arg2: *mut ::core::ffi::c_void,
+    ) -> ::core::ffi::c_int,
+>;
+pub type dtrace_aggregate_walk_f = ::core::option::Option<
+    unsafe extern "C" fn(
+        arg1: *mut dtrace_hdl_t,
+        arg2: dtrace_aggregate_f,
+        arg3: *mut ::core::ffi::c_void,
+    ) -> ::core::ffi::c_int,
+>;
+pub type dtrace_stmt_f = ::core::option::Option<
+    unsafe extern "C" fn(
+        arg1: *mut dtrace_hdl_t,
+        arg2: *mut dtrace_prog_t,
+        arg3: *mut dtrace_stmtdesc_t,
+        arg4: *mut ::core::ffi::c_void,
+    ) -> ::core::ffi::c_int,
+>;
+
+pub unsafe extern "C" fn dtrace_open(
+    _arg1: ::core::ffi::c_int,
+    _arg2: ::core::ffi::c_int,
+    arg3: *mut ::core::ffi::c_int,
+) -> *mut dtrace_hdl_t {
+    if !arg3.is_null() {
+        *arg3 = STUB_ERRNO;
+    }
+    ::core::ptr::null_mut()
+}
+
+pub unsafe extern "C" fn dtrace_close(
+    _arg1: *mut dtrace_hdl_t,
+) {}
+
+pub unsafe extern "C" fn dtrace_go(
+    _arg1: *mut dtrace_hdl_t,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_stop(
+    _arg1: *mut dtrace_hdl_t,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_sleep(
+    _arg1: *mut dtrace_hdl_t,
+) {}
+
+pub unsafe extern "C" fn dtrace_errno(
+    _arg1: *mut dtrace_hdl_t,
+) -> ::core::ffi::c_int {
+    STUB_ERRNO
+}
+
+pub unsafe extern "C" fn dtrace_errmsg(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: ::core::ffi::c_int,
+) -> *const ::core::ffi::c_char {
+    STUB_MESSAGE.as_ptr()
+}
+
+pub unsafe extern "C" fn dtrace_faultstr(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: ::core::ffi::c_int,
+) -> *const ::core::ffi::c_char {
+    STUB_MESSAGE.as_ptr()
+}
+
+pub unsafe extern "C" fn dtrace_setopt(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *const ::core::ffi::c_char,
+    _arg3: *const ::core::ffi::c_char,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_getopt(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *const ::core::ffi::c_char,
+    _arg3: *mut dtrace_optval_t,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_update(
+    _arg1: *mut dtrace_hdl_t,
+) {}
+
+pub unsafe extern "C" fn dtrace_uaddr2str(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: pid_t,
+    _arg3: u64,
+    _arg4: *mut ::core::ffi::c_char,
+    _arg5: ::core::ffi::c_int,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_lookup_by_addr(
+    _arg1: *mut dtrace_hdl_t,
+    _addr: u64,
+    _symp: *mut GElf_Sym,
+    _info: *mut dtrace_syminfo_t,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_program_strcompile(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *const ::core::ffi::c_char,
+    _arg3: dtrace_probespec_t,
+    _arg4: ::core::ffi::c_uint,
+    _arg5: ::core::ffi::c_int,
+    _arg6: *const *mut ::core::ffi::c_char,
+) -> *mut dtrace_prog_t {
+    ::core::ptr::null_mut()
+}
+
+pub unsafe extern "C" fn dtrace_program_fcompile(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *mut FILE,
+    _arg3: ::core::ffi::c_uint,
+    _arg4: ::core::ffi::c_int,
+    _arg5: *const *mut ::core::ffi::c_char,
+) -> *mut dtrace_prog_t {
+    ::core::ptr::null_mut()
+}
+
+pub unsafe extern "C" fn dtrace_dof_create(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *mut dtrace_prog_t,
+    _arg3: ::core::ffi::c_uint,
+) -> *mut ::core::ffi::c_void {
+    ::core::ptr::null_mut()
+}
+
+pub unsafe extern "C" fn dtrace_dof_destroy(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *mut ::core::ffi::c_void,
+) {}
+
+pub unsafe extern "C" fn dtrace_program_link(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *mut dtrace_prog_t,
+    _arg3: ::core::ffi::c_uint,
+    _arg4: *const ::core::ffi::c_char,
+    _arg5: ::core::ffi::c_int,
+    _arg6: *const *mut ::core::ffi::c_char,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_lookup_by_type(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *const ::core::ffi::c_char,
+    _arg3: *const ::core::ffi::c_char,
+    _arg4: *mut dtrace_typeinfo_t,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_symbol_type(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *const GElf_Sym,
+    _arg3: *const dtrace_syminfo_t,
+    _arg4: *mut dtrace_typeinfo_t,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn ctf_type_kind(
+    _arg1: *mut ctf_file_t,
+    _arg2: ctf_id_t,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn ctf_type_size(
+    _arg1: *mut ctf_file_t,
+    _arg2: ctf_id_t,
+) -> ssize_t {
+    -1
+}
+
+pub unsafe extern "C" fn ctf_type_resolve(
+    _arg1: *mut ctf_file_t,
+    _arg2: ctf_id_t,
+) -> ctf_id_t {
+    -1
+}
+
+pub unsafe extern "C" fn ctf_type_name(
+    _arg1: *mut ctf_file_t,
+    _arg2: ctf_id_t,
+    _arg3: *mut ::core::ffi::c_char,
+    _arg4: usize,
+) -> *mut ::core::ffi::c_char {
+    ::core::ptr::null_mut()
+}
+
+pub unsafe extern "C" fn ctf_type_reference(
+    _arg1: *mut ctf_file_t,
+    _arg2: ctf_id_t,
+) -> ctf_id_t {
+    -1
+}
+
+pub unsafe extern "C" fn ctf_type_encoding(
+    _arg1: *mut ctf_file_t,
+    _arg2: ctf_id_t,
+    _arg3: *mut ctf_encoding_t,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn ctf_array_info(
+    _arg1: *mut ctf_file_t,
+    _arg2: ctf_id_t,
+    _arg3: *mut ctf_arinfo_t,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn ctf_enum_name(
+    _arg1: *mut ctf_file_t,
+    _arg2: ctf_id_t,
+    _arg3: ::core::ffi::c_int,
+) -> *const ::core::ffi::c_char {
+    ::core::ptr::null()
+}
+
+pub unsafe extern "C" fn dt_module_lookup_by_name(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *const ::core::ffi::c_char,
+) -> *mut dt_module_t {
+    ::core::ptr::null_mut()
+}
+
+pub unsafe extern "C" fn dt_module_getctf(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *mut dt_module_t,
+) -> *mut ctf_file_t {
+    ::core::ptr::null_mut()
+}
+
+pub unsafe extern "C" fn ctf_member_iter(
+    _arg1: *mut ctf_file_t,
+    _arg2: ctf_id_t,
+    _arg3: ctf_member_f,
+    _arg4: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_getopt_dof(
+    _arg1: *mut dtrace_hdl_t,
+) -> *mut ::core::ffi::c_void {
+    ::core::ptr::null_mut()
+}
+
+pub unsafe extern "C" fn dt_program_destroy(_arg1: *mut dtrace_hdl_t, _arg2: *mut dtrace_prog_t) {}
+
+pub unsafe extern "C" fn dtrace_program_exec(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *mut dtrace_prog_t,
+    _arg3: *mut dtrace_proginfo_t,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_program_info(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *mut dtrace_prog_t,
+    _arg3: *mut dtrace_proginfo_t,
+) {}
+
+pub unsafe extern "C" fn dtrace_stmt_iter(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *mut dtrace_prog_t,
+    _arg3: dtrace_stmt_f,
+    _arg4: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_printf_create(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *const ::core::ffi::c_char,
+) -> *mut ::core::ffi::c_void {
+    ::core::ptr::null_mut()
+}
+
+pub unsafe extern "C" fn dtrace_printa_create(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *const ::core::ffi::c_char,
+) -> *mut ::core::ffi::c_void {
+    ::core::ptr::null_mut()
+}
+
+pub unsafe extern "C" fn dtrace_printf_format(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *mut ::core::ffi::c_void,
+    _arg3: *mut ::core::ffi::c_char,
+    _arg4: usize,
+) -> usize {
+    0
+}
+
+pub unsafe extern "C" fn dtrace_printf_destroy(_arg1: *mut ::core::ffi::c_void) {}
+
+pub unsafe extern "C" fn dt_status(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: processorid_t,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_status(
+    _arg1: *mut dtrace_hdl_t,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_consume(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *mut FILE,
+    _arg3: dtrace_consume_probe_f,
+    _arg4: dtrace_consume_rec_f,
+    _arg5: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_work(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *mut FILE,
+    _arg3: dtrace_consume_probe_f,
+    _arg4: dtrace_consume_rec_f,
+    _arg5: *mut ::core::ffi::c_void,
+) -> dtrace_workstatus_t {
+    dtrace_workstatus_t::DTRACE_WORKSTATUS_ERROR
+}
+
+pub unsafe extern "C" fn dtrace_handle_err(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: dtrace_handle_err_f,
+    _arg3: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_handle_drop(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: dtrace_handle_drop_f,
+    _arg3: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_proc_create(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *const ::core::ffi::c_char,
+    _arg3: *const *mut ::core::ffi::c_char,
+    _arg4: proc_child_func,
+    _arg5: *mut ::core::ffi::c_void,
+) -> *mut ps_prochandle {
+    ::core::ptr::null_mut()
+}
+
+pub unsafe extern "C" fn dtrace_proc_grab(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: pid_t,
+    _arg3: ::core::ffi::c_int,
+) -> *mut ps_prochandle {
+    ::core::ptr::null_mut()
+}
+
+pub unsafe extern "C" fn dtrace_proc_release(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *mut ps_prochandle,
+) {}
+
+pub unsafe extern "C" fn dtrace_proc_continue(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *mut ps_prochandle,
+) {}
+
+pub unsafe extern "C" fn dtrace_probe_iter(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *const dtrace_probedesc_t,
+    _arg3: dtrace_probe_f,
+    _arg4: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_probe_info(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *const dtrace_probedesc_t,
+    _arg3: *mut dtrace_probeinfo_t,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_stability_name(
+    _arg1: dtrace_stability_t,
+) -> *const ::core::ffi::c_char {
+    STUB_MESSAGE.as_ptr()
+}
+
+pub unsafe extern "C" fn dtrace_class_name(
+    _arg1: dtrace_class_t,
+) -> *const ::core::ffi::c_char {
+    STUB_MESSAGE.as_ptr()
+}
+
+pub unsafe extern "C" fn dtrace_handle_proc(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: dtrace_handle_proc_f,
+    _arg3: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_handle_buffered(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: dtrace_handle_buffered_f,
+    _arg3: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_handle_setopt(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: dtrace_handle_setopt_f,
+    _arg3: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_aggregate_snap(
+    _arg1: *mut dtrace_hdl_t,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_aggregate_clear(
+    _arg1: *mut dtrace_hdl_t,
+) {}
+
+pub unsafe extern "C" fn dtrace_aggregate_print(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: *mut FILE,
+    _arg3: dtrace_aggregate_walk_f,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_aggregate_walk(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: dtrace_aggregate_f,
+    _arg3: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_aggregate_walk_sorted(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: dtrace_aggregate_f,
+    _arg3: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_aggregate_walk_keysorted(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: dtrace_aggregate_f,
+    _arg3: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_aggregate_walk_valsorted(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: dtrace_aggregate_f,
+    _arg3: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_aggregate_walk_keyvarsorted(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: dtrace_aggregate_f,
+    _arg3: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_aggregate_walk_valvarsorted(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: dtrace_aggregate_f,
+    _arg3: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_aggregate_walk_keyrevsorted(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: dtrace_aggregate_f,
+    _arg3: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_aggregate_walk_valrevsorted(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: dtrace_aggregate_f,
+    _arg3: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_aggregate_walk_keyvarrevsorted(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: dtrace_aggregate_f,
+    _arg3: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_aggregate_walk_valvarrevsorted(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: dtrace_aggregate_f,
+    _arg3: *mut ::core::ffi::c_void,
+) -> ::core::ffi::c_int {
+    -1
+}
+
+pub unsafe extern "C" fn dtrace_epid_info(
+    _arg1: *mut dtrace_hdl_t,
+    _arg2: dtrace_epid_t,
+    _arg3: *mut *mut dtrace_eprobedesc_t,
+    _arg4: *mut *mut dtrace_probedesc_t,
+) -> ::core::ffi::c_int {
+    -1
+}
@@ -0,0 +1,245 @@
+/// Runtime options controlling how stacks are recorded and printed, see `dtrace_hdl::set_stack_options`.
+///
+/// Options left to `None` keep their current value. How the frames are resolved to symbols is not a runtime option of
+/// libdtrace, see [`StackFormat`] to print raw addresses instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StackOptions {
+    /// Number of frames recorded by `stack()`, the `stackframes` option
+    pub stackframes: Option<u32>,
+    /// Number of frames recorded by `ustack()`, the `ustackframes` option
+    pub ustackframes: Option<u32>,
+    /// Number of frames recorded by `jstack()`, the `jstackframes` option
+    pub jstackframes: Option<u32>,
+    /// Maximum length of the frames recorded by `jstack()`, in bytes, the `jstackstrsize` option
+    pub jstackstrsize: Option<u32>,
+    /// Number of spaces frames are indented by when printed, the `stackindent` option
+    pub stackindent: Option<u32>,
+}
+
+impl StackOptions {
+    /// Returns the options to set, as `(option, value)` pairs.
+    pub(crate) fn options(&self) -> Vec<(&'static str, String)> {
+        [
+            ("stackframes", self.stackframes),
+            ("ustackframes", self.ustackframes),
+            ("jstackframes", self.jstackframes),
+            ("jstackstrsize", self.jstackstrsize),
+            ("stackindent", self.stackindent),
+        ]
+        .into_iter()
+        .filter_map(|(option, value)| Some((option, value?.to_string())))
+        .collect()
+    }
+}
+
+/// How stack frames are formatted by `dtrace_hdl::symbolize_stack`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum StackFormat {
+    /// `module`function+0xoffset`, as printed by dtrace(1)
+    #[default]
+    Symbols,
+    /// The raw address, e.g. to symbolize offline
+    Raw,
+}
+
+/// An address resolved to the module and symbol containing it, see `dtrace_hdl::lookup_symbol`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Symbol {
+    /// The address looked up
+    pub address: u64,
+    /// Name of the module containing the address, if any
+    pub module: Option<String>,
+    /// Name of the symbol containing the address, if any
+    pub name: Option<String>,
+    /// Offset of the address from the start of the symbol
+    pub offset: u64,
+}
+
+impl std::fmt::Display for Symbol {
+    /// Formats the symbol like dtrace(1) prints stack frames.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (&self.module, &self.name) {
+            (Some(module), Some(name)) if self.offset > 0 => {
+                write!(f, "{}`{}+0x{:x}", module, name, self.offset)
+            }
+            (Some(module), Some(name)) => write!(f, "{}`{}", module, name),
+            (Some(module), None) => write!(f, "{}`0x{:x}", module, self.address),
+            (None, _) => write!(f, "0x{:x}", self.address),
+        }
+    }
+}
+
+/// Decodes the frames of a kernel `stack()` record, the unused trailing frames being zero.
+///
+/// # Arguments
+///
+/// * `data` - The bytes of the record.
+///
+/// # Returns
+///
+/// Returns the addresses of the frames, innermost first.
+pub fn stack_frames(data: &[u8]) -> Vec<u64> {
+    data.chunks_exact(std::mem::size_of::<u64>())
+        .map(|frame| u64::from_ne_bytes(frame.try_into().unwrap()))
+        .take_while(|&pc| pc != 0)
+        .collect()
+}
+
+/// A stack record, with the depth it was recorded with, see `RecordVisitor::visit_stack_frames`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Stack {
+    /// Addresses of the frames, innermost first
+    pub frames: Vec<u64>,
+    /// The process of a user stack, `None` for a kernel stack
+    pub pid: Option<crate::pid_t>,
+    /// Number of frames the action records, from the `stackframes`, `ustackframes` or `jstackframes` option unless
+    /// the action gives it
+    pub depth: usize,
+    /// `true` if all the frames of the record are used, so the stack was likely cut off at `depth`
+    pub truncated: bool,
+}
+
+impl Stack {
+    /// Decodes the first `depth` frames of a stack record, see [`stack_frames`].
+    pub fn decode(data: &[u8], depth: usize, pid: Option<crate::pid_t>) -> Self {
+        let data = &data[..data.len().min(depth * std::mem::size_of::<u64>())];
+        let frames = stack_frames(data);
+        Self {
+            truncated: depth > 0 && frames.len() == depth,
+            frames,
+            pid,
+            depth,
+        }
+    }
+}
+
+/// The symbol-printing action a record was produced by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolAction {
+    /// `sym()` or `func()`, a kernel address printed as its symbol
+    Sym,
+    /// `mod()`, a kernel address printed as its module
+    Mod,
+    /// `usym()` or `ufunc()`, a user address printed as its symbol
+    USym,
+    /// `umod()`, a user address printed as its module
+    UMod,
+    /// `uaddr()`, a user address printed as its symbol and offset
+    UAddr,
+}
+
+impl SymbolAction {
+    /// Returns the symbol-printing action of the record action `action`, if any.
+    pub fn from_action(action: crate::dtrace_actkind_t) -> Option<Self> {
+        match action as u32 {
+            crate::DTRACEACT_SYM => Some(SymbolAction::Sym),
+            crate::DTRACEACT_MOD => Some(SymbolAction::Mod),
+            crate::DTRACEACT_USYM => Some(SymbolAction::USym),
+            crate::DTRACEACT_UMOD => Some(SymbolAction::UMod),
+            crate::DTRACEACT_UADDR => Some(SymbolAction::UAddr),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` for the actions recording a user address.
+    pub fn is_user(&self) -> bool {
+        matches!(self, SymbolAction::USym | SymbolAction::UMod | SymbolAction::UAddr)
+    }
+}
+
+/// A record of a symbol-printing action, see `dtrace_hdl::decode_symbol`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SymbolRef {
+    /// The action that produced the record
+    pub action: SymbolAction,
+    /// The process the address belongs to, for user addresses
+    pub pid: Option<crate::pid_t>,
+    /// The address recorded
+    pub address: u64,
+    /// The address resolved as dtrace(1) prints it, if it could be resolved
+    pub name: Option<String>,
+}
+
+impl std::fmt::Display for SymbolRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "0x{:x}", self.address),
+        }
+    }
+}
+
+/// A symbol of a [`SymbolMap`], covering the addresses from `start` to `start + size`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MappedSymbol {
+    /// Address of the start of the symbol
+    pub start: u64,
+    /// Size of the symbol in bytes. For user symbols, the smallest size covering the addresses the map was built from
+    pub size: u64,
+    /// Name of the module defining the symbol
+    pub module: String,
+    /// Name of the symbol
+    pub name: String,
+}
+
+impl std::fmt::Display for MappedSymbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}`{}", self.module, self.name)
+    }
+}
+
+/// The symbols containing a set of addresses of a process or of the kernel, see `dtrace_hdl::symbol_map`, to
+/// symbolize raw addresses after the process exited or on another machine.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct SymbolMap {
+    /// The process the symbols belong to, or `None` for kernel symbols
+    pub pid: Option<crate::pid_t>,
+    /// The symbols, ordered by address
+    pub symbols: Vec<MappedSymbol>,
+}
+
+impl SymbolMap {
+    /// Returns the symbol containing `addr`, if any.
+    pub fn lookup(&self, addr: u64) -> Option<&MappedSymbol> {
+        let index = self.symbols.partition_point(|symbol| symbol.start <= addr).checked_sub(1)?;
+        let symbol = &self.symbols[index];
+        (addr - symbol.start < symbol.size).then_some(symbol)
+    }
+
+    /// Writes the map in the perf map format, one `start size module`symbol` line per symbol with hexadecimal
+    /// addresses, as read by perf and the profilers supporting JIT symbol maps.
+    pub fn write_perf_map(&self, mut writer: impl std::io::Write) -> std::io::Result<()> {
+        for symbol in &self.symbols {
+            writeln!(writer, "{:x} {:x} {}", symbol.start, symbol.size, symbol)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the map to the perf map file of its process, see [`perf_map_path`].
+    ///
+    /// # Returns
+    ///
+    /// Returns the path of the file written.
+    pub fn save_perf_map(&self) -> std::io::Result<std::path::PathBuf> {
+        let path = perf_map_path(self.pid.unwrap_or(0));
+        self.write_perf_map(std::io::BufWriter::new(std::fs::File::create(&path)?))?;
+        Ok(path)
+    }
+}
+
+/// Returns the path profilers look up the perf map of the process `pid` at, `perf-<pid>.map` in the temporary
+/// directory (`/tmp` on Unix).
+pub fn perf_map_path(pid: crate::pid_t) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("perf-{}.map", pid))
+}
+
+/// Parses a user address formatted by `dtrace_hdl::dtrace_uaddr2str` as `module`symbol+0xoffset`, returning the
+/// module, the symbol and the offset, or `None` if the address was not resolved to a symbol.
+pub(crate) fn parse_uaddr(text: &str) -> Option<(&str, &str, u64)> {
+    let (module, symbol) = text.split_once('`')?;
+    let (name, offset) = match symbol.split_once("+0x") {
+        Some((name, offset)) => (name, u64::from_str_radix(offset, 16).ok()?),
+        None => (symbol, 0),
+    };
+    (!name.is_empty() && !name.starts_with("0x")).then_some((module, name, offset))
+}
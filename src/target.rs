@@ -0,0 +1,225 @@
+use ::core::ffi::{c_char, c_void};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// A command to run and trace as `$target`, like `dtrace -c`.
+///
+/// ```no_run
+/// use libdtrace_rs::session::TraceSession;
+/// use libdtrace_rs::target::TargetCommand;
+///
+/// let mut session = TraceSession::new("syscall:::entry /pid == $target/ { @[probefunc] = count(); }")
+///     .with_target(TargetCommand::new("notepad.exe").arg("file.txt"));
+/// session.start().unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetCommand {
+    /// The executable to run
+    pub program: String,
+    /// Arguments passed to the executable
+    pub args: Vec<String>,
+    /// Whether the process is placed in a Job Object killing it and its children when the session ends, see
+    /// [`kill_on_close`](Self::kill_on_close)
+    pub kill_on_close: bool,
+}
+
+impl TargetCommand {
+    /// Creates a command running `program` without arguments.
+    pub fn new(program: &str) -> Self {
+        Self {
+            program: program.to_string(),
+            args: Vec::new(),
+            kill_on_close: false,
+        }
+    }
+
+    /// Adds an argument.
+    pub fn arg(mut self, arg: &str) -> Self {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    /// Adds several arguments.
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_string()));
+        self
+    }
+
+    /// Places the process in a Job Object, so the process and every process it creates are killed when the session
+    /// ends, including when the consumer crashes. Without it, processes started by the target can outlive the
+    /// session.
+    pub fn kill_on_close(mut self) -> Self {
+        self.kill_on_close = true;
+        self
+    }
+}
+
+/// A running process to attach to and trace as `$target`, like `dtrace -p`.
+///
+/// The process is stopped while the program is enabled and resumes running untraced when the session ends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttachTarget {
+    /// ID of the process
+    pub pid: crate::pid_t,
+    /// When `$target` is evaluated, see `evaltime`
+    pub evaltime: Option<String>,
+}
+
+impl AttachTarget {
+    /// Creates a target attaching to the process `pid`.
+    pub fn pid(pid: crate::pid_t) -> Self {
+        Self {
+            pid,
+            evaltime: None,
+        }
+    }
+
+    /// Sets the `evaltime` option, controlling at which point of the process start-up (`exec`, `preinit`,
+    /// `postinit` or `main`) the program is evaluated against it.
+    pub fn evaltime(mut self, evaltime: &str) -> Self {
+        self.evaltime = Some(evaltime.to_string());
+        self
+    }
+}
+
+/// The process a session traces as `$target`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// A command run by the session
+    Command(TargetCommand),
+    /// A running process the session attaches to
+    Attach(AttachTarget),
+}
+
+impl From<TargetCommand> for Target {
+    fn from(command: TargetCommand) -> Self {
+        Target::Command(command)
+    }
+}
+
+impl From<AttachTarget> for Target {
+    fn from(target: AttachTarget) -> Self {
+        Target::Attach(target)
+    }
+}
+
+/// A change of the state of the target process of a session, see `Event::Target`.
+///
+/// `pid` is the ID of the target, `None` if the process created for a command could not be found.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TargetEvent {
+    /// The target was created or grabbed, and resumed once tracing started
+    Started { pid: Option<u32> },
+    /// A process was started by the target or one of its descendants. Only reported for commands placed in a Job
+    /// Object, see [`TargetCommand::kill_on_close`]
+    Forked { pid: Option<u32>, child: u32 },
+    /// libdtrace reported a notice about the target, e.g. that it could not be instrumented
+    Notice { pid: Option<u32>, message: String },
+    /// The session stopped tracing the target before it exited: a command is killed, an attached process resumes
+    /// running untraced
+    Stopped { pid: Option<u32> },
+    /// The target exited, with its exit code if it could be retrieved
+    Exited { pid: Option<u32>, code: Option<u32> },
+}
+
+impl std::fmt::Display for TargetEvent {
+    /// Formats the event like the notices of dtrace(1), e.g. `pid 1234 has exited with code 0`.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let pid = match self {
+            TargetEvent::Started { pid }
+            | TargetEvent::Forked { pid, .. }
+            | TargetEvent::Notice { pid, .. }
+            | TargetEvent::Stopped { pid }
+            | TargetEvent::Exited { pid, .. } => pid,
+        };
+        match pid {
+            Some(pid) => write!(f, "pid {}", pid)?,
+            None => write!(f, "target")?,
+        }
+        match self {
+            TargetEvent::Started { .. } => write!(f, " started"),
+            TargetEvent::Forked { child, .. } => write!(f, " started pid {}", child),
+            TargetEvent::Notice { message, .. } => write!(f, ": {}", message),
+            TargetEvent::Stopped { .. } => write!(f, " is no longer traced"),
+            TargetEvent::Exited { code: Some(code), .. } => write!(f, " has exited with code {}", code),
+            TargetEvent::Exited { code: None, .. } => write!(f, " has exited"),
+        }
+    }
+}
+
+/// State of the target of a session, updated by [`notify`].
+#[derive(Debug, Default)]
+pub(crate) struct TargetState {
+    /// Set once the target exited or was lost
+    pub(crate) exited: AtomicBool,
+    /// Notices about the target not delivered yet
+    pub(crate) notices: Mutex<Vec<String>>,
+}
+
+/// Process handler updating the [`TargetState`] passed as `arg`.
+///
+/// libdtrace passes a message for notices about the process and no message when the process exited or was lost.
+pub(crate) unsafe extern "C" fn notify(
+    _proc: *mut crate::ps_prochandle,
+    msg: *const c_char,
+    arg: *mut c_void,
+) {
+    let state = &*(arg as *const TargetState);
+    if msg.is_null() {
+        state.exited.store(true, Ordering::Relaxed);
+    } else {
+        state.notices.lock().unwrap().push(crate::diagnostics::message(msg));
+    }
+}
+
+/// A handle of the target process, kept open so its exit code can be retrieved after it exited.
+pub(crate) struct TargetProcess {
+    #[cfg_attr(not(windows), allow(dead_code))]
+    handle: *mut c_void,
+}
+
+// Process handles may be used and closed from any thread.
+unsafe impl Send for TargetProcess {}
+
+impl TargetProcess {
+    /// Opens the process `pid`, or returns `None` if it cannot be opened.
+    pub(crate) fn open(pid: u32) -> Option<Self> {
+        #[cfg(windows)]
+        unsafe {
+            let access = crate::win32::PROCESS_QUERY_LIMITED_INFORMATION | crate::win32::SYNCHRONIZE;
+            let handle = crate::win32::OpenProcess(access, 0, pid);
+            (!handle.is_null()).then_some(Self { handle })
+        }
+        #[cfg(not(windows))]
+        {
+            let _ = pid;
+            None
+        }
+    }
+
+    /// Returns the exit code of the process, or `None` if it is still running or the code cannot be retrieved.
+    pub(crate) fn exit_code(&self) -> Option<u32> {
+        #[cfg(windows)]
+        unsafe {
+            let mut code = 0u32;
+            (crate::win32::GetExitCodeProcess(self.handle, &mut code) != 0 && code != crate::win32::STILL_ACTIVE)
+                .then_some(code)
+        }
+        #[cfg(not(windows))]
+        None
+    }
+}
+
+impl Drop for TargetProcess {
+    fn drop(&mut self) {
+        #[cfg(windows)]
+        unsafe {
+            crate::win32::CloseHandle(self.handle);
+        }
+    }
+}
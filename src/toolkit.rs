@@ -0,0 +1,385 @@
+//! Toolkits: collections of D scripts distributed as a directory or a tar archive, compiled as a unit, see
+//! [`Toolkit`].
+//!
+//! A toolkit holds a manifest, [`MANIFEST`], next to its main script and the D libraries and headers it needs:
+//!
+//! ```text
+//! toolkit.conf
+//! main.d
+//! lib/io.d
+//! include/common.h
+//! ```
+//!
+//! The manifest lists the metadata of the toolkit as `key = value` lines, followed by the DTrace options it runs
+//! with in an `[options]` section. Lines starting with `#` are comments.
+//!
+//! ```text
+//! name = iosnoop
+//! version = 1.2
+//! description = Traces file I/O by process
+//! main = main.d
+//! libdir = lib
+//! include = include
+//! cpp = true
+//!
+//! [options]
+//! bufsize = 4m
+//! quiet =
+//! ```
+//!
+//! `main` defaults to `main.d`, `libdir` and `include` to the `lib` and `include` directories if they exist. Other
+//! keys are kept as [`ToolkitMetadata::extra`].
+//!
+//! ```no_run
+//! use libdtrace_rs::toolkit::Toolkit;
+//! use libdtrace_rs::wrapper::dtrace_hdl;
+//!
+//! let toolkit = Toolkit::load("iosnoop.tar").unwrap();
+//! println!("{} {}", toolkit.metadata().name, toolkit.metadata().version);
+//! let mut handle = dtrace_hdl::dtrace_open(libdtrace_rs::DTRACE_VERSION as i32, 0).unwrap();
+//! let options = toolkit.compile_options().arg("notepad.exe");
+//! toolkit.configure(&mut handle).unwrap();
+//! let prog = toolkit.compile(&handle, &options).unwrap();
+//! handle.dtrace_program_exec(prog, None).unwrap();
+//! ```
+
+use crate::compile::CompileOptions;
+use crate::utils::Error;
+use crate::wrapper::dtrace_hdl;
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Name of the manifest of a toolkit, at its root.
+pub const MANIFEST: &str = "toolkit.conf";
+
+/// Size of the blocks of a tar archive.
+const BLOCK: usize = 512;
+
+/// Number of archives extracted by the process, to name their directories.
+static EXTRACTED: AtomicUsize = AtomicUsize::new(0);
+
+/// What the manifest of a toolkit says about it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ToolkitMetadata {
+    /// Name of the toolkit, the name of its directory if the manifest does not have one
+    pub name: String,
+    /// Version of the toolkit, empty if not given
+    pub version: String,
+    /// What the toolkit does, empty if not given
+    pub description: String,
+    /// Other keys of the manifest
+    pub extra: BTreeMap<String, String>,
+}
+
+/// A toolkit loaded from a directory or a tar archive, see the [module](self) docs.
+#[derive(Debug)]
+pub struct Toolkit {
+    metadata: ToolkitMetadata,
+    root: PathBuf,
+    main: PathBuf,
+    libdir: Option<PathBuf>,
+    include: Vec<PathBuf>,
+    cpp: bool,
+    options: Vec<(String, String)>,
+    /// The directory the toolkit was extracted to from an archive, removed with the toolkit
+    extracted: Option<PathBuf>,
+}
+
+impl Toolkit {
+    /// Loads the toolkit at `path`, a directory or a tar archive, see `from_dir` and `from_archive`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        match path.is_dir() {
+            true => Self::from_dir(path),
+            false => Self::from_archive(path),
+        }
+    }
+
+    /// Loads the toolkit in the directory `root`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the toolkit, or an error if its manifest can not be read or parsed, or names files that do not exist.
+    pub fn from_dir(root: impl AsRef<Path>) -> Result<Self, Error> {
+        let root = root.as_ref().to_path_buf();
+        let manifest = root.join(MANIFEST);
+        let text = std::fs::read_to_string(&manifest)
+            .map_err(|err| Error::from(err).with_context(format!("reading {}", manifest.display())))?;
+        let mut toolkit = Self {
+            metadata: ToolkitMetadata {
+                name: root.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+                ..Default::default()
+            },
+            main: root.join("main.d"),
+            libdir: Some(root.join("lib")).filter(|dir| dir.is_dir()),
+            include: Some(root.join("include")).filter(|dir| dir.is_dir()).into_iter().collect(),
+            cpp: false,
+            options: Vec::new(),
+            extracted: None,
+            root,
+        };
+        toolkit
+            .parse_manifest(&text)
+            .map_err(|err| err.with_context(format!("parsing {}", manifest.display())))?;
+
+        if !toolkit.main.is_file() {
+            return Err(Error::new(
+                -1,
+                format!("The main script {} of the toolkit does not exist", toolkit.main.display()),
+            ));
+        }
+        Ok(toolkit)
+    }
+
+    /// Loads the toolkit in the tar archive at `path`, extracting it to a temporary directory that is removed with the
+    /// toolkit. The manifest may be at the root of the archive or in one of its top-level directories.
+    ///
+    /// # Returns
+    ///
+    /// Returns the toolkit, or an error if the archive can not be read or extracted, or holds no valid toolkit.
+    pub fn from_archive(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let context = || format!("extracting toolkit {}", path.display());
+        let archive = std::fs::read(path).map_err(|err| Error::from(err).with_context(context()))?;
+        let dir = std::env::temp_dir().join(format!(
+            "dtrace-toolkit-{}-{}",
+            std::process::id(),
+            EXTRACTED.fetch_add(1, Ordering::Relaxed)
+        ));
+        let root = extract(&archive, &dir).map_err(|err| {
+            let _ = std::fs::remove_dir_all(&dir);
+            err.with_context(context())
+        });
+        match root.and_then(Self::from_dir) {
+            Ok(mut toolkit) => {
+                // Without a name in the manifest, the toolkit is named after the archive rather than the directory.
+                if toolkit.metadata.name == dir.file_name().unwrap_or_default().to_string_lossy() {
+                    toolkit.metadata.name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+                }
+                toolkit.extracted = Some(dir);
+                Ok(toolkit)
+            }
+            Err(err) => {
+                let _ = std::fs::remove_dir_all(&dir);
+                Err(err)
+            }
+        }
+    }
+
+    fn parse_manifest(&mut self, text: &str) -> Result<(), Error> {
+        let mut in_options = false;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                in_options = match line {
+                    "[options]" => true,
+                    _ => return Err(Error::new(-1, format!("line {}: unknown section {}", number + 1, line))),
+                };
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(Error::new(-1, format!("line {}: expected `key = value`", number + 1))),
+            };
+            if in_options {
+                self.options.push((key.to_string(), value.to_string()));
+                continue;
+            }
+            match key {
+                "name" => self.metadata.name = value.to_string(),
+                "version" => self.metadata.version = value.to_string(),
+                "description" => self.metadata.description = value.to_string(),
+                "main" => self.main = self.file(value)?,
+                "libdir" => self.libdir = Some(self.file(value)?),
+                "include" => {
+                    self.include = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|dir| !dir.is_empty())
+                        .map(|dir| self.file(dir))
+                        .collect::<Result<_, _>>()?
+                }
+                "cpp" => {
+                    self.cpp = value.parse().map_err(|_| {
+                        Error::new(-1, format!("line {}: expected `true` or `false`, not `{}`", number + 1, value))
+                    })?
+                }
+                _ => {
+                    self.metadata.extra.insert(key.to_string(), value.to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the path of `name`, relative to the root, refusing paths leading out of the toolkit.
+    fn file(&self, name: &str) -> Result<PathBuf, Error> {
+        match relative(name) {
+            Some(path) => Ok(self.root.join(path)),
+            None => Err(Error::new(-1, format!("`{}` is not a path within the toolkit", name))),
+        }
+    }
+
+    /// Returns the metadata of the toolkit.
+    pub fn metadata(&self) -> &ToolkitMetadata {
+        &self.metadata
+    }
+
+    /// Returns the directory of the toolkit.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the path of the main script.
+    pub fn main(&self) -> &Path {
+        &self.main
+    }
+
+    /// Returns the directory of the D libraries of the toolkit, if it has one.
+    pub fn libdir(&self) -> Option<&Path> {
+        self.libdir.as_deref()
+    }
+
+    /// Returns the directories searched for the headers included by the toolkit, after its root.
+    pub fn include_dirs(&self) -> &[PathBuf] {
+        &self.include
+    }
+
+    /// Returns the DTrace options of the toolkit, in the order of the manifest.
+    pub fn options(&self) -> &[(String, String)] {
+        &self.options
+    }
+
+    /// Returns the source of the main script.
+    pub fn source(&self) -> Result<String, Error> {
+        std::fs::read_to_string(&self.main)
+            .map_err(|err| Error::from(err).with_context(format!("reading {}", self.main.display())))
+    }
+
+    /// Returns the options the main script is compiled with, running the preprocessor if the manifest asks for it.
+    pub fn compile_options(&self) -> CompileOptions {
+        match self.cpp {
+            true => CompileOptions::new().use_cpp(),
+            false => CompileOptions::new(),
+        }
+    }
+
+    /// Sets the options of the toolkit on `handle`, with the library and include directories.
+    ///
+    /// libdtrace loads the D libraries when the handle compiles its first program, so the handle must not have
+    /// compiled a program before.
+    pub fn configure(&self, handle: &mut dtrace_hdl) -> Result<(), Error> {
+        let context = || format!("configuring toolkit {}", self.metadata.name);
+        if let Some(libdir) = &self.libdir {
+            handle
+                .dtrace_setopt("libdir", &libdir.to_string_lossy())
+                .map_err(|err| err.with_context(context()))?;
+        }
+        for dir in std::iter::once(&self.root).chain(&self.include) {
+            handle
+                .dtrace_setopt("incdir", &dir.to_string_lossy())
+                .map_err(|err| err.with_context(context()))?;
+        }
+        for (option, value) in &self.options {
+            handle.dtrace_setopt(option, value).map_err(|err| err.with_context(context()))?;
+        }
+        Ok(())
+    }
+
+    /// Compiles the main script of the toolkit with `options`, usually `compile_options` with the arguments of the
+    /// script. `handle` must be configured for the toolkit first, see `configure`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the compiled program, or the first error reading or compiling the script.
+    pub fn compile<'a>(
+        &self,
+        handle: &'a dtrace_hdl,
+        options: &CompileOptions,
+    ) -> Result<&'a mut crate::dtrace_prog, Error> {
+        let source = self.source()?;
+        handle
+            .compile(&source, options)
+            .map_err(|err| err.with_context(format!("compiling toolkit {}", self.metadata.name)))
+    }
+}
+
+impl Drop for Toolkit {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.extracted {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
+
+/// Returns `name` as a relative path without `..`, or `None` if it would lead elsewhere.
+fn relative(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+        .then(|| path.to_path_buf())
+}
+
+/// Extracts the regular files and directories of the tar archive `archive` to `dir`.
+///
+/// # Returns
+///
+/// Returns the root of the toolkit: `dir`, or the top-level directory of the archive holding the manifest.
+fn extract(archive: &[u8], dir: &Path) -> Result<PathBuf, Error> {
+    let mut offset = 0;
+    while offset + BLOCK <= archive.len() {
+        let header = &archive[offset..offset + BLOCK];
+        if header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+        let name = match &header[257..262] == b"ustar" && header[345] != 0 {
+            true => format!("{}/{}", field(&header[345..500]), field(&header[..100])),
+            false => field(&header[..100]),
+        };
+        let size = usize::from_str_radix(field(&header[124..136]).trim(), 8)
+            .map_err(|_| Error::new(-1, format!("Invalid size of `{}` in the archive", name)))?;
+        let data = archive
+            .get(offset + BLOCK..offset + BLOCK + size)
+            .ok_or_else(|| Error::new(-1, format!("The archive ends within `{}`", name)))?;
+        offset += BLOCK + size.div_ceil(BLOCK) * BLOCK;
+
+        let path = match relative(name.trim_end_matches('/')) {
+            Some(path) => dir.join(path),
+            None => return Err(Error::new(-1, format!("`{}` of the archive is outside the toolkit", name))),
+        };
+        let written = match header[156] {
+            b'0' | 0 => path
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|()| std::fs::write(&path, data)),
+            b'5' => std::fs::create_dir_all(&path),
+            // Links and special files have no place in a toolkit.
+            _ => Ok(()),
+        };
+        written.map_err(|err| Error::from(err).with_context(format!("writing {}", path.display())))?;
+    }
+
+    if dir.join(MANIFEST).is_file() {
+        return Ok(dir.to_path_buf());
+    }
+    let roots: Vec<_> = std::fs::read_dir(dir)
+        .map_err(|err| Error::from(err).with_context(format!("reading {}", dir.display())))?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.join(MANIFEST).is_file())
+        .collect();
+    match <[PathBuf; 1]>::try_from(roots) {
+        Ok([root]) => Ok(root),
+        Err(roots) if roots.is_empty() => Err(Error::new(-1, format!("The archive has no {}", MANIFEST))),
+        Err(_) => Err(Error::new(-1, "The archive holds several toolkits")),
+    }
+}
+
+/// Returns the text of a NUL-terminated header field.
+fn field(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
@@ -0,0 +1,209 @@
+use crate::aggregate::{AggregateEntry, Histogram};
+use crate::types::{dtrace_aggwalk_order, dtrace_handler, dtrace_handler_kind};
+use crate::wrapper::dtrace_hdl;
+use ::core::ffi::{c_int, c_void, CStr};
+use crossterm::event::{self, Event, KeyCode};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table};
+use ratatui::{Frame, Terminal};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Number of output lines kept for the output panel
+const OUTPUT_LINES: usize = 100;
+/// How long to wait for key presses between two work passes
+const TICK: Duration = Duration::from_millis(250);
+
+/// State shared with the callbacks registered by [`run`].
+#[derive(Default)]
+struct State {
+    firings: u64,
+    drops: u64,
+    output: VecDeque<String>,
+}
+
+unsafe extern "C" fn count_firing(
+    _data: *const crate::dtrace_probedata_t,
+    arg: *mut c_void,
+) -> c_int {
+    let state = &mut *(arg as *mut State);
+    state.firings += 1;
+
+    crate::DTRACE_CONSUME_THIS as c_int
+}
+
+unsafe extern "C" fn count_drops(drop: *const crate::dtrace_dropdata_t, arg: *mut c_void) -> c_int {
+    let state = &mut *(arg as *mut State);
+    state.drops += (*drop).dtdda_drops;
+
+    crate::DTRACE_HANDLE_OK as c_int
+}
+
+unsafe extern "C" fn collect_output(
+    bufdata: *const crate::dtrace_bufdata_t,
+    arg: *mut c_void,
+) -> c_int {
+    let state = &mut *(arg as *mut State);
+    let msg = CStr::from_ptr((*bufdata).dtbda_buffered).to_string_lossy();
+    for line in msg.lines().filter(|line| !line.trim().is_empty()) {
+        if state.output.len() == OUTPUT_LINES {
+            state.output.pop_front();
+        }
+        state.output.push_back(line.to_string());
+    }
+
+    crate::DTRACE_HANDLE_OK as c_int
+}
+
+/// Restores the terminal when the live view exits, even on error.
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = execute!(std::io::stdout(), LeaveAlternateScreen);
+    }
+}
+
+/// Shows a live view ("dtrace top") of a running DTrace instance in the terminal.
+///
+/// The view displays the aggregation entries with the highest values, the number of dropped
+/// records, the rate of probe firings and the most recent buffered output. It keeps consuming
+/// data with `dtrace_work` until `q` or `Esc` is pressed.
+///
+/// The function registers its own buffered and drop handlers, so it must be called on a handle
+/// that has neither registered, after `dtrace_go` has been called. The handlers are removed
+/// before it returns.
+///
+/// # Arguments
+///
+/// * `handle` - The DTrace instance to display.
+///
+/// # Returns
+///
+/// Returns `Ok(())` once the user quits, or the first DTrace or terminal error encountered.
+pub fn run(handle: &mut dtrace_hdl) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = State::default();
+    let arg = &mut state as *mut State as *mut c_void;
+    handle.dtrace_register_handler(dtrace_handler::Buffered(Some(collect_output)), Some(arg))?;
+    if let Err(err) = handle.dtrace_register_handler(dtrace_handler::Drop(Some(count_drops)), Some(arg)) {
+        handle.remove_handler(dtrace_handler_kind::Buffered);
+        return Err(err.into());
+    }
+    let result = show(handle, arg);
+
+    // The handlers point at `state`, which does not outlive this call.
+    handle.remove_handler(dtrace_handler_kind::Buffered);
+    handle.remove_handler(dtrace_handler_kind::Drop);
+    result
+}
+
+/// Runs the live view of [`run`], with the handlers registered with `arg`, the [`State`] of the view.
+fn show(handle: &mut dtrace_hdl, arg: *mut c_void) -> Result<(), Box<dyn std::error::Error>> {
+    enable_raw_mode()?;
+    let _guard = TerminalGuard;
+    execute!(std::io::stdout(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(std::io::stdout()))?;
+
+    let mut done = false;
+    let mut last = (Instant::now(), 0);
+    let mut rate = 0.0;
+    loop {
+        if event::poll(TICK)? {
+            if let Event::Key(key) = event::read()? {
+                if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                    break;
+                }
+            }
+        }
+
+        if !done {
+            let status = handle.dtrace_work(
+                None,
+                Some(count_firing),
+                Some(crate::callbacks::chew_rec),
+                Some(unsafe { &mut *arg }),
+            )?;
+            done = status == crate::dtrace_workstatus_t::DTRACE_WORKSTATUS_DONE;
+        }
+
+        let state = unsafe { &*(arg as *const State) };
+        let elapsed = last.0.elapsed().as_secs_f64();
+        if elapsed >= 1.0 {
+            rate = (state.firings - last.1) as f64 / elapsed;
+            last = (Instant::now(), state.firings);
+        }
+
+        let mut entries = handle.aggregate_entries(dtrace_aggwalk_order::None)?;
+        entries.sort_by_key(|entry| std::cmp::Reverse(value(entry)));
+        terminal.draw(|frame| draw(frame, state, &entries, rate, done))?;
+    }
+
+    Ok(())
+}
+
+fn value(entry: &AggregateEntry) -> i64 {
+    match Histogram::from_value(&entry.value) {
+        Some(hist) => hist.total(),
+        None => crate::render::scalar(&entry.value),
+    }
+}
+
+fn draw(frame: &mut Frame, state: &State, entries: &[AggregateEntry], rate: f64, done: bool) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(5),
+            Constraint::Length(8),
+        ])
+        .split(frame.size());
+
+    let status = format!(
+        " firings: {}  ({:.1}/s)   drops: {}   {}   [q] quit",
+        state.firings,
+        rate,
+        state.drops,
+        if done { "tracing stopped" } else { "tracing" }
+    );
+    frame.render_widget(
+        Paragraph::new(status).block(Block::default().borders(Borders::ALL).title("dtrace top")),
+        chunks[0],
+    );
+
+    let rows = entries.iter().map(|entry| {
+        Row::new(vec![
+            format!("@{}", entry.name),
+            entry.key.to_string(),
+            value(entry).to_string(),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(20),
+            Constraint::Min(20),
+            Constraint::Length(16),
+        ],
+    )
+    .header(
+        Row::new(vec!["AGGREGATION", "KEY", "VALUE"])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    )
+    .block(Block::default().borders(Borders::ALL).title("aggregations"));
+    frame.render_widget(table, chunks[1]);
+
+    let height = chunks[2].height.saturating_sub(2) as usize;
+    let skip = state.output.len().saturating_sub(height);
+    let output: Vec<&str> = state.output.iter().skip(skip).map(String::as_str).collect();
+    frame.render_widget(
+        Paragraph::new(output.join("\n")).block(Block::default().borders(Borders::ALL).title("output")),
+        chunks[2],
+    );
+}
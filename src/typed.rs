@@ -0,0 +1,235 @@
+use crate::typeinfo::{encoding, kind, members, resolve, type_name, type_size, TypeKind};
+use ::core::ffi::CStr;
+
+/// A value recorded by the `print()` action, decoded with the CTF type of the printed expression, see
+/// `dtrace_hdl::decode_print`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrintedValue {
+    /// Name of the type of the printed expression, e.g. `struct _UNICODE_STRING`
+    pub type_name: String,
+    /// The decoded value
+    pub value: TypedValue,
+}
+
+impl std::fmt::Display for PrintedValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {}", self.type_name, self.value)
+    }
+}
+
+/// A value decoded according to its C type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypedValue {
+    /// A signed integer, including bit-fields
+    Int(i64),
+    /// An unsigned integer, including bit-fields
+    UInt(u64),
+    Bool(bool),
+    Float(f64),
+    /// A pointer, as the address it holds
+    Pointer(u64),
+    /// An enum value, with the name of its enumerator if it has one
+    Enum { value: i64, name: Option<String> },
+    /// An array of characters, up to its first NUL character
+    Str(String),
+    Array(Vec<TypedValue>),
+    Struct(Vec<TypedField>),
+    /// A union, with every member decoded from the same bytes
+    Union(Vec<TypedField>),
+    /// A value of a type that cannot be decoded, e.g. a function, as raw bytes
+    Bytes(Vec<u8>),
+}
+
+/// A member of a struct or union value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypedField {
+    /// Name of the member, empty for anonymous members
+    pub name: String,
+    /// Name of the type of the member
+    pub type_name: String,
+    pub value: TypedValue,
+}
+
+impl TypedValue {
+    /// Returns the member `name` of a struct or union value.
+    pub fn field(&self, name: &str) -> Option<&TypedValue> {
+        match self {
+            TypedValue::Struct(fields) | TypedValue::Union(fields) => {
+                fields.iter().find(|field| field.name == name).map(|field| &field.value)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for TypedValue {
+    /// Formats the value on a single line, e.g. `{ .Length = 0x10, .Buffer = 0xffffe0012345678 }`.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TypedValue::Int(value) => write!(f, "{}", value),
+            TypedValue::UInt(value) => write!(f, "{:#x}", value),
+            TypedValue::Bool(value) => write!(f, "{}", value),
+            TypedValue::Float(value) => write!(f, "{}", value),
+            TypedValue::Pointer(value) => write!(f, "{:#x}", value),
+            TypedValue::Enum { name: Some(name), .. } => write!(f, "{}", name),
+            TypedValue::Enum { value, name: None } => write!(f, "{}", value),
+            TypedValue::Str(value) => write!(f, "{:?}", value),
+            TypedValue::Array(values) => {
+                write!(f, "[ ")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, " ]")
+            }
+            TypedValue::Struct(fields) | TypedValue::Union(fields) => {
+                write!(f, "{{ ")?;
+                for (i, field) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, ".{} = {}", field.name, field.value)?;
+                }
+                write!(f, " }}")
+            }
+            TypedValue::Bytes(bytes) => {
+                for byte in bytes {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Decodes the bytes of a value of the CTF type `id`.
+///
+/// # Safety
+///
+/// `ctfp` must be the CTF data of a module of an open handle.
+pub(crate) unsafe fn decode(ctfp: *mut crate::ctf_file_t, id: crate::ctf_id_t, bytes: &[u8]) -> TypedValue {
+    let id = resolve(ctfp, id);
+    let size = type_size(ctfp, id) as usize;
+    if bytes.len() < size {
+        return TypedValue::Bytes(bytes.to_vec());
+    }
+    let bytes = &bytes[..size];
+
+    match kind(ctfp, id) {
+        TypeKind::Integer => match encoding(ctfp, id) {
+            Some(enc) if enc.cte_format & crate::CTF_INT_BOOL != 0 => TypedValue::Bool(bytes.iter().any(|&b| b != 0)),
+            Some(enc) => integer(bytes, enc.cte_format & crate::CTF_INT_SIGNED != 0),
+            None => TypedValue::Bytes(bytes.to_vec()),
+        },
+        TypeKind::Float => match size {
+            4 => TypedValue::Float(f32::from_ne_bytes(bytes.try_into().unwrap()) as f64),
+            8 => TypedValue::Float(f64::from_ne_bytes(bytes.try_into().unwrap())),
+            _ => TypedValue::Bytes(bytes.to_vec()),
+        },
+        TypeKind::Pointer => match integer(bytes, false) {
+            TypedValue::UInt(address) => TypedValue::Pointer(address),
+            value => value,
+        },
+        TypeKind::Enum => match integer(bytes, true) {
+            TypedValue::Int(value) => {
+                let name = crate::ctf_enum_name(ctfp, id, value as ::core::ffi::c_int);
+                TypedValue::Enum {
+                    value,
+                    name: (!name.is_null()).then(|| CStr::from_ptr(name).to_string_lossy().into_owned()),
+                }
+            }
+            value => value,
+        },
+        TypeKind::Array => {
+            let mut info: crate::ctf_arinfo_t = std::mem::zeroed();
+            if crate::ctf_array_info(ctfp, id, &mut info) != 0 {
+                return TypedValue::Bytes(bytes.to_vec());
+            }
+            let element = resolve(ctfp, info.ctr_contents);
+            let element_size = type_size(ctfp, element) as usize;
+            let is_char = kind(ctfp, element) == TypeKind::Integer
+                && encoding(ctfp, element).is_some_and(|enc| enc.cte_format & crate::CTF_INT_CHAR != 0);
+            if is_char {
+                let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+                return TypedValue::Str(String::from_utf8_lossy(&bytes[..len]).into_owned());
+            }
+            if element_size == 0 {
+                return TypedValue::Bytes(bytes.to_vec());
+            }
+            TypedValue::Array(
+                bytes
+                    .chunks_exact(element_size)
+                    .map(|element_bytes| decode(ctfp, element, element_bytes))
+                    .collect(),
+            )
+        }
+        kind @ (TypeKind::Struct | TypeKind::Union) => {
+            let fields = members(ctfp, id)
+                .into_iter()
+                .map(|member| TypedField {
+                    type_name: type_name(ctfp, member.id),
+                    value: decode_member(ctfp, member.id, member.offset, bytes),
+                    name: member.name,
+                })
+                .collect();
+            if kind == TypeKind::Union {
+                TypedValue::Union(fields)
+            } else {
+                TypedValue::Struct(fields)
+            }
+        }
+        _ => TypedValue::Bytes(bytes.to_vec()),
+    }
+}
+
+/// Decodes the member of type `id` at the bit offset `offset` of a struct, extracting bit-fields.
+unsafe fn decode_member(ctfp: *mut crate::ctf_file_t, id: crate::ctf_id_t, offset: u64, bytes: &[u8]) -> TypedValue {
+    let resolved = resolve(ctfp, id);
+    let size = type_size(ctfp, resolved);
+    let bitfield = match kind(ctfp, resolved) {
+        TypeKind::Integer | TypeKind::Enum => encoding(ctfp, resolved)
+            .filter(|enc| !offset.is_multiple_of(8) || enc.cte_offset != 0 || enc.cte_bits as u64 != size * 8),
+        _ => None,
+    };
+    let Some(enc) = bitfield else {
+        return match bytes.get(offset as usize / 8..) {
+            Some(bytes) => decode(ctfp, id, bytes),
+            None => TypedValue::Bytes(Vec::new()),
+        };
+    };
+
+    // Bit-fields are allocated from the least significant bit on x86 and x64.
+    let offset = offset + enc.cte_offset as u64;
+    let bits = (enc.cte_bits as u64).min(64);
+    let start = offset as usize / 8;
+    let mut raw = [0u8; 16];
+    for (dst, src) in raw.iter_mut().zip(bytes.iter().skip(start)) {
+        *dst = *src;
+    }
+    let value = (u128::from_le_bytes(raw) >> (offset % 8)) as u64;
+    let value = if bits == 64 { value } else { value & ((1u64 << bits) - 1) };
+    if enc.cte_format & crate::CTF_INT_SIGNED != 0 && bits < 64 && bits > 0 {
+        let shift = 64 - bits;
+        TypedValue::Int(((value << shift) as i64) >> shift)
+    } else {
+        TypedValue::UInt(value)
+    }
+}
+
+/// Decodes an integer of 1, 2, 4 or 8 bytes.
+fn integer(bytes: &[u8], signed: bool) -> TypedValue {
+    let value = match bytes.len() {
+        1 => bytes[0] as u64,
+        2 => u16::from_ne_bytes(bytes.try_into().unwrap()) as u64,
+        4 => u32::from_ne_bytes(bytes.try_into().unwrap()) as u64,
+        8 => u64::from_ne_bytes(bytes.try_into().unwrap()),
+        _ => return TypedValue::Bytes(bytes.to_vec()),
+    };
+    if !signed {
+        return TypedValue::UInt(value);
+    }
+    let shift = 64 - bytes.len() as u32 * 8;
+    TypedValue::Int(((value << shift) as i64) >> shift)
+}
@@ -0,0 +1,206 @@
+use ::core::ffi::{c_char, c_int, c_ulong, c_void, CStr};
+
+/// Kind of a C type, as described by the CTF (Compact C Type Format) data of a module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TypeKind {
+    Integer,
+    Float,
+    Pointer,
+    Array,
+    Function,
+    Struct,
+    Union,
+    Enum,
+    /// A struct, union or enum declared without its definition
+    Forward,
+    Typedef,
+    Volatile,
+    Const,
+    Restrict,
+    Unknown,
+}
+
+impl TypeKind {
+    /// Returns the kind of the CTF kind `kind`, one of the `CTF_K_*` constants.
+    pub fn from_ctf(kind: c_int) -> Self {
+        match kind as u32 {
+            crate::CTF_K_INTEGER => TypeKind::Integer,
+            crate::CTF_K_FLOAT => TypeKind::Float,
+            crate::CTF_K_POINTER => TypeKind::Pointer,
+            crate::CTF_K_ARRAY => TypeKind::Array,
+            crate::CTF_K_FUNCTION => TypeKind::Function,
+            crate::CTF_K_STRUCT => TypeKind::Struct,
+            crate::CTF_K_UNION => TypeKind::Union,
+            crate::CTF_K_ENUM => TypeKind::Enum,
+            crate::CTF_K_FORWARD => TypeKind::Forward,
+            crate::CTF_K_TYPEDEF => TypeKind::Typedef,
+            crate::CTF_K_VOLATILE => TypeKind::Volatile,
+            crate::CTF_K_CONST => TypeKind::Const,
+            crate::CTF_K_RESTRICT => TypeKind::Restrict,
+            _ => TypeKind::Unknown,
+        }
+    }
+}
+
+/// A member of a struct or union.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TypeMember {
+    /// Name of the member, empty for anonymous members
+    pub name: String,
+    /// Name of the type of the member
+    pub type_name: String,
+    /// Offset of the member from the start of the struct, in bits
+    pub offset: u64,
+}
+
+/// Description of a C type, see `dtrace_hdl::lookup_type`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TypeInfo {
+    /// Name of the module defining the type
+    pub object: String,
+    /// Name of the type, e.g. `struct _EPROCESS *`
+    pub name: String,
+    /// Kind of the type
+    pub kind: TypeKind,
+    /// Size of the type in bytes, if it has one
+    pub size: Option<u64>,
+    /// Name of the type a typedef or qualifier resolves to, if `name` is one
+    pub resolved: Option<String>,
+    /// Members of the struct or union `name` resolves to, in declaration order
+    pub members: Vec<TypeMember>,
+}
+
+impl TypeInfo {
+    /// Describes a type found by libdtrace.
+    ///
+    /// # Safety
+    ///
+    /// `info` must have been filled by a successful libdtrace type lookup of a handle that is still open.
+    pub unsafe fn from_raw(info: &crate::dtrace_typeinfo_t) -> Self {
+        let ctfp = info.dtt_ctfp;
+        let id = info.dtt_type;
+        let resolved_id = resolve(ctfp, id);
+
+        let size = crate::ctf_type_size(ctfp, id) as i64;
+        let name = type_name(ctfp, id);
+        let resolved = (resolved_id != id)
+            .then(|| type_name(ctfp, resolved_id))
+            .filter(|resolved| *resolved != name);
+
+        let members = match TypeKind::from_ctf(crate::ctf_type_kind(ctfp, resolved_id)) {
+            TypeKind::Struct | TypeKind::Union => members(ctfp, resolved_id)
+                .into_iter()
+                .map(|member| TypeMember {
+                    name: member.name,
+                    type_name: type_name(ctfp, member.id),
+                    offset: member.offset,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Self {
+            object: if info.dtt_object.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr(info.dtt_object).to_string_lossy().into_owned()
+            },
+            name,
+            kind: TypeKind::from_ctf(crate::ctf_type_kind(ctfp, id)),
+            size: (size >= 0).then_some(size as u64),
+            resolved,
+            members,
+        }
+    }
+}
+
+impl std::fmt::Display for TypeInfo {
+    /// Formats the type like a C declaration, with the offset of each member.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}`{}", self.object, self.name)?;
+        if let Some(resolved) = &self.resolved {
+            write!(f, " ({})", resolved)?;
+        }
+        if let Some(size) = self.size {
+            write!(f, ", {} bytes", size)?;
+        }
+        if self.members.is_empty() {
+            return Ok(());
+        }
+        writeln!(f, " {{")?;
+        for member in &self.members {
+            writeln!(f, "    {} {}; /* offset {} */", member.type_name, member.name, member.offset / 8)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Resolves typedefs and qualifiers of the type `id`.
+pub(crate) unsafe fn resolve(ctfp: *mut crate::ctf_file_t, id: crate::ctf_id_t) -> crate::ctf_id_t {
+    match crate::ctf_type_resolve(ctfp, id) {
+        -1 => id,
+        resolved => resolved,
+    }
+}
+
+/// Returns the kind of the type `id`.
+pub(crate) unsafe fn kind(ctfp: *mut crate::ctf_file_t, id: crate::ctf_id_t) -> TypeKind {
+    TypeKind::from_ctf(crate::ctf_type_kind(ctfp, id))
+}
+
+/// Returns the size of the type `id` in bytes, `0` if it has none.
+pub(crate) unsafe fn type_size(ctfp: *mut crate::ctf_file_t, id: crate::ctf_id_t) -> u64 {
+    (crate::ctf_type_size(ctfp, id) as i64).max(0) as u64
+}
+
+/// Returns the encoding of the integer or float type `id`.
+pub(crate) unsafe fn encoding(ctfp: *mut crate::ctf_file_t, id: crate::ctf_id_t) -> Option<crate::ctf_encoding_t> {
+    let mut enc: crate::ctf_encoding_t = std::mem::zeroed();
+    (crate::ctf_type_encoding(ctfp, id, &mut enc) == 0).then_some(enc)
+}
+
+/// Returns the name of the CTF type `id`.
+pub(crate) unsafe fn type_name(ctfp: *mut crate::ctf_file_t, id: crate::ctf_id_t) -> String {
+    let mut buf = [0 as c_char; 512];
+    if crate::ctf_type_name(ctfp, id, buf.as_mut_ptr(), buf.len()).is_null() {
+        return format!("<type {}>", id);
+    }
+    CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned()
+}
+
+/// A member of a struct or union, as listed by `ctf_member_iter`.
+pub(crate) struct RawMember {
+    pub(crate) name: String,
+    pub(crate) id: crate::ctf_id_t,
+    /// Offset in bits
+    pub(crate) offset: u64,
+}
+
+/// Lists the members of the struct or union `id`, in declaration order.
+pub(crate) unsafe fn members(ctfp: *mut crate::ctf_file_t, id: crate::ctf_id_t) -> Vec<RawMember> {
+    let mut members: Vec<RawMember> = Vec::new();
+    crate::ctf_member_iter(ctfp, id, Some(collect_member), &mut members as *mut _ as *mut c_void);
+    members
+}
+
+/// Member iterator appending every member to the `Vec<RawMember>` passed as `arg`.
+// `c_ulong` is 32 bits wide on Windows
+#[allow(clippy::useless_conversion)]
+unsafe extern "C" fn collect_member(
+    name: *const c_char,
+    id: crate::ctf_id_t,
+    offset: c_ulong,
+    arg: *mut c_void,
+) -> c_int {
+    let members = &mut *(arg as *mut Vec<RawMember>);
+    members.push(RawMember {
+        name: if name.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(name).to_string_lossy().into_owned()
+        },
+        id,
+        offset: u64::from(offset),
+    });
+    0
+}
@@ -22,6 +22,7 @@ pub enum dtrace_aggwalk_order {
 }
 
 #[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum dtrace_status {
     /// No Status
     None = crate::DTRACE_STATUS_NONE,
@@ -48,6 +49,7 @@ impl From<u32> for dtrace_status {
     }
 }
 
+#[derive(Clone, Copy)]
 pub enum dtrace_handler {
     Buffered(crate::dtrace_handle_buffered_f),
     Drop(crate::dtrace_handle_drop_f),
@@ -55,3 +57,78 @@ pub enum dtrace_handler {
     Proc(crate::dtrace_handle_proc_f),
     SetOpt(crate::dtrace_handle_setopt_f),
 }
+
+impl dtrace_handler {
+    /// Returns the kind of the handler.
+    pub fn kind(&self) -> dtrace_handler_kind {
+        match self {
+            dtrace_handler::Buffered(_) => dtrace_handler_kind::Buffered,
+            dtrace_handler::Drop(_) => dtrace_handler_kind::Drop,
+            dtrace_handler::Err(_) => dtrace_handler_kind::Err,
+            dtrace_handler::Proc(_) => dtrace_handler_kind::Proc,
+            dtrace_handler::SetOpt(_) => dtrace_handler_kind::SetOpt,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum dtrace_handler_kind {
+    /// Buffered output handler
+    Buffered,
+    /// Drop handler
+    Drop,
+    /// Error handler
+    Err,
+    /// Process handler
+    Proc,
+    /// Option handler
+    SetOpt,
+}
+
+/// What the fields of an incomplete probe description name, e.g. `BEGIN` or `NtClose:entry`, wrapping
+/// `dtrace_probespec`.
+///
+/// A probe description has up to four fields, `provider:module:function:name`. When it has fewer, they are read from
+/// the field given by the spec backwards, the fields before them being empty and matching anything, and the fields
+/// after them must be absent. With the default, [`ProbeSpec::Name`], `BEGIN` is the probe `:::BEGIN` and
+/// `NtClose:entry` the probe `::NtClose:entry`, as in the clauses of a D program. The other specs are those of the
+/// `-P`, `-m` and `-f` options of dtrace(1), e.g. `syscall` compiled with [`ProbeSpec::Provider`] enables every probe
+/// of the `syscall` provider.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ProbeSpec {
+    /// The last field is a provider, e.g. `syscall` for `syscall:::`
+    Provider,
+    /// The last field is a module, e.g. `ntdll.dll` for `:ntdll.dll::`
+    Module,
+    /// The last field is a function, e.g. `syscall::NtClose` for `syscall::NtClose:`
+    Function,
+    /// The last field is a probe name, e.g. `BEGIN` for `:::BEGIN`, as in the clauses of a D program
+    #[default]
+    Name,
+}
+
+impl From<ProbeSpec> for crate::dtrace_probespec {
+    fn from(spec: ProbeSpec) -> Self {
+        match spec {
+            ProbeSpec::Provider => crate::dtrace_probespec::DTRACE_PROBESPEC_PROVIDER,
+            ProbeSpec::Module => crate::dtrace_probespec::DTRACE_PROBESPEC_MOD,
+            ProbeSpec::Function => crate::dtrace_probespec::DTRACE_PROBESPEC_FUNC,
+            ProbeSpec::Name => crate::dtrace_probespec::DTRACE_PROBESPEC_NAME,
+        }
+    }
+}
+
+impl TryFrom<crate::dtrace_probespec> for ProbeSpec {
+    type Error = crate::utils::Error;
+
+    /// Converts a raw spec, failing for `DTRACE_PROBESPEC_NONE`, which libdtrace does not compile with.
+    fn try_from(spec: crate::dtrace_probespec) -> Result<Self, Self::Error> {
+        match spec {
+            crate::dtrace_probespec::DTRACE_PROBESPEC_PROVIDER => Ok(ProbeSpec::Provider),
+            crate::dtrace_probespec::DTRACE_PROBESPEC_MOD => Ok(ProbeSpec::Module),
+            crate::dtrace_probespec::DTRACE_PROBESPEC_FUNC => Ok(ProbeSpec::Function),
+            crate::dtrace_probespec::DTRACE_PROBESPEC_NAME => Ok(ProbeSpec::Name),
+            _ => Err(crate::utils::Error::new(-1, format!("Unsupported probe spec {}", spec as u32))),
+        }
+    }
+}
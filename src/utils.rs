@@ -1,27 +1,175 @@
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Error {
-    _errno: i32,
+    errno: i32,
+    /// The message, looked up from `errno` when displayed if empty
     message: String,
+    context: Option<String>,
+    /// The kind of the error when it is not told by `errno`, see `kind`
+    kind: Option<ErrorKind>,
+}
+
+impl Error {
+    pub(crate) fn new(errno: i32, message: impl Into<String>) -> Self {
+        Self {
+            errno,
+            message: message.into(),
+            context: None,
+            kind: None,
+        }
+    }
+
+    /// Returns the error number, a libdtrace `EDT_*` or system error number, or -1 for errors of the wrapper.
+    pub fn errno(&self) -> i32 {
+        self.errno
+    }
+
+    /// Returns the message of the error, without its context.
+    pub fn message(&self) -> String {
+        match self.message.is_empty() {
+            true => crate::wrapper::dtrace_hdl::errno_message(self.errno),
+            false => self.message.clone(),
+        }
+    }
+
+    /// Records what was being done when the error occurred, e.g. the option being set.
+    pub(crate) fn with_context(mut self, context: impl Into<String>) -> Self {
+        self.context = Some(context.into());
+        self
+    }
+
+    /// Classifies the error as `kind` regardless of its error number, e.g. [`ErrorKind::NotElevated`] for a permission
+    /// error of a process that is not elevated.
+    pub(crate) fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Returns what was being done when the error occurred, if known.
+    pub fn context(&self) -> Option<&str> {
+        self.context.as_deref()
+    }
+
+    /// Classifies the error by its error number, see [`ErrorKind`].
+    pub fn kind(&self) -> ErrorKind {
+        if let Some(kind) = self.kind {
+            return kind;
+        }
+        let kind = ERROR_KINDS
+            .iter()
+            .find(|(errno, _)| *errno == self.errno)
+            .map_or(ErrorKind::Other, |&(_, kind)| kind);
+        // Probe descriptions matching nothing are reported by the compiler, with a message naming the description.
+        match kind {
+            ErrorKind::Compile if self.message().contains("does not match any probes") => ErrorKind::NoMatch,
+            kind => kind,
+        }
+    }
+
+    /// Returns `true` if a D program or its preprocessing failed, see [`ErrorKind::Compile`].
+    pub fn is_compile_error(&self) -> bool {
+        self.kind() == ErrorKind::Compile
+    }
+
+    /// Returns the compile error, with its location in the program, if a D program failed to compile, see
+    /// [`CompileError`](crate::compile::CompileError).
+    pub fn compile_error(&self) -> Option<crate::compile::CompileError> {
+        crate::compile::CompileError::from_error(self)
+    }
+
+    /// Returns `true` if the consumer lacks a privilege, see [`ErrorKind::Permission`] and
+    /// [`ErrorKind::NotElevated`].
+    pub fn is_permission_error(&self) -> bool {
+        matches!(self.kind(), ErrorKind::Permission | ErrorKind::NotElevated)
+    }
+
+    /// Returns `true` if DTrace could not be opened because the process is not elevated, see
+    /// [`ErrorKind::NotElevated`].
+    pub fn is_not_elevated(&self) -> bool {
+        self.kind() == ErrorKind::NotElevated
+    }
+
+    /// Returns `true` if probe descriptions matched no probes, see [`ErrorKind::NoMatch`].
+    pub fn is_no_match(&self) -> bool {
+        self.kind() == ErrorKind::NoMatch
+    }
+}
+
+/// Category of an [`Error`], to handle errors without comparing error numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// A D program could not be compiled or preprocessed
+    Compile,
+    /// The consumer is not allowed to trace, e.g. it is not elevated or destructive actions are not permitted
+    Permission,
+    /// DTrace could not be opened for lack of privileges and the process is not elevated, e.g. it was started from a
+    /// regular command prompt. Running it as administrator, e.g. with `elevation::relaunch_elevated`, fixes it
+    NotElevated,
+    /// Probe descriptions matched no probes
+    NoMatch,
+    /// Any other error
+    Other,
+}
+
+/// System error numbers libdtrace reports for missing privileges, identical in the C runtimes of all platforms.
+const EPERM: i32 = 1;
+const EACCES: i32 = 13;
+
+/// The error numbers of each error kind.
+const ERROR_KINDS: &[(i32, ErrorKind)] = &[
+    (crate::EDT_COMPILER as i32, ErrorKind::Compile),
+    (crate::EDT_CPPFORK as i32, ErrorKind::Compile),
+    (crate::EDT_CPPEXEC as i32, ErrorKind::Compile),
+    (crate::EDT_CPPENT as i32, ErrorKind::Compile),
+    (crate::EDT_CPPERR as i32, ErrorKind::Compile),
+    (EPERM, ErrorKind::Permission),
+    (EACCES, ErrorKind::Permission),
+    (crate::EDT_ACCESS as i32, ErrorKind::Permission),
+    (crate::EDT_DESTRUCTIVE as i32, ErrorKind::Permission),
+    (crate::EDT_NOPROBE as i32, ErrorKind::NoMatch),
+    (crate::EDT_NOPROBES as i32, ErrorKind::NoMatch),
+];
+
+/// Maximum number of characters of D source quoted in error contexts.
+const EXCERPT_LEN: usize = 60;
+
+/// Returns the first line of the D program `source`, shortened to quote it in an error context.
+pub(crate) fn excerpt(source: &str) -> String {
+    let mut lines = source.trim().lines();
+    let line = lines.next().unwrap_or_default().trim();
+    let mut excerpt: String = line.chars().take(EXCERPT_LEN).collect();
+    if line.chars().count() > EXCERPT_LEN || lines.next().is_some() {
+        excerpt.push_str("...");
+    }
+    excerpt
 }
 
 impl From<::core::ffi::c_int> for Error {
     fn from(value: ::core::ffi::c_int) -> Self {
-        let message = crate::wrapper::dtrace_hdl::dtrace_errmsg(None, value).to_string();
-        Self { _errno: value, message }
+        // The message does not depend on a handle, so it is looked up when displayed.
+        Self::new(value, String::new())
     }
 }
 
 impl From<&crate::wrapper::dtrace_hdl> for Error {
     fn from(handle: &crate::wrapper::dtrace_hdl) -> Self {
         let errno = handle.dtrace_errno();
-        let message = crate::wrapper::dtrace_hdl::dtrace_errmsg(Some(handle), errno).to_string();
-        Self { _errno: errno, message }
+        let message = crate::wrapper::dtrace_hdl::dtrace_errmsg(Some(handle), errno);
+        Self::new(errno, message)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Self::new(error.raw_os_error().unwrap_or(-1), error.to_string())
     }
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Error: {}", self.message)
+        match &self.context {
+            Some(context) => write!(f, "Error: {}: {}", context, self.message()),
+            None => write!(f, "Error: {}", self.message()),
+        }
     }
 }
 
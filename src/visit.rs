@@ -0,0 +1,281 @@
+//! Decoding trace data with a [`RecordVisitor`], so custom decoding or serialization is written once and driven by
+//! `dtrace_hdl::work_with_visitor` as well as by a session, see `TraceSession::with_visitor`.
+//!
+//! ```no_run
+//! use libdtrace_rs::probe::EnabledProbe;
+//! use libdtrace_rs::visit::RecordVisitor;
+//!
+//! /// Collects the firings as lines of `probe value value ...`.
+//! #[derive(Default)]
+//! struct Lines(Vec<String>);
+//!
+//! impl RecordVisitor for Lines {
+//!     fn visit_probe(&mut self, _cpu: libdtrace_rs::processorid_t, probe: Option<&EnabledProbe>) {
+//!         let name = probe.map(|probe| probe.probe.to_string()).unwrap_or_default();
+//!         self.0.push(name);
+//!     }
+//!
+//!     fn visit_int(&mut self, value: i64) {
+//!         if let Some(line) = self.0.last_mut() {
+//!             line.push_str(&format!(" {}", value));
+//!         }
+//!     }
+//! }
+//!
+//! # let mut handle: libdtrace_rs::wrapper::dtrace_hdl = unimplemented!();
+//! let mut lines = Lines::default();
+//! handle.work_with_visitor(&mut lines).unwrap();
+//! ```
+
+use crate::aggregate::{AggregateEntry, KeyItem};
+use crate::probe::{EnabledProbe, EpidCache, RecordDescription};
+use crate::symbol::SymbolAction;
+use crate::utils::Error;
+use ::core::ffi::{c_int, c_void};
+use std::sync::{Arc, Mutex};
+
+/// Receives the decoded records of trace data. Every method does nothing by default.
+///
+/// For each firing of a probe, `visit_probe` is called first, then one method per record in the order the clause
+/// recorded them, then `visit_end`.
+pub trait RecordVisitor {
+    /// Returns `false` to skip a firing of `probe` on `cpu` before its records are decoded, in which case no other
+    /// method is called for the firing. Every firing is visited by default, see [`crate::filter::Filtered`].
+    fn accepts_probe(&mut self, _cpu: crate::processorid_t, _probe: Option<&EnabledProbe>) -> bool {
+        true
+    }
+
+    /// A probe fired on `cpu`, with the enabled probe it fired for if it is known.
+    fn visit_probe(&mut self, _cpu: crate::processorid_t, _probe: Option<&EnabledProbe>) {}
+
+    /// An integer was recorded, e.g. by `trace(pid)`.
+    fn visit_int(&mut self, _value: i64) {}
+
+    /// A string was recorded, e.g. by `trace(execname)`.
+    fn visit_string(&mut self, _value: &str) {}
+
+    /// Data that is neither an integer nor a string was recorded, e.g. by `tracemem()`.
+    fn visit_bytes(&mut self, _value: &[u8]) {}
+
+    /// A stack was recorded, with the addresses of its frames innermost first. `pid` is the process of a user stack,
+    /// `None` for a kernel stack.
+    fn visit_stack(&mut self, _frames: &[u64], _pid: Option<crate::pid_t>) {}
+
+    /// A stack was recorded, with the depth it was recorded with and whether it was cut off. Calls `visit_stack`
+    /// unless overridden.
+    fn visit_stack_frames(&mut self, stack: &crate::symbol::Stack) {
+        self.visit_stack(&stack.frames, stack.pid);
+    }
+
+    /// An address was recorded to be printed as a symbol, e.g. by `sym()` or `ufunc()`. `pid` is the process of a
+    /// user address. See `dtrace_hdl::decode_symbol` to resolve it.
+    fn visit_symbol(&mut self, _action: SymbolAction, _address: u64, _pid: Option<crate::pid_t>) {}
+
+    /// Output formatted by `printf()`, `printa()` or `system()`.
+    fn visit_printf(&mut self, _output: &str) {}
+
+    /// The program called `exit()` with `status`.
+    fn visit_exit(&mut self, _status: i32) {}
+
+    /// An entry of an aggregation, see `dtrace_hdl::visit_aggregates`.
+    fn visit_aggregate(&mut self, _entry: &AggregateEntry) {}
+
+    /// The names of the process and thread that fired the probe, delivered before `visit_end` by a
+    /// [`crate::names::Enricher`].
+    fn visit_names(&mut self, _names: &crate::names::Names) {}
+
+    /// All records of the firing were visited.
+    fn visit_end(&mut self) {}
+
+    /// A record of an action kind the crate does not know was recorded, e.g. by a newer libdtrace, with
+    /// [`UnknownActionPolicy::PassThrough`].
+    fn visit_unknown(&mut self, _action: crate::dtrace_actkind_t, _bytes: &[u8]) {}
+
+    /// A record was decoded by the decoder registered for its action kind, see
+    /// [`DecoderRegistry`](crate::decoders::DecoderRegistry).
+    fn visit_decoded(&mut self, _action: crate::dtrace_actkind_t, _value: &crate::typed::TypedValue) {}
+
+    /// An entry of an aggregation was decoded by the decoder registered for its aggregation, see
+    /// [`DecoderRegistry`](crate::decoders::DecoderRegistry). Calls `visit_aggregate` unless overridden.
+    fn visit_decoded_aggregate(&mut self, entry: &AggregateEntry, _value: &crate::typed::TypedValue) {
+        self.visit_aggregate(entry);
+    }
+
+    /// Returns what to do with records of action kinds the crate does not know, see [`is_known_action`].
+    fn unknown_actions(&self) -> UnknownActionPolicy {
+        UnknownActionPolicy::PassThrough
+    }
+}
+
+/// What a [`RecordVisitor`] receives for records of an action kind the crate does not know, see
+/// `RecordVisitor::unknown_actions`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum UnknownActionPolicy {
+    /// Abort consuming with an error naming the action kind
+    Error,
+    /// Ignore the record
+    Skip,
+    /// Deliver the raw bytes of the record to `visit_unknown`
+    #[default]
+    PassThrough,
+}
+
+/// Returns `true` if records of `action` are decoded by the crate, `false` for action kinds added to libdtrace after
+/// the bindings were generated.
+pub fn is_known_action(action: crate::dtrace_actkind_t) -> bool {
+    matches!(
+        action as u32,
+        crate::DTRACEACT_DIFEXPR
+            | crate::DTRACEACT_EXIT
+            | crate::DTRACEACT_PRINTF
+            | crate::DTRACEACT_PRINTA
+            | crate::DTRACEACT_LIBACT
+            | crate::DTRACEACT_TRACEMEM
+            | crate::DTRACEACT_TRACEMEM_DYNSIZE
+            | crate::DTRACEACT_USTACK
+            | crate::DTRACEACT_JSTACK
+            | crate::DTRACEACT_USYM
+            | crate::DTRACEACT_UMOD
+            | crate::DTRACEACT_UADDR
+            | crate::DTRACEACT_STOP
+            | crate::DTRACEACT_RAISE
+            | crate::DTRACEACT_SYSTEM
+            | crate::DTRACEACT_FREOPEN
+            | crate::DTRACEACT_STACK
+            | crate::DTRACEACT_SYM
+            | crate::DTRACEACT_MOD
+            | crate::DTRACEACT_BREAKPOINT
+            | crate::DTRACEACT_PANIC
+            | crate::DTRACEACT_CHILL
+            | crate::DTRACEACT_SPECULATE
+            | crate::DTRACEACT_COMMIT
+            | crate::DTRACEACT_DISCARD
+    )
+}
+
+/// State of the consume callbacks driving a visitor.
+pub(crate) struct VisitContext<'a> {
+    pub(crate) visitor: &'a mut dyn RecordVisitor,
+    pub(crate) handle: *mut crate::dtrace_hdl_t,
+    pub(crate) epids: Arc<EpidCache>,
+    /// Custom decoders, tried before the standard decoding
+    pub(crate) decoders: Arc<crate::decoders::DecoderRegistry>,
+    /// Output of the formatting actions, delivered before the next record as it is formatted after its callback
+    pub(crate) output: Arc<Mutex<Vec<String>>>,
+    /// Why consuming was aborted, with [`UnknownActionPolicy::Error`]
+    pub(crate) error: Option<Error>,
+}
+
+impl VisitContext<'_> {
+    /// Delivers the output formatted since the last record.
+    pub(crate) fn flush(&mut self) {
+        for output in std::mem::take(&mut *self.output.lock().unwrap()) {
+            self.visitor.visit_printf(&output);
+        }
+    }
+}
+
+/// Returns `true` if `bufdata` holds the output of a formatting action, which is delivered to `visit_printf`.
+pub(crate) fn is_printf(bufdata: &crate::dtrace_bufdata_t) -> bool {
+    let action = match unsafe { bufdata.dtbda_recdesc.as_ref() } {
+        Some(rec) => rec.dtrd_action as u32,
+        None => return false,
+    };
+    matches!(action, crate::DTRACEACT_PRINTF | crate::DTRACEACT_PRINTA | crate::DTRACEACT_SYSTEM)
+}
+
+/// Probe callback calling `visit_probe` of the visitor of the [`VisitContext`] passed as `arg`.
+pub(crate) unsafe extern "C" fn visit_probe(data: *const crate::dtrace_probedata_t, arg: *mut c_void) -> c_int {
+    let ctx = &mut *(arg as *mut VisitContext);
+    ctx.flush();
+    let data = &*data;
+    let probe = match data.dtpda_edesc.as_ref() {
+        Some(edesc) => ctx.epids.lookup(ctx.handle, edesc.dtepd_epid),
+        None => None,
+    };
+    if !ctx.visitor.accepts_probe(data.dtpda_cpu, probe.as_deref()) {
+        // The records of the firing are skipped, and so is their output.
+        return crate::DTRACE_CONSUME_NEXT as c_int;
+    }
+    ctx.visitor.visit_probe(data.dtpda_cpu, probe.as_deref());
+
+    crate::DTRACE_CONSUME_THIS as c_int
+}
+
+/// Record callback decoding each record for the visitor of the [`VisitContext`] passed as `arg`.
+pub(crate) unsafe extern "C" fn visit_rec(
+    data: *const crate::dtrace_probedata_t,
+    rec: *const crate::dtrace_recdesc_t,
+    arg: *mut c_void,
+) -> c_int {
+    let ctx = &mut *(arg as *mut VisitContext);
+    ctx.flush();
+    let rec = match rec.as_ref() {
+        Some(rec) => RecordDescription::from(rec),
+        None => {
+            ctx.visitor.visit_end();
+            return crate::DTRACE_CONSUME_NEXT as c_int;
+        }
+    };
+    // libdtrace points the data of the firing at the record it passes.
+    let addr = (*data).dtpda_data as *const u8;
+    let bytes = std::slice::from_raw_parts(addr, rec.size as usize);
+    let word = |index: usize| (addr as *const u64).add(index).read_unaligned();
+
+    if let Some(value) = ctx.decoders.decode_record(rec.action, bytes) {
+        ctx.visitor.visit_decoded(rec.action, &value);
+        // libdtrace does not process the records of action kinds it does not know either.
+        return match is_known_action(rec.action) {
+            true => crate::DTRACE_CONSUME_THIS as c_int,
+            false => crate::DTRACE_CONSUME_NEXT as c_int,
+        };
+    }
+
+    if !is_known_action(rec.action) {
+        match ctx.visitor.unknown_actions() {
+            UnknownActionPolicy::Error => {
+                ctx.error = Some(Error::new(
+                    -1,
+                    format!("Unknown action kind {} in the record at offset {}", rec.action, rec.offset),
+                ));
+                return crate::DTRACE_CONSUME_ABORT as c_int;
+            }
+            UnknownActionPolicy::Skip => {}
+            UnknownActionPolicy::PassThrough => ctx.visitor.visit_unknown(rec.action, bytes),
+        }
+        return crate::DTRACE_CONSUME_NEXT as c_int;
+    }
+
+    match rec.action as u32 {
+        crate::DTRACEACT_EXIT => {
+            ctx.visitor.visit_exit((addr as *const i32).read_unaligned());
+            return crate::DTRACE_CONSUME_NEXT as c_int;
+        }
+        crate::DTRACEACT_PRINTF | crate::DTRACEACT_PRINTA | crate::DTRACEACT_SYSTEM | crate::DTRACEACT_FREOPEN => {}
+        crate::DTRACEACT_STACK => {
+            let stack = crate::symbol::Stack::decode(bytes, rec.arg as usize, None);
+            ctx.visitor.visit_stack_frames(&stack);
+        }
+        crate::DTRACEACT_USTACK | crate::DTRACEACT_JSTACK => {
+            // User stacks start with the ID of the process, followed by the frames and their strings.
+            let frames = (rec.arg & 0xffff_ffff) as usize;
+            let frames_data = bytes.get(std::mem::size_of::<u64>()..).unwrap_or_default();
+            let stack = crate::symbol::Stack::decode(frames_data, frames, Some(word(0) as crate::pid_t));
+            ctx.visitor.visit_stack_frames(&stack);
+        }
+        action => match SymbolAction::from_action(action as crate::dtrace_actkind_t) {
+            // User addresses are recorded after the ID of the process.
+            Some(symbol) if symbol.is_user() => {
+                ctx.visitor.visit_symbol(symbol, word(1), Some(word(0) as crate::pid_t))
+            }
+            Some(symbol) => ctx.visitor.visit_symbol(symbol, word(0), None),
+            None => match KeyItem::from_bytes(bytes) {
+                KeyItem::Int(value) => ctx.visitor.visit_int(value),
+                KeyItem::Str(value) => ctx.visitor.visit_string(&value),
+                KeyItem::Bytes(value) => ctx.visitor.visit_bytes(&value),
+            },
+        },
+    }
+
+    crate::DTRACE_CONSUME_THIS as c_int
+}
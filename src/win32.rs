@@ -0,0 +1,202 @@
+//! The Win32 declarations used by the crate, for the Windows features libdtrace does not wrap, like Job Objects.
+#![allow(non_snake_case, non_camel_case_types, clippy::upper_case_acronyms)]
+
+use ::core::ffi::c_void;
+
+pub const JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE: u32 = 0x2000;
+/// `JobObjectExtendedLimitInformation` of `JOBOBJECTINFOCLASS`
+pub const JOB_OBJECT_EXTENDED_LIMIT_INFORMATION: i32 = 9;
+/// `JobObjectAssociateCompletionPortInformation` of `JOBOBJECTINFOCLASS`
+pub const JOB_OBJECT_ASSOCIATE_COMPLETION_PORT_INFORMATION: i32 = 7;
+pub const JOB_OBJECT_MSG_ACTIVE_PROCESS_ZERO: u32 = 4;
+pub const JOB_OBJECT_MSG_NEW_PROCESS: u32 = 6;
+pub const JOB_OBJECT_MSG_EXIT_PROCESS: u32 = 7;
+pub const JOB_OBJECT_MSG_ABNORMAL_EXIT_PROCESS: u32 = 8;
+pub const PROCESS_TERMINATE: u32 = 0x0001;
+pub const PROCESS_SET_QUOTA: u32 = 0x0100;
+pub const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+pub const THREAD_QUERY_LIMITED_INFORMATION: u32 = 0x0800;
+pub const SYNCHRONIZE: u32 = 0x0010_0000;
+pub const STILL_ACTIVE: u32 = 259;
+pub const TH32CS_SNAPPROCESS: u32 = 0x0002;
+pub const INVALID_HANDLE_VALUE: *mut c_void = -1isize as *mut c_void;
+pub const PIPE_ACCESS_OUTBOUND: u32 = 0x0000_0002;
+pub const PIPE_TYPE_BYTE: u32 = 0x0000_0000;
+pub const PIPE_WAIT: u32 = 0x0000_0000;
+pub const PIPE_REJECT_REMOTE_CLIENTS: u32 = 0x0000_0008;
+pub const ERROR_PIPE_CONNECTED: i32 = 535;
+pub const EVENTLOG_ERROR_TYPE: u16 = 0x0001;
+pub const EVENTLOG_WARNING_TYPE: u16 = 0x0002;
+pub const EVENTLOG_INFORMATION_TYPE: u16 = 0x0004;
+/// `HKEY_LOCAL_MACHINE`, sign-extended like the `HKEY` constants of the Windows headers
+pub const HKEY_LOCAL_MACHINE: *mut c_void = 0x8000_0002u32 as i32 as isize as *mut c_void;
+pub const KEY_SET_VALUE: u32 = 0x0002;
+pub const REG_OPTION_NON_VOLATILE: u32 = 0;
+pub const REG_EXPAND_SZ: u32 = 2;
+pub const REG_DWORD: u32 = 4;
+pub const TOKEN_QUERY: u32 = 0x0008;
+/// `TokenElevation` of `TOKEN_INFORMATION_CLASS`
+pub const TOKEN_ELEVATION: i32 = 20;
+pub const SEE_MASK_NOCLOSEPROCESS: u32 = 0x0000_0040;
+pub const SW_SHOWNORMAL: i32 = 1;
+pub const INFINITE: u32 = 0xffff_ffff;
+pub const ERROR_CANCELLED: i32 = 1223;
+
+#[repr(C)]
+pub struct JOBOBJECT_BASIC_LIMIT_INFORMATION {
+    pub PerProcessUserTimeLimit: i64,
+    pub PerJobUserTimeLimit: i64,
+    pub LimitFlags: u32,
+    pub MinimumWorkingSetSize: usize,
+    pub MaximumWorkingSetSize: usize,
+    pub ActiveProcessLimit: u32,
+    pub Affinity: usize,
+    pub PriorityClass: u32,
+    pub SchedulingClass: u32,
+}
+
+#[repr(C)]
+pub struct IO_COUNTERS {
+    pub ReadOperationCount: u64,
+    pub WriteOperationCount: u64,
+    pub OtherOperationCount: u64,
+    pub ReadTransferCount: u64,
+    pub WriteTransferCount: u64,
+    pub OtherTransferCount: u64,
+}
+
+#[repr(C)]
+pub struct JOBOBJECT_EXTENDED_LIMIT_INFORMATION {
+    pub BasicLimitInformation: JOBOBJECT_BASIC_LIMIT_INFORMATION,
+    pub IoInfo: IO_COUNTERS,
+    pub ProcessMemoryLimit: usize,
+    pub JobMemoryLimit: usize,
+    pub PeakProcessMemoryUsed: usize,
+    pub PeakJobMemoryUsed: usize,
+}
+
+#[repr(C)]
+pub struct JOBOBJECT_ASSOCIATE_COMPLETION_PORT {
+    pub CompletionKey: *mut c_void,
+    pub CompletionPort: *mut c_void,
+}
+
+#[repr(C)]
+pub struct PROCESSENTRY32W {
+    pub dwSize: u32,
+    pub cntUsage: u32,
+    pub th32ProcessID: u32,
+    pub th32DefaultHeapID: usize,
+    pub th32ModuleID: u32,
+    pub cntThreads: u32,
+    pub th32ParentProcessID: u32,
+    pub pcPriClassBase: i32,
+    pub dwFlags: u32,
+    pub szExeFile: [u16; 260],
+}
+
+#[repr(C)]
+pub struct SHELLEXECUTEINFOW {
+    pub cbSize: u32,
+    pub fMask: u32,
+    pub hwnd: *mut c_void,
+    pub lpVerb: *const u16,
+    pub lpFile: *const u16,
+    pub lpParameters: *const u16,
+    pub lpDirectory: *const u16,
+    pub nShow: i32,
+    pub hInstApp: *mut c_void,
+    pub lpIDList: *mut c_void,
+    pub lpClass: *const u16,
+    pub hkeyClass: *mut c_void,
+    pub dwHotKey: u32,
+    pub hIconOrMonitor: *mut c_void,
+    pub hProcess: *mut c_void,
+}
+
+#[link(name = "kernel32")]
+extern "system" {
+    pub fn CreateJobObjectW(attributes: *mut c_void, name: *const u16) -> *mut c_void;
+    pub fn SetInformationJobObject(job: *mut c_void, class: i32, info: *mut c_void, len: u32) -> i32;
+    pub fn AssignProcessToJobObject(job: *mut c_void, process: *mut c_void) -> i32;
+    pub fn TerminateJobObject(job: *mut c_void, code: u32) -> i32;
+    pub fn OpenProcess(access: u32, inherit: i32, pid: u32) -> *mut c_void;
+    pub fn CloseHandle(handle: *mut c_void) -> i32;
+    pub fn OpenThread(access: u32, inherit: i32, tid: u32) -> *mut c_void;
+    pub fn QueryFullProcessImageNameW(process: *mut c_void, flags: u32, name: *mut u16, size: *mut u32) -> i32;
+    pub fn GetThreadDescription(thread: *mut c_void, description: *mut *mut u16) -> i32;
+    pub fn LocalFree(memory: *mut c_void) -> *mut c_void;
+    pub fn GetExitCodeProcess(process: *mut c_void, code: *mut u32) -> i32;
+    pub fn CreateIoCompletionPort(file: *mut c_void, port: *mut c_void, key: usize, threads: u32) -> *mut c_void;
+    pub fn GetQueuedCompletionStatus(
+        port: *mut c_void,
+        bytes: *mut u32,
+        key: *mut usize,
+        overlapped: *mut *mut c_void,
+        timeout: u32,
+    ) -> i32;
+    pub fn GetCurrentProcessId() -> u32;
+    pub fn GetCurrentThread() -> *mut c_void;
+    pub fn SetThreadPriority(thread: *mut c_void, priority: i32) -> i32;
+    pub fn SetThreadAffinityMask(thread: *mut c_void, mask: usize) -> usize;
+    pub fn CreateToolhelp32Snapshot(flags: u32, pid: u32) -> *mut c_void;
+    pub fn Process32FirstW(snapshot: *mut c_void, entry: *mut PROCESSENTRY32W) -> i32;
+    pub fn Process32NextW(snapshot: *mut c_void, entry: *mut PROCESSENTRY32W) -> i32;
+    pub fn CreateNamedPipeW(
+        name: *const u16,
+        open_mode: u32,
+        pipe_mode: u32,
+        max_instances: u32,
+        out_buffer_size: u32,
+        in_buffer_size: u32,
+        default_timeout: u32,
+        attributes: *mut c_void,
+    ) -> *mut c_void;
+    pub fn ConnectNamedPipe(pipe: *mut c_void, overlapped: *mut c_void) -> i32;
+    pub fn GetCurrentProcess() -> *mut c_void;
+    pub fn WaitForSingleObject(handle: *mut c_void, timeout: u32) -> u32;
+}
+
+#[link(name = "advapi32")]
+extern "system" {
+    pub fn RegisterEventSourceW(server: *const u16, source: *const u16) -> *mut c_void;
+    pub fn DeregisterEventSource(source: *mut c_void) -> i32;
+    pub fn ReportEventW(
+        source: *mut c_void,
+        kind: u16,
+        category: u16,
+        event_id: u32,
+        sid: *mut c_void,
+        num_strings: u16,
+        data_size: u32,
+        strings: *const *const u16,
+        data: *mut c_void,
+    ) -> i32;
+    pub fn RegCreateKeyExW(
+        key: *mut c_void,
+        subkey: *const u16,
+        reserved: u32,
+        class: *mut u16,
+        options: u32,
+        access: u32,
+        attributes: *mut c_void,
+        result: *mut *mut c_void,
+        disposition: *mut u32,
+    ) -> i32;
+    pub fn RegSetValueExW(
+        key: *mut c_void,
+        name: *const u16,
+        reserved: u32,
+        kind: u32,
+        data: *const u8,
+        size: u32,
+    ) -> i32;
+    pub fn RegCloseKey(key: *mut c_void) -> i32;
+    pub fn OpenProcessToken(process: *mut c_void, access: u32, token: *mut *mut c_void) -> i32;
+    pub fn GetTokenInformation(token: *mut c_void, class: i32, info: *mut c_void, len: u32, returned: *mut u32) -> i32;
+}
+
+#[link(name = "shell32")]
+extern "system" {
+    pub fn ShellExecuteExW(info: *mut SHELLEXECUTEINFOW) -> i32;
+}
@@ -1,15 +1,70 @@
 #![allow(dead_code)]
 use crate::types::{dtrace_aggwalk_order, dtrace_status};
 use crate::utils::{Error, self};
+use crate::consumer::{ConsumeContext, ConsumptionStats, ConsumptionTable, ProbeStats, ProbeStatsTable};
+use crate::diagnostics::{DropCounts, StatementSource, StatusWatch};
 use ::core::ffi::c_int;
+use crate::config::{ConsumerConfig, ProgramConfig};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+/// Serializes the D compiler of libdtrace, which keeps the state of the program being parsed in globals, across all
+/// handles of the process.
+static COMPILER: Mutex<()> = Mutex::new(());
+
 /// Represents a handle to a DTrace instance.
+///
+/// libdtrace does not support concurrent calls on a handle, so the calls changing the state of tracing (setting
+/// options, starting, sleeping and stopping, consuming and snapshotting data, updating modules) take `&mut self`.
+/// Calls taking `&self` only read the handle or keep their state behind locks: compiling and enabling programs,
+/// registering handlers and subscribers, and walking and printing aggregations are serialized by a lock of the
+/// handle, so they can be made from several threads sharing it.
+///
+/// Several handles can be open at once, e.g. to trace two targets with different options, and used from different
+/// threads. Each handle has its own options, programs, handlers, output subscribers, drop and error counts and
+/// caches, so nothing registered on one handle sees the data of another. The only state libdtrace shares between
+/// handles is its D compiler, so programs are compiled one at a time across the process.
 pub struct dtrace_hdl {
     handle: *mut crate::dtrace_hdl_t,
+    epids: Arc<crate::probe::EpidCache>,
+    aggregations: crate::aggregate::AggregationCache,
+    probe_stats: ProbeStatsTable,
+    consumption: ConsumptionTable,
+    handlers: crate::handler::Handlers,
+    buffered: Arc<crate::handler::BufferedSubscribers>,
+    drops: Arc<std::sync::Mutex<DropCounts>>,
+    errors: Arc<std::sync::atomic::AtomicU64>,
+    config: Arc<Mutex<ConsumerConfig>>,
+    compiled: Mutex<HashMap<usize, ProgramConfig>>,
+    statements: Arc<Mutex<Vec<StatementSource>>>,
+    status_watch: Mutex<StatusWatch>,
+    perf: crate::perf::PerfCounters,
+    clock: Mutex<Option<crate::clock::Clock>>,
+    /// Serializes the calls changing the state of the handle through `&self`, see `snapshot_aggregations`
+    state_lock: Mutex<()>,
+    decoders: Mutex<Arc<crate::decoders::DecoderRegistry>>,
 }
 
 impl From<*mut crate::dtrace_hdl_t> for dtrace_hdl {
     fn from(value: *mut crate::dtrace_hdl_t) -> Self {
-        Self { handle: value }
+        Self {
+            handle: value,
+            epids: Default::default(),
+            aggregations: Default::default(),
+            probe_stats: Default::default(),
+            consumption: Default::default(),
+            handlers: Default::default(),
+            buffered: Default::default(),
+            drops: Default::default(),
+            errors: Default::default(),
+            config: Default::default(),
+            compiled: Default::default(),
+            statements: Default::default(),
+            status_watch: Default::default(),
+            perf: Default::default(),
+            clock: Default::default(),
+            state_lock: Default::default(),
+            decoders: Default::default(),
+        }
     }
 }
 
@@ -36,6 +91,8 @@ impl dtrace_hdl {
     ///     * `DTRACE_O_NOSYS` - Do not attempt to enable any DTrace providers.
     ///     * `DTRACE_O_LP64` - Force DTrace to operate in 64-bit mode.
     ///     * `DTRACE_O_ILP32` - Force DTrace to operate in 32-bit mode.
+    ///
+    ///   [`OpenBuilder`](crate::open::OpenBuilder) sets the flags by name and rejects contradicting ones.
     /// # Returns
     ///
     /// Returns a `Result` containing the `dtrace_hdl` handle if successful, or an error code if
@@ -46,24 +103,66 @@ impl dtrace_hdl {
         let handle = unsafe { crate::dtrace_open(version, flags, &mut errp) };
 
         if handle.is_null() {
-            return Err(Error::from(errp));
+            let error = Error::from(errp);
+            // The most common failure of a first run: the consumer was not started as administrator.
+            if error.is_permission_error() && !crate::elevation::is_elevated() {
+                return Err(error
+                    .with_kind(crate::utils::ErrorKind::NotElevated)
+                    .with_context("opening DTrace, which requires running as administrator"));
+            }
+            return Err(error);
         }
 
-        Ok(handle.into())
+        let handle = Self::from(handle);
+        {
+            let mut config = handle.config.lock().unwrap();
+            config.version = version;
+            config.flags = flags;
+        }
+        Ok(handle)
     }
 
     /// Starts the execution of the program.
     ///
     /// This action enables the specified probes. After `dtrace_go` function is called, the probes start to generate data.
+    /// The clock converting timestamps to wall-clock time is captured by the first status check that follows, see
+    /// `clock`.
     /// # Returns
     ///
     /// * `Ok(())` - If the program execution is successful.
     /// * `Err(errno)` - If the program execution fails. The error number (`errno`) is returned.
-    pub fn dtrace_go(&self) -> Result<(), Error> {
+    pub fn dtrace_go(&mut self) -> Result<(), Error> {
         match unsafe { crate::dtrace_go(self.handle) } {
             0 => Ok(()),
-            _ => Err(Error::from(self)),
+            _ => Err(Error::from(&*self)),
+        }
+    }
+
+    /// Retrieves the clock converting the `timestamp` values of this handle to wall-clock time.
+    ///
+    /// # Returns
+    ///
+    /// Returns the clock captured by the first call to `dtrace_status` or one of the `work` methods after `dtrace_go`,
+    /// or `None` before.
+    pub fn clock(&self) -> Option<crate::clock::Clock> {
+        *self.clock.lock().unwrap()
+    }
+
+    /// Runs `check`, a call to libdtrace checking the status, capturing the clock if it is the first status check.
+    ///
+    /// The first status check after `dtrace_go` records the current high-resolution time, the clock `timestamp`
+    /// counts with, so the clock is read without checking the status out of turn. `dtrace_work` checks the status
+    /// before consuming, so the wall-clock time is read before the call.
+    fn capture_clock<R>(&self, check: impl FnOnce() -> R) -> R {
+        let wall = self.clock.lock().unwrap().is_none().then(std::time::SystemTime::now);
+        let result = check();
+        if let Some(wall) = wall {
+            let timestamp = unsafe { (*self.handle).dt_laststatus };
+            if timestamp > 0 {
+                *self.clock.lock().unwrap() = Some(crate::clock::Clock::new(wall, timestamp as u64));
+            }
         }
+        result
     }
 
     /// Stops the DTrace data consumption.
@@ -76,10 +175,10 @@ impl dtrace_hdl {
     ///
     /// * `Ok(())` - If the stop operation is successful.
     /// * `Err(String)` - If the stop operation fails. The error message is returned.
-    pub fn dtrace_stop(&self) -> Result<(), Error> {
+    pub fn dtrace_stop(&mut self) -> Result<(), Error> {
         match unsafe { crate::dtrace_stop(self.handle) } {
             0 => Ok(()),
-            _ => Err(Error::from(self)),
+            _ => Err(Error::from(&*self)),
         }
     }
 
@@ -94,7 +193,7 @@ impl dtrace_hdl {
     /// The function calculates the earliest time for it to wake up based on the last occurrence of these three events and their associated rates. If that earliest time is in the past, the function returns, otherwise it sleeps until that time.
     ///
     /// Note: You do not have to call the `dtrace_sleep()` function itself from a consumer. You can use the `dtrace_getopt()` function to get the values of the appropriate rate and use timers based on those values.
-    pub fn dtrace_sleep(&self) {
+    pub fn dtrace_sleep(&mut self) {
         unsafe {
             crate::dtrace_sleep(self.handle);
         }
@@ -119,19 +218,28 @@ impl dtrace_hdl {
     ///
     /// # Returns
     ///
-    /// Returns the error message as a [`String`].
-    pub fn dtrace_errmsg<'a>(handle: Option<&'a Self>, errno: c_int) -> &'a str {
+    /// Returns the error message. Messages that are not valid UTF-8, e.g. quoting a path in the system code page, have
+    /// their invalid sequences replaced with `U+FFFD`. The message is copied, as libdtrace reuses its buffer for the
+    /// next error of the handle.
+    pub fn dtrace_errmsg(handle: Option<&Self>, errno: c_int) -> String {
         unsafe {
             let handle = match handle {
                 Some(handle) => handle.handle,
                 None => std::ptr::null_mut(),
             };
             let msg = crate::dtrace_errmsg(handle, errno);
-            let msg = ::core::ffi::CStr::from_ptr(msg);
-            msg.to_str().unwrap()
+            if msg.is_null() {
+                return format!("Unknown error {}", errno);
+            }
+            ::core::ffi::CStr::from_ptr(msg).to_string_lossy().into_owned()
         }
     }
 
+    /// Retrieves the error message of an error number without a handle, e.g. for the errors of `dtrace_open`.
+    pub fn errno_message(errno: c_int) -> String {
+        Self::dtrace_errmsg(None, errno)
+    }
+
     /// Sets a DTrace option to the specified value.
     ///
     /// # Arguments
@@ -145,12 +253,20 @@ impl dtrace_hdl {
     ///
     /// Returns `Ok(())` if the option was set successfully, or an error code if the option could
     /// not be set.
-    pub fn dtrace_setopt(&self, option: &str, value: &str) -> Result<(), Error> {
+    pub fn dtrace_setopt(&mut self, option: &str, value: &str) -> Result<(), Error> {
         let option = std::ffi::CString::new(option).unwrap();
         let value = std::ffi::CString::new(value).unwrap();
         match unsafe { crate::dtrace_setopt(self.handle, option.as_ptr(), value.as_ptr()) } {
-            0 => Ok(()),
-            _ => Err(Error::from(self)),
+            0 => {
+                let (option, value) = (option.to_str().unwrap(), value.to_str().unwrap());
+                self.config.lock().unwrap().set_option(option, value);
+                Ok(())
+            }
+            _ => Err(Error::from(&*self).with_context(format!(
+                "setting option {}={}",
+                option.to_string_lossy(),
+                value.to_string_lossy()
+            ))),
         }
     }
 
@@ -172,16 +288,125 @@ impl dtrace_hdl {
         }
     }
 
+    /// Retrieves the CPUs known to the handle and the CPU the `cpu` option pins tracing to.
+    ///
+    /// Once tracing is started, the CPUs are those `dtrace_go` allocated buffers for. Before, they are the CPUs
+    /// libdtrace reports online among those of the system.
+    pub fn cpu_info(&self) -> crate::cpu::CpuInfo {
+        let pinned = self
+            .dtrace_getopt("cpu")
+            .ok()
+            .filter(|&value| value != crate::DTRACEOPT_UNSET)
+            .map(|value| value as crate::processorid_t);
+        unsafe {
+            let aggregate = &(*self.handle).dt_aggregate;
+            if !aggregate.dtat_cpus.is_null() && aggregate.dtat_maxcpu > 0 {
+                // dtrace_go lists only the pinned CPU when there is one, so the online CPUs are queried again.
+                let max_cpu = aggregate.dtat_maxcpu as usize;
+                let cpus = match pinned {
+                    Some(_) => self.online_cpus(max_cpu),
+                    None => std::slice::from_raw_parts(aggregate.dtat_cpus, aggregate.dtat_ncpus as usize).to_vec(),
+                };
+                return crate::cpu::CpuInfo::new(max_cpu, cpus, pinned);
+            }
+        }
+        let max_cpu = std::thread::available_parallelism().map_or(1, |cpus| cpus.get());
+        crate::cpu::CpuInfo::new(max_cpu, self.online_cpus(max_cpu), pinned)
+    }
+
+    /// Returns the ids below `max_cpu` of the CPUs libdtrace reports online.
+    fn online_cpus(&self, max_cpu: usize) -> Vec<crate::processorid_t> {
+        (0..max_cpu as crate::processorid_t)
+            .filter(|&cpu| unsafe { crate::dt_status(self.handle, cpu) } != -1)
+            .collect()
+    }
+
+    /// Returns the raw libdtrace handle, to call libdtrace functions that are not wrapped yet.
+    ///
+    /// The handle remains owned by `self` and must not be closed.
+    pub fn as_raw(&self) -> *mut crate::dtrace_hdl_t {
+        self.handle
+    }
+
+    /// Releases ownership of the raw libdtrace handle, which is no longer closed on drop.
+    ///
+    /// The state of the handlers registered through the wrapper is leaked, so they stay valid for as long as the raw
+    /// handle is used.
+    pub fn into_raw(self) -> *mut crate::dtrace_hdl_t {
+        let this = std::mem::ManuallyDrop::new(self);
+        this.handle
+    }
+
+    /// Takes ownership of a raw libdtrace handle, which is closed on drop.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be a valid handle returned by `dtrace_open` or `into_raw`, that is not owned by anything else.
+    pub unsafe fn from_raw(handle: *mut crate::dtrace_hdl_t) -> Self {
+        Self::from(handle)
+    }
+
+    /// Updates the modules and providers known to the DTrace instance, making the probes of drivers and libraries
+    /// loaded since it was opened available to programs compiled afterwards.
+    pub fn dtrace_update(&mut self) {
+        unsafe { crate::dtrace_update(self.handle) }
+    }
+
+    /// Retrieves the names of the modules known to the DTrace instance.
+    pub fn modules(&self) -> Vec<String> {
+        let mut modules = Vec::new();
+        unsafe {
+            let mut node = (*self.handle).dt_modlist.dl_next;
+            while !node.is_null() {
+                // The list links are the first member of each module.
+                let module = &*(node as *const crate::dt_module_t);
+                modules.push(crate::probe::field(&module.dm_name));
+                node = module.dm_list.dl_next;
+            }
+        }
+        modules
+    }
+
+    /// Calls `dtrace_update` and reports the modules that were loaded or unloaded.
+    pub fn update_modules(&mut self) -> crate::probe::ModuleChanges {
+        let before = self.modules();
+        self.dtrace_update();
+        let after = self.modules();
+        crate::probe::ModuleChanges {
+            loaded: after.iter().filter(|m| !before.contains(m)).cloned().collect(),
+            unloaded: before.iter().filter(|m| !after.contains(m)).cloned().collect(),
+        }
+    }
+
+    /// Retrieves the configuration of this handle: the version and flags it was opened with, the options set, the
+    /// handlers registered and the programs compiled from strings and enabled on it.
+    ///
+    /// Handlers registered by `dtrace_register_handler` with an argument cannot be registered again, as nothing keeps
+    /// the argument alive for the new handle: opening the configuration fails for them. Handlers registered by
+    /// `register_handler_with` own their context and are registered again with it.
+    ///
+    /// [`ConsumerConfig::open`](crate::config::ConsumerConfig::open) opens a new handle with the same configuration,
+    /// e.g. to recover from an error or to change buffer sizes.
+    pub fn clone_config(&self) -> ConsumerConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    /// Returns the configuration of this handle, shared with handlers recording the options set by programs.
+    pub(crate) fn shared_config(&self) -> Arc<Mutex<ConsumerConfig>> {
+        self.config.clone()
+    }
+
     /* General Purpose APIs END */
 
     /* Programming APIs START */
-    /// Compiles a DTrace program from a string representation.
+    /// Compiles a DTrace program from a string representation, like `compile` with the flags and arguments given
+    /// positionally.
     ///
     /// # Arguments
     ///
     /// * `program` - The DTrace program as a string.
-    /// * `spec` - spec to indicate the context of the probe you are using.
-    ///     * Available values can be found [here](https://docs.oracle.com/en/operating-systems/solaris/oracle-solaris/11.4/dtrace-guide/dtrace_program_strcompile-function.html)
+    /// * `spec` - What the fields of incomplete probe descriptions name, [`ProbeSpec::Name`](crate::types::ProbeSpec)
+    ///   for D programs.
     ///
     /// * `flags` - Flags to control the compilation behavior. Common flags:
     ///     * `DTRACE_C_ZDEFS` - Instructs the compiler to permit probes, whose definitions do not match the existing probes.
@@ -199,61 +424,110 @@ impl dtrace_hdl {
     pub fn dtrace_program_strcompile<'a>(
         &'a self,
         program: &str,
-        spec: crate::dtrace_probespec,
+        spec: crate::types::ProbeSpec,
+        flags: u32,
+        args: Option<Vec<String>>,
+    ) -> Result<&'a mut crate::dtrace_prog, Error> {
+        self.compile(program, &crate::compile::CompileOptions { spec, flags, args })
+    }
+
+    /// Compiles a DTrace program from a file, or from the standard input if `file` is `None`, see `compile_file`.
+    pub fn dtrace_program_fcompile<'a>(
+        &'a self,
+        file: Option<&utils::File>,
         flags: u32,
         args: Option<Vec<String>>,
     ) -> Result<&'a mut crate::dtrace_prog, Error> {
+        let options = crate::compile::CompileOptions {
+            flags,
+            args,
+            ..Default::default()
+        };
+        self.fcompile(file, &options)
+    }
+
+    /// Compiles a D program from a string with `options`, the flags and arguments of the program, see
+    /// [`CompileOptions`](crate::compile::CompileOptions).
+    ///
+    /// # Returns
+    ///
+    /// Returns the compiled program, or the compilation error.
+    pub fn compile<'a>(
+        &'a self,
+        program: &str,
+        options: &crate::compile::CompileOptions,
+    ) -> Result<&'a mut crate::dtrace_prog, Error> {
+        let config = ProgramConfig {
+            source: program.to_string(),
+            spec: options.spec,
+            flags: options.flags,
+            args: options.args.clone(),
+        };
         let program = std::ffi::CString::new(program).unwrap();
 
-        // Break the arguments into argc and argv
-        let (argc, argv) = match args {
+        // Break the arguments into argc and argv, keeping both alive until the program is compiled
+        let args: Option<Vec<std::ffi::CString>> = options
+            .args
+            .as_ref()
+            .map(|args| args.iter().map(|arg| std::ffi::CString::new(arg.as_str()).unwrap()).collect());
+        let argv: Option<Vec<*mut ::core::ffi::c_char>> = args
+            .as_ref()
+            .map(|args| args.iter().map(|arg| arg.as_ptr() as *mut ::core::ffi::c_char).collect());
+        let (argc, argv) = match &argv {
             None => (0, std::ptr::null()),
-            Some(args) => {
-                let mut argv: Vec<*mut ::core::ffi::c_char> = Vec::new();
-                for arg in args {
-                    let arg = std::ffi::CString::new(arg).unwrap();
-                    argv.push(arg.as_ptr() as *mut ::core::ffi::c_char);
-                }
-                (argv.len() as c_int, argv.as_ptr())
-            }
+            Some(argv) => (argv.len() as c_int, argv.as_ptr()),
         };
 
+        let _guard = self.state_lock.lock().unwrap();
         let prog;
         unsafe {
+            let _compiler = COMPILER.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
             prog = crate::dtrace_program_strcompile(
                 self.handle,
                 program.as_ptr(),
-                spec,
-                flags,
+                options.spec.into(),
+                options.flags,
                 argc,
                 argv,
             );
         }
 
         if prog.is_null() {
-            return Err(Error::from(self));
+            let context = format!("compiling `{}`", utils::excerpt(&config.source));
+            return Err(Error::from(self).with_context(context));
         }
 
+        self.compiled.lock().unwrap().insert(prog as usize, config);
         unsafe { Ok(&mut *prog) }
     }
 
-    pub fn dtrace_program_fcompile<'a>(
+    /// Compiles a D program from a file with `options`, see `compile`. The probe specifier context of the options
+    /// does not apply to files.
+    pub fn compile_file<'a>(
+        &'a self,
+        file: &utils::File,
+        options: &crate::compile::CompileOptions,
+    ) -> Result<&'a mut crate::dtrace_prog, Error> {
+        self.fcompile(Some(file), options)
+    }
+
+    /// Compiles a D program from `file`, or from the standard input if `file` is `None`, with `options`.
+    fn fcompile<'a>(
         &'a self,
         file: Option<&utils::File>,
-        flags: u32,
-        args: Option<Vec<String>>,
+        options: &crate::compile::CompileOptions,
     ) -> Result<&'a mut crate::dtrace_prog, Error> {
-        // Break the arguments into argc and argv
-        let (argc, argv) = match args {
+        // Break the arguments into argc and argv, keeping both alive until the program is compiled
+        let args: Option<Vec<std::ffi::CString>> = options
+            .args
+            .as_ref()
+            .map(|args| args.iter().map(|arg| std::ffi::CString::new(arg.as_str()).unwrap()).collect());
+        let argv: Option<Vec<*mut ::core::ffi::c_char>> = args
+            .as_ref()
+            .map(|args| args.iter().map(|arg| arg.as_ptr() as *mut ::core::ffi::c_char).collect());
+        let (argc, argv) = match &argv {
             None => (0, std::ptr::null()),
-            Some(args) => {
-                let mut argv: Vec<*mut ::core::ffi::c_char> = Vec::new();
-                for arg in args {
-                    let arg = std::ffi::CString::new(arg).unwrap();
-                    argv.push(arg.as_ptr() as *mut ::core::ffi::c_char);
-                }
-                (argv.len() as c_int, argv.as_ptr())
-            }
+            Some(argv) => (argv.len() as c_int, argv.as_ptr()),
         };
 
         let file = match file {
@@ -261,13 +535,15 @@ impl dtrace_hdl {
             None => std::ptr::null_mut(),
         };
 
+        let _guard = self.state_lock.lock().unwrap();
         let prog;
         unsafe {
-            prog = crate::dtrace_program_fcompile(self.handle, file, flags, argc, argv);
+            let _compiler = COMPILER.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            prog = crate::dtrace_program_fcompile(self.handle, file, options.flags, argc, argv);
         }
 
         if prog.is_null() {
-            return Err(Error::from(self));
+            return Err(Error::from(self).with_context("compiling program from file"));
         }
 
         unsafe { Ok(&mut *prog) }
@@ -294,9 +570,24 @@ impl dtrace_hdl {
             Some(info) => info,
             None => std::ptr::null_mut(),
         };
+        let key = program as *mut crate::dtrace_prog as usize;
+        let _guard = self.state_lock.lock().unwrap();
         match unsafe { crate::dtrace_program_exec(self.handle, program, info) } {
-            0 => Ok(()),
-            _ => Err(Error::from(self)),
+            0 => {
+                let config = self.compiled.lock().unwrap().remove(&key);
+                self.record_statements(program, config.as_ref().map(|config| config.source.as_str()));
+                if let Some(config) = config {
+                    self.config.lock().unwrap().programs.push(config);
+                }
+                Ok(())
+            }
+            _ => {
+                let context = match self.compiled.lock().unwrap().get(&key) {
+                    Some(program) => format!("enabling `{}`", utils::excerpt(&program.source)),
+                    None => "enabling program".to_string(),
+                };
+                Err(Error::from(self).with_context(context))
+            }
         }
     }
 
@@ -334,198 +625,1901 @@ impl dtrace_hdl {
         }
     }
 
-    /* Programming APIs END */
-
-    /* Data Consumption APIs START */
-    /// Determines the status of the running DTrace instance.
+    /// Lists the probes a compiled program would enable, without enabling them.
+    ///
+    /// Useful to warn about descriptions matching no probes before starting to trace.
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - A mutable reference to the data structure representing the compiled program. This is returned by the `dtrace_strcompile()` function.
     ///
     /// # Returns
     ///
-    /// * `Ok(dtrace_status)` - If the status is successfully determined.
-    /// * `Err(errno)` - If the status could not be determined.
-    pub fn dtrace_status(&self) -> Result<dtrace_status, Error> {
-        match unsafe { crate::dtrace_status(self.handle) } {
-            -1 => Err(Error::from(self)),
-            status => Ok(dtrace_status::from(status as u32)),
+    /// Returns the matched probes ordered by probe ID, each probe listed once even if several statements match it.
+    pub fn program_probes(
+        &self,
+        program: &mut crate::dtrace_prog,
+    ) -> Result<Vec<crate::probe::ProbeDescription>, Error> {
+        let mut probes = Vec::new();
+        for (desc, _) in self.program_descriptions(program)? {
+            probes.extend(self.matching_probes(&desc)?.iter().map(crate::probe::ProbeDescription::from));
         }
+        probes.sort_by_key(|probe| probe.id);
+        probes.dedup_by_key(|probe| probe.id);
+        Ok(probes)
     }
 
-    /// Consumes data from the principal buffers.
+    /// Counts the probes a compiled program would enable, without enabling them, see `program_probes`.
+    pub fn program_match_count(&self, program: &mut crate::dtrace_prog) -> Result<usize, Error> {
+        Ok(self.program_probes(program)?.len())
+    }
+
+    /// Lists the probes each statement of a compiled program would enable, with their stability, like `dtrace -lv`,
+    /// without enabling them.
     ///
     /// # Arguments
     ///
-    /// * `file` - An optional file handle for output.
-    /// * `p_hldr` - A pointer to a function that processes an `enabling control block (ECB)`. An `ECB` is a clause from a D program associated with the enabled probe.
-    /// * `r_hldr` - A pointer to a function that processes a records from the `ECB`.
-    /// * `arg` - An optional argument to be passed to the `p_hldr` and `r_hldr` functions. This argument can maintain any state between successive invocations of the functions.
+    /// * `program` - A mutable reference to the data structure representing the compiled program. This is returned by the `dtrace_strcompile()` function.
     ///
     /// # Returns
     ///
-    /// * `Ok(())` - If the consumption is successful.
-    /// * `Err(errno)` - If the consumption fails. The error number (`errno`) is returned.
-    pub fn dtrace_consume(
+    /// Returns a [`StatementListing`](crate::probe::StatementListing) for every statement, in program order. Probes
+    /// whose information libdtrace cannot read, such as probes removed since they were matched, are left out.
+    pub fn program_listing(
         &self,
-        file: Option<&utils::File>,
-        p_hldr: crate::dtrace_consume_probe_f,
-        r_hldr: crate::dtrace_consume_rec_f,
-        arg: Option<*mut ::core::ffi::c_void>,
-    ) -> Result<(), Error> {
-        let file = match file {
-            Some(file) => file.file,
-            None => std::ptr::null_mut(),
-        };
-        let arg = match arg {
-            Some(arg) => arg,
-            None => std::ptr::null_mut(),
-        };
-
-        match unsafe { crate::dtrace_consume(self.handle, file, p_hldr, r_hldr, arg) } {
-            0 => Ok(()),
-            _ => Err(Error::from(self)),
+        program: &mut crate::dtrace_prog,
+    ) -> Result<Vec<crate::probe::StatementListing>, Error> {
+        let mut listing = Vec::new();
+        for (desc, attr) in self.program_descriptions(program)? {
+            let probes = self
+                .matching_probes(&desc)?
+                .iter()
+                .filter_map(|probe| {
+                    let mut info: crate::dtrace_probeinfo_t = unsafe { std::mem::zeroed() };
+                    if unsafe { crate::dtrace_probe_info(self.handle, probe, &mut info) } != 0 {
+                        return None;
+                    }
+                    Some(crate::probe::ListedProbe {
+                        probe: crate::probe::ProbeDescription::from(probe),
+                        attributes: crate::probe::Attributes::from(&info.dtp_attr),
+                        arg_attributes: crate::probe::Attributes::from(&info.dtp_arga),
+                    })
+                })
+                .collect();
+            listing.push(crate::probe::StatementListing {
+                description: crate::probe::ProbeDescription::from(&desc),
+                attributes: crate::probe::Attributes::from(&attr),
+                probes,
+            });
         }
+        Ok(listing)
     }
 
-    /// Performs all of the work that must to be done periodically by a DTrace consumer.
-    ///
-    /// This function corresponds to the `statusrate`, `switchrate`, and `aggrate` rates. It first calls `dtrace_status()` to determine the status of the trace and then calls `dtrace_aggregate_snap()` and `dtrace_consume()` to consume any aggregation buffer or principal buffer data.
+    /// Returns the statements of a compiled program, to change their format strings or actions before it is enabled.
     ///
     /// # Arguments
     ///
-    /// * `file` - An optional file handle for output.
-    /// * `chew` - A function pointer that is called for each enabled probe ID (EPID) that is processed from the buffer.
-    /// * `chewrec` - A function pointer that is called for each record that is processed for an EPID.
-    /// * `arg` - An optional argument to be passed to the `chew` and `chewrec` functions. This argument can maintain any state between successive invocations of the functions.
+    /// * `program` - A mutable reference to the data structure representing the compiled program. This is returned by the `dtrace_strcompile()` function.
     ///
     /// # Returns
     ///
-    /// * `DTRACE_WORKSTATUS_OKAY` - If the work is successfully performed.
-    /// * `DTRACE_WORKSTATUS_DONE` - If the work is done and no more work is expected.
-    /// * `DTRACE_WORKSTATUS_ERROR` - If an error occurs while performing the work.
-    pub fn dtrace_work(
-        &self,
-        file: Option<&utils::File>,
-        p_hldr: crate::dtrace_consume_probe_f,
-        r_hldr: crate::dtrace_consume_rec_f,
-        arg: Option<&mut ::core::ffi::c_void>,
-    ) -> Result<crate::dtrace_workstatus_t, Error> {
-        let file = match file {
-            Some(file) => file.file,
-            None => std::ptr::null_mut(),
-        };
-        let arg = match arg {
-            Some(arg) => arg,
-            None => std::ptr::null_mut(),
-        };
-        match unsafe { crate::dtrace_work(self.handle, file, p_hldr, r_hldr, arg) } {
-            crate::dtrace_workstatus_t::DTRACE_WORKSTATUS_ERROR => {
-                Err(Error::from(self))
-            }
-            status => Ok(status),
-        }
+    /// Returns a [`Statement`](crate::statement::Statement) for every statement, in program order.
+    pub fn program_statements<'a>(
+        &'a self,
+        program: &'a mut crate::dtrace_prog,
+    ) -> Result<Vec<crate::statement::Statement<'a>>, Error> {
+        let mut stmts: Vec<*mut crate::dtrace_stmtdesc_t> = Vec::new();
+        self.dtrace_stmt_iter(
+            program,
+            Some(crate::statement::collect_stmt),
+            Some(&mut stmts as *mut _ as *mut ::core::ffi::c_void),
+        )?;
+        Ok(stmts
+            .into_iter()
+            .map(|stmt| unsafe { crate::statement::Statement::new(self, stmt) })
+            .collect())
     }
 
-    /* Data Consumption APIs END */
-
-    /* Handler APIs START */
-    /// Sets a handler functions for processing trace data.
-    /// 
-    /// # Arguments
-    /// 
-    /// * `handler` - An enum variant from [`dtrace_handler`] representing the handler function to be called for each trace record. Possible values:
-    ///     * `Buffered(handler)` - The handler function to be called for each buffered trace record.
-    ///         * If [`None`] is passed to `dtrace_work`, `dtrace_consume` or `dtrace_aggregate_print` function, then libdtrace makes use of the buffered I/O handler to process buffered trace data.
-    ///         * The handler function must have the following signature:
-    ///             ```rs
-    ///                 unsafe extern "C" fn(*const dtrace_bufdata_t, *mut c_void) -> c_int
-    ///             ```
-    ///     * `Drop(handler)` - The handler function to be called for each dropped trace record.
-    ///         * The handler function must have the following signature:
-    ///             ```rs
-    ///                 unsafe extern "C" fn(*const dtrace_dropdata_t, *mut c_void) -> c_int
-    ///             ```
-    ///     * `Err(handler)` - To register a handler function for processing errors such as accessing an invalid address or dividing by zero.
-    ///         * The handler function must have the following signature:
-    ///             ```rs
-    ///                 unsafe extern "C" fn(*const dtrace_errdata_t, *mut c_void) -> c_int
-    ///             ```
-    ///     * `SetOpt(handler)` - This handler is called whenever a DTrace option is set from inside a D program.
-    ///         * The handler function must have the following signature:
-    ///             ```rs
-    ///                 unsafe extern "C" fn(*const dtrace_setoptdata_t, *mut c_void) -> c_int
-    ///             ```
-    ///     * `Proc(handler)` - Unsupported.
-    /// * `arg` - An optional argument to be passed to the handler function. This argument can maintain any state between successive invocations of the handler function.
-    /// 
+    /// Lists every probe known to the instance with its stability and the native types of its arguments, like
+    /// `dtrace -lv`.
+    ///
     /// # Returns
-    /// 
-    /// Returns `Ok(())` if the handler was set successfully, or an error code if the handler could
-    /// not be set.
-    pub fn dtrace_register_handler(
-        &self,
-        handler: crate::types::dtrace_handler,
-        arg: Option<*mut ::core::ffi::c_void>,
-    ) -> Result<(), Error> {
-        let status;
-        let arg = match arg {
-            Some(arg) => arg,
-            None => std::ptr::null_mut(),
-        };
-
-        unsafe {
-            status = match handler {
-                crate::types::dtrace_handler::Buffered(handler) => {
-                    crate::dtrace_handle_buffered(self.handle, handler, arg)
-                }
-                crate::types::dtrace_handler::Drop(handler) => {
-                    crate::dtrace_handle_drop(self.handle, handler, arg)
-                }
-                crate::types::dtrace_handler::Err(handler) => {
-                    crate::dtrace_handle_err(self.handle, handler, arg)
-                }
-                crate::types::dtrace_handler::SetOpt(handler) => {
-                    crate::dtrace_handle_setopt(self.handle, handler, arg)
-                }
-                crate::types::dtrace_handler::Proc(handler) => {
-                    crate::dtrace_handle_proc(self.handle, handler, arg)
+    ///
+    /// Returns a [`CatalogEntry`](crate::probe::CatalogEntry) for every probe, ordered by probe ID, or the error
+    /// listing the probes. Probes whose information libdtrace cannot read, such as probes removed since they were
+    /// listed, are left out.
+    pub fn probe_catalog(&self) -> Result<Vec<crate::probe::CatalogEntry>, Error> {
+        let all: crate::dtrace_probedesc_t = unsafe { std::mem::zeroed() };
+        let mut catalog: Vec<crate::probe::CatalogEntry> = self
+            .matching_probes(&all)?
+            .iter()
+            .filter_map(|probe| {
+                let mut info: crate::dtrace_probeinfo_t = unsafe { std::mem::zeroed() };
+                if unsafe { crate::dtrace_probe_info(self.handle, probe, &mut info) } != 0 {
+                    return None;
                 }
-            };
-        }
-
-        if status == 0 {
-            Ok(())
-        } else {
-            Err(Error::from(self))
-        }
+                let args = if info.dtp_argv.is_null() {
+                    &[][..]
+                } else {
+                    unsafe { std::slice::from_raw_parts(info.dtp_argv, info.dtp_argc.max(0) as usize) }
+                };
+                Some(crate::probe::CatalogEntry {
+                    listing: crate::probe::ListedProbe {
+                        probe: crate::probe::ProbeDescription::from(probe),
+                        attributes: crate::probe::Attributes::from(&info.dtp_attr),
+                        arg_attributes: crate::probe::Attributes::from(&info.dtp_arga),
+                    },
+                    args: args
+                        .iter()
+                        .map(|arg| {
+                            if arg.dtt_ctfp.is_null() {
+                                String::new()
+                            } else {
+                                unsafe { crate::typeinfo::type_name(arg.dtt_ctfp, arg.dtt_type) }
+                            }
+                        })
+                        .collect(),
+                })
+            })
+            .collect();
+        catalog.sort_by_key(|entry| entry.listing.probe.id);
+        Ok(catalog)
     }
 
-    /* Handler APIs END */
-
-    /* Aggregation APIs START */
-    /// Retrieves aggregation data from the kernel
+    /// Writes the catalog of probes of the instance (see `probe_catalog`) to `writer`, for external tools to browse.
     ///
-    /// This function is called to transfer data from the in-kernel aggregation buffers to the userspace (consumer). The data is not processed at this point.
+    /// # Arguments
+    ///
+    /// * `writer` - Where to write the catalog.
+    /// * `format` - The format of the catalog, one of the members of the [`CatalogFormat`](crate::probe::CatalogFormat) enum.
     ///
     /// # Returns
     ///
-    /// * `Ok(())` - If the aggregation data is successfully retrieved.
-    /// * `Err(errno)` - If the aggregation data could not be retrieved. The error number (`errno`) is returned.
-    pub fn dtrace_aggregate_snap(&self) -> Result<(), Error> {
-        match unsafe { crate::dtrace_aggregate_snap(self.handle) } {
-            0 => Ok(()),
-            _ => Err(Error::from(self)),
+    /// Returns the number of probes written, or the error writing them.
+    pub fn export_probe_catalog(
+        &self,
+        mut writer: impl std::io::Write,
+        format: crate::probe::CatalogFormat,
+    ) -> Result<usize, Error> {
+        let catalog = self.probe_catalog()?;
+        match format {
+            crate::probe::CatalogFormat::Json => {
+                writer.write_all(b"[")?;
+                for (i, entry) in catalog.iter().enumerate() {
+                    writer.write_all(if i == 0 { b"\n" } else { b",\n" })?;
+                    entry.write_json(&mut writer)?;
+                }
+                writer.write_all(b"\n]\n")?;
+            }
+            crate::probe::CatalogFormat::Csv => {
+                writeln!(writer, "{}", crate::probe::CatalogEntry::CSV_HEADER)?;
+                for entry in &catalog {
+                    entry.write_csv(&mut writer)?;
+                    writeln!(writer)?;
+                }
+            }
         }
+        writer.flush()?;
+        Ok(catalog.len())
     }
 
-    /// Processes DTrace aggregate data.
+    /// Lists the instruction offsets of a function of a process that pid probes can instrument, like
+    /// `dtrace -l -n 'pid<pid>:<module>:<function>:'`.
     ///
-    /// The function can be passed a specific `walk()` function. If passed `None`, it defaults to the `dtrace_aggregate_walk_sorted()` function,
-    /// and the callback function passed to the `walk()` function is the default function that the libdtrace library uses to print aggregate data.
+    /// Compiling a description of the function creates its offset probes without enabling them, so they can be listed.
     ///
     /// # Arguments
     ///
-    /// * `file` - An optional file handle for output.
-    /// * `handler` - A function pointer that is called for each aggregate buffer that is processed.
-    /// * `arg` - An optional argument to be passed to the `handler` function. This argument can maintain any state between successive invocations of the function.
+    /// * `pid` - The ID of the process.
+    /// * `module` - The module defining the function, e.g. `ntdll.dll`.
+    /// * `function` - The name of the function.
+    ///
+    /// # Returns
+    ///
+    /// Returns the offsets in increasing order, usable with [`PidProbe::offset`](crate::pid::PidProbe::offset), or an
+    /// error if the function could not be instrumented.
+    pub fn pid_offsets(&self, pid: crate::pid_t, module: &str, function: &str) -> Result<Vec<u64>, Error> {
+        let description = format!("pid{}:{}:{}:", pid, module, function);
+        let program = self
+            .compile(&format!("{} {{}}", description), &crate::compile::CompileOptions::new())
+            .map_err(|error| error.with_context(format!("listing the probes of `{}`", description)))?;
+        let probes = self.program_probes(program);
+        unsafe { self.discard_program(program) };
+
+        let mut offsets: Vec<u64> = probes?
+            .iter()
+            .filter_map(|probe| match crate::pid::PidSite::parse(&probe.name) {
+                Some(crate::pid::PidSite::Offset(offset)) => Some(offset),
+                _ => None,
+            })
+            .collect();
+        offsets.sort_unstable();
+        Ok(offsets)
+    }
+
+    /// Frees a compiled program that is not going to be enabled, which the handle would keep until it is closed
+    /// otherwise.
+    ///
+    /// # Safety
+    ///
+    /// `program` must have been compiled by this handle, not be enabled, and not be used afterwards.
+    pub unsafe fn discard_program(&self, program: &mut crate::dtrace_prog) {
+        let _guard = self.state_lock.lock().unwrap();
+        let program = program as *mut crate::dtrace_prog;
+        self.compiled.lock().unwrap().remove(&(program as usize));
+        crate::dt_program_destroy(self.handle, program);
+    }
+
+    /// Retrieves the probe description and its attributes of every statement of `program`.
+    fn program_descriptions(
+        &self,
+        program: &mut crate::dtrace_prog,
+    ) -> Result<Vec<(crate::dtrace_probedesc_t, crate::dtrace_attribute_t)>, Error> {
+        let mut descs: Vec<(crate::dtrace_probedesc_t, crate::dtrace_attribute_t)> = Vec::new();
+        self.dtrace_stmt_iter(
+            program,
+            Some(crate::probe::collect_stmt_probe),
+            Some(&mut descs as *mut _ as *mut ::core::ffi::c_void),
+        )?;
+        Ok(descs)
+    }
+
+    /// Records the clause and the matching probes of every ECB of an enabled program, to attribute faults to them.
+    ///
+    /// libdtrace compiles each probe description of each clause into one ECB description in source order, so the
+    /// ECBs of the program are matched to the clauses of `source` in order. If they do not line up, e.g. for a
+    /// program compiled from a file, the ECBs are recorded without their source.
+    fn record_statements(&self, program: &mut crate::dtrace_prog, source: Option<&str>) {
+        let mut stmts: Vec<(usize, crate::dtrace_probedesc_t)> = Vec::new();
+        if self
+            .dtrace_stmt_iter(
+                program,
+                Some(crate::probe::collect_stmt_ecb),
+                Some(&mut stmts as *mut _ as *mut ::core::ffi::c_void),
+            )
+            .is_err()
+        {
+            return;
+        }
+        stmts.dedup_by_key(|(ecb, _)| *ecb);
+
+        let clauses: Vec<crate::script::Clause> = source
+            .map(crate::script::clauses)
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|clause| std::iter::repeat_n(clause.clone(), clause.descriptions))
+            .collect();
+        let clauses = (clauses.len() == stmts.len()).then_some(clauses);
+
+        let mut statements = self.statements.lock().unwrap();
+        let program = statements.last().map_or(0, |statement| statement.program + 1);
+        for (ecb, (_, desc)) in stmts.iter().enumerate() {
+            let clause = clauses.as_ref().map(|clauses| &clauses[ecb]);
+            statements.push(StatementSource {
+                program,
+                ecb,
+                description: crate::probe::ProbeDescription::from(desc),
+                line: clause.map(|clause| clause.line),
+                clause: clause.map(|clause| clause.header.clone()),
+                probes: self
+                    .matching_probes(desc)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|probe| probe.dtpd_id)
+                    .collect(),
+            });
+        }
+    }
+
+    /// Lists the probes matching `desc`. libdtrace fails when nothing matches, which yields no probes; any other
+    /// failure is returned.
+    fn matching_probes(&self, desc: &crate::dtrace_probedesc_t) -> Result<Vec<crate::dtrace_probedesc_t>, Error> {
+        let mut probes: Vec<crate::dtrace_probedesc_t> = Vec::new();
+        let status = unsafe {
+            crate::dtrace_probe_iter(
+                self.handle,
+                desc,
+                Some(crate::probe::collect_probe),
+                &mut probes as *mut _ as *mut ::core::ffi::c_void,
+            )
+        };
+        if status != 0 {
+            let error = Error::from(self);
+            if !error.is_no_match() {
+                return Err(error);
+            }
+        }
+        Ok(probes)
+    }
+
+    /// Creates the DOF (DTrace Object Format) of a compiled program, the form programs are handed to the kernel in.
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - The compiled program.
+    /// * `flags` - `DTRACE_D_*` flags, `0` by default.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<u8>)` - The DOF of the program.
+    /// * `Err(Error)` - If the DOF could not be created.
+    pub fn dtrace_dof_create(&self, program: &mut crate::dtrace_prog, flags: u32) -> Result<Vec<u8>, Error> {
+        let dof = unsafe { crate::dtrace_dof_create(self.handle, program, flags) };
+        self.take_dof(dof).ok_or_else(|| Error::from(self).with_context("creating DOF"))
+    }
+
+    /// Creates the DOF holding the options set on the handle, which apply to the programs enabled with it.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<u8>)` - The DOF of the options.
+    /// * `Err(Error)` - If the DOF could not be created.
+    pub fn dtrace_getopt_dof(&self) -> Result<Vec<u8>, Error> {
+        let dof = unsafe { crate::dtrace_getopt_dof(self.handle) };
+        self.take_dof(dof)
+            .ok_or_else(|| Error::from(self).with_context("creating option DOF"))
+    }
+
+    /// Links the USDT provider definitions of a compiled program against application object files, like `dtrace -G`:
+    /// the probe sites of the objects are recorded and an object file holding the DOF section is written, to link
+    /// into the application.
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - The compiled provider definitions.
+    /// * `flags` - `DTRACE_D_*` flags, e.g. `DTRACE_D_STRIP` to leave the object files' probe sites untouched.
+    /// * `file` - The path of the object file to write.
+    /// * `objects` - The paths of the application object files containing the probe sites.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the object file was written.
+    /// * `Err(Error)` - If the program could not be linked.
+    pub fn dtrace_program_link(
+        &self,
+        program: &mut crate::dtrace_prog,
+        flags: u32,
+        file: &str,
+        objects: &[&str],
+    ) -> Result<(), Error> {
+        let path = std::ffi::CString::new(file).unwrap();
+        // Keep the paths alive until the program is linked
+        let objects: Vec<std::ffi::CString> =
+            objects.iter().map(|object| std::ffi::CString::new(*object).unwrap()).collect();
+        let objv: Vec<*mut ::core::ffi::c_char> = objects
+            .iter()
+            .map(|object| object.as_ptr() as *mut ::core::ffi::c_char)
+            .collect();
+        match unsafe {
+            crate::dtrace_program_link(
+                self.handle,
+                program,
+                flags,
+                path.as_ptr(),
+                objv.len() as c_int,
+                objv.as_ptr(),
+            )
+        } {
+            0 => Ok(()),
+            _ => Err(Error::from(self).with_context(format!("linking `{}`", file))),
+        }
+    }
+
+    /// Copies a DOF created by libdtrace and frees it.
+    fn take_dof(&self, dof: *mut ::core::ffi::c_void) -> Option<Vec<u8>> {
+        if dof.is_null() {
+            return None;
+        }
+        let bytes = unsafe {
+            let size = (*(dof as *const crate::dof_hdr_t)).dofh_loadsz as usize;
+            std::slice::from_raw_parts(dof as *const u8, size).to_vec()
+        };
+        unsafe { crate::dtrace_dof_destroy(self.handle, dof) };
+        Some(bytes)
+    }
+
+    /* Programming APIs END */
+
+    /* Process Control APIs START */
+    /// Creates a process in a stopped state, to be traced as `$target`.
+    ///
+    /// The process is resumed with `dtrace_proc_continue`, usually after `dtrace_go`. It must be created before the
+    /// programs referring to `$target` are compiled.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The executable to run.
+    /// * `args` - The arguments passed to the process, not including the executable.
+    ///
+    /// # Returns
+    ///
+    /// Returns the handle of the process, or an error code if the process could not be created.
+    pub fn dtrace_proc_create(
+        &self,
+        file: &str,
+        args: &[String],
+    ) -> Result<*mut crate::ps_prochandle, Error> {
+        let file = std::ffi::CString::new(file).unwrap();
+        let args: Vec<std::ffi::CString> = args
+            .iter()
+            .map(|arg| std::ffi::CString::new(arg.as_str()).unwrap())
+            .collect();
+        let mut argv: Vec<*mut ::core::ffi::c_char> = vec![file.as_ptr() as *mut _];
+        argv.extend(args.iter().map(|arg| arg.as_ptr() as *mut ::core::ffi::c_char));
+        argv.push(std::ptr::null_mut());
+
+        let proc = unsafe {
+            crate::dtrace_proc_create(self.handle, file.as_ptr(), argv.as_ptr(), None, std::ptr::null_mut())
+        };
+        if proc.is_null() {
+            return Err(Error::from(self));
+        }
+        Ok(proc)
+    }
+
+    /// Creates a process like `dtrace_proc_create` and places it in a Job Object, so the process and the processes
+    /// it creates are killed when the job is closed, even if the consumer crashes.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The executable to run.
+    /// * `args` - The arguments passed to the process, not including the executable.
+    /// * `job` - The job to place the process in, see [`JobObject`](crate::job::JobObject).
+    ///
+    /// # Returns
+    ///
+    /// Returns the handle of the process, or an error if the process could not be created or placed in the job.
+    /// The process stays stopped until it is resumed with `dtrace_proc_continue`, so it cannot create processes
+    /// outside the job.
+    pub fn proc_create_in_job(
+        &self,
+        file: &str,
+        args: &[String],
+        job: &crate::job::JobObject,
+    ) -> Result<*mut crate::ps_prochandle, Error> {
+        self.proc_create_with_pid(file, args, Some(job)).map(|(proc, _)| proc)
+    }
+
+    /// Creates a process like `dtrace_proc_create`, also returning its ID, which libdtrace does not report.
+    ///
+    /// The process is found among the processes created by the consumer while libdtrace creates it.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The executable to run.
+    /// * `args` - The arguments passed to the process, not including the executable.
+    /// * `job` - A job to place the process in, see `proc_create_in_job`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the handle of the process and its ID, `None` if it could not be told apart from other processes of the
+    /// same executable created meanwhile. Fails if the process could not be created, or could not be found or placed
+    /// in `job`. Only the process is placed in `job`, not the other processes created meanwhile.
+    pub fn proc_create_with_pid(
+        &self,
+        file: &str,
+        args: &[String],
+        job: Option<&crate::job::JobObject>,
+    ) -> Result<(*mut crate::ps_prochandle, Option<u32>), Error> {
+        let before = crate::job::child_processes()?;
+        let proc = self.dtrace_proc_create(file, args)?;
+        let after = match crate::job::child_processes() {
+            Ok(after) => after,
+            Err(err) => {
+                unsafe { self.dtrace_proc_release(proc) };
+                return Err(err);
+            }
+        };
+        // Other threads of the consumer may create processes meanwhile, so only the new processes running the
+        // executable of the target are candidates.
+        let created: Vec<u32> = after
+            .into_iter()
+            .filter(|(pid, exe)| !before.contains_key(pid) && crate::job::runs_file(exe, file))
+            .map(|(pid, _)| pid)
+            .collect();
+        let pid = match created[..] {
+            [pid] => Some(pid),
+            _ => None,
+        };
+        if let Some(job) = job {
+            let assigned = match (pid, created.is_empty()) {
+                (Some(pid), _) => job.assign(pid),
+                (None, true) => Err(Error::new(-1, format!("The process created for `{}` was not found", file))),
+                (None, false) => Err(Error::new(
+                    -1,
+                    format!("The process created for `{}` cannot be told apart from other new processes", file),
+                )),
+            };
+            if let Err(err) = assigned {
+                unsafe { self.dtrace_proc_release(proc) };
+                return Err(err);
+            }
+        }
+        Ok((proc, pid))
+    }
+
+    /// Grabs a running process, to be traced as `$target`.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The ID of the process.
+    /// * `flags` - Flags passed to libproc when grabbing the process, usually `0`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the handle of the process, or an error code if the process could not be grabbed.
+    pub fn dtrace_proc_grab(
+        &self,
+        pid: crate::pid_t,
+        flags: c_int,
+    ) -> Result<*mut crate::ps_prochandle, Error> {
+        let proc = unsafe { crate::dtrace_proc_grab(self.handle, pid, flags) };
+        if proc.is_null() {
+            return Err(Error::from(self));
+        }
+        Ok(proc)
+    }
+
+    /// Resumes a process created with `dtrace_proc_create` or grabbed with `dtrace_proc_grab`.
+    ///
+    /// # Safety
+    ///
+    /// `proc` must be a process handle returned by this DTrace instance that was not released.
+    pub unsafe fn dtrace_proc_continue(&self, proc: *mut crate::ps_prochandle) {
+        crate::dtrace_proc_continue(self.handle, proc)
+    }
+
+    /// Releases a process. A created process is killed, a grabbed process resumes running untraced.
+    ///
+    /// # Safety
+    ///
+    /// `proc` must be a process handle returned by this DTrace instance that was not released.
+    pub unsafe fn dtrace_proc_release(&self, proc: *mut crate::ps_prochandle) {
+        crate::dtrace_proc_release(self.handle, proc)
+    }
+    /* Process Control APIs END */
+
+    /* Symbol Lookup APIs START */
+    /// Sets the runtime options controlling how stacks are recorded and printed.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The options to set, options left to `None` are not changed.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if all options were set, or the error of the first option that could not be set.
+    pub fn set_stack_options(&mut self, options: &crate::symbol::StackOptions) -> Result<(), Error> {
+        for (option, value) in options.options() {
+            self.dtrace_setopt(option, &value)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the number of frames recorded by `stack()` without an argument, the `stackframes` option.
+    ///
+    /// Stacks deeper than `frames` are cut off, see `Stack::truncated`.
+    pub fn set_stackframes(&mut self, frames: u32) -> Result<(), Error> {
+        self.set_frames_option("stackframes", frames)
+    }
+
+    /// Sets the number of frames recorded by `ustack()` without an argument, the `ustackframes` option.
+    pub fn set_ustackframes(&mut self, frames: u32) -> Result<(), Error> {
+        self.set_frames_option("ustackframes", frames)
+    }
+
+    /// Sets the number of frames recorded by `jstack()` without an argument, the `jstackframes` option.
+    pub fn set_jstackframes(&mut self, frames: u32) -> Result<(), Error> {
+        self.set_frames_option("jstackframes", frames)
+    }
+
+    fn set_frames_option(&mut self, option: &str, frames: u32) -> Result<(), Error> {
+        if frames == 0 {
+            return Err(Error::new(-1, format!("The {} option must be at least 1", option)));
+        }
+        self.dtrace_setopt(option, &frames.to_string())
+    }
+
+    /// Looks up the kernel module and symbol containing an address.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address to look up.
+    /// * `sym` - Receives the symbol containing the address. If `None`, only the module is looked up.
+    ///
+    /// # Returns
+    ///
+    /// Returns the names of the module and symbol, or an error if no module, or no symbol if `sym` is given, contains
+    /// the address.
+    pub fn dtrace_lookup_by_addr(
+        &self,
+        addr: u64,
+        sym: Option<&mut crate::GElf_Sym>,
+    ) -> Result<crate::dtrace_syminfo_t, Error> {
+        let sym = match sym {
+            Some(sym) => sym as *mut crate::GElf_Sym,
+            None => std::ptr::null_mut(),
+        };
+        let mut info: crate::dtrace_syminfo_t = unsafe { std::mem::zeroed() };
+        match unsafe { crate::dtrace_lookup_by_addr(self.handle, addr, sym, &mut info) } {
+            0 => Ok(info),
+            _ => Err(Error::from(self)),
+        }
+    }
+
+    /// Resolves a kernel address to the module and symbol containing it.
+    ///
+    /// Addresses that cannot be resolved are returned with the names left to `None`.
+    pub fn lookup_symbol(&self, addr: u64) -> crate::symbol::Symbol {
+        let name = |name: *const ::core::ffi::c_char| {
+            (!name.is_null())
+                .then(|| unsafe { ::core::ffi::CStr::from_ptr(name) }.to_string_lossy().into_owned())
+        };
+        let mut sym: crate::GElf_Sym = unsafe { std::mem::zeroed() };
+        if let Ok(info) = self.dtrace_lookup_by_addr(addr, Some(&mut sym)) {
+            return crate::symbol::Symbol {
+                address: addr,
+                module: name(info.dts_object),
+                name: name(info.dts_name),
+                offset: addr.saturating_sub(sym.st_value),
+            };
+        }
+        crate::symbol::Symbol {
+            address: addr,
+            module: self
+                .dtrace_lookup_by_addr(addr, None)
+                .ok()
+                .and_then(|info| name(info.dts_object)),
+            name: None,
+            offset: 0,
+        }
+    }
+
+    /// Formats the frames of a kernel stack, as decoded by [`stack_frames`](crate::symbol::stack_frames).
+    ///
+    /// # Arguments
+    ///
+    /// * `frames` - The addresses of the frames.
+    /// * `format` - Whether to resolve the frames to symbols like dtrace(1), or to keep the raw addresses.
+    ///
+    /// # Returns
+    ///
+    /// Returns one line per frame, without indentation.
+    pub fn symbolize_stack(&self, frames: &[u64], format: crate::symbol::StackFormat) -> Vec<String> {
+        frames
+            .iter()
+            .map(|&pc| match format {
+                crate::symbol::StackFormat::Symbols => self.lookup_symbol(pc).to_string(),
+                crate::symbol::StackFormat::Raw => format!("0x{:x}", pc),
+            })
+            .collect()
+    }
+
+    /// Formats a user address of a process as its module, symbol and offset.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The process the address belongs to.
+    /// * `addr` - The address to format.
+    ///
+    /// # Returns
+    ///
+    /// Returns `module`symbol+0xoffset`, or the raw address if it cannot be resolved.
+    pub fn dtrace_uaddr2str(&self, pid: crate::pid_t, addr: u64) -> String {
+        let mut buf = [0 as ::core::ffi::c_char; 1024];
+        unsafe {
+            crate::dtrace_uaddr2str(self.handle, pid, addr, buf.as_mut_ptr(), buf.len() as c_int);
+            ::core::ffi::CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned()
+        }
+    }
+
+    /// Builds the map of the symbols containing `addresses`, e.g. addresses captured by a ring-buffer session, while
+    /// the modules of the process are still loaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The process the addresses belong to, or `None` for kernel addresses.
+    /// * `addresses` - The addresses to resolve. Addresses that cannot be resolved to a symbol are left out.
+    ///
+    /// # Returns
+    ///
+    /// Returns the [`SymbolMap`](crate::symbol::SymbolMap), which can be written as a perf map. Kernel symbols have
+    /// their full size; user symbols span up to the highest address resolved to them, as libdtrace does not report
+    /// the size of user symbols.
+    pub fn symbol_map(&self, pid: Option<crate::pid_t>, addresses: &[u64]) -> crate::symbol::SymbolMap {
+        let mut symbols: HashMap<u64, crate::symbol::MappedSymbol> = HashMap::new();
+        for &addr in addresses {
+            let symbol = match pid {
+                Some(pid) => {
+                    let text = self.dtrace_uaddr2str(pid, addr);
+                    let Some((module, name, offset)) = crate::symbol::parse_uaddr(&text) else {
+                        continue;
+                    };
+                    crate::symbol::MappedSymbol {
+                        start: addr.wrapping_sub(offset),
+                        size: offset + 1,
+                        module: module.to_string(),
+                        name: name.to_string(),
+                    }
+                }
+                None => {
+                    let mut sym: crate::GElf_Sym = unsafe { std::mem::zeroed() };
+                    let Ok(info) = self.dtrace_lookup_by_addr(addr, Some(&mut sym)) else {
+                        continue;
+                    };
+                    if info.dts_object.is_null() || info.dts_name.is_null() {
+                        continue;
+                    }
+                    crate::symbol::MappedSymbol {
+                        start: sym.st_value,
+                        size: sym.st_size.max(addr.saturating_sub(sym.st_value) + 1),
+                        module: unsafe { ::core::ffi::CStr::from_ptr(info.dts_object) }.to_string_lossy().into_owned(),
+                        name: unsafe { ::core::ffi::CStr::from_ptr(info.dts_name) }.to_string_lossy().into_owned(),
+                    }
+                }
+            };
+            let entry = symbols.entry(symbol.start).or_insert_with(|| symbol.clone());
+            entry.size = entry.size.max(symbol.size);
+        }
+
+        let mut symbols: Vec<crate::symbol::MappedSymbol> = symbols.into_values().collect();
+        symbols.sort_by_key(|symbol| symbol.start);
+        crate::symbol::SymbolMap { pid, symbols }
+    }
+
+    /// Decodes the record of a `sym()`, `func()`, `mod()`, `usym()`, `ufunc()`, `umod()` or `uaddr()` action, resolving
+    /// the recorded address like dtrace(1) prints it.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The probe data passed to a record callback.
+    /// * `rec` - The record passed to the record callback.
+    ///
+    /// # Returns
+    ///
+    /// Returns the decoded record, or `None` if `rec` is not a symbol-printing record.
+    ///
+    /// # Safety
+    ///
+    /// `data` and `rec` must be the arguments of a record callback of this handle, or null.
+    pub unsafe fn decode_symbol(
+        &self,
+        data: *const crate::dtrace_probedata_t,
+        rec: *const crate::dtrace_recdesc_t,
+    ) -> Option<crate::symbol::SymbolRef> {
+        use crate::symbol::{SymbolAction, SymbolRef};
+
+        if data.is_null() || rec.is_null() {
+            return None;
+        }
+        let rec = &*rec;
+        let action = SymbolAction::from_action(rec.dtrd_action)?;
+        // libdtrace points the data of the firing at the record it passes.
+        let addr = (*data).dtpda_data as *const u64;
+
+        if !action.is_user() {
+            let address = addr.read_unaligned();
+            let symbol = self.lookup_symbol(address);
+            let name = match action {
+                SymbolAction::Mod => symbol.module,
+                _ => symbol.module.map(|module| match symbol.name {
+                    Some(name) => format!("{}`{}", module, name),
+                    None => format!("{}`0x{:x}", module, address),
+                }),
+            };
+            return Some(SymbolRef {
+                action,
+                pid: None,
+                address,
+                name,
+            });
+        }
+
+        // User addresses are recorded after the ID of the process.
+        let pid = addr.read_unaligned() as crate::pid_t;
+        let address = addr.add(1).read_unaligned();
+        let resolved = self.dtrace_uaddr2str(pid, address);
+        let name = match action {
+            _ if resolved.starts_with("0x") => None,
+            SymbolAction::UMod => resolved.split('`').next().map(str::to_string),
+            SymbolAction::USym => resolved.split('+').next().map(str::to_string),
+            _ => Some(resolved),
+        };
+        Some(SymbolRef {
+            action,
+            pid: Some(pid),
+            address,
+            name,
+        })
+    }
+
+    /// Looks up a C type by name in the CTF data of the modules.
+    ///
+    /// # Arguments
+    ///
+    /// * `object` - The module to search, or `None` to search every module.
+    /// * `name` - The name of the type, e.g. `struct _EPROCESS`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the type found, or an error if no module defines it.
+    pub fn dtrace_lookup_by_type(&self, object: Option<&str>, name: &str) -> Result<crate::dtrace_typeinfo_t, Error> {
+        // DTRACE_OBJ_EVERY, a sentinel pointer
+        const OBJ_EVERY: *const ::core::ffi::c_char = usize::MAX as *const ::core::ffi::c_char;
+        let object = object.map(|object| std::ffi::CString::new(object).unwrap());
+        let name = std::ffi::CString::new(name).unwrap();
+        let mut info: crate::dtrace_typeinfo_t = unsafe { std::mem::zeroed() };
+        let object_ptr = object.as_ref().map_or(OBJ_EVERY, |object| object.as_ptr());
+        match unsafe { crate::dtrace_lookup_by_type(self.handle, object_ptr, name.as_ptr(), &mut info) } {
+            0 => Ok(info),
+            _ => Err(Error::from(self)),
+        }
+    }
+
+    /// Looks up the C type of a symbol found by `dtrace_lookup_by_addr`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the type of the symbol, or an error if the module has no type data for it.
+    pub fn dtrace_symbol_type(
+        &self,
+        sym: &crate::GElf_Sym,
+        info: &crate::dtrace_syminfo_t,
+    ) -> Result<crate::dtrace_typeinfo_t, Error> {
+        let mut tinfo: crate::dtrace_typeinfo_t = unsafe { std::mem::zeroed() };
+        match unsafe { crate::dtrace_symbol_type(self.handle, sym, info, &mut tinfo) } {
+            0 => Ok(tinfo),
+            _ => Err(Error::from(self)),
+        }
+    }
+
+    /// Describes a C type by name, e.g. to display the layout of a struct.
+    ///
+    /// ```no_run
+    /// # let handle: libdtrace_rs::wrapper::dtrace_hdl = unimplemented!();
+    /// let eprocess = handle.lookup_type(Some("nt"), "struct _EPROCESS").unwrap();
+    /// println!("{}", eprocess);
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `object` - The module to search, or `None` to search every module.
+    /// * `name` - The name of the type.
+    ///
+    /// # Returns
+    ///
+    /// Returns the description of the type, or an error if no module defines it.
+    pub fn lookup_type(&self, object: Option<&str>, name: &str) -> Result<crate::typeinfo::TypeInfo, Error> {
+        let info = self
+            .dtrace_lookup_by_type(object, name)
+            .map_err(|error| error.with_context(format!("looking up type `{}`", name)))?;
+        Ok(unsafe { crate::typeinfo::TypeInfo::from_raw(&info) })
+    }
+
+    /// Describes the C type of the kernel symbol containing an address, e.g. a global variable or a function.
+    ///
+    /// # Returns
+    ///
+    /// Returns the description of the type, or an error if the address cannot be resolved or has no type data.
+    pub fn symbol_type(&self, addr: u64) -> Result<crate::typeinfo::TypeInfo, Error> {
+        let mut sym: crate::GElf_Sym = unsafe { std::mem::zeroed() };
+        let info = self.dtrace_lookup_by_addr(addr, Some(&mut sym))?;
+        let tinfo = self.dtrace_symbol_type(&sym, &info)?;
+        Ok(unsafe { crate::typeinfo::TypeInfo::from_raw(&tinfo) })
+    }
+
+    /// Describes the native types of the arguments of a probe, the types behind `args[0]`, `args[1]`, ... of e.g. an
+    /// `fbt` probe.
+    ///
+    /// # Arguments
+    ///
+    /// * `probe` - The probe, as listed by `program_probes`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the type of each argument, `None` for arguments without type data, or an error if the probe does
+    /// not exist.
+    pub fn probe_arg_types(
+        &self,
+        probe: &crate::probe::ProbeDescription,
+    ) -> Result<Vec<Option<crate::typeinfo::TypeInfo>>, Error> {
+        let desc = probe.to_raw();
+        let mut info: crate::dtrace_probeinfo_t = unsafe { std::mem::zeroed() };
+        if unsafe { crate::dtrace_probe_info(self.handle, &desc, &mut info) } != 0 {
+            return Err(Error::from(self).with_context(format!("looking up probe `{}`", probe)));
+        }
+        if info.dtp_argv.is_null() {
+            return Ok(Vec::new());
+        }
+        let args = unsafe { std::slice::from_raw_parts(info.dtp_argv, info.dtp_argc.max(0) as usize) };
+        Ok(args
+            .iter()
+            .map(|arg| (!arg.dtt_ctfp.is_null()).then(|| unsafe { crate::typeinfo::TypeInfo::from_raw(arg) }))
+            .collect())
+    }
+
+    /* Symbol Lookup APIs END */
+
+    /* Data Consumption APIs START */
+    /// Determines the status of the running DTrace instance.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(dtrace_status)` - If the status is successfully determined.
+    /// * `Err(errno)` - If the status could not be determined.
+    pub fn dtrace_status(&mut self) -> Result<dtrace_status, Error> {
+        match self.capture_clock(|| unsafe { crate::dtrace_status(self.handle) }) {
+            -1 => Err(Error::from(&*self)),
+            status => {
+                let status = dtrace_status::from(status as u32);
+                self.status_watch.lock().unwrap().update(status, self.drop_counts());
+                Ok(status)
+            }
+        }
+    }
+
+    /// Watches the status of tracing, instead of polling `dtrace_status` and comparing the results.
+    ///
+    /// The watchers are notified when the status changes, e.g. from `Ok` to `Filled`, `Stopped` or `Exited`, and when
+    /// the drop counts of the default drop handler (see `register_default_drop_handler`) increase. The status is
+    /// checked on every call to `dtrace_work` and `dtrace_status`.
+    ///
+    /// ```no_run
+    /// use libdtrace_rs::diagnostics::StatusEvent;
+    ///
+    /// # let mut handle: libdtrace_rs::wrapper::dtrace_hdl = unimplemented!();
+    /// let status = handle.watch_status();
+    /// std::thread::spawn(move || {
+    ///     for event in status {
+    ///         if let StatusEvent::Drops { previous, current } = event {
+    ///             eprintln!("{} records dropped", current.total() - previous.total());
+    ///         }
+    ///     }
+    /// });
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// Returns the receiver of the [`StatusEvent`](crate::diagnostics::StatusEvent)s, which ends when the handle is
+    /// closed. Dropping it stops watching.
+    pub fn watch_status(&self) -> std::sync::mpsc::Receiver<crate::diagnostics::StatusEvent> {
+        self.status_watch.lock().unwrap().subscribe()
+    }
+
+    /// Consumes data from the principal buffers.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - An optional destination of the output, which goes to the buffered handler otherwise, see [`OutputSink`](crate::output::OutputSink).
+    /// * `p_hldr` - A pointer to a function that processes an `enabling control block (ECB)`. An `ECB` is a clause from a D program associated with the enabled probe.
+    /// * `r_hldr` - A pointer to a function that processes a records from the `ECB`.
+    /// * `arg` - An optional argument to be passed to the `p_hldr` and `r_hldr` functions. This argument can maintain any state between successive invocations of the functions.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the consumption is successful.
+    /// * `Err(errno)` - If the consumption fails. The error number (`errno`) is returned.
+    pub fn dtrace_consume(
+        &mut self,
+        out: Option<&mut dyn crate::output::OutputSink>,
+        p_hldr: crate::dtrace_consume_probe_f,
+        r_hldr: crate::dtrace_consume_rec_f,
+        arg: Option<*mut ::core::ffi::c_void>,
+    ) -> Result<(), Error> {
+        let arg = match arg {
+            Some(arg) => arg,
+            None => std::ptr::null_mut(),
+        };
+        let mut ctx = ConsumeContext::new(&self.probe_stats, &self.consumption, &self.perf, p_hldr, r_hldr, arg);
+        let (p_hldr, r_hldr, arg) = ctx.callbacks();
+
+        let status = self.with_output(out, || {
+            self.perf.time_call(|| unsafe {
+                crate::dtrace_consume(self.handle, std::ptr::null_mut(), p_hldr, r_hldr, arg)
+            })
+        })?;
+        match status {
+            0 => Ok(()),
+            _ => Err(Error::from(&*self)),
+        }
+    }
+
+    /// Performs all of the work that must to be done periodically by a DTrace consumer.
+    ///
+    /// This function corresponds to the `statusrate`, `switchrate`, and `aggrate` rates. It first calls `dtrace_status()` to determine the status of the trace and then calls `dtrace_aggregate_snap()` and `dtrace_consume()` to consume any aggregation buffer or principal buffer data.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - An optional destination of the output, which goes to the buffered handler otherwise, see [`OutputSink`](crate::output::OutputSink).
+    /// * `chew` - A function pointer that is called for each enabled probe ID (EPID) that is processed from the buffer.
+    /// * `chewrec` - A function pointer that is called for each record that is processed for an EPID.
+    /// * `arg` - An optional argument to be passed to the `chew` and `chewrec` functions. This argument can maintain any state between successive invocations of the functions.
+    ///
+    /// # Returns
+    ///
+    /// * `DTRACE_WORKSTATUS_OKAY` - If the work is successfully performed.
+    /// * `DTRACE_WORKSTATUS_DONE` - If the work is done and no more work is expected.
+    /// * `DTRACE_WORKSTATUS_ERROR` - If an error occurs while performing the work.
+    pub fn dtrace_work(
+        &mut self,
+        out: Option<&mut dyn crate::output::OutputSink>,
+        p_hldr: crate::dtrace_consume_probe_f,
+        r_hldr: crate::dtrace_consume_rec_f,
+        arg: Option<&mut ::core::ffi::c_void>,
+    ) -> Result<crate::dtrace_workstatus_t, Error> {
+        let arg = match arg {
+            Some(arg) => arg,
+            None => std::ptr::null_mut(),
+        };
+        let mut ctx = ConsumeContext::new(&self.probe_stats, &self.consumption, &self.perf, p_hldr, r_hldr, arg);
+        let (p_hldr, r_hldr, arg) = ctx.callbacks();
+        let pass = self.consumption.begin();
+        let status = self.with_output(out, || {
+            self.perf.time_call(|| {
+                self.capture_clock(|| unsafe {
+                    crate::dtrace_work(self.handle, std::ptr::null_mut(), p_hldr, r_hldr, arg)
+                })
+            })
+        })?;
+        if let Some(start) = pass {
+            // The switchrate is kept as the number of nanoseconds between switches.
+            let switchrate = self.dtrace_getopt("switchrate").ok().filter(|&rate| rate > 0);
+            self.consumption.end(start, switchrate.map(|rate| std::time::Duration::from_nanos(rate as u64)));
+        }
+        if status == crate::dtrace_workstatus_t::DTRACE_WORKSTATUS_ERROR {
+            return Err(Error::from(&*self));
+        }
+        if self.status_watch.lock().unwrap().is_watched() {
+            let _ = self.dtrace_status();
+        }
+        Ok(status)
+    }
+
+    /// Performs the periodic work of a consumer like `dtrace_work`, decoding the consumed records for `visitor`.
+    ///
+    /// Records are also formatted and handed to the buffered handler as with `dtrace_work`, except the value of
+    /// `exit()` and records of unknown action kinds, see
+    /// [`UnknownActionPolicy`](crate::visit::UnknownActionPolicy).
+    ///
+    /// # Arguments
+    ///
+    /// * `visitor` - Receives the firings and their records, see [`RecordVisitor`](crate::visit::RecordVisitor).
+    ///
+    /// # Returns
+    ///
+    /// Returns the status of the work, see `dtrace_work`.
+    pub fn work_with_visitor(
+        &mut self,
+        visitor: &mut dyn crate::visit::RecordVisitor,
+    ) -> Result<crate::dtrace_workstatus_t, Error> {
+        let output: Arc<Mutex<Vec<String>>> = Default::default();
+        let pending = output.clone();
+        let subscriber = self.subscribe_buffered(move |bufdata| {
+            if crate::visit::is_printf(bufdata) {
+                let text = unsafe { ::core::ffi::CStr::from_ptr(bufdata.dtbda_buffered) };
+                pending.lock().unwrap().push(text.to_string_lossy().into_owned());
+            }
+            crate::DTRACE_HANDLE_OK as c_int
+        })?;
+        let mut ctx = crate::visit::VisitContext {
+            visitor,
+            handle: self.handle,
+            epids: self.epids.clone(),
+            decoders: self.decoders(),
+            output,
+            error: None,
+        };
+        let status = self.dtrace_work(
+            None,
+            Some(crate::visit::visit_probe),
+            Some(crate::visit::visit_rec),
+            Some(unsafe { &mut *(&mut ctx as *mut crate::visit::VisitContext as *mut ::core::ffi::c_void) }),
+        );
+        ctx.flush();
+        self.unsubscribe_buffered(subscriber);
+        match ctx.error.take() {
+            Some(err) => Err(err),
+            None => status,
+        }
+    }
+
+    /// Performs the periodic work of a consumer like `dtrace_work`, handing the undecoded firings to `consumer`.
+    ///
+    /// This is the path of least overhead: the callbacks of `consumer` are called directly by libdtrace, without the
+    /// probe statistics, performance counters and status watchers of the other consume paths, see
+    /// [`raw`](crate::raw).
+    ///
+    /// # Arguments
+    ///
+    /// * `consumer` - Receives the firings and their records, see [`RawConsumer`](crate::raw::RawConsumer).
+    ///
+    /// # Returns
+    ///
+    /// Returns the status of the work, see `dtrace_work`.
+    pub fn work_raw<C: crate::raw::RawConsumer>(
+        &mut self,
+        consumer: &mut C,
+    ) -> Result<crate::dtrace_workstatus_t, Error> {
+        let (p_hldr, r_hldr, arg) = crate::raw::callbacks(consumer);
+        let status = self.capture_clock(|| unsafe {
+            crate::dtrace_work(self.handle, std::ptr::null_mut(), p_hldr, r_hldr, arg)
+        });
+        if status == crate::dtrace_workstatus_t::DTRACE_WORKSTATUS_ERROR {
+            return Err(Error::from(&*self));
+        }
+        Ok(status)
+    }
+
+    /// Consumes data from the principal buffers like `dtrace_consume`, handing the undecoded firings to `consumer`,
+    /// see `work_raw`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the consumption is successful.
+    /// * `Err(errno)` - If the consumption fails, or was aborted by `consumer`.
+    pub fn consume_raw<C: crate::raw::RawConsumer>(&mut self, consumer: &mut C) -> Result<(), Error> {
+        let (p_hldr, r_hldr, arg) = crate::raw::callbacks(consumer);
+        match unsafe { crate::dtrace_consume(self.handle, std::ptr::null_mut(), p_hldr, r_hldr, arg) } {
+            0 => Ok(()),
+            _ => Err(Error::from(&*self)),
+        }
+    }
+
+    /// Snapshots the aggregations and hands each entry to `visitor`, see `aggregate_snapshot`.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The order in which the entries are visited.
+    /// * `visitor` - Receives the entries through `visit_aggregate`, or `visit_decoded_aggregate` for the entries
+    ///   decoded by the decoders of the handle, see `set_decoders`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of entries visited, or the error of the snapshot or the walk.
+    pub fn visit_aggregates(
+        &mut self,
+        order: dtrace_aggwalk_order,
+        visitor: &mut dyn crate::visit::RecordVisitor,
+    ) -> Result<usize, Error> {
+        let snapshot = self.aggregate_snapshot(order)?;
+        let decoders = self.decoders();
+        for entry in &snapshot.entries {
+            match decoders.decode_aggregate(entry) {
+                Some(value) => visitor.visit_decoded_aggregate(entry, &value),
+                None => visitor.visit_aggregate(entry),
+            }
+        }
+        Ok(snapshot.entries.len())
+    }
+
+    /// Decodes the records and aggregation entries handed to visitors with the decoders of `registry`, replacing the
+    /// decoders set before, see [`DecoderRegistry`](crate::decoders::DecoderRegistry).
+    pub fn set_decoders(&self, registry: impl Into<Arc<crate::decoders::DecoderRegistry>>) {
+        *self.decoders.lock().unwrap() = registry.into();
+    }
+
+    /// Returns the decoders of the handle, see `set_decoders`.
+    pub fn decoders(&self) -> Arc<crate::decoders::DecoderRegistry> {
+        self.decoders.lock().unwrap().clone()
+    }
+
+    /// Maps an enabled probe ID (EPID), such as the one found in the enabling description of probe data, back to its
+    /// enabling and probe descriptions.
+    ///
+    /// The descriptions are cached on the handle, so repeated lookups of the same EPID do not re-parse them.
+    ///
+    /// # Arguments
+    ///
+    /// * `epid` - The enabled probe ID to look up.
+    ///
+    /// # Returns
+    ///
+    /// Returns the [`EnabledProbe`](crate::probe::EnabledProbe), or [`None`] if the EPID is not known to this instance.
+    pub fn lookup_epid(&self, epid: crate::dtrace_epid_t) -> Option<Arc<crate::probe::EnabledProbe>> {
+        unsafe { self.epids.lookup(self.handle, epid) }
+    }
+
+    /// Maps an aggregation ID, such as the one found in the description of aggregation data, back to the description
+    /// of its aggregation.
+    ///
+    /// The descriptions are cached on the handle as they are first seen, also by `aggregate_entries`, so decoding
+    /// entries does not re-parse them.
+    ///
+    /// # Arguments
+    ///
+    /// * `aggid` - The aggregation ID to look up.
+    ///
+    /// # Returns
+    ///
+    /// Returns the [`AggregationDescription`](crate::aggregate::AggregationDescription), or [`None`] if the
+    /// aggregation is not known to this instance.
+    pub fn lookup_aggregation(
+        &self,
+        aggid: crate::dtrace_aggid_t,
+    ) -> Option<Arc<crate::aggregate::AggregationDescription>> {
+        unsafe { self.aggregations.lookup(self.handle, aggid) }
+    }
+
+    /// Lists the aggregations of the programs enabled on the instance, e.g. to check that every aggregation an
+    /// [`AggregationRouter`](crate::aggregate::AggregationRouter) has a callback for exists.
+    ///
+    /// # Returns
+    ///
+    /// Returns the [`AggregationDescription`](crate::aggregate::AggregationDescription) of every aggregation, ordered
+    /// by aggregation ID. Aggregations appear once libdtrace has seen their data, e.g. after `dtrace_aggregate_snap`.
+    pub fn aggregations(&self) -> Vec<Arc<crate::aggregate::AggregationDescription>> {
+        let maxagg = unsafe { (*self.handle).dt_maxagg };
+        (0..maxagg)
+            .filter_map(|aggid| self.lookup_aggregation(aggid as crate::dtrace_aggid_t))
+            .collect()
+    }
+
+    /// Enables or disables the collection of per-probe firing statistics.
+    ///
+    /// While enabled, `dtrace_consume` and `dtrace_work` count the firings and recorded bytes of every probe and
+    /// remember when each probe was last seen. Disabling the collection discards the statistics gathered so far.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether statistics should be collected.
+    pub fn set_probe_stats(&self, enabled: bool) {
+        self.probe_stats.set_enabled(enabled);
+    }
+
+    /// Retrieves the time spent inside libdtrace, in the consume callbacks and decoding trace data since the handle
+    /// was opened or `reset_perf_report` was called.
+    ///
+    /// Only available with the `metrics` feature, without it no time is measured.
+    #[cfg(feature = "metrics")]
+    pub fn perf_report(&self) -> crate::perf::PerfReport {
+        self.perf.report()
+    }
+
+    /// Resets the times reported by `perf_report`.
+    #[cfg(feature = "metrics")]
+    pub fn reset_perf_report(&self) {
+        self.perf.reset();
+    }
+
+    /// Enables or disables the collection of statistics on the `dtrace_work` passes, see [`ConsumptionStats`].
+    ///
+    /// While enabled, `dtrace_work` and `work_with_visitor` time every pass and count the bytes of the firings it
+    /// consumes; `work_raw` is not observed. Disabling the collection discards the statistics gathered so far.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether statistics should be collected.
+    pub fn set_consumption_stats(&self, enabled: bool) {
+        self.consumption.set_enabled(enabled);
+    }
+
+    /// Retrieves the statistics on the `dtrace_work` passes collected since they were enabled with
+    /// `set_consumption_stats`.
+    pub fn consumption_stats(&self) -> ConsumptionStats {
+        self.consumption.snapshot()
+    }
+
+    /// Retrieves the per-probe firing statistics collected since they were enabled with `set_probe_stats`.
+    ///
+    /// # Returns
+    ///
+    /// Returns one [`ProbeStats`] entry for every probe that fired, in no particular order.
+    pub fn probe_stats(&self) -> Vec<ProbeStats> {
+        self.probe_stats.snapshot()
+    }
+
+    /// Retrieves a format string from the format table of the compiled programs.
+    ///
+    /// Records produced by `printf()` and `printa()` reference their format string by index (the `dtrd_format` field of
+    /// the record description). This function reconstructs the original format string for such an index.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The 1-based format index, as found in `dtrd_format`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the format string, or [`None`] if the index does not refer to a format string.
+    pub fn format_string(&self, format: u16) -> Option<String> {
+        unsafe {
+            let hdl = &*self.handle;
+            if format == 0 || format as c_int > hdl.dt_maxformat || hdl.dt_formats.is_null() {
+                return None;
+            }
+
+            let fmtdata = *hdl.dt_formats.add(format as usize - 1);
+            if fmtdata.is_null() {
+                return None;
+            }
+
+            let len = crate::dtrace_printf_format(self.handle, fmtdata, std::ptr::null_mut(), 0);
+            let mut buf = vec![0u8; len];
+            crate::dtrace_printf_format(
+                self.handle,
+                fmtdata,
+                buf.as_mut_ptr() as *mut ::core::ffi::c_char,
+                len,
+            );
+            let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+            Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+        }
+    }
+
+    /// Retrieves the whole format string table of the compiled programs.
+    ///
+    /// # Returns
+    ///
+    /// Returns `(index, format)` pairs for every format string, where `index` is the value records use in `dtrd_format`.
+    pub fn format_strings(&self) -> Vec<(u16, String)> {
+        let max = unsafe { (*self.handle).dt_maxformat }.clamp(0, u16::MAX as c_int) as u16;
+        (1..=max)
+            .filter_map(|format| self.format_string(format).map(|fmt| (format, fmt)))
+            .collect()
+    }
+
+    /// Decodes a record of the `print()` action into a value tree, using the CTF type of the printed expression.
+    ///
+    /// `print()` records are `DTRACEACT_DIFEXPR` records whose format index refers to a string of the handle naming the
+    /// type of the expression, as `module`type ID`, instead of a format string.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The probe data passed to the record callback.
+    /// * `rec` - The record passed to the record callback.
+    ///
+    /// # Returns
+    ///
+    /// Returns the decoded value, or `None` if `rec` is not a `print()` record or its type cannot be found.
+    ///
+    /// # Safety
+    ///
+    /// `data` and `rec` must be the arguments of a record callback of this handle, or null.
+    pub unsafe fn decode_print(
+        &self,
+        data: *const crate::dtrace_probedata_t,
+        rec: *const crate::dtrace_recdesc_t,
+    ) -> Option<crate::typed::PrintedValue> {
+        if data.is_null() || rec.is_null() {
+            return None;
+        }
+        let rec = &*rec;
+        let hdl = &*self.handle;
+        // The type name is a string of the handle, as `dt_strdata_lookup` finds it, not a format.
+        if rec.dtrd_action as u32 != crate::DTRACEACT_DIFEXPR
+            || rec.dtrd_format == 0
+            || rec.dtrd_format as c_int > hdl.dt_maxstrdata
+            || hdl.dt_strdata.is_null()
+        {
+            return None;
+        }
+        let strdata = *hdl.dt_strdata.add(rec.dtrd_format as usize - 1);
+        if strdata.is_null() {
+            return None;
+        }
+
+        let typename = ::core::ffi::CStr::from_ptr(strdata).to_str().ok()?;
+        let (module, id) = typename.rsplit_once('`')?;
+        let id: crate::ctf_id_t = id.parse().ok()?;
+        let module = std::ffi::CString::new(module).ok()?;
+        let dmp = crate::dt_module_lookup_by_name(self.handle, module.as_ptr());
+        if dmp.is_null() {
+            return None;
+        }
+        let ctfp = crate::dt_module_getctf(self.handle, dmp);
+        if ctfp.is_null() {
+            return None;
+        }
+
+        // libdtrace points the data of the firing at the record it passes.
+        let bytes = std::slice::from_raw_parts((*data).dtpda_data as *const u8, rec.dtrd_size as usize);
+        Some(crate::typed::PrintedValue {
+            type_name: crate::typeinfo::type_name(ctfp, id),
+            value: crate::typed::decode(ctfp, id, bytes),
+        })
+    }
+
+    /* Data Consumption APIs END */
+
+    /* Handler APIs START */
+    /// Sets a handler functions for processing trace data.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `handler` - An enum variant from [`dtrace_handler`] representing the handler function to be called for each trace record. Possible values:
+    ///     * `Buffered(handler)` - The handler function to be called for each buffered trace record.
+    ///         * If [`None`] is passed to `dtrace_work`, `dtrace_consume` or `dtrace_aggregate_print` function, then libdtrace makes use of the buffered I/O handler to process buffered trace data.
+    ///         * The handler function must have the following signature:
+    ///             ```rs
+    ///                 unsafe extern "C" fn(*const dtrace_bufdata_t, *mut c_void) -> c_int
+    ///             ```
+    ///     * `Drop(handler)` - The handler function to be called for each dropped trace record.
+    ///         * The handler function must have the following signature:
+    ///             ```rs
+    ///                 unsafe extern "C" fn(*const dtrace_dropdata_t, *mut c_void) -> c_int
+    ///             ```
+    ///     * `Err(handler)` - To register a handler function for processing errors such as accessing an invalid address or dividing by zero.
+    ///         * The handler function must have the following signature:
+    ///             ```rs
+    ///                 unsafe extern "C" fn(*const dtrace_errdata_t, *mut c_void) -> c_int
+    ///             ```
+    ///     * `SetOpt(handler)` - This handler is called whenever a DTrace option is set from inside a D program.
+    ///         * The handler function must have the following signature:
+    ///             ```rs
+    ///                 unsafe extern "C" fn(*const dtrace_setoptdata_t, *mut c_void) -> c_int
+    ///             ```
+    ///     * `Proc(handler)` - Unsupported.
+    /// * `arg` - An optional argument to be passed to the handler function. This argument can maintain any state between successive invocations of the handler function.
+    /// 
+    /// # Returns
+    /// 
+    /// Returns `Ok(())` if the handler was set successfully, or an error code if the handler could
+    /// not be set. Registering a second handler of the same kind is an error, use `replace_handler` instead.
+    pub fn dtrace_register_handler(
+        &self,
+        handler: crate::types::dtrace_handler,
+        arg: Option<*mut ::core::ffi::c_void>,
+    ) -> Result<(), Error> {
+        let _guard = self.state_lock.lock().unwrap();
+        self.register_raw(handler, arg)
+    }
+
+    /// Registers `handler` like `dtrace_register_handler`, the caller holding `state_lock`.
+    fn register_raw(
+        &self,
+        handler: crate::types::dtrace_handler,
+        arg: Option<*mut ::core::ffi::c_void>,
+    ) -> Result<(), Error> {
+        let arg = match arg {
+            Some(arg) => arg,
+            None => std::ptr::null_mut(),
+        };
+        self.install_handler(handler, arg, None)?;
+
+        // Nothing keeps a raw argument alive for another handle, so only handlers without one are registered again.
+        let kind = handler.kind();
+        let replayable = arg.is_null();
+        self.config.lock().unwrap().set_handler(
+            kind,
+            Arc::new(move |hdl: &Self| match replayable {
+                true => hdl.dtrace_register_handler(handler, None),
+                false => Err(Error::new(
+                    -1,
+                    format!("The {:?} handler has a raw argument, register it with register_handler_with", kind),
+                )),
+            }),
+        );
+        Ok(())
+    }
+
+    /// Registers `handler` and records it as installed, keeping `state` alive until it is removed, the caller holding
+    /// `state_lock`.
+    fn install_handler(
+        &self,
+        handler: crate::types::dtrace_handler,
+        arg: *mut ::core::ffi::c_void,
+        state: Option<Box<dyn std::any::Any + Send>>,
+    ) -> Result<(), Error> {
+        let kind = handler.kind();
+        if self.handlers.is_installed(kind) {
+            return Err(Error::new(
+                -1,
+                format!("A {:?} handler is already registered, use replace_handler to replace it", kind),
+            ));
+        }
+
+        let status;
+        unsafe {
+            status = match handler {
+                crate::types::dtrace_handler::Buffered(handler) => {
+                    crate::dtrace_handle_buffered(self.handle, handler, arg)
+                }
+                crate::types::dtrace_handler::Drop(handler) => {
+                    crate::dtrace_handle_drop(self.handle, handler, arg)
+                }
+                crate::types::dtrace_handler::Err(handler) => {
+                    crate::dtrace_handle_err(self.handle, handler, arg)
+                }
+                crate::types::dtrace_handler::SetOpt(handler) => {
+                    crate::dtrace_handle_setopt(self.handle, handler, arg)
+                }
+                crate::types::dtrace_handler::Proc(handler) => {
+                    crate::dtrace_handle_proc(self.handle, handler, arg)
+                }
+            };
+        }
+
+        if status == 0 {
+            self.handlers.install(kind, state);
+            Ok(())
+        } else {
+            Err(Error::from(self))
+        }
+    }
+
+    /// Replaces the handler of the same kind as `handler`, or registers it if none is registered yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - An enum variant from [`dtrace_handler`](crate::types::dtrace_handler), see `dtrace_register_handler`.
+    /// * `arg` - An optional argument to be passed to the handler function.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the handler was set successfully, or an error code if the handler could
+    /// not be set.
+    pub fn replace_handler(
+        &self,
+        handler: crate::types::dtrace_handler,
+        arg: Option<*mut ::core::ffi::c_void>,
+    ) -> Result<(), Error> {
+        let _guard = self.state_lock.lock().unwrap();
+        self.uninstall_handler(handler.kind());
+        self.register_raw(handler, arg)
+    }
+
+    /// Replaces the handler of the same kind as `handler` with a handler receiving a typed context, see
+    /// `register_handler_with`.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the handler was set successfully, or an error code if the handler could
+    /// not be set.
+    pub fn replace_handler_with<T: Send + Sync + 'static>(
+        &self,
+        handler: crate::handler::Handler<T>,
+        ctx: Arc<T>,
+    ) -> Result<(), Error> {
+        let _guard = self.state_lock.lock().unwrap();
+        self.uninstall_handler(handler.kind());
+        self.register_typed(handler, ctx)
+    }
+
+    /// Removes the registered handler of the given kind, restoring the libdtrace default behavior.
+    ///
+    /// Without a drop or error handler, libdtrace aborts consumption when data is dropped or an error occurs. Without
+    /// a buffered handler, output can only be written to a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The kind of handler to remove.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if a handler of that kind was registered.
+    pub fn remove_handler(&self, kind: crate::types::dtrace_handler_kind) -> bool {
+        let _guard = self.state_lock.lock().unwrap();
+        self.uninstall_handler(kind)
+    }
+
+    /// Removes the handler of the given kind like `remove_handler`, the caller holding `state_lock`.
+    fn uninstall_handler(&self, kind: crate::types::dtrace_handler_kind) -> bool {
+        use crate::types::dtrace_handler_kind;
+        unsafe {
+            let hdl = &mut *self.handle;
+            match kind {
+                dtrace_handler_kind::Buffered => {
+                    hdl.dt_bufhdlr = None;
+                    hdl.dt_bufarg = std::ptr::null_mut();
+                }
+                dtrace_handler_kind::Drop => {
+                    hdl.dt_drophdlr = None;
+                    hdl.dt_droparg = std::ptr::null_mut();
+                }
+                dtrace_handler_kind::Err => {
+                    hdl.dt_errhdlr = None;
+                    hdl.dt_errarg = std::ptr::null_mut();
+                }
+                dtrace_handler_kind::Proc => {
+                    hdl.dt_prochdlr = None;
+                    hdl.dt_procarg = std::ptr::null_mut();
+                }
+                dtrace_handler_kind::SetOpt => {
+                    hdl.dt_setopthdlr = None;
+                    hdl.dt_setoptarg = std::ptr::null_mut();
+                }
+            }
+        }
+        if kind == dtrace_handler_kind::Buffered {
+            self.buffered.clear();
+        }
+        self.config.lock().unwrap().remove_handler(kind);
+        self.handlers.remove(kind)
+    }
+
+    /// Adds a subscriber to buffered output.
+    ///
+    /// libdtrace supports a single buffered handler, so the first subscriber installs a buffered handler that fans
+    /// the output out to every subscriber in the order they subscribed. Subscribing fails if a buffered handler was
+    /// registered by other means. Subscribers must not subscribe or unsubscribe from within the callback.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscriber` - Called for each buffered trace record. Returning `DTRACE_HANDLE_ABORT` aborts consumption once
+    ///   all subscribers have seen the record.
+    ///
+    /// # Returns
+    ///
+    /// Returns the ID of the subscriber, to be passed to `unsubscribe_buffered`.
+    pub fn subscribe_buffered(
+        &self,
+        subscriber: impl FnMut(&crate::dtrace_bufdata_t) -> c_int + Send + 'static,
+    ) -> Result<usize, Error> {
+        let _guard = self.state_lock.lock().unwrap();
+        let (id, first) = self.buffered.add(Box::new(subscriber));
+        if first {
+            let state = Box::new(self.buffered.clone());
+            let arg = Arc::as_ptr(&self.buffered) as *mut ::core::ffi::c_void;
+            let handler = crate::types::dtrace_handler::Buffered(Some(crate::handler::fan_out));
+            if let Err(err) = self.install_handler(handler, arg, Some(state)) {
+                self.buffered.remove(id);
+                return Err(err);
+            }
+        }
+        Ok(id)
+    }
+
+    /// Removes a subscriber added with `subscribe_buffered`.
+    ///
+    /// Removing the last subscriber also removes the buffered handler installed for the subscribers.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID returned by `subscribe_buffered`.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the subscriber existed.
+    pub fn unsubscribe_buffered(&self, id: usize) -> bool {
+        let _guard = self.state_lock.lock().unwrap();
+        let (removed, empty) = self.buffered.remove(id);
+        if removed && empty {
+            self.uninstall_handler(crate::types::dtrace_handler_kind::Buffered);
+        }
+        removed
+    }
+
+    /// Registers the default drop handler, which logs a warning through the `log` crate for every drop and counts the
+    /// dropped records, see `drop_counts`.
+    ///
+    /// Without a drop handler, libdtrace aborts consumption as soon as data is dropped.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the handler was set successfully, or an error code if the handler could
+    /// not be set.
+    pub fn register_default_drop_handler(&self) -> Result<(), Error> {
+        self.register_handler_with(
+            crate::handler::Handler::Drop(crate::diagnostics::log_drop),
+            self.drops.clone(),
+        )?;
+        self.config.lock().unwrap().set_handler(
+            crate::types::dtrace_handler_kind::Drop,
+            Arc::new(|hdl: &Self| hdl.register_default_drop_handler()),
+        );
+        Ok(())
+    }
+
+    /// Retrieves the number of records dropped since the default drop handler was registered.
+    pub fn drop_counts(&self) -> DropCounts {
+        *self.drops.lock().unwrap()
+    }
+
+    /// Assesses the pressure on the dynamic variable space from the drops counted by the default drop handler and
+    /// the current `dynvarsize` and `cleanrate` options, suggesting new values for them if variables were dropped.
+    ///
+    /// ```no_run
+    /// # let mut handle: libdtrace_rs::wrapper::dtrace_hdl = unimplemented!();
+    /// let pressure = handle.dynvar_pressure();
+    /// for (option, value) in pressure.suggested_options() {
+    ///     println!("dynamic variable drops, consider -x {}={}", option, value);
+    /// }
+    /// ```
+    pub fn dynvar_pressure(&self) -> crate::diagnostics::DynvarPressure {
+        let option = |name: &str| {
+            self.dtrace_getopt(name)
+                .ok()
+                .filter(|&value| value != crate::DTRACEOPT_UNSET)
+                .map(|value| value as u64)
+        };
+        crate::diagnostics::DynvarPressure::new(&self.drop_counts(), option("dynvarsize"), option("cleanrate"))
+    }
+
+    /// Registers the default error handler, which logs an error through the `log` crate for every fault, naming the
+    /// offending probe, action and address, and counts the faults, see `error_count`.
+    ///
+    /// Without an error handler, libdtrace aborts consumption as soon as a fault occurs.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the handler was set successfully, or an error code if the handler could
+    /// not be set.
+    pub fn register_default_error_handler(&self) -> Result<(), Error> {
+        self.register_handler_with(
+            crate::handler::Handler::Err(crate::diagnostics::log_error),
+            self.errors.clone(),
+        )?;
+        self.config.lock().unwrap().set_handler(
+            crate::types::dtrace_handler_kind::Err,
+            Arc::new(|hdl: &Self| hdl.register_default_error_handler()),
+        );
+        Ok(())
+    }
+
+    /// Retrieves the number of faults since the default error handler was registered.
+    pub fn error_count(&self) -> u64 {
+        self.errors.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Describes a fault reported to an error handler, attributed to the clause of the enabled programs it occurred in
+    /// when the probe that fired is enabled by a single clause.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The error data passed to the error handler.
+    ///
+    /// # Returns
+    ///
+    /// Returns the [`Fault`](crate::diagnostics::Fault), with the probe, action and DIF offset of the fault and, when
+    /// resolvable, the line and text of the faulting clause.
+    pub fn fault(&self, data: &crate::dtrace_errdata_t) -> crate::diagnostics::Fault {
+        crate::diagnostics::Fault::new(data, &self.statements.lock().unwrap())
+    }
+
+    /// Returns the table of the ECBs of the enabled programs, for error handlers that cannot borrow the handle.
+    pub(crate) fn statement_sources(&self) -> Arc<Mutex<Vec<StatementSource>>> {
+        self.statements.clone()
+    }
+
+    /// Lists the statements of the programs enabled by `dtrace_program_exec`, one per probe description of each
+    /// clause, with the probes each matched, in the order they were enabled.
+    pub fn statement_matches(&self) -> Vec<StatementSource> {
+        self.statements.lock().unwrap().clone()
+    }
+
+    /// Lists the statements of the enabled programs that matched no probe, e.g. a clause naming a misspelled function
+    /// in a program whose other clauses match, which enables without an error.
+    ///
+    /// ```no_run
+    /// # let handle: libdtrace_rs::wrapper::dtrace_hdl = unimplemented!();
+    /// for statement in handle.unmatched_statements() {
+    ///     eprintln!("warning: {}", statement);
+    /// }
+    /// ```
+    pub fn unmatched_statements(&self) -> Vec<StatementSource> {
+        self.statements
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|statement| statement.probes.is_empty())
+            .cloned()
+            .collect()
+    }
+
+    /// Sets a handler function that receives a typed context instead of a raw pointer.
+    ///
+    /// The context is shared with the handler through an [`Arc`] which the handle keeps alive until it is closed, so
+    /// no manual `Box::into_raw`/`Box::from_raw` bookkeeping is needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - An enum variant from [`Handler`](crate::handler::Handler) holding the handler function. The
+    ///   function receives the handler data and a reference to the context, e.g. for `Buffered(handler)`:
+    ///     ```rs
+    ///         fn(&dtrace_bufdata_t, &T) -> c_int
+    ///     ```
+    /// * `ctx` - The context passed to every invocation of the handler.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the handler was set successfully, or an error code if the handler could
+    /// not be set.
+    pub fn register_handler_with<T: Send + Sync + 'static>(
+        &self,
+        handler: crate::handler::Handler<T>,
+        ctx: Arc<T>,
+    ) -> Result<(), Error> {
+        let _guard = self.state_lock.lock().unwrap();
+        self.register_typed(handler, ctx)
+    }
+
+    /// Registers `handler` like `register_handler_with`, the caller holding `state_lock`.
+    fn register_typed<T: Send + Sync + 'static>(
+        &self,
+        handler: crate::handler::Handler<T>,
+        ctx: Arc<T>,
+    ) -> Result<(), Error> {
+        let (raw, arg, state) = handler.into_raw(ctx.clone());
+        self.install_handler(raw, arg, Some(state))?;
+
+        self.config.lock().unwrap().set_handler(
+            handler.kind(),
+            Arc::new(move |hdl: &Self| hdl.register_handler_with(handler, ctx.clone())),
+        );
+        Ok(())
+    }
+
+    /// Runs `f` with `handler` temporarily installed as the buffered handler.
+    ///
+    /// libdtrace refuses to replace a registered buffered handler, so the handler is swapped directly on the
+    /// underlying handle and the previous one is restored once `f` returns (or panics).
+    fn with_buffered_handler<T>(
+        &self,
+        handler: crate::dtrace_handle_buffered_f,
+        arg: *mut ::core::ffi::c_void,
+        f: impl FnOnce() -> T,
+    ) -> T {
+        struct Restore(
+            *mut crate::dtrace_hdl_t,
+            crate::dtrace_handle_buffered_f,
+            *mut ::core::ffi::c_void,
+        );
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                unsafe {
+                    (*self.0).dt_bufhdlr = self.1;
+                    (*self.0).dt_bufarg = self.2;
+                }
+            }
+        }
+
+        let _restore = unsafe {
+            let hdl = &mut *self.handle;
+            let restore = Restore(self.handle, hdl.dt_bufhdlr, hdl.dt_bufarg);
+            hdl.dt_bufhdlr = handler;
+            hdl.dt_bufarg = arg;
+            restore
+        };
+        f()
+    }
+
+    /// Runs `f` with the output of libdtrace written to `out` if it is set, see [`OutputSink`](crate::output::OutputSink).
+    ///
+    /// # Returns
+    ///
+    /// Returns the result of `f`, or the error that stopped writing to `out`.
+    fn with_output<T>(&self, out: Option<&mut dyn crate::output::OutputSink>, f: impl FnOnce() -> T) -> Result<T, Error> {
+        let out = match out {
+            Some(out) => out,
+            None => return Ok(f()),
+        };
+        let mut bridge = crate::output::SinkBridge::new(out);
+        let arg = &mut bridge as *mut crate::output::SinkBridge as *mut ::core::ffi::c_void;
+        let result = self.with_buffered_handler(Some(crate::output::buffered_to_sink), arg, f);
+        match bridge.error.take() {
+            Some(err) => Err(Error::from(err).with_context("writing output")),
+            None => Ok(result),
+        }
+    }
+
+    /* Handler APIs END */
+
+    /* Aggregation APIs START */
+    /// Retrieves aggregation data from the kernel
+    ///
+    /// This function is called to transfer data from the in-kernel aggregation buffers to the userspace (consumer). The data is not processed at this point.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the aggregation data is successfully retrieved.
+    /// * `Err(errno)` - If the aggregation data could not be retrieved. The error number (`errno`) is returned.
+    pub fn dtrace_aggregate_snap(&mut self) -> Result<(), Error> {
+        match self.perf.time_call(|| unsafe { crate::dtrace_aggregate_snap(self.handle) }) {
+            0 => Ok(()),
+            _ => Err(Error::from(&*self)),
+        }
+    }
+
+    /// Processes DTrace aggregate data.
+    ///
+    /// The function can be passed a specific `walk()` function. If passed `None`, it defaults to the `dtrace_aggregate_walk_sorted()` function,
+    /// and the callback function passed to the `walk()` function is the default function that the libdtrace library uses to print aggregate data.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - An optional destination of the output, which goes to the buffered handler otherwise, see [`OutputSink`](crate::output::OutputSink).
+    /// * `handler` - A function pointer that is called for each aggregate buffer that is processed.
+    /// * `arg` - An optional argument to be passed to the `handler` function. This argument can maintain any state between successive invocations of the function.
     ///
     /// # Returns
     ///
@@ -533,21 +2527,109 @@ impl dtrace_hdl {
     /// * `Err(i32)` - If the processing fails. The error number is returned.
     pub fn dtrace_aggregate_print(
         &self,
-        file: Option<&utils::File>,
+        out: Option<&mut dyn crate::output::OutputSink>,
         handler: crate::dtrace_aggregate_walk_f,
     ) -> Result<(), Error> {
-        let file = match file {
-            Some(file) => file.file,
-            None => std::ptr::null_mut(),
-        };
+        let _guard = self.state_lock.lock().unwrap();
+        let status = self.with_output(out, || unsafe {
+            crate::dtrace_aggregate_print(self.handle, std::ptr::null_mut(), handler)
+        })?;
+        match status {
+            0 => Ok(()),
+            _ => Err(Error::from(self)),
+        }
+    }
 
-        match unsafe { crate::dtrace_aggregate_print(self.handle, file, handler) }
-        {
+    /// Processes DTrace aggregate data like `dtrace_aggregate_print`, but captures the output in a [`String`].
+    ///
+    /// The output is collected through a temporarily installed buffered handler, so no file handle is needed. A
+    /// previously registered buffered handler is restored afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - A function pointer that is called for each aggregate buffer that is processed.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The formatted aggregation data.
+    /// * `Err(Error)` - If the processing fails.
+    pub fn aggregate_print_to_string(
+        &self,
+        handler: crate::dtrace_aggregate_walk_f,
+    ) -> Result<String, Error> {
+        let mut out = Vec::new();
+        self.dtrace_aggregate_print(Some(&mut out), handler)?;
+        Ok(String::from_utf8_lossy(&out).into_owned())
+    }
+
+    /// Processes DTrace aggregate data like `dtrace_aggregate_print`, handing the formatted output to `chunk` piece by
+    /// piece instead, each paired with the entry it formats.
+    ///
+    /// The output is intercepted through a temporarily installed buffered handler, so it does not reach a previously
+    /// registered one. An entry is decoded once for all of its chunks, see
+    /// [`PrintaChunk`](crate::aggregate::PrintaChunk).
+    ///
+    /// ```no_run
+    /// use libdtrace_rs::aggregate::ChunkKind;
+    ///
+    /// # let handle: libdtrace_rs::wrapper::dtrace_hdl = unimplemented!();
+    /// handle
+    ///     .aggregate_print_chunks(None, |chunk| match (chunk.kind, &chunk.entry) {
+    ///         (ChunkKind::Value, Some(entry)) => print!("{}={}", entry.name, chunk.text.trim_start()),
+    ///         _ => print!("{}", chunk.text),
+    ///     })
+    ///     .unwrap();
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - A function pointer that is called for each aggregate buffer that is processed.
+    /// * `chunk` - Called for each chunk of output.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the processing is successful.
+    /// * `Err(Error)` - If the processing fails.
+    pub fn aggregate_print_chunks(
+        &self,
+        handler: crate::dtrace_aggregate_walk_f,
+        mut chunk: impl FnMut(&crate::aggregate::PrintaChunk),
+    ) -> Result<(), Error> {
+        let _guard = self.state_lock.lock().unwrap();
+        let mut ctx = crate::aggregate::PrintaContext {
+            descriptions: &self.aggregations,
+            callback: &mut chunk,
+            entry: None,
+        };
+        let arg = &mut ctx as *mut crate::aggregate::PrintaContext as *mut ::core::ffi::c_void;
+        let status = self.with_buffered_handler(Some(crate::aggregate::printa_chunk), arg, || unsafe {
+            crate::dtrace_aggregate_print(self.handle, std::ptr::null_mut(), handler)
+        });
+        match status {
             0 => Ok(()),
             _ => Err(Error::from(self)),
         }
     }
 
+    /// Processes DTrace aggregate data like `dtrace_aggregate_print`, but writes the output to `writer`.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The destination of the formatted aggregation data.
+    /// * `handler` - A function pointer that is called for each aggregate buffer that is processed.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the processing and writing is successful.
+    /// * `Err(Error)` - If the processing or writing fails.
+    pub fn aggregate_print_to(
+        &self,
+        mut writer: impl std::io::Write,
+        handler: crate::dtrace_aggregate_walk_f,
+    ) -> Result<(), Error> {
+        self.dtrace_aggregate_print(Some(&mut writer), handler)
+    }
+
     /// Processes DTrace aggregate data.
     ///
     /// # Arguments
@@ -566,13 +2648,23 @@ impl dtrace_hdl {
         arg: Option<*mut ::core::ffi::c_void>,
         order: dtrace_aggwalk_order,
     ) -> Result<(), Error> {
-        let status;
+        let _guard = self.state_lock.lock().unwrap();
+        self.walk_aggregates(handler, arg, order)
+    }
+
+    /// Walks the aggregations like `dtrace_aggregate_walk`, the caller holding `state_lock`.
+    fn walk_aggregates(
+        &self,
+        handler: crate::dtrace_aggregate_f,
+        arg: Option<*mut ::core::ffi::c_void>,
+        order: dtrace_aggwalk_order,
+    ) -> Result<(), Error> {
         let arg = match arg {
             Some(arg) => arg,
             None => std::ptr::null_mut(),
         };
-        unsafe {
-            status = match order {
+        let status = self.perf.time_call(|| unsafe {
+            match order {
                 dtrace_aggwalk_order::None => {
                     crate::dtrace_aggregate_walk(self.handle, handler, arg)
                 }
@@ -600,8 +2692,8 @@ impl dtrace_hdl {
                 dtrace_aggwalk_order::ValVarRevSorted => {
                     crate::dtrace_aggregate_walk_valvarrevsorted(self.handle, handler, arg)
                 }
-            };
-        }
+            }
+        });
 
         if status == 0 {
             Ok(())
@@ -610,5 +2702,150 @@ impl dtrace_hdl {
         }
     }
 
+    /// Decodes the aggregation data retrieved by the last `dtrace_aggregate_snap` call.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The order in which the entries are returned. One of the members of the [`dtrace_aggwalk_order`] enum.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<AggregateEntry>)` - The decoded entries of all aggregations.
+    /// * `Err(Error)` - If the aggregations could not be walked.
+    pub fn aggregate_entries(
+        &self,
+        order: dtrace_aggwalk_order,
+    ) -> Result<Vec<crate::aggregate::AggregateEntry>, Error> {
+        let _guard = self.state_lock.lock().unwrap();
+        self.collect_aggregates(order)
+    }
+
+    /// Hands the entries of the aggregation data retrieved by the last `dtrace_aggregate_snap` call to `entry`
+    /// without decoding them, e.g. to hash their keys or to send them to another process as they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The order in which the entries are walked.
+    /// * `entry` - Called with each entry, which borrows the data of libdtrace for the duration of the call.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If all entries were walked.
+    /// * `Err(Error)` - If the aggregations could not be walked.
+    pub fn aggregate_walk_raw(
+        &self,
+        order: dtrace_aggwalk_order,
+        mut entry: impl FnMut(&crate::aggregate::RawAggregate),
+    ) -> Result<(), Error> {
+        let _guard = self.state_lock.lock().unwrap();
+        let mut walk = crate::aggregate::RawWalk {
+            descriptions: &self.aggregations,
+            callback: &mut entry,
+        };
+        self.walk_aggregates(
+            Some(crate::aggregate::walk_raw),
+            Some(&mut walk as *mut _ as *mut ::core::ffi::c_void),
+            order,
+        )
+    }
+
+    /// Decodes the aggregation data like `aggregate_entries`, the caller holding `state_lock`.
+    fn collect_aggregates(
+        &self,
+        order: dtrace_aggwalk_order,
+    ) -> Result<Vec<crate::aggregate::AggregateEntry>, Error> {
+        let mut collector = crate::aggregate::Collector {
+            entries: Vec::new(),
+            perf: &self.perf,
+            descriptions: &self.aggregations,
+        };
+        self.walk_aggregates(
+            Some(crate::aggregate::collect),
+            Some(&mut collector as *mut _ as *mut ::core::ffi::c_void),
+            order,
+        )?;
+        Ok(collector.entries)
+    }
+
+    /// Snapshots the aggregation buffers with `dtrace_aggregate_snap` and decodes all entries.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The order in which the entries are walked.
+    ///
+    /// # Returns
+    ///
+    /// Returns the decoded entries, or the error of the snapshot or the walk.
+    pub fn aggregate_snapshot(
+        &mut self,
+        order: dtrace_aggwalk_order,
+    ) -> Result<crate::aggregate::AggregateSnapshot, Error> {
+        self.dtrace_aggregate_snap()?;
+        Ok(self.aggregate_entries(order)?.into())
+    }
+
+    /// Snapshots the aggregation buffers and decodes the entries of all aggregations in one step, sorted like
+    /// dtrace(1) prints them.
+    ///
+    /// Other threads cannot snapshot, walk or print the aggregations of the handle in between, so the entries of
+    /// all aggregations come from the same snapshot, e.g. to report several aggregations for one interval.
+    ///
+    /// ```no_run
+    /// # let handle: libdtrace_rs::wrapper::dtrace_hdl = unimplemented!();
+    /// let snapshot = handle.snapshot_aggregations().unwrap();
+    /// let aggregations = snapshot.into_map();
+    /// ```
+    pub fn snapshot_aggregations(&self) -> Result<crate::aggregate::AggregateSnapshot, Error> {
+        let _guard = self.state_lock.lock().unwrap();
+        match self.perf.time_call(|| unsafe { crate::dtrace_aggregate_snap(self.handle) }) {
+            0 => Ok(self.collect_aggregates(dtrace_aggwalk_order::Sorted)?.into()),
+            _ => Err(Error::from(self).with_context("snapshotting aggregations")),
+        }
+    }
+
+    /// Normalizes the aggregation `name` in libdtrace, like D's `normalize(@name, normal)`: values printed by
+    /// libdtrace afterwards, e.g. by `dtrace_aggregate_print`, are divided by `normal`, and so are the values
+    /// returned by [`AggregateEntry::normalized`](crate::aggregate::AggregateEntry::normalized).
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the aggregation, without the leading `@`.
+    /// * `normal` - The divisor, e.g. the number of seconds the aggregation was collected over.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the aggregation was normalized.
+    /// * `Err(Error)` - If the aggregations could not be walked.
+    pub fn aggregate_normalize(&self, name: &str, normal: u64) -> Result<(), Error> {
+        let mut normalization = crate::aggregate::Normalization {
+            name,
+            normal: Some(normal),
+        };
+        self.dtrace_aggregate_walk(
+            Some(crate::aggregate::normalize),
+            Some(&mut normalization as *mut _ as *mut ::core::ffi::c_void),
+            dtrace_aggwalk_order::None,
+        )
+    }
+
+    /// Removes the normalization of the aggregation `name` in libdtrace, like D's `denormalize(@name)`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the aggregation, without the leading `@`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the aggregation was denormalized.
+    /// * `Err(Error)` - If the aggregations could not be walked.
+    pub fn aggregate_denormalize(&self, name: &str) -> Result<(), Error> {
+        let mut normalization = crate::aggregate::Normalization { name, normal: None };
+        self.dtrace_aggregate_walk(
+            Some(crate::aggregate::normalize),
+            Some(&mut normalization as *mut _ as *mut ::core::ffi::c_void),
+            dtrace_aggwalk_order::None,
+        )
+    }
+
     /* Aggregation APIs END */
 }